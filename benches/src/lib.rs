@@ -208,6 +208,141 @@ r#"
 	});
 }
 
+#[bench]
+fn float_const_loop(b: &mut Bencher) {
+	let wasm = wabt::wat2wasm(
+r#"
+;; Sums up a float constant in a loop.
+(func (export "sum") (param i32) (result f64)
+	(local f64)
+	(block
+		(br_if 0 (i32.eqz (get_local 0)))
+		(loop
+			(set_local 1 (f64.add (get_local 1) (f64.const 0.5)))
+			(set_local 0 (i32.sub (get_local 0) (i32.const 1)))
+			(br_if 0 (get_local 0))
+		)
+	)
+	(get_local 1)
+)
+"#
+	).unwrap();
+
+	let module = Module::from_buffer(&wasm).unwrap();
+
+	let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+		.expect("failed to instantiate wasm module")
+		.assert_no_start();
+
+	b.iter(|| {
+		let value = instance
+			.invoke_export("sum", &[RuntimeValue::I32(1000)], &mut NopExternals);
+		assert_matches!(value, Ok(Some(RuntimeValue::F64(v))) if v.to_float() == 500.0);
+	});
+}
+
+// Mixes integer and float arithmetic, exercising the untyped value stack with values
+// of every numeric type.
+#[bench]
+fn mixed_arith_loop(b: &mut Bencher) {
+	let wasm = wabt::wat2wasm(
+r#"
+;; Hashes the loop counter FNV style while summing up a float derived from it.
+(func (export "mix") (param i32) (result i64)
+	(local i64 f64)
+	(set_local 1 (i64.const 0xcbf29ce484222325))
+	(block
+		(br_if 0 (i32.eqz (get_local 0)))
+		(loop
+			(set_local 1 (i64.mul
+				(i64.xor (get_local 1) (i64.extend_u/i32 (get_local 0)))
+				(i64.const 0x100000001b3)))
+			(set_local 2 (f64.add
+				(get_local 2)
+				(f64.convert_s/i32 (i32.rotl (get_local 0) (i32.const 7)))))
+			(set_local 0 (i32.sub (get_local 0) (i32.const 1)))
+			(br_if 0 (get_local 0))
+		)
+	)
+	(i64.xor (get_local 1) (i64.reinterpret/f64 (get_local 2)))
+)
+"#
+	).unwrap();
+
+	let module = Module::from_buffer(&wasm).unwrap();
+
+	let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+		.expect("failed to instantiate wasm module")
+		.assert_no_start();
+
+	let mut hash: u64 = 0xcbf29ce484222325;
+	let mut sum = 0f64;
+	for n in (1..=1000u32).rev() {
+		hash = (hash ^ n as u64).wrapping_mul(0x100000001b3);
+		sum += n.rotate_left(7) as i32 as f64;
+	}
+	let expected = (hash ^ sum.to_bits()) as i64;
+
+	b.iter(|| {
+		let value = instance
+			.invoke_export("mix", &[RuntimeValue::I32(1000)], &mut NopExternals);
+		assert_matches!(value, Ok(Some(RuntimeValue::I64(v))) if v == expected);
+	});
+}
+
+// A module with many functions of which only `main` runs, like a large program
+// that is invoked for a single short task.
+fn many_functions_wasm() -> Vec<u8> {
+	let mut wat = String::from("(module\n");
+	for _ in 0..2000 {
+		wat.push_str(
+			"(func (param i32) (result i32)
+				(local i32)
+				(set_local 1 (i32.mul (get_local 0) (i32.const 31)))
+				(block
+					(loop
+						(br_if 1 (i32.gt_u (get_local 1) (i32.const 1000)))
+						(set_local 1 (i32.add (get_local 1) (i32.const 7)))
+						(br 0)
+					)
+				)
+				(i32.xor (get_local 1) (get_local 0))
+			)\n",
+		);
+	}
+	wat.push_str("(func (export \"main\") (result i32) (call 0 (i32.const 1))))");
+	wabt::wat2wasm(&wat).unwrap()
+}
+
+fn start_and_run_main(module: &Module) {
+	let instance = ModuleInstance::new(module, &ImportsBuilder::default())
+		.expect("failed to instantiate wasm module")
+		.assert_no_start();
+	let value = instance.invoke_export("main", &[], &mut NopExternals);
+	assert_matches!(value, Ok(Some(RuntimeValue::I32(_))));
+}
+
+#[bench]
+fn startup_many_functions(b: &mut Bencher) {
+	let wasm = many_functions_wasm();
+
+	b.iter(|| {
+		let module = Module::from_buffer(&wasm).unwrap();
+		start_and_run_main(&module);
+	});
+}
+
+#[cfg(feature = "lazy_compilation")]
+#[bench]
+fn startup_many_functions_lazy(b: &mut Bencher) {
+	let wasm = many_functions_wasm();
+
+	b.iter(|| {
+		let module = Module::from_buffer_lazy(&wasm).unwrap();
+		start_and_run_main(&module);
+	});
+}
+
 // This is used for testing overhead of a function call
 // is not too large.
 #[bench]
@@ -276,3 +411,61 @@ fn recursive_trap(b: &mut Bencher) {
 		assert_matches!(value, Err(_));
 	});
 }
+
+// Measures the overhead of calling a host function with several arguments.
+#[bench]
+fn host_calls(b: &mut Bencher) {
+	use wasmi::{
+		Error, FuncInstance, FuncRef, ModuleImportResolver, RuntimeArgs, Signature,
+	};
+
+	struct HostResolver;
+
+	impl ModuleImportResolver for HostResolver {
+		fn resolve_func(&self, _field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+			Ok(FuncInstance::alloc_host_closure(
+				signature.clone(),
+				|args: RuntimeArgs| {
+					let a: i32 = args.nth_checked(0)?;
+					let d: i32 = args.nth_checked(3)?;
+					Ok(Some(RuntimeValue::I32(a ^ d)))
+				},
+			))
+		}
+	}
+
+	let wasm = wabt::wat2wasm(
+		r#"
+(module
+  (import "env" "mix" (func $mix (param i32 i64 f64 i32) (result i32)))
+  (func (export "call") (param i32) (result i32)
+	(local i32)
+	(block
+	  (loop
+		(br_if 1 (i32.eqz (get_local 0)))
+		(set_local 1
+		  (call $mix (get_local 1) (i64.const 2) (f64.const 3) (get_local 0)))
+		(set_local 0 (i32.sub (get_local 0) (i32.const 1)))
+		(br 0)
+	  )
+	)
+	(get_local 1)
+  )
+)
+		"#
+	).unwrap();
+	let module = Module::from_buffer(&wasm).unwrap();
+
+	let instance = ModuleInstance::new(
+		&module,
+		&ImportsBuilder::new().with_resolver("env", &HostResolver),
+	)
+	.expect("failed to instantiate wasm module")
+	.assert_no_start();
+
+	b.iter(|| {
+		let value = instance
+			.invoke_export("call", &[RuntimeValue::I32(10000)], &mut NopExternals);
+		assert_matches!(value, Ok(Some(RuntimeValue::I32(_))));
+	});
+}
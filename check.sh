@@ -6,4 +6,11 @@ cd $(dirname $0)
 
 cargo check --tests
 
+# Check every optional feature on its own, see the `[features]` section of `Cargo.toml`.
+for feature in stack_height_checks defensive_checks execution_deadline overflow_tracing \
+    compact_bytecode source_offsets stepping lazy_compilation atomics trap_state import_usage \
+    preserve_nops vec_memory grow_failure_injection; do
+    cargo check --tests --features=${feature}
+done
+
 cd -
@@ -0,0 +1,99 @@
+use crate::{Error, Module};
+use core::fmt;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A thread-safe in-memory cache of validated and compiled modules.
+///
+/// Modules are keyed by the contents of the wasm binary they were created from, so loading
+/// identical bytes again returns the already compiled [`Module`] instead of deserializing and
+/// validating it a second time. The full bytes are compared on lookup, so distinct binaries
+/// never share an entry even if their hashes collide.
+///
+/// The cache is purely opt-in: [`Module::from_buffer`] never consults it.
+///
+/// [`Module`]: struct.Module.html
+/// [`Module::from_buffer`]: struct.Module.html#method.from_buffer
+#[derive(Default)]
+pub struct ModuleCache {
+    modules: Mutex<HashMap<Box<[u8]>, Arc<Module>>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl fmt::Debug for ModuleCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ModuleCache")
+            .field("len", &self.len())
+            .field("hits", &self.hits())
+            .field("misses", &self.misses())
+            .finish()
+    }
+}
+
+impl ModuleCache {
+    /// Creates an empty cache.
+    pub fn new() -> ModuleCache {
+        ModuleCache::default()
+    }
+
+    /// Returns the module compiled from `buffer`, creating and caching it on a miss.
+    ///
+    /// The cache is not locked while a module is being validated, so concurrent misses for
+    /// the same bytes may validate them more than once. All callers still receive the module
+    /// that ended up in the cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `buffer` is not a valid module, see [`Module::from_buffer`].
+    /// Invalid modules are not cached.
+    ///
+    /// [`Module::from_buffer`]: struct.Module.html#method.from_buffer
+    pub fn validate_cached<B: AsRef<[u8]>>(&self, buffer: B) -> Result<Arc<Module>, Error> {
+        let buffer = buffer.as_ref();
+        if let Some(module) = self.lock().get(buffer) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Arc::clone(module));
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let module = Arc::new(Module::from_buffer(buffer)?);
+        Ok(Arc::clone(
+            self.lock().entry(buffer.into()).or_insert(module),
+        ))
+    }
+
+    /// Returns the number of lookups that were served from the cache.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of lookups that had to validate their module.
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of cached modules.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if no module is cached.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Removes all cached modules.
+    ///
+    /// Modules still referenced elsewhere stay alive until their last `Arc` is dropped.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Box<[u8]>, Arc<Module>>> {
+        // The map is never left in an inconsistent state, so a poisoned lock is still usable.
+        self.modules
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -1,7 +1,9 @@
-use crate::host::Externals;
-use crate::isa;
+use crate::host::{Externals, RuntimeArgs, SharedExternals};
 use crate::module::ModuleInstance;
-use crate::runner::{check_function_args, Interpreter, InterpreterState, StackRecycler};
+use crate::prepare::FuncCode;
+use crate::runner::{
+    check_function_args, check_function_result, Interpreter, InterpreterState, StackRecycler,
+};
 use crate::types::ValueType;
 use crate::value::RuntimeValue;
 use crate::{Signature, Trap};
@@ -55,8 +57,15 @@ pub(crate) enum FuncInstanceInternal {
         signature: Signature,
         host_func_index: usize,
     },
+    HostClosure {
+        signature: Signature,
+        closure: HostClosure,
+    },
 }
 
+/// A self-contained host function, see [`FuncInstance::alloc_host_closure`].
+pub(crate) type HostClosure = Rc<dyn Fn(RuntimeArgs) -> Result<Option<RuntimeValue>, Trap>>;
+
 impl fmt::Debug for FuncInstance {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.as_internal() {
@@ -68,6 +77,9 @@ impl fmt::Debug for FuncInstance {
             FuncInstanceInternal::Host { ref signature, .. } => {
                 write!(f, "Host {{ signature={:?} }}", signature)
             }
+            FuncInstanceInternal::HostClosure { ref signature, .. } => {
+                write!(f, "HostClosure {{ signature={:?} }}", signature)
+            }
         }
     }
 }
@@ -89,6 +101,26 @@ impl FuncInstance {
         FuncRef(Rc::new(FuncInstance(func)))
     }
 
+    /// Allocate a function instance for a host function backed by a closure.
+    ///
+    /// Unlike functions allocated with [`alloc_host`], calls to this function instance
+    /// don't go through [`Externals`]. Instead, `closure` is invoked directly with the
+    /// arguments of the call, so it has to capture all the state it needs by itself.
+    /// This call will be made with the `signature` provided here.
+    ///
+    /// [`alloc_host`]: #method.alloc_host
+    /// [`Externals`]: trait.Externals.html
+    pub fn alloc_host_closure<F>(signature: Signature, closure: F) -> FuncRef
+    where
+        F: Fn(RuntimeArgs) -> Result<Option<RuntimeValue>, Trap> + 'static,
+    {
+        let func = FuncInstanceInternal::HostClosure {
+            signature,
+            closure: Rc::new(closure),
+        };
+        FuncRef(Rc::new(FuncInstance(func)))
+    }
+
     /// Returns [signature] of this function instance.
     ///
     /// This function instance can only be called with matching signatures.
@@ -98,6 +130,7 @@ impl FuncInstance {
         match *self.as_internal() {
             FuncInstanceInternal::Internal { ref signature, .. } => signature,
             FuncInstanceInternal::Host { ref signature, .. } => signature,
+            FuncInstanceInternal::HostClosure { ref signature, .. } => signature,
         }
     }
 
@@ -121,7 +154,25 @@ impl FuncInstance {
     pub(crate) fn body(&self) -> Option<Rc<FuncBody>> {
         match *self.as_internal() {
             FuncInstanceInternal::Internal { ref body, .. } => Some(Rc::clone(body)),
-            FuncInstanceInternal::Host { .. } => None,
+            FuncInstanceInternal::Host { .. } | FuncInstanceInternal::HostClosure { .. } => None,
+        }
+    }
+
+    /// Invokes this host function with arguments that were already checked against its signature.
+    fn invoke_host<E: Externals>(
+        &self,
+        args: RuntimeArgs,
+        externals: &mut E,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        match *self.as_internal() {
+            FuncInstanceInternal::Internal { .. } => {
+                unreachable!("internal functions are executed by the interpreter")
+            }
+            FuncInstanceInternal::Host {
+                ref host_func_index,
+                ..
+            } => externals.invoke_index(*host_func_index, args),
+            FuncInstanceInternal::HostClosure { ref closure, .. } => closure(args),
         }
     }
 
@@ -145,13 +196,27 @@ impl FuncInstance {
                 let mut interpreter = Interpreter::new(func, args, None)?;
                 interpreter.start_execution(externals)
             }
-            FuncInstanceInternal::Host {
-                ref host_func_index,
-                ..
-            } => externals.invoke_index(*host_func_index, args.into()),
+            FuncInstanceInternal::Host { .. } | FuncInstanceInternal::HostClosure { .. } => {
+                func.invoke_host(args.into(), externals)
+            }
         }
     }
 
+    /// Invoke this function with externals that only need a shared borrow.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`invoke`].
+    ///
+    /// [`invoke`]: #method.invoke
+    pub fn invoke_shared<E: SharedExternals>(
+        func: &FuncRef,
+        args: &[RuntimeValue],
+        externals: &E,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        Self::invoke(func, args, &mut &*externals)
+    }
+
     /// Invoke this function using recycled stacks.
     ///
     /// # Errors
@@ -173,10 +238,9 @@ impl FuncInstance {
                 stack_recycler.recycle(interpreter);
                 return_value
             }
-            FuncInstanceInternal::Host {
-                ref host_func_index,
-                ..
-            } => externals.invoke_index(*host_func_index, args.into()),
+            FuncInstanceInternal::Host { .. } | FuncInstanceInternal::HostClosure { .. } => {
+                func.invoke_host(args.into(), externals)
+            }
         }
     }
 
@@ -207,16 +271,15 @@ impl FuncInstance {
                     kind: FuncInvocationKind::Internal(interpreter),
                 })
             }
-            FuncInstanceInternal::Host {
-                ref host_func_index,
-                ..
-            } => Ok(FuncInvocation {
-                kind: FuncInvocationKind::Host {
-                    args,
-                    host_func_index: *host_func_index,
-                    finished: false,
-                },
-            }),
+            FuncInstanceInternal::Host { .. } | FuncInstanceInternal::HostClosure { .. } => {
+                Ok(FuncInvocation {
+                    kind: FuncInvocationKind::Host {
+                        args,
+                        func: func.clone(),
+                        finished: false,
+                    },
+                })
+            }
         }
     }
 }
@@ -251,6 +314,43 @@ impl From<Trap> for ResumableError {
     }
 }
 
+/// A call of a host function at which an invocation was suspended by
+/// [`FuncInvocation::run_until_host_call`].
+///
+/// [`FuncInvocation::run_until_host_call`]: struct.FuncInvocation.html#method.run_until_host_call
+#[derive(Debug, Clone)]
+pub struct PendingHostCall {
+    func: FuncRef,
+    args: Vec<RuntimeValue>,
+}
+
+impl PendingHostCall {
+    pub(crate) fn new(func: FuncRef, args: Vec<RuntimeValue>) -> PendingHostCall {
+        PendingHostCall { func, args }
+    }
+
+    /// The host function to call.
+    pub fn func(&self) -> &FuncRef {
+        &self.func
+    }
+
+    /// The arguments to call the host function with.
+    pub fn args(&self) -> &[RuntimeValue] {
+        &self.args
+    }
+}
+
+/// The state an invocation reached in [`FuncInvocation::run_until_host_call`].
+///
+/// [`FuncInvocation::run_until_host_call`]: struct.FuncInvocation.html#method.run_until_host_call
+#[derive(Debug, Clone)]
+pub enum ExecutionStep {
+    /// The invocation has finished with the given return value.
+    Finished(Option<RuntimeValue>),
+    /// The invocation is suspended until the host function is called on its behalf.
+    HostCall(PendingHostCall),
+}
+
 /// A resumable invocation handle. This struct is returned by `FuncInstance::invoke_resumable`.
 pub struct FuncInvocation<'args> {
     kind: FuncInvocationKind<'args>,
@@ -260,7 +360,7 @@ enum FuncInvocationKind<'args> {
     Internal(Interpreter),
     Host {
         args: Cow<'args, [RuntimeValue]>,
-        host_func_index: usize,
+        func: FuncRef,
         finished: bool,
     },
 }
@@ -300,13 +400,13 @@ impl<'args> FuncInvocation<'args> {
             FuncInvocationKind::Host {
                 ref args,
                 ref mut finished,
-                ref host_func_index,
+                ref func,
             } => {
                 if *finished {
                     return Err(ResumableError::AlreadyStarted);
                 }
                 *finished = true;
-                Ok(externals.invoke_index(*host_func_index, args.as_ref().into())?)
+                Ok(func.invoke_host(args.as_ref().into(), externals)?)
             }
         }
     }
@@ -324,18 +424,63 @@ impl<'args> FuncInvocation<'args> {
         return_val: Option<RuntimeValue>,
         externals: &'externals mut E,
     ) -> Result<Option<RuntimeValue>, ResumableError> {
-        use crate::TrapKind;
+        check_function_result(self.resumable_value_type(), return_val.as_ref())?;
+
+        match &mut self.kind {
+            FuncInvocationKind::Internal(interpreter) => {
+                if interpreter.state().is_resumable() {
+                    Ok(interpreter.resume_execution(return_val, externals)?)
+                } else {
+                    Err(ResumableError::AlreadyStarted)
+                }
+            }
+            FuncInvocationKind::Host { .. } => Err(ResumableError::NotResumable),
+        }
+    }
 
-        if return_val.map(|v| v.value_type()) != self.resumable_value_type() {
-            return Err(ResumableError::Trap(Trap::new(
-                TrapKind::UnexpectedSignature,
-            )));
+    /// Start the invocation execution and run it until it either finishes or calls a host function.
+    ///
+    /// Instead of calling the host function through [`Externals`], the invocation is suspended
+    /// and the call is returned as [`ExecutionStep::HostCall`]. The caller is expected to make the
+    /// call, e.g. on its own scheduler, and continue the invocation with its result by
+    /// [`resume_until_host_call`] (or [`resume_execution`] to run the rest to completion).
+    ///
+    /// Invocations of a host function itself can't be run this way and return a `NotResumable`
+    /// error.
+    ///
+    /// [`Externals`]: trait.Externals.html
+    /// [`ExecutionStep::HostCall`]: enum.ExecutionStep.html#variant.HostCall
+    /// [`resume_until_host_call`]: #method.resume_until_host_call
+    /// [`resume_execution`]: #method.resume_execution
+    pub fn run_until_host_call(&mut self) -> Result<ExecutionStep, ResumableError> {
+        match self.kind {
+            FuncInvocationKind::Internal(ref mut interpreter) => {
+                if interpreter.state() != &InterpreterState::Initialized {
+                    return Err(ResumableError::AlreadyStarted);
+                }
+                Ok(interpreter.start_until_host_call()?)
+            }
+            FuncInvocationKind::Host { .. } => Err(ResumableError::NotResumable),
         }
+    }
+
+    /// Resume an execution suspended at a host call with the call's `return_val` and run it
+    /// until it either finishes or calls the next host function.
+    ///
+    /// `return_val` must be of the value type [`resumable_value_type`]. Otherwise,
+    /// `UnexpectedSignature` trap will be returned.
+    ///
+    /// [`resumable_value_type`]: #method.resumable_value_type
+    pub fn resume_until_host_call(
+        &mut self,
+        return_val: Option<RuntimeValue>,
+    ) -> Result<ExecutionStep, ResumableError> {
+        check_function_result(self.resumable_value_type(), return_val.as_ref())?;
 
         match &mut self.kind {
             FuncInvocationKind::Internal(interpreter) => {
                 if interpreter.state().is_resumable() {
-                    Ok(interpreter.resume_execution(return_val, externals)?)
+                    Ok(interpreter.resume_until_host_call(return_val)?)
                 } else {
                     Err(ResumableError::AlreadyStarted)
                 }
@@ -348,5 +493,5 @@ impl<'args> FuncInvocation<'args> {
 #[derive(Clone, Debug)]
 pub struct FuncBody {
     pub locals: Vec<Local>,
-    pub code: isa::Instructions,
+    pub code: FuncCode,
 }
@@ -1,8 +1,8 @@
 use crate::types::ValueType;
-use crate::value::RuntimeValue;
+use crate::value::{ExternRef, RuntimeValue};
 use crate::Error;
 use alloc::rc::Rc;
-use core::cell::Cell;
+use core::cell::{Cell, RefCell};
 use parity_wasm::elements::ValueType as EValueType;
 
 /// Reference to a global variable (See [`GlobalInstance`] for details).
@@ -29,8 +29,11 @@ impl ::core::ops::Deref for GlobalRef {
 /// Attempt to change value of immutable global or to change type of
 /// the value (e.g. assign [`I32`] value to a global that was created with [`I64`] type) will lead to an error.
 ///
+/// Globals holding an `externref` are represented by [`ExternRefGlobalInstance`] instead.
+///
 /// [`I32`]: enum.RuntimeValue.html#variant.I32
 /// [`I64`]: enum.RuntimeValue.html#variant.I64
+/// [`ExternRefGlobalInstance`]: struct.ExternRefGlobalInstance.html
 #[derive(Debug)]
 pub struct GlobalInstance {
     val: Cell<RuntimeValue>,
@@ -61,7 +64,7 @@ impl GlobalInstance {
                 "Attempt to change an immutable variable".into(),
             ));
         }
-        if self.value_type() != val.value_type() {
+        if !val.matches_type(self.value_type()) {
             return Err(Error::Global("Attempt to change variable type".into()));
         }
         self.val.set(val);
@@ -89,3 +92,65 @@ impl GlobalInstance {
         self.value_type().into_elements()
     }
 }
+
+/// Reference to a global variable holding an `externref` (See [`ExternRefGlobalInstance`]
+/// for details).
+///
+/// This reference has a reference-counting semantics.
+///
+/// [`ExternRefGlobalInstance`]: struct.ExternRefGlobalInstance.html
+#[derive(Clone, Debug)]
+pub struct ExternRefGlobalRef(Rc<ExternRefGlobalInstance>);
+
+impl ::core::ops::Deref for ExternRefGlobalRef {
+    type Target = ExternRefGlobalInstance;
+    fn deref(&self) -> &ExternRefGlobalInstance {
+        &self.0
+    }
+}
+
+/// Runtime representation of a global variable holding an `externref`, or null.
+///
+/// Such globals can only be created and accessed by the host for now, since wasm modules
+/// can't declare or import them yet.
+#[derive(Debug)]
+pub struct ExternRefGlobalInstance {
+    val: RefCell<Option<ExternRef>>,
+    mutable: bool,
+}
+
+impl ExternRefGlobalInstance {
+    /// Allocate a global variable instance holding `val`, or null if `val` is `None`.
+    pub fn alloc(val: Option<ExternRef>, mutable: bool) -> ExternRefGlobalRef {
+        ExternRefGlobalRef(Rc::new(ExternRefGlobalInstance {
+            val: RefCell::new(val),
+            mutable,
+        }))
+    }
+
+    /// Change the `externref` held by this global variable, setting it to null if `val`
+    /// is `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if this global isn't mutable.
+    pub fn set(&self, val: Option<ExternRef>) -> Result<(), Error> {
+        if !self.mutable {
+            return Err(Error::Global(
+                "Attempt to change an immutable variable".into(),
+            ));
+        }
+        *self.val.borrow_mut() = val;
+        Ok(())
+    }
+
+    /// Get the `externref` held by this global variable, `None` if it is null.
+    pub fn get(&self) -> Option<ExternRef> {
+        self.val.borrow().clone()
+    }
+
+    /// Returns if this global variable is mutable.
+    pub fn is_mutable(&self) -> bool {
+        self.mutable
+    }
+}
@@ -1,5 +1,11 @@
+use crate::func::FuncRef;
+use crate::memory::MemoryRef;
+use crate::module::ModuleRef;
 use crate::value::{FromRuntimeValue, RuntimeValue};
-use crate::{Trap, TrapKind};
+use crate::{Error, Signature, Trap, TrapKind};
+use alloc::{boxed::Box, vec::Vec};
+use core::fmt;
+use validation::DEFAULT_MEMORY_INDEX;
 
 use downcast_rs::{impl_downcast, DowncastSync};
 
@@ -133,7 +139,7 @@ impl_downcast!(HostError);
 ///
 /// ```rust
 /// use wasmi::{
-///     Externals, RuntimeValue, RuntimeArgs, Error, ModuleImportResolver,
+///     Externals, RuntimeValue, RuntimeArgs, Error, InstantiationError, ModuleImportResolver,
 ///     FuncRef, ValueType, Signature, FuncInstance, Trap,
 /// };
 ///
@@ -185,16 +191,16 @@ impl_downcast!(HostError);
 ///         let index = match field_name {
 ///             "add" => ADD_FUNC_INDEX,
 ///             _ => {
-///                 return Err(Error::Instantiation(
+///                 return Err(Error::Instantiation(InstantiationError::Other(
 ///                     format!("Export {} not found", field_name),
-///                 ))
+///                 )))
 ///             }
 ///         };
 ///
 ///         if !self.check_signature(index, signature) {
-///             return Err(Error::Instantiation(
+///             return Err(Error::Instantiation(InstantiationError::Other(
 ///                 format!("Export {} has a bad signature", field_name)
-///             ));
+///             )));
 ///         }
 ///
 ///         Ok(FuncInstance::alloc_host(
@@ -211,6 +217,280 @@ pub trait Externals {
         index: usize,
         args: RuntimeArgs,
     ) -> Result<Option<RuntimeValue>, Trap>;
+
+    /// Perform invoke of a host function by specified `index` on behalf of wasm code.
+    ///
+    /// The [`Caller`] gives access to the module instance the call originates from.
+    /// By default the caller is ignored and the call is forwarded to [`invoke_index`].
+    ///
+    /// Host functions invoked directly by the embedder, i.e. via [`FuncInstance::invoke`],
+    /// have no caller and always go through [`invoke_index`].
+    ///
+    /// [`Caller`]: struct.Caller.html
+    /// [`invoke_index`]: #tymethod.invoke_index
+    /// [`FuncInstance::invoke`]: struct.FuncInstance.html#method.invoke
+    fn invoke_index_with_caller(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+        caller: Caller,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let _ = caller;
+        self.invoke_index(index, args)
+    }
+
+    /// Decides what happens when `call_indirect` finds a function with another signature
+    /// than the `expected` one in the table.
+    ///
+    /// By default this returns `None`, which traps with [`TrapKind::UnexpectedSignature`]
+    /// as the spec requires. Returning a function of the `expected` signature calls it in
+    /// place of the `found` one instead, which allows embedders to implement a softer
+    /// failure for dynamic dispatch, e.g. a host function returning a sentinel value.
+    /// A returned function of any other signature traps as well.
+    ///
+    /// Note that this deviates from the spec for every non-`None` result.
+    ///
+    /// [`TrapKind::UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    fn on_signature_mismatch(&mut self, expected: &Signature, found: &FuncRef) -> Option<FuncRef> {
+        let _ = (expected, found);
+        None
+    }
+
+    /// Inspects the `result` of the host function `func` called by wasm code before it is
+    /// handed back to the caller.
+    ///
+    /// Called once `result` is known to match the signature of `func`. By default `result` is
+    /// passed on unchanged. Returning another value substitutes it, which allows to apply
+    /// value-level policies like clamping, while returning `Err` traps, e.g. to reject values
+    /// the guest should never see. A substituted value must match the signature of `func` as
+    /// well, otherwise the call traps with [`TrapKind::UnexpectedSignature`].
+    ///
+    /// Results of host functions invoked directly by the embedder or supplied when resuming
+    /// an invocation are not passed to this method.
+    ///
+    /// [`TrapKind::UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    fn on_host_result(
+        &mut self,
+        func: &FuncRef,
+        result: Option<RuntimeValue>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let _ = func;
+        Ok(result)
+    }
+}
+
+/// Host functions that only need a shared borrow of their state.
+///
+/// An alternative to [`Externals`] for host state that is read-mostly or uses interior
+/// mutability such as `Cell`, `RefCell` or atomics. A shared reference to an implementor is
+/// itself [`Externals`], so it can be passed to any entry point as `&mut &host`, while
+/// [`FuncInstance::invoke_shared`] and [`ModuleInstance::invoke_export_shared`] take `&host`
+/// directly. Since no exclusive borrow is held during execution, host functions may for
+/// example re-enter the calling instance with the same state.
+///
+/// [`Externals`]: trait.Externals.html
+/// [`FuncInstance::invoke_shared`]: struct.FuncInstance.html#method.invoke_shared
+/// [`ModuleInstance::invoke_export_shared`]: struct.ModuleInstance.html#method.invoke_export_shared
+pub trait SharedExternals {
+    /// Perform invoke of a host function by specified `index`.
+    fn invoke_index(&self, index: usize, args: RuntimeArgs) -> Result<Option<RuntimeValue>, Trap>;
+
+    /// Perform invoke of a host function by specified `index` on behalf of wasm code.
+    ///
+    /// See [`Externals::invoke_index_with_caller`].
+    ///
+    /// [`Externals::invoke_index_with_caller`]: trait.Externals.html#method.invoke_index_with_caller
+    fn invoke_index_with_caller(
+        &self,
+        index: usize,
+        args: RuntimeArgs,
+        caller: Caller,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let _ = caller;
+        self.invoke_index(index, args)
+    }
+
+    /// Decides what happens on a `call_indirect` signature mismatch.
+    ///
+    /// See [`Externals::on_signature_mismatch`].
+    ///
+    /// [`Externals::on_signature_mismatch`]: trait.Externals.html#method.on_signature_mismatch
+    fn on_signature_mismatch(&self, expected: &Signature, found: &FuncRef) -> Option<FuncRef> {
+        let _ = (expected, found);
+        None
+    }
+
+    /// Inspects the result of a host function called by wasm code.
+    ///
+    /// See [`Externals::on_host_result`].
+    ///
+    /// [`Externals::on_host_result`]: trait.Externals.html#method.on_host_result
+    fn on_host_result(
+        &self,
+        func: &FuncRef,
+        result: Option<RuntimeValue>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let _ = func;
+        Ok(result)
+    }
+}
+
+impl<E: SharedExternals + ?Sized> Externals for &E {
+    fn invoke_index(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        SharedExternals::invoke_index(*self, index, args)
+    }
+
+    fn invoke_index_with_caller(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+        caller: Caller,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        SharedExternals::invoke_index_with_caller(*self, index, args, caller)
+    }
+
+    fn on_signature_mismatch(&mut self, expected: &Signature, found: &FuncRef) -> Option<FuncRef> {
+        SharedExternals::on_signature_mismatch(*self, expected, found)
+    }
+
+    fn on_host_result(
+        &mut self,
+        func: &FuncRef,
+        result: Option<RuntimeValue>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        SharedExternals::on_host_result(*self, func, result)
+    }
+}
+
+/// Context of a host function call made from wasm code.
+///
+/// Passed to [`Externals::invoke_index_with_caller`].
+///
+/// # Examples
+///
+/// Reading a string passed by the guest as a pointer and a length:
+///
+/// ```rust
+/// use wasmi::{Caller, Externals, RuntimeArgs, RuntimeValue, Trap, TrapKind};
+///
+/// struct HostExternals {
+///     log: Vec<String>,
+/// }
+///
+/// impl Externals for HostExternals {
+///     fn invoke_index(
+///         &mut self,
+///         _index: usize,
+///         _args: RuntimeArgs,
+///     ) -> Result<Option<RuntimeValue>, Trap> {
+///         // The only host function needs access to the caller's memory.
+///         Err(TrapKind::Unreachable.into())
+///     }
+///
+///     fn invoke_index_with_caller(
+///         &mut self,
+///         _index: usize,
+///         args: RuntimeArgs,
+///         caller: Caller,
+///     ) -> Result<Option<RuntimeValue>, Trap> {
+///         let ptr: u32 = args.nth_checked(0)?;
+///         let len: u32 = args.nth_checked(1)?;
+///         let memory = caller.memory().ok_or(TrapKind::MemoryAccessOutOfBounds)?;
+///         self.log.push(memory.read_str(ptr, len)?);
+///         Ok(None)
+///     }
+/// }
+/// ```
+///
+/// [`Externals::invoke_index_with_caller`]: trait.Externals.html#method.invoke_index_with_caller
+pub struct Caller<'a> {
+    module: &'a ModuleRef,
+    function: &'a FuncRef,
+    position: u32,
+    stack_headroom: usize,
+}
+
+impl<'a> Caller<'a> {
+    pub(crate) fn new(
+        module: &'a ModuleRef,
+        function: &'a FuncRef,
+        position: u32,
+        stack_headroom: usize,
+    ) -> Caller<'a> {
+        Caller {
+            module,
+            function,
+            position,
+            stack_headroom,
+        }
+    }
+
+    /// Returns the module instance of the calling function.
+    pub fn module(&self) -> &ModuleRef {
+        self.module
+    }
+
+    /// Returns the calling function.
+    pub fn function(&self) -> &FuncRef {
+        self.function
+    }
+
+    /// Returns the index of the calling function in the function index space of its module.
+    ///
+    /// The index space includes imported functions.
+    pub fn func_index(&self) -> Option<u32> {
+        self.module.func_index(self.function)
+    }
+
+    /// Returns the position of the call instruction within the calling function.
+    ///
+    /// Note that this is the position in the code compiled by wasmi rather than an
+    /// offset into the original wasm binary. It is mostly useful to tell apart different
+    /// call sites, e.g. when logging or reporting a trap. With the `source_offsets` feature,
+    /// [`Module::source_offsets`] maps it back to the wasm binary.
+    ///
+    /// The unit of the position depends on how the code is stored: by default it counts
+    /// compiled instructions (each `br_table` target taking one slot), while with the
+    /// `compact_bytecode` feature it is a byte offset into the encoded code. Positions
+    /// taken with one configuration can't be compared with those of the other.
+    ///
+    /// [`Module::source_offsets`]: struct.Module.html#method.source_offsets
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+
+    /// Returns the linear memory of the calling module instance, if it has one.
+    ///
+    /// Unlike [`ModuleInstance::export_by_name`] this also works if the memory isn't exported.
+    ///
+    /// [`ModuleInstance::export_by_name`]: struct.ModuleInstance.html#method.export_by_name
+    pub fn memory(&self) -> Option<MemoryRef> {
+        self.module.memory_by_index(DEFAULT_MEMORY_INDEX)
+    }
+
+    /// Returns the value of the global exported by the calling module instance under `name`.
+    pub fn get_global(&self, name: &str) -> Option<RuntimeValue> {
+        self.module.global_value(name)
+    }
+
+    /// Sets the value of the global exported by the calling module instance under `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if there is no such global, it is immutable or `value`
+    /// has a different type.
+    pub fn set_global(&self, name: &str, value: RuntimeValue) -> Result<(), Error> {
+        self.module.set_global_value(name, value)
+    }
+
+    /// Returns the number of values that can still be pushed onto the value stack.
+    pub fn stack_headroom(&self) -> usize {
+        self.stack_headroom
+    }
 }
 
 /// Implementation of [`Externals`] that just traps on [`invoke_index`].
@@ -229,6 +509,273 @@ impl Externals for NopExternals {
     }
 }
 
+/// A deterministic source of random bytes for guests, e.g. to implement WASI's `random_get`.
+///
+/// The bytes are produced by a xorshift generator seeded at construction, so that guests
+/// which consume randomness produce reproducible results across runs. This is useful for
+/// testing, but the bytes are predictable and must not be used where security matters.
+#[derive(Debug, Clone)]
+pub struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    /// Creates a generator from the given seed.
+    pub fn new(seed: u64) -> SeededRandom {
+        // The all-zero state is a fixed point of xorshift.
+        let state = if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        };
+        SeededRandom { state }
+    }
+
+    /// Returns the next pseudo-random number.
+    pub fn next_u64(&mut self) -> u64 {
+        // xorshift64*
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Fills `buf` with pseudo-random bytes.
+    pub fn fill_bytes(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    /// Implements `random_get(buf: i32, buf_len: i32) -> i32`.
+    ///
+    /// Fills `buf_len` bytes of the caller's memory starting at `buf` and returns 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the caller has no memory or the buffer is out of its bounds.
+    pub fn random_get(
+        &mut self,
+        args: RuntimeArgs,
+        caller: &Caller,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let buf: u32 = args.nth_checked(0)?;
+        let buf_len: u32 = args.nth_checked(1)?;
+        let memory = caller.memory().ok_or(TrapKind::MemoryAccessOutOfBounds)?;
+        let end = buf
+            .checked_add(buf_len)
+            .ok_or(TrapKind::MemoryAccessOutOfBounds)?;
+        memory.with_direct_access_mut(|mem| {
+            let bytes = mem
+                .get_mut(buf as usize..end as usize)
+                .ok_or(TrapKind::MemoryAccessOutOfBounds)?;
+            self.fill_bytes(bytes);
+            Ok(Some(RuntimeValue::I32(0)))
+        })
+    }
+}
+
+/// The WASI error code for an invalid file descriptor.
+const ERRNO_BADF: i32 = 8;
+
+/// The WASI error code for an invalid argument.
+const ERRNO_INVAL: i32 = 28;
+
+/// The maximum number of iovecs WASI allows to pass to a single `fd_read` or `fd_write`.
+///
+/// The iovecs are checked before any I/O, which needs memory proportional to their number.
+const IOV_MAX: u32 = 1024;
+
+/// The maximum number of bytes [`Stdio::fd_read`] requests from the stdin source at once.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A source of input bytes, see [`Stdio::with_stdin`].
+type Source = Box<dyn FnMut(&mut [u8]) -> usize>;
+
+/// A sink for output bytes, see [`Stdio::with_stdout`].
+type Sink = Box<dyn FnMut(&[u8])>;
+
+/// Standard streams for guests, e.g. to implement WASI's `fd_read` and `fd_write`.
+///
+/// Reads from fd 0 are served by the stdin source, writes to fds 1 and 2 are passed to
+/// the stdout and stderr sinks. This allows to capture the output of a guest or to
+/// redirect it per instance. By default stdin is empty and all output is discarded.
+pub struct Stdio {
+    stdin: Source,
+    stdout: Sink,
+    stderr: Sink,
+}
+
+impl fmt::Debug for Stdio {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Stdio").finish()
+    }
+}
+
+impl Default for Stdio {
+    fn default() -> Stdio {
+        Stdio::new()
+    }
+}
+
+impl Stdio {
+    /// Creates standard streams with an empty stdin that discard all output.
+    pub fn new() -> Stdio {
+        Stdio {
+            stdin: Box::new(|_| 0),
+            stdout: Box::new(|_| {}),
+            stderr: Box::new(|_| {}),
+        }
+    }
+
+    /// Sets the source of stdin.
+    ///
+    /// The source fills the given buffer and returns the number of bytes written to it.
+    /// Returning 0 signals the end of the input.
+    pub fn with_stdin<F: FnMut(&mut [u8]) -> usize + 'static>(mut self, source: F) -> Stdio {
+        self.stdin = Box::new(source);
+        self
+    }
+
+    /// Sets the sink that receives everything written to stdout.
+    pub fn with_stdout<F: FnMut(&[u8]) + 'static>(mut self, sink: F) -> Stdio {
+        self.stdout = Box::new(sink);
+        self
+    }
+
+    /// Sets the sink that receives everything written to stderr.
+    pub fn with_stderr<F: FnMut(&[u8]) + 'static>(mut self, sink: F) -> Stdio {
+        self.stderr = Box::new(sink);
+        self
+    }
+
+    /// Implements `fd_write(fd: i32, iovs: i32, iovs_len: i32, nwritten: i32) -> i32`.
+    ///
+    /// Writes the buffers described by the `iovs_len` (pointer, length) pairs at `iovs` to
+    /// stdout or stderr and stores the number of bytes written at `nwritten`. Returns 0 on
+    /// success, the WASI error code for a bad file descriptor for fds other than 1 and 2 and
+    /// the one for an invalid argument if `iovs_len` exceeds WASI's `IOV_MAX` of 1024.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the caller has no memory or any of the buffers is out of its bounds.
+    /// Nothing is passed to the sink in that case.
+    pub fn fd_write(
+        &mut self,
+        args: RuntimeArgs,
+        caller: &Caller,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let fd: u32 = args.nth_checked(0)?;
+        let iovs: u32 = args.nth_checked(1)?;
+        let iovs_len: u32 = args.nth_checked(2)?;
+        let nwritten_ptr: u32 = args.nth_checked(3)?;
+        let sink = match fd {
+            1 => &mut self.stdout,
+            2 => &mut self.stderr,
+            _ => return Ok(Some(RuntimeValue::I32(ERRNO_BADF))),
+        };
+        if iovs_len > IOV_MAX {
+            return Ok(Some(RuntimeValue::I32(ERRNO_INVAL)));
+        }
+        let memory = caller.memory().ok_or(TrapKind::MemoryAccessOutOfBounds)?;
+
+        // All buffers are checked up front, so the sink never sees part of the output of a
+        // write that traps.
+        let mut nwritten: u32 = 0;
+        for (ptr, len) in read_iovecs(&memory, iovs, iovs_len)? {
+            let buf = memory
+                .get(ptr, len as usize)
+                .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+            sink(&buf);
+            nwritten = nwritten.wrapping_add(len);
+        }
+        memory
+            .set_value(nwritten_ptr, nwritten)
+            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+        Ok(Some(RuntimeValue::I32(0)))
+    }
+
+    /// Implements `fd_read(fd: i32, iovs: i32, iovs_len: i32, nread: i32) -> i32`.
+    ///
+    /// Fills the buffers described by the `iovs_len` (pointer, length) pairs at `iovs` from
+    /// stdin and stores the number of bytes read at `nread`. Returns 0 on success, the WASI
+    /// error code for a bad file descriptor for fds other than 0 and the one for an invalid
+    /// argument if `iovs_len` exceeds WASI's `IOV_MAX` of 1024.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the caller has no memory or any of the buffers is out of its bounds.
+    /// Nothing is requested from the source in that case.
+    pub fn fd_read(
+        &mut self,
+        args: RuntimeArgs,
+        caller: &Caller,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let fd: u32 = args.nth_checked(0)?;
+        let iovs: u32 = args.nth_checked(1)?;
+        let iovs_len: u32 = args.nth_checked(2)?;
+        let nread_ptr: u32 = args.nth_checked(3)?;
+        if fd != 0 {
+            return Ok(Some(RuntimeValue::I32(ERRNO_BADF)));
+        }
+        if iovs_len > IOV_MAX {
+            return Ok(Some(RuntimeValue::I32(ERRNO_INVAL)));
+        }
+        let memory = caller.memory().ok_or(TrapKind::MemoryAccessOutOfBounds)?;
+
+        // The buffers are filled in chunks of at most `READ_CHUNK_SIZE` bytes, so a guest
+        // can't make the host allocate more than that however large buffers it passes.
+        let mut chunk = Vec::new();
+        let mut nread: u32 = 0;
+        'iovecs: for (mut ptr, len) in read_iovecs(&memory, iovs, iovs_len)? {
+            let mut remaining = len as usize;
+            while remaining > 0 {
+                let chunk_len = remaining.min(READ_CHUNK_SIZE);
+                chunk.resize(chunk_len, 0);
+                let read = (self.stdin)(&mut chunk).min(chunk_len);
+                memory
+                    .set(ptr, &chunk[..read])
+                    .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+                nread = nread.wrapping_add(read as u32);
+                if read < chunk_len {
+                    break 'iovecs;
+                }
+                ptr += read as u32;
+                remaining -= read;
+            }
+        }
+        memory
+            .set_value(nread_ptr, nread)
+            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+        Ok(Some(RuntimeValue::I32(0)))
+    }
+}
+
+/// Reads `count` WASI iovecs, i.e. pairs of `u32` pointers and lengths, starting at `ptr`.
+///
+/// Traps if any of the iovecs or the buffers they describe is out of the bounds of `memory`.
+fn read_iovecs(memory: &MemoryRef, ptr: u32, count: u32) -> Result<Vec<(u32, u32)>, Trap> {
+    let read_u32 = |offset: Option<u32>| -> Result<u32, Trap> {
+        offset
+            .and_then(|offset| memory.get_value(offset).ok())
+            .ok_or_else(|| TrapKind::MemoryAccessOutOfBounds.into())
+    };
+    (0..count)
+        .map(|i| {
+            let offset = i.checked_mul(8).and_then(|offset| ptr.checked_add(offset));
+            let buf_ptr = read_u32(offset)?;
+            let buf_len = read_u32(offset.and_then(|offset| offset.checked_add(4)))?;
+            memory
+                .check_region(buf_ptr as usize, buf_len as usize)
+                .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+            Ok((buf_ptr, buf_len))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -1,10 +1,11 @@
 use crate::func::FuncRef;
 use crate::global::GlobalRef;
 use crate::memory::MemoryRef;
-use crate::module::ModuleRef;
-use crate::table::TableRef;
+use crate::module::{ExternType, ExternVal, ModuleRef};
+use crate::table::{TableElementType, TableRef};
 use crate::types::{GlobalDescriptor, MemoryDescriptor, TableDescriptor};
-use crate::{Error, Signature};
+use crate::{Error, InstantiationError, Signature};
+use alloc::string::ToString;
 use alloc::{collections::BTreeMap, string::String};
 
 /// Resolver of a module's dependencies.
@@ -150,8 +151,9 @@ impl<'a> ImportResolver for ImportsBuilder<'a> {
         signature: &Signature,
     ) -> Result<FuncRef, Error> {
         self.resolver(module_name)
-            .ok_or_else(|| Error::Instantiation(format!("Module {} not found", module_name)))?
+            .ok_or_else(|| missing_import(module_name, field_name))?
             .resolve_func(field_name, signature)
+            .map_err(|err| in_module(err, module_name))
     }
 
     fn resolve_global(
@@ -161,8 +163,9 @@ impl<'a> ImportResolver for ImportsBuilder<'a> {
         global_type: &GlobalDescriptor,
     ) -> Result<GlobalRef, Error> {
         self.resolver(module_name)
-            .ok_or_else(|| Error::Instantiation(format!("Module {} not found", module_name)))?
+            .ok_or_else(|| missing_import(module_name, field_name))?
             .resolve_global(field_name, global_type)
+            .map_err(|err| in_module(err, module_name))
     }
 
     fn resolve_memory(
@@ -172,8 +175,9 @@ impl<'a> ImportResolver for ImportsBuilder<'a> {
         memory_type: &MemoryDescriptor,
     ) -> Result<MemoryRef, Error> {
         self.resolver(module_name)
-            .ok_or_else(|| Error::Instantiation(format!("Module {} not found", module_name)))?
+            .ok_or_else(|| missing_import(module_name, field_name))?
             .resolve_memory(field_name, memory_type)
+            .map_err(|err| in_module(err, module_name))
     }
 
     fn resolve_table(
@@ -183,8 +187,9 @@ impl<'a> ImportResolver for ImportsBuilder<'a> {
         table_type: &TableDescriptor,
     ) -> Result<TableRef, Error> {
         self.resolver(module_name)
-            .ok_or_else(|| Error::Instantiation(format!("Module {} not found", module_name)))?
+            .ok_or_else(|| missing_import(module_name, field_name))?
             .resolve_table(field_name, table_type)
+            .map_err(|err| in_module(err, module_name))
     }
 }
 
@@ -198,10 +203,7 @@ pub trait ModuleImportResolver {
     ///
     /// [`ImportResolver::resolve_func`]: trait.ImportResolver.html#tymethod.resolve_func
     fn resolve_func(&self, field_name: &str, _signature: &Signature) -> Result<FuncRef, Error> {
-        Err(Error::Instantiation(format!(
-            "Export {} not found",
-            field_name
-        )))
+        Err(missing_import("", field_name))
     }
 
     /// Resolve a global variable.
@@ -214,10 +216,7 @@ pub trait ModuleImportResolver {
         field_name: &str,
         _global_type: &GlobalDescriptor,
     ) -> Result<GlobalRef, Error> {
-        Err(Error::Instantiation(format!(
-            "Export {} not found",
-            field_name
-        )))
+        Err(missing_import("", field_name))
     }
 
     /// Resolve a memory.
@@ -230,10 +229,7 @@ pub trait ModuleImportResolver {
         field_name: &str,
         _memory_type: &MemoryDescriptor,
     ) -> Result<MemoryRef, Error> {
-        Err(Error::Instantiation(format!(
-            "Export {} not found",
-            field_name
-        )))
+        Err(missing_import("", field_name))
     }
 
     /// Resolve a table.
@@ -246,44 +242,66 @@ pub trait ModuleImportResolver {
         field_name: &str,
         _table_type: &TableDescriptor,
     ) -> Result<TableRef, Error> {
-        Err(Error::Instantiation(format!(
-            "Export {} not found",
-            field_name
-        )))
+        Err(missing_import("", field_name))
     }
 }
 
+/// Resolves imports with the exports of an already instantiated module.
+///
+/// This allows linking wasm modules together: a function imported this way is
+/// executed in the context (i.e. memories, tables and globals) of the module that
+/// defines it, not the one that imports it.
+///
+/// Note that functions only hold weak references to their defining module, so the
+/// exporting [`ModuleRef`] must be kept alive for as long as the importing one is used.
+///
+/// [`ModuleRef`]: struct.ModuleRef.html
 impl ModuleImportResolver for ModuleRef {
-    fn resolve_func(&self, field_name: &str, _signature: &Signature) -> Result<FuncRef, Error> {
-        self.export_by_name(field_name)
-            .ok_or_else(|| Error::Instantiation(format!("Export {} not found", field_name)))?
-            .as_func()
-            .cloned()
-            .ok_or_else(|| Error::Instantiation(format!("Export {} is not a function", field_name)))
+    fn resolve_func(&self, field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+        let export = find_export(self, field_name)?;
+        let func = export.as_func().cloned().ok_or_else(|| {
+            type_mismatch(field_name, ExternType::Func(signature.clone()), &export)
+        })?;
+        if func.signature() != signature {
+            return Err(type_mismatch(
+                field_name,
+                ExternType::Func(signature.clone()),
+                &export,
+            ));
+        }
+        Ok(func)
     }
 
     fn resolve_global(
         &self,
         field_name: &str,
-        _global_type: &GlobalDescriptor,
+        global_type: &GlobalDescriptor,
     ) -> Result<GlobalRef, Error> {
-        self.export_by_name(field_name)
-            .ok_or_else(|| Error::Instantiation(format!("Export {} not found", field_name)))?
-            .as_global()
-            .cloned()
-            .ok_or_else(|| Error::Instantiation(format!("Export {} is not a global", field_name)))
+        let export = find_export(self, field_name)?;
+        export.as_global().cloned().ok_or_else(|| {
+            type_mismatch(
+                field_name,
+                ExternType::Global(global_type.value_type()),
+                &export,
+            )
+        })
     }
 
     fn resolve_memory(
         &self,
         field_name: &str,
-        _memory_type: &MemoryDescriptor,
+        memory_type: &MemoryDescriptor,
     ) -> Result<MemoryRef, Error> {
-        self.export_by_name(field_name)
-            .ok_or_else(|| Error::Instantiation(format!("Export {} not found", field_name)))?
-            .as_memory()
-            .cloned()
-            .ok_or_else(|| Error::Instantiation(format!("Export {} is not a memory", field_name)))
+        let export = find_export(self, field_name)?;
+        export.as_memory().cloned().ok_or_else(|| {
+            type_mismatch(
+                field_name,
+                ExternType::Memory {
+                    shared: memory_type.is_shared(),
+                },
+                &export,
+            )
+        })
     }
 
     fn resolve_table(
@@ -291,10 +309,75 @@ impl ModuleImportResolver for ModuleRef {
         field_name: &str,
         _table_type: &TableDescriptor,
     ) -> Result<TableRef, Error> {
-        self.export_by_name(field_name)
-            .ok_or_else(|| Error::Instantiation(format!("Export {} not found", field_name)))?
-            .as_table()
-            .cloned()
-            .ok_or_else(|| Error::Instantiation(format!("Export {} is not a table", field_name)))
+        let export = find_export(self, field_name)?;
+        export.as_table().cloned().ok_or_else(|| {
+            type_mismatch(
+                field_name,
+                ExternType::Table(TableElementType::FuncRef),
+                &export,
+            )
+        })
+    }
+}
+
+fn find_export(module: &ModuleRef, field_name: &str) -> Result<ExternVal, Error> {
+    module
+        .export_by_name(field_name)
+        .ok_or_else(|| missing_import("", field_name))
+}
+
+fn missing_import(module_name: &str, field_name: &str) -> Error {
+    InstantiationError::MissingImport {
+        module: module_name.to_string(),
+        field: field_name.to_string(),
+    }
+    .into()
+}
+
+fn type_mismatch(field_name: &str, expected: ExternType, actual: &ExternVal) -> Error {
+    InstantiationError::ImportTypeMismatch {
+        module: String::new(),
+        field: field_name.to_string(),
+        expected,
+        actual: actual.extern_type(),
+    }
+    .into()
+}
+
+/// Fills in the module name of errors returned by a [`ModuleImportResolver`], which
+/// only knows the names of fields.
+///
+/// [`ModuleImportResolver`]: trait.ModuleImportResolver.html
+fn in_module(err: Error, module_name: &str) -> Error {
+    let module = module_name.to_string();
+    match err {
+        Error::Instantiation(InstantiationError::MissingImport { field, .. }) => {
+            InstantiationError::MissingImport { module, field }.into()
+        }
+        Error::Instantiation(InstantiationError::ImportTypeMismatch {
+            field,
+            expected,
+            actual,
+            ..
+        }) => InstantiationError::ImportTypeMismatch {
+            module,
+            field,
+            expected,
+            actual,
+        }
+        .into(),
+        Error::Instantiation(InstantiationError::LimitsMismatch {
+            field,
+            expected,
+            actual,
+            ..
+        }) => InstantiationError::LimitsMismatch {
+            module,
+            field,
+            expected,
+            actual,
+        }
+        .into(),
+        err => err,
     }
 }
@@ -58,7 +58,7 @@
 //!
 //! # Differences from Wasm
 //!
-//! - There is no `nop` instruction.
+//! - There is no `nop` instruction, unless the `preserve_nops` feature is enabled.
 //! - All control flow structures are flattened to plain gotos.
 //! - Implicit returns via reaching function scope `End` are replaced with an explicit `return` instruction.
 //! - Locals live on the value stack now.
@@ -66,36 +66,46 @@
 //! - *.const store value in straight encoding.
 //! - Reserved immediates are ignored for `call_indirect`, `current_memory`, `grow_memory`.
 //!
+//! # Encoding
+//!
+//! By default every instruction is stored as an `InstructionInternal`. With the
+//! `compact_bytecode` feature the instructions are encoded into bytes instead, see the
+//! `compact` module. Positions, e.g. branch destinations, count the units of the code,
+//! so they are byte offsets in the compact encoding.
+//!
 
 use alloc::vec::Vec;
 
-/// Should we keep a value before "discarding" a stack frame?
-///
-/// Note that this is a `enum` since Wasm doesn't support multiple return
-/// values at the moment.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub enum Keep {
-    None,
-    /// Pop one value from the yet-to-be-discarded stack frame to the
-    /// current stack frame.
-    Single,
-}
+#[cfg(feature = "compact_bytecode")]
+mod compact;
 
-impl Keep {
-    /// Reutrns a number of items that should be kept on the stack.
-    pub fn count(&self) -> u32 {
-        match *self {
-            Keep::None => 0,
-            Keep::Single => 1,
-        }
-    }
-}
+/// The unit code is stored in.
+#[cfg(not(feature = "compact_bytecode"))]
+pub(crate) type CodeUnit = InstructionInternal;
+/// The unit code is stored in.
+#[cfg(feature = "compact_bytecode")]
+pub(crate) type CodeUnit = u8;
+
+/// Number of code units taken by each target of a `br_table`.
+#[cfg(not(feature = "compact_bytecode"))]
+const BR_TABLE_TARGET_SIZE: usize = 1;
+#[cfg(feature = "compact_bytecode")]
+const BR_TABLE_TARGET_SIZE: usize = compact::TARGET_SIZE;
+
+/// Number of code units taken by a `call` or `call_indirect` instruction.
+#[cfg(not(feature = "compact_bytecode"))]
+pub(crate) const CALL_SIZE: u32 = 1;
+#[cfg(feature = "compact_bytecode")]
+pub(crate) const CALL_SIZE: u32 = compact::CALL_SIZE;
 
 /// Specifies how many values we should keep and how many we should drop.
+///
+/// The topmost `keep` values are moved down by `drop` slots, discarding the `drop` values
+/// below them.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct DropKeep {
     pub drop: u32,
-    pub keep: Keep,
+    pub keep: u32,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -117,14 +127,21 @@ pub enum Reloc {
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct BrTargets<'a> {
-    stream: &'a [InstructionInternal],
+    stream: &'a [CodeUnit],
 }
 
 impl<'a> BrTargets<'a> {
-    pub(crate) fn from_internal(targets: &'a [InstructionInternal]) -> Self {
+    pub(crate) fn from_internal(targets: &'a [CodeUnit]) -> Self {
         BrTargets { stream: targets }
     }
 
+    /// Returns the number of targets, including the default one.
+    #[cfg(any(test, feature = "defensive_checks"))]
+    pub(crate) fn len(&self) -> u32 {
+        (self.stream.len() / BR_TABLE_TARGET_SIZE) as u32
+    }
+
+    #[cfg(not(feature = "compact_bytecode"))]
     #[inline]
     pub fn get(&self, index: u32) -> Target {
         match self.stream[index.min(self.stream.len() as u32 - 1) as usize] {
@@ -132,6 +149,24 @@ impl<'a> BrTargets<'a> {
             _ => panic!("BrTable has incorrect target count"),
         }
     }
+
+    #[cfg(feature = "compact_bytecode")]
+    #[inline]
+    pub fn get(&self, index: u32) -> Target {
+        let count = self.stream.len() / BR_TABLE_TARGET_SIZE;
+        compact::br_table_target(self.stream, (index as usize).min(count - 1))
+    }
+}
+
+/// Encodes `targets` the way they follow a `br_table` in the code, for use with
+/// [`BrTargets::from_internal`].
+#[cfg(test)]
+pub(crate) fn encode_br_targets(targets: &[Target]) -> Vec<CodeUnit> {
+    let mut code = Instructions::with_capacity(targets.len());
+    for &target in targets {
+        code.push(InstructionInternal::BrTableTarget(target));
+    }
+    code.vec
 }
 
 /// The main interpreted instruction type. This is what is returned by `InstructionIter`, but
@@ -165,6 +200,11 @@ pub enum Instruction<'a> {
     /// Validation ensures that there should be at least one target.
     BrTable(BrTargets<'a>),
 
+    /// Does nothing.
+    ///
+    /// Only emitted with the `preserve_nops` feature, in order to keep positions in the
+    /// compiled code closer to the original ones.
+    Nop,
     Unreachable,
     Return(DropKeep),
 
@@ -203,6 +243,20 @@ pub enum Instruction<'a> {
 
     CurrentMemory,
     GrowMemory,
+    /// Copies a region from memory `src_mem` to memory `dst_mem`, which might be the same.
+    MemoryCopy {
+        src_mem: u32,
+        dst_mem: u32,
+    },
+    /// Wakes threads waiting at an address of the default memory, see `atomics` feature.
+    MemoryAtomicNotify(u32),
+    /// Waits at an address of the default memory for a 32-bit value to change.
+    MemoryAtomicWait32(u32),
+    /// Waits at an address of the default memory for a 64-bit value to change.
+    MemoryAtomicWait64(u32),
+
+    TableInit(u32),
+    ElemDrop(u32),
 
     I32Const(i32),
     I64Const(i64),
@@ -339,6 +393,15 @@ pub enum Instruction<'a> {
     I64ReinterpretF64,
     F32ReinterpretI32,
     F64ReinterpretI64,
+
+    I32TruncSatSF32,
+    I32TruncSatUF32,
+    I32TruncSatSF64,
+    I32TruncSatUF64,
+    I64TruncSatSF32,
+    I64TruncSatUF32,
+    I64TruncSatSF64,
+    I64TruncSatUF64,
 }
 
 /// The internally-stored instruction type. This differs from `Instruction` in that the `BrTable`
@@ -358,9 +421,12 @@ pub(crate) enum InstructionInternal {
     Br(Target),
     BrIfEqz(Target),
     BrIfNez(Target),
-    BrTable { count: u32 },
+    BrTable {
+        count: u32,
+    },
     BrTableTarget(Target),
 
+    Nop,
     Unreachable,
     Return(DropKeep),
 
@@ -399,6 +465,24 @@ pub(crate) enum InstructionInternal {
 
     CurrentMemory,
     GrowMemory,
+    /// Copies a region from memory `src_mem` to memory `dst_mem`, which might be the same.
+    MemoryCopy {
+        src_mem: u32,
+        dst_mem: u32,
+    },
+    // `parity-wasm` only decodes these with the `atomics` feature.
+    /// Wakes threads waiting at an address of the default memory, see `atomics` feature.
+    #[cfg_attr(not(feature = "atomics"), allow(dead_code))]
+    MemoryAtomicNotify(u32),
+    /// Waits at an address of the default memory for a 32-bit value to change.
+    #[cfg_attr(not(feature = "atomics"), allow(dead_code))]
+    MemoryAtomicWait32(u32),
+    /// Waits at an address of the default memory for a 64-bit value to change.
+    #[cfg_attr(not(feature = "atomics"), allow(dead_code))]
+    MemoryAtomicWait64(u32),
+
+    TableInit(u32),
+    ElemDrop(u32),
 
     I32Const(i32),
     I64Const(i64),
@@ -535,28 +619,483 @@ pub(crate) enum InstructionInternal {
     I64ReinterpretF64,
     F32ReinterpretI32,
     F64ReinterpretI64,
+
+    // The compiler doesn't emit these yet, since `parity-wasm` can't decode the
+    // non-trapping float-to-int conversions.
+    #[allow(dead_code)]
+    I32TruncSatSF32,
+    #[allow(dead_code)]
+    I32TruncSatUF32,
+    #[allow(dead_code)]
+    I32TruncSatSF64,
+    #[allow(dead_code)]
+    I32TruncSatUF64,
+    #[allow(dead_code)]
+    I64TruncSatSF32,
+    #[allow(dead_code)]
+    I64TruncSatUF32,
+    #[allow(dead_code)]
+    I64TruncSatSF64,
+    #[allow(dead_code)]
+    I64TruncSatUF64,
+}
+
+impl<'a> Instruction<'a> {
+    /// Returns the number of operands this instruction pops off the value stack.
+    ///
+    /// For calls this doesn't include the arguments of the callee.
+    #[cfg(feature = "defensive_checks")]
+    pub(crate) fn operand_count(&self) -> usize {
+        match *self {
+            Instruction::GetLocal(_)
+            | Instruction::Br(_)
+            | Instruction::Nop
+            | Instruction::Unreachable
+            | Instruction::Return(_)
+            | Instruction::Call(_)
+            | Instruction::GetGlobal(_)
+            | Instruction::CurrentMemory
+            | Instruction::ElemDrop(_)
+            | Instruction::I32Const(_)
+            | Instruction::I64Const(_)
+            | Instruction::F32Const(_)
+            | Instruction::F64Const(_) => 0,
+            Instruction::BrIfEqz(_)
+            | Instruction::BrIfNez(_)
+            | Instruction::BrTable(_)
+            | Instruction::Drop
+            | Instruction::SetLocal(_)
+            | Instruction::TeeLocal(_)
+            | Instruction::SetGlobal(_)
+            | Instruction::CallIndirect(_)
+            | Instruction::I32Load(_)
+            | Instruction::I64Load(_)
+            | Instruction::F32Load(_)
+            | Instruction::F64Load(_)
+            | Instruction::I32Load8S(_)
+            | Instruction::I32Load8U(_)
+            | Instruction::I32Load16S(_)
+            | Instruction::I32Load16U(_)
+            | Instruction::I64Load8S(_)
+            | Instruction::I64Load8U(_)
+            | Instruction::I64Load16S(_)
+            | Instruction::I64Load16U(_)
+            | Instruction::I64Load32S(_)
+            | Instruction::I64Load32U(_)
+            | Instruction::GrowMemory
+            | Instruction::I32Eqz
+            | Instruction::I64Eqz
+            | Instruction::I32Clz
+            | Instruction::I32Ctz
+            | Instruction::I32Popcnt
+            | Instruction::I64Clz
+            | Instruction::I64Ctz
+            | Instruction::I64Popcnt
+            | Instruction::F32Abs
+            | Instruction::F32Neg
+            | Instruction::F32Ceil
+            | Instruction::F32Floor
+            | Instruction::F32Trunc
+            | Instruction::F32Nearest
+            | Instruction::F32Sqrt
+            | Instruction::F64Abs
+            | Instruction::F64Neg
+            | Instruction::F64Ceil
+            | Instruction::F64Floor
+            | Instruction::F64Trunc
+            | Instruction::F64Nearest
+            | Instruction::F64Sqrt
+            | Instruction::I32WrapI64
+            | Instruction::I32TruncSF32
+            | Instruction::I32TruncUF32
+            | Instruction::I32TruncSF64
+            | Instruction::I32TruncUF64
+            | Instruction::I64ExtendSI32
+            | Instruction::I64ExtendUI32
+            | Instruction::I64TruncSF32
+            | Instruction::I64TruncUF32
+            | Instruction::I64TruncSF64
+            | Instruction::I64TruncUF64
+            | Instruction::F32ConvertSI32
+            | Instruction::F32ConvertUI32
+            | Instruction::F32ConvertSI64
+            | Instruction::F32ConvertUI64
+            | Instruction::F32DemoteF64
+            | Instruction::F64ConvertSI32
+            | Instruction::F64ConvertUI32
+            | Instruction::F64ConvertSI64
+            | Instruction::F64ConvertUI64
+            | Instruction::F64PromoteF32
+            | Instruction::I32ReinterpretF32
+            | Instruction::I64ReinterpretF64
+            | Instruction::F32ReinterpretI32
+            | Instruction::F64ReinterpretI64
+            | Instruction::I32TruncSatSF32
+            | Instruction::I32TruncSatUF32
+            | Instruction::I32TruncSatSF64
+            | Instruction::I32TruncSatUF64
+            | Instruction::I64TruncSatSF32
+            | Instruction::I64TruncSatUF32
+            | Instruction::I64TruncSatSF64
+            | Instruction::I64TruncSatUF64 => 1,
+            Instruction::Select
+            | Instruction::MemoryCopy { .. }
+            | Instruction::MemoryAtomicWait32(_)
+            | Instruction::MemoryAtomicWait64(_)
+            | Instruction::TableInit(_) => 3,
+            // All the remaining instructions are stores and binary operators.
+            _ => 2,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Instructions {
-    vec: Vec<InstructionInternal>,
+    vec: Vec<CodeUnit>,
+    /// Number of instructions, counting each `BrTable` target separately.
+    #[cfg(feature = "compact_bytecode")]
+    len: u32,
+    /// Positions of the instructions execution can continue at, in ascending order.
+    #[cfg(all(feature = "compact_bytecode", feature = "defensive_checks"))]
+    instruction_starts: Vec<u32>,
+    /// Number of locals, including parameters, of the function.
+    locals_count: u32,
+    /// Maximum operand stack height reached while validating the function.
+    max_stack_height: u32,
+    /// Operand stack height expected before executing the instruction at the same index.
+    #[cfg(feature = "stack_height_checks")]
+    stack_heights: Vec<u32>,
+    /// Index of the wasm instruction in the function body the instruction at the same index
+    /// was compiled from, until [`resolve_source_offsets`] replaces it with the byte offset
+    /// of that wasm instruction.
+    ///
+    /// [`resolve_source_offsets`]: #method.resolve_source_offsets
+    #[cfg(feature = "source_offsets")]
+    source_offsets: Vec<u32>,
 }
 
 impl Instructions {
     pub fn with_capacity(capacity: usize) -> Self {
         Instructions {
             vec: Vec::with_capacity(capacity),
+            #[cfg(feature = "compact_bytecode")]
+            len: 0,
+            #[cfg(all(feature = "compact_bytecode", feature = "defensive_checks"))]
+            instruction_starts: Vec::new(),
+            locals_count: 0,
+            max_stack_height: 0,
+            #[cfg(feature = "stack_height_checks")]
+            stack_heights: Vec::with_capacity(capacity),
+            #[cfg(feature = "source_offsets")]
+            source_offsets: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Records `height` as the expected operand stack height for all instructions
+    /// pushed since the last call.
+    #[cfg(feature = "stack_height_checks")]
+    pub(crate) fn record_stack_height(&mut self, height: u32) {
+        self.stack_heights.resize(self.vec.len(), height);
+    }
+
+    /// Returns the expected operand stack height before executing the instruction at `pc`.
+    ///
+    /// Returns `None` if no height was recorded, e.g. for hand-assembled code.
+    #[cfg(feature = "stack_height_checks")]
+    pub(crate) fn stack_height(&self, pc: u32) -> Option<u32> {
+        self.stack_heights.get(pc as usize).copied()
+    }
+
+    /// Records `index` as the index of the wasm instruction all instructions pushed since
+    /// the last call were compiled from.
+    #[cfg(feature = "source_offsets")]
+    pub(crate) fn record_source(&mut self, index: u32) {
+        self.source_offsets.resize(self.vec.len(), index);
+    }
+
+    /// Replaces the recorded wasm instruction indices with the offsets of the instructions,
+    /// given the offsets of all wasm instructions of the function body.
+    #[cfg(feature = "source_offsets")]
+    pub(crate) fn resolve_source_offsets(&mut self, instruction_offsets: &[u32]) {
+        for source in &mut self.source_offsets {
+            *source = instruction_offsets[*source as usize];
+        }
+    }
+
+    /// Returns the offset of the wasm instruction each instruction was compiled from.
+    #[cfg(feature = "source_offsets")]
+    pub(crate) fn source_offsets(&self) -> &[u32] {
+        &self.source_offsets
+    }
+
+    /// Returns `true` if execution can continue at `pc`, i.e. it points to an instruction
+    /// which isn't part of a `BrTable`.
+    #[cfg(all(feature = "defensive_checks", not(feature = "compact_bytecode")))]
+    pub(crate) fn is_branch_target(&self, pc: u32) -> bool {
+        match self.vec.get(pc as usize) {
+            Some(InstructionInternal::BrTableTarget(_)) | None => false,
+            Some(_) => true,
         }
     }
 
+    /// Returns `true` if execution can continue at `pc`, i.e. it points to the start of an
+    /// instruction which isn't part of a `BrTable`.
+    #[cfg(all(feature = "defensive_checks", feature = "compact_bytecode"))]
+    pub(crate) fn is_branch_target(&self, pc: u32) -> bool {
+        self.instruction_starts.binary_search(&pc).is_ok()
+    }
+
     pub fn current_pc(&self) -> u32 {
         self.vec.len() as u32
     }
 
+    /// Returns the number of instructions, counting each `BrTable` target separately.
+    #[cfg(not(feature = "compact_bytecode"))]
+    pub(crate) fn len(&self) -> u32 {
+        self.vec.len() as u32
+    }
+
+    /// Returns the number of instructions, counting each `BrTable` target separately.
+    #[cfg(feature = "compact_bytecode")]
+    pub(crate) fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Records the size of the stack frame the function needs.
+    pub(crate) fn set_frame_size(&mut self, locals_count: u32, max_stack_height: u32) {
+        self.locals_count = locals_count;
+        self.max_stack_height = max_stack_height;
+    }
+
+    /// Returns the number of locals, including parameters, of the function.
+    pub(crate) fn locals_count(&self) -> u32 {
+        self.locals_count
+    }
+
+    /// Returns the maximum operand stack height reached by the function.
+    pub(crate) fn max_stack_height(&self) -> u32 {
+        self.max_stack_height
+    }
+
+    #[cfg(not(feature = "compact_bytecode"))]
     pub(crate) fn push(&mut self, instruction: InstructionInternal) {
         self.vec.push(instruction);
     }
 
+    #[cfg(feature = "compact_bytecode")]
+    pub(crate) fn push(&mut self, instruction: InstructionInternal) {
+        #[cfg(feature = "defensive_checks")]
+        if !matches!(instruction, InstructionInternal::BrTableTarget(_)) {
+            self.instruction_starts.push(self.current_pc());
+        }
+        compact::encode(instruction, &mut self.vec);
+        self.len += 1;
+    }
+
+    /// Appends an instruction given in the form it is returned by [`InstructionIter`].
+    ///
+    /// This allows to assemble code by hand, bypassing the validation and compilation
+    /// that normally produce it.
+    #[cfg(test)]
+    pub(crate) fn push_instruction(&mut self, instruction: Instruction) {
+        let internal = match instruction {
+            Instruction::BrTable(targets) => {
+                self.push(InstructionInternal::BrTable {
+                    count: targets.len(),
+                });
+                for index in 0..targets.len() {
+                    self.push(InstructionInternal::BrTableTarget(targets.get(index)));
+                }
+                return;
+            }
+            Instruction::GetLocal(x) => InstructionInternal::GetLocal(x),
+            Instruction::SetLocal(x) => InstructionInternal::SetLocal(x),
+            Instruction::TeeLocal(x) => InstructionInternal::TeeLocal(x),
+            Instruction::Br(x) => InstructionInternal::Br(x),
+            Instruction::BrIfEqz(x) => InstructionInternal::BrIfEqz(x),
+            Instruction::BrIfNez(x) => InstructionInternal::BrIfNez(x),
+            Instruction::Nop => InstructionInternal::Nop,
+            Instruction::Unreachable => InstructionInternal::Unreachable,
+            Instruction::Return(x) => InstructionInternal::Return(x),
+            Instruction::Call(x) => InstructionInternal::Call(x),
+            Instruction::CallIndirect(x) => InstructionInternal::CallIndirect(x),
+            Instruction::Drop => InstructionInternal::Drop,
+            Instruction::Select => InstructionInternal::Select,
+            Instruction::GetGlobal(x) => InstructionInternal::GetGlobal(x),
+            Instruction::SetGlobal(x) => InstructionInternal::SetGlobal(x),
+            Instruction::I32Load(x) => InstructionInternal::I32Load(x),
+            Instruction::I64Load(x) => InstructionInternal::I64Load(x),
+            Instruction::F32Load(x) => InstructionInternal::F32Load(x),
+            Instruction::F64Load(x) => InstructionInternal::F64Load(x),
+            Instruction::I32Load8S(x) => InstructionInternal::I32Load8S(x),
+            Instruction::I32Load8U(x) => InstructionInternal::I32Load8U(x),
+            Instruction::I32Load16S(x) => InstructionInternal::I32Load16S(x),
+            Instruction::I32Load16U(x) => InstructionInternal::I32Load16U(x),
+            Instruction::I64Load8S(x) => InstructionInternal::I64Load8S(x),
+            Instruction::I64Load8U(x) => InstructionInternal::I64Load8U(x),
+            Instruction::I64Load16S(x) => InstructionInternal::I64Load16S(x),
+            Instruction::I64Load16U(x) => InstructionInternal::I64Load16U(x),
+            Instruction::I64Load32S(x) => InstructionInternal::I64Load32S(x),
+            Instruction::I64Load32U(x) => InstructionInternal::I64Load32U(x),
+            Instruction::I32Store(x) => InstructionInternal::I32Store(x),
+            Instruction::I64Store(x) => InstructionInternal::I64Store(x),
+            Instruction::F32Store(x) => InstructionInternal::F32Store(x),
+            Instruction::F64Store(x) => InstructionInternal::F64Store(x),
+            Instruction::I32Store8(x) => InstructionInternal::I32Store8(x),
+            Instruction::I32Store16(x) => InstructionInternal::I32Store16(x),
+            Instruction::I64Store8(x) => InstructionInternal::I64Store8(x),
+            Instruction::I64Store16(x) => InstructionInternal::I64Store16(x),
+            Instruction::I64Store32(x) => InstructionInternal::I64Store32(x),
+            Instruction::CurrentMemory => InstructionInternal::CurrentMemory,
+            Instruction::GrowMemory => InstructionInternal::GrowMemory,
+            Instruction::MemoryCopy { src_mem, dst_mem } => {
+                InstructionInternal::MemoryCopy { src_mem, dst_mem }
+            }
+            Instruction::MemoryAtomicNotify(x) => InstructionInternal::MemoryAtomicNotify(x),
+            Instruction::MemoryAtomicWait32(x) => InstructionInternal::MemoryAtomicWait32(x),
+            Instruction::MemoryAtomicWait64(x) => InstructionInternal::MemoryAtomicWait64(x),
+            Instruction::TableInit(x) => InstructionInternal::TableInit(x),
+            Instruction::ElemDrop(x) => InstructionInternal::ElemDrop(x),
+            Instruction::I32Const(x) => InstructionInternal::I32Const(x),
+            Instruction::I64Const(x) => InstructionInternal::I64Const(x),
+            Instruction::F32Const(x) => InstructionInternal::F32Const(x),
+            Instruction::F64Const(x) => InstructionInternal::F64Const(x),
+            Instruction::I32Eqz => InstructionInternal::I32Eqz,
+            Instruction::I32Eq => InstructionInternal::I32Eq,
+            Instruction::I32Ne => InstructionInternal::I32Ne,
+            Instruction::I32LtS => InstructionInternal::I32LtS,
+            Instruction::I32LtU => InstructionInternal::I32LtU,
+            Instruction::I32GtS => InstructionInternal::I32GtS,
+            Instruction::I32GtU => InstructionInternal::I32GtU,
+            Instruction::I32LeS => InstructionInternal::I32LeS,
+            Instruction::I32LeU => InstructionInternal::I32LeU,
+            Instruction::I32GeS => InstructionInternal::I32GeS,
+            Instruction::I32GeU => InstructionInternal::I32GeU,
+            Instruction::I64Eqz => InstructionInternal::I64Eqz,
+            Instruction::I64Eq => InstructionInternal::I64Eq,
+            Instruction::I64Ne => InstructionInternal::I64Ne,
+            Instruction::I64LtS => InstructionInternal::I64LtS,
+            Instruction::I64LtU => InstructionInternal::I64LtU,
+            Instruction::I64GtS => InstructionInternal::I64GtS,
+            Instruction::I64GtU => InstructionInternal::I64GtU,
+            Instruction::I64LeS => InstructionInternal::I64LeS,
+            Instruction::I64LeU => InstructionInternal::I64LeU,
+            Instruction::I64GeS => InstructionInternal::I64GeS,
+            Instruction::I64GeU => InstructionInternal::I64GeU,
+            Instruction::F32Eq => InstructionInternal::F32Eq,
+            Instruction::F32Ne => InstructionInternal::F32Ne,
+            Instruction::F32Lt => InstructionInternal::F32Lt,
+            Instruction::F32Gt => InstructionInternal::F32Gt,
+            Instruction::F32Le => InstructionInternal::F32Le,
+            Instruction::F32Ge => InstructionInternal::F32Ge,
+            Instruction::F64Eq => InstructionInternal::F64Eq,
+            Instruction::F64Ne => InstructionInternal::F64Ne,
+            Instruction::F64Lt => InstructionInternal::F64Lt,
+            Instruction::F64Gt => InstructionInternal::F64Gt,
+            Instruction::F64Le => InstructionInternal::F64Le,
+            Instruction::F64Ge => InstructionInternal::F64Ge,
+            Instruction::I32Clz => InstructionInternal::I32Clz,
+            Instruction::I32Ctz => InstructionInternal::I32Ctz,
+            Instruction::I32Popcnt => InstructionInternal::I32Popcnt,
+            Instruction::I32Add => InstructionInternal::I32Add,
+            Instruction::I32Sub => InstructionInternal::I32Sub,
+            Instruction::I32Mul => InstructionInternal::I32Mul,
+            Instruction::I32DivS => InstructionInternal::I32DivS,
+            Instruction::I32DivU => InstructionInternal::I32DivU,
+            Instruction::I32RemS => InstructionInternal::I32RemS,
+            Instruction::I32RemU => InstructionInternal::I32RemU,
+            Instruction::I32And => InstructionInternal::I32And,
+            Instruction::I32Or => InstructionInternal::I32Or,
+            Instruction::I32Xor => InstructionInternal::I32Xor,
+            Instruction::I32Shl => InstructionInternal::I32Shl,
+            Instruction::I32ShrS => InstructionInternal::I32ShrS,
+            Instruction::I32ShrU => InstructionInternal::I32ShrU,
+            Instruction::I32Rotl => InstructionInternal::I32Rotl,
+            Instruction::I32Rotr => InstructionInternal::I32Rotr,
+            Instruction::I64Clz => InstructionInternal::I64Clz,
+            Instruction::I64Ctz => InstructionInternal::I64Ctz,
+            Instruction::I64Popcnt => InstructionInternal::I64Popcnt,
+            Instruction::I64Add => InstructionInternal::I64Add,
+            Instruction::I64Sub => InstructionInternal::I64Sub,
+            Instruction::I64Mul => InstructionInternal::I64Mul,
+            Instruction::I64DivS => InstructionInternal::I64DivS,
+            Instruction::I64DivU => InstructionInternal::I64DivU,
+            Instruction::I64RemS => InstructionInternal::I64RemS,
+            Instruction::I64RemU => InstructionInternal::I64RemU,
+            Instruction::I64And => InstructionInternal::I64And,
+            Instruction::I64Or => InstructionInternal::I64Or,
+            Instruction::I64Xor => InstructionInternal::I64Xor,
+            Instruction::I64Shl => InstructionInternal::I64Shl,
+            Instruction::I64ShrS => InstructionInternal::I64ShrS,
+            Instruction::I64ShrU => InstructionInternal::I64ShrU,
+            Instruction::I64Rotl => InstructionInternal::I64Rotl,
+            Instruction::I64Rotr => InstructionInternal::I64Rotr,
+            Instruction::F32Abs => InstructionInternal::F32Abs,
+            Instruction::F32Neg => InstructionInternal::F32Neg,
+            Instruction::F32Ceil => InstructionInternal::F32Ceil,
+            Instruction::F32Floor => InstructionInternal::F32Floor,
+            Instruction::F32Trunc => InstructionInternal::F32Trunc,
+            Instruction::F32Nearest => InstructionInternal::F32Nearest,
+            Instruction::F32Sqrt => InstructionInternal::F32Sqrt,
+            Instruction::F32Add => InstructionInternal::F32Add,
+            Instruction::F32Sub => InstructionInternal::F32Sub,
+            Instruction::F32Mul => InstructionInternal::F32Mul,
+            Instruction::F32Div => InstructionInternal::F32Div,
+            Instruction::F32Min => InstructionInternal::F32Min,
+            Instruction::F32Max => InstructionInternal::F32Max,
+            Instruction::F32Copysign => InstructionInternal::F32Copysign,
+            Instruction::F64Abs => InstructionInternal::F64Abs,
+            Instruction::F64Neg => InstructionInternal::F64Neg,
+            Instruction::F64Ceil => InstructionInternal::F64Ceil,
+            Instruction::F64Floor => InstructionInternal::F64Floor,
+            Instruction::F64Trunc => InstructionInternal::F64Trunc,
+            Instruction::F64Nearest => InstructionInternal::F64Nearest,
+            Instruction::F64Sqrt => InstructionInternal::F64Sqrt,
+            Instruction::F64Add => InstructionInternal::F64Add,
+            Instruction::F64Sub => InstructionInternal::F64Sub,
+            Instruction::F64Mul => InstructionInternal::F64Mul,
+            Instruction::F64Div => InstructionInternal::F64Div,
+            Instruction::F64Min => InstructionInternal::F64Min,
+            Instruction::F64Max => InstructionInternal::F64Max,
+            Instruction::F64Copysign => InstructionInternal::F64Copysign,
+            Instruction::I32WrapI64 => InstructionInternal::I32WrapI64,
+            Instruction::I32TruncSF32 => InstructionInternal::I32TruncSF32,
+            Instruction::I32TruncUF32 => InstructionInternal::I32TruncUF32,
+            Instruction::I32TruncSF64 => InstructionInternal::I32TruncSF64,
+            Instruction::I32TruncUF64 => InstructionInternal::I32TruncUF64,
+            Instruction::I64ExtendSI32 => InstructionInternal::I64ExtendSI32,
+            Instruction::I64ExtendUI32 => InstructionInternal::I64ExtendUI32,
+            Instruction::I64TruncSF32 => InstructionInternal::I64TruncSF32,
+            Instruction::I64TruncUF32 => InstructionInternal::I64TruncUF32,
+            Instruction::I64TruncSF64 => InstructionInternal::I64TruncSF64,
+            Instruction::I64TruncUF64 => InstructionInternal::I64TruncUF64,
+            Instruction::F32ConvertSI32 => InstructionInternal::F32ConvertSI32,
+            Instruction::F32ConvertUI32 => InstructionInternal::F32ConvertUI32,
+            Instruction::F32ConvertSI64 => InstructionInternal::F32ConvertSI64,
+            Instruction::F32ConvertUI64 => InstructionInternal::F32ConvertUI64,
+            Instruction::F32DemoteF64 => InstructionInternal::F32DemoteF64,
+            Instruction::F64ConvertSI32 => InstructionInternal::F64ConvertSI32,
+            Instruction::F64ConvertUI32 => InstructionInternal::F64ConvertUI32,
+            Instruction::F64ConvertSI64 => InstructionInternal::F64ConvertSI64,
+            Instruction::F64ConvertUI64 => InstructionInternal::F64ConvertUI64,
+            Instruction::F64PromoteF32 => InstructionInternal::F64PromoteF32,
+            Instruction::I32ReinterpretF32 => InstructionInternal::I32ReinterpretF32,
+            Instruction::I64ReinterpretF64 => InstructionInternal::I64ReinterpretF64,
+            Instruction::F32ReinterpretI32 => InstructionInternal::F32ReinterpretI32,
+            Instruction::F64ReinterpretI64 => InstructionInternal::F64ReinterpretI64,
+            Instruction::I32TruncSatSF32 => InstructionInternal::I32TruncSatSF32,
+            Instruction::I32TruncSatUF32 => InstructionInternal::I32TruncSatUF32,
+            Instruction::I32TruncSatSF64 => InstructionInternal::I32TruncSatSF64,
+            Instruction::I32TruncSatUF64 => InstructionInternal::I32TruncSatUF64,
+            Instruction::I64TruncSatSF32 => InstructionInternal::I64TruncSatSF32,
+            Instruction::I64TruncSatUF32 => InstructionInternal::I64TruncSatUF32,
+            Instruction::I64TruncSatSF64 => InstructionInternal::I64TruncSatSF64,
+            Instruction::I64TruncSatUF64 => InstructionInternal::I64TruncSatUF64,
+        };
+        self.push(internal);
+    }
+
+    #[cfg(not(feature = "compact_bytecode"))]
     pub fn patch_relocation(&mut self, reloc: Reloc, dst_pc: u32) {
         match reloc {
             Reloc::Br { pc } => match self.vec[pc as usize] {
@@ -572,6 +1111,11 @@ impl Instructions {
         }
     }
 
+    #[cfg(feature = "compact_bytecode")]
+    pub fn patch_relocation(&mut self, reloc: Reloc, dst_pc: u32) {
+        compact::patch_relocation(&mut self.vec, reloc, dst_pc);
+    }
+
     pub fn iterate_from(&self, position: u32) -> InstructionIter {
         InstructionIter {
             instructions: &self.vec,
@@ -581,7 +1125,7 @@ impl Instructions {
 }
 
 pub struct InstructionIter<'a> {
-    instructions: &'a [InstructionInternal],
+    instructions: &'a [CodeUnit],
     position: u32,
 }
 
@@ -597,9 +1141,16 @@ impl<'a> Iterator for InstructionIter<'a> {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        let internal = self.instructions.get(self.position as usize)?;
-
-        let out = match *internal {
+        #[cfg(not(feature = "compact_bytecode"))]
+        let (internal, next_position) = (
+            *self.instructions.get(self.position as usize)?,
+            self.position + 1,
+        );
+        #[cfg(feature = "compact_bytecode")]
+        let (internal, next_position) = compact::decode(self.instructions, self.position)?;
+        self.position = next_position;
+
+        let out = match internal {
             InstructionInternal::GetLocal(x) => Instruction::GetLocal(x),
             InstructionInternal::SetLocal(x) => Instruction::SetLocal(x),
             InstructionInternal::TeeLocal(x) => Instruction::TeeLocal(x),
@@ -607,16 +1158,16 @@ impl<'a> Iterator for InstructionIter<'a> {
             InstructionInternal::BrIfEqz(x) => Instruction::BrIfEqz(x),
             InstructionInternal::BrIfNez(x) => Instruction::BrIfNez(x),
             InstructionInternal::BrTable { count } => {
-                let start = self.position as usize + 1;
+                let start = self.position as usize;
+                let end = start + count as usize * BR_TABLE_TARGET_SIZE;
 
-                self.position += count;
+                self.position = end as u32;
 
-                Instruction::BrTable(BrTargets::from_internal(
-                    &self.instructions[start..start + count as usize],
-                ))
+                Instruction::BrTable(BrTargets::from_internal(&self.instructions[start..end]))
             }
             InstructionInternal::BrTableTarget(_) => panic!("Executed BrTableTarget"),
 
+            InstructionInternal::Nop => Instruction::Nop,
             InstructionInternal::Unreachable => Instruction::Unreachable,
             InstructionInternal::Return(x) => Instruction::Return(x),
 
@@ -655,6 +1206,15 @@ impl<'a> Iterator for InstructionIter<'a> {
 
             InstructionInternal::CurrentMemory => Instruction::CurrentMemory,
             InstructionInternal::GrowMemory => Instruction::GrowMemory,
+            InstructionInternal::MemoryCopy { src_mem, dst_mem } => {
+                Instruction::MemoryCopy { src_mem, dst_mem }
+            }
+            InstructionInternal::MemoryAtomicNotify(x) => Instruction::MemoryAtomicNotify(x),
+            InstructionInternal::MemoryAtomicWait32(x) => Instruction::MemoryAtomicWait32(x),
+            InstructionInternal::MemoryAtomicWait64(x) => Instruction::MemoryAtomicWait64(x),
+
+            InstructionInternal::TableInit(x) => Instruction::TableInit(x),
+            InstructionInternal::ElemDrop(x) => Instruction::ElemDrop(x),
 
             InstructionInternal::I32Const(x) => Instruction::I32Const(x),
             InstructionInternal::I64Const(x) => Instruction::I64Const(x),
@@ -791,10 +1351,16 @@ impl<'a> Iterator for InstructionIter<'a> {
             InstructionInternal::I64ReinterpretF64 => Instruction::I64ReinterpretF64,
             InstructionInternal::F32ReinterpretI32 => Instruction::F32ReinterpretI32,
             InstructionInternal::F64ReinterpretI64 => Instruction::F64ReinterpretI64,
+            InstructionInternal::I32TruncSatSF32 => Instruction::I32TruncSatSF32,
+            InstructionInternal::I32TruncSatUF32 => Instruction::I32TruncSatUF32,
+            InstructionInternal::I32TruncSatSF64 => Instruction::I32TruncSatSF64,
+            InstructionInternal::I32TruncSatUF64 => Instruction::I32TruncSatUF64,
+            InstructionInternal::I64TruncSatSF32 => Instruction::I64TruncSatSF32,
+            InstructionInternal::I64TruncSatUF32 => Instruction::I64TruncSatUF32,
+            InstructionInternal::I64TruncSatSF64 => Instruction::I64TruncSatSF64,
+            InstructionInternal::I64TruncSatUF64 => Instruction::I64TruncSatUF64,
         };
 
-        self.position += 1;
-
         Some(out)
     }
 }
@@ -0,0 +1,287 @@
+//! A compact encoding of compiled code, used with the `compact_bytecode` feature.
+//!
+//! Instead of one fixed size `InstructionInternal` per instruction, every instruction is
+//! encoded as a one byte opcode followed by its immediates. Indices, offsets and integer
+//! constants are LEB128 encoded, so most instructions take one to three bytes instead of
+//! the size of the largest variant. Positions in the code are byte offsets.
+//!
+//! Some immediates have a fixed size:
+//!
+//! - Branch targets, so that relocations can be patched once the destination is known and
+//!   `br_table` targets can be looked up by index.
+//! - The indices of calls, so that the position of a call can be recovered from the position
+//!   of the following instruction, see [`CALL_SIZE`].
+
+use super::{DropKeep, InstructionInternal, Reloc, Target};
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+/// Size of an encoded [`Target`]: the destination and the numbers of dropped and kept values.
+pub(super) const TARGET_SIZE: usize = 12;
+
+/// Size of a `br_table` without its targets: the opcode and the number of targets.
+const BR_TABLE_HEADER_SIZE: usize = 5;
+
+/// Size of a `call` or `call_indirect`: the opcode and the index.
+pub(super) const CALL_SIZE: u32 = 5;
+
+macro_rules! opcodes {
+    (
+        units: [$($unit:ident),* $(,)?],
+        indices: [$($index:ident),* $(,)?],
+        calls: [$($call:ident),* $(,)?],
+        branches: [$($branch:ident),* $(,)?],
+        others: [$($other:ident),* $(,)?],
+    ) => {
+        /// Assigns an opcode to each variant of `InstructionInternal`, except `BrTableTarget`.
+        #[allow(dead_code, clippy::upper_case_acronyms)]
+        #[repr(u8)]
+        enum Opcode {
+            $($unit,)*
+            $($index,)*
+            $($call,)*
+            $($branch,)*
+            $($other,)*
+        }
+
+        /// The opcodes as constants that can be matched on.
+        #[allow(non_upper_case_globals)]
+        mod op {
+            use super::Opcode;
+
+            $(pub const $unit: u8 = Opcode::$unit as u8;)*
+            $(pub const $index: u8 = Opcode::$index as u8;)*
+            $(pub const $call: u8 = Opcode::$call as u8;)*
+            $(pub const $branch: u8 = Opcode::$branch as u8;)*
+            $(pub const $other: u8 = Opcode::$other as u8;)*
+        }
+
+        /// Appends the encoding of `instruction` to `code`.
+        pub(super) fn encode(instruction: InstructionInternal, code: &mut Vec<u8>) {
+            match instruction {
+                $(InstructionInternal::$unit => code.push(op::$unit),)*
+                $(InstructionInternal::$index(index) => {
+                    code.push(op::$index);
+                    write_uleb(code, index);
+                })*
+                $(InstructionInternal::$call(index) => {
+                    code.push(op::$call);
+                    code.extend_from_slice(&index.to_le_bytes());
+                })*
+                $(InstructionInternal::$branch(target) => {
+                    code.push(op::$branch);
+                    write_target(code, target);
+                })*
+                InstructionInternal::BrTable { count } => {
+                    code.push(op::BrTable);
+                    code.extend_from_slice(&count.to_le_bytes());
+                }
+                // Targets follow their `br_table` without an opcode.
+                InstructionInternal::BrTableTarget(target) => write_target(code, target),
+                InstructionInternal::Return(drop_keep) => {
+                    code.push(op::Return);
+                    write_uleb(code, drop_keep.drop);
+                    write_uleb(code, drop_keep.keep);
+                }
+                InstructionInternal::MemoryCopy { src_mem, dst_mem } => {
+                    code.push(op::MemoryCopy);
+                    write_uleb(code, src_mem);
+                    write_uleb(code, dst_mem);
+                }
+                InstructionInternal::I32Const(value) => {
+                    code.push(op::I32Const);
+                    write_sleb(code, value.into());
+                }
+                InstructionInternal::I64Const(value) => {
+                    code.push(op::I64Const);
+                    write_sleb(code, value);
+                }
+                InstructionInternal::F32Const(bits) => {
+                    code.push(op::F32Const);
+                    code.extend_from_slice(&bits.to_le_bytes());
+                }
+                InstructionInternal::F64Const(bits) => {
+                    code.push(op::F64Const);
+                    code.extend_from_slice(&bits.to_le_bytes());
+                }
+            }
+        }
+
+        /// Decodes the instruction at `position`.
+        ///
+        /// Returns the instruction and the position following it, or `None` at the end of
+        /// `code`. For `br_table` the returned position is the one of its first target.
+        #[inline]
+        pub(super) fn decode(code: &[u8], position: u32) -> Option<(InstructionInternal, u32)> {
+            let mut position = position as usize;
+            let opcode = *code.get(position)?;
+            position += 1;
+            let instruction = match opcode {
+                $(op::$unit => InstructionInternal::$unit,)*
+                $(op::$index => InstructionInternal::$index(read_uleb(code, &mut position)),)*
+                $(op::$call => InstructionInternal::$call(read_u32(code, &mut position)),)*
+                $(op::$branch => InstructionInternal::$branch(read_target(code, &mut position)),)*
+                op::BrTable => InstructionInternal::BrTable {
+                    count: read_u32(code, &mut position),
+                },
+                op::Return => InstructionInternal::Return(DropKeep {
+                    drop: read_uleb(code, &mut position),
+                    keep: read_uleb(code, &mut position),
+                }),
+                op::MemoryCopy => InstructionInternal::MemoryCopy {
+                    src_mem: read_uleb(code, &mut position),
+                    dst_mem: read_uleb(code, &mut position),
+                },
+                op::I32Const => InstructionInternal::I32Const(read_sleb(code, &mut position) as i32),
+                op::I64Const => InstructionInternal::I64Const(read_sleb(code, &mut position)),
+                op::F32Const => InstructionInternal::F32Const(read_u32(code, &mut position)),
+                op::F64Const => InstructionInternal::F64Const(read_u64(code, &mut position)),
+                _ => panic!("invalid opcode {} at {}", opcode, position - 1),
+            };
+            Some((instruction, position as u32))
+        }
+    };
+}
+
+opcodes! {
+    units: [
+        Nop, Unreachable, Drop, Select, CurrentMemory, GrowMemory, I32Eqz, I32Eq, I32Ne, I32LtS,
+        I32LtU, I32GtS, I32GtU, I32LeS, I32LeU, I32GeS, I32GeU, I64Eqz, I64Eq, I64Ne, I64LtS,
+        I64LtU, I64GtS, I64GtU, I64LeS, I64LeU, I64GeS, I64GeU, F32Eq, F32Ne, F32Lt, F32Gt, F32Le,
+        F32Ge, F64Eq, F64Ne, F64Lt, F64Gt, F64Le, F64Ge, I32Clz, I32Ctz, I32Popcnt, I32Add, I32Sub,
+        I32Mul, I32DivS, I32DivU, I32RemS, I32RemU, I32And, I32Or, I32Xor, I32Shl, I32ShrS,
+        I32ShrU, I32Rotl, I32Rotr, I64Clz, I64Ctz, I64Popcnt, I64Add, I64Sub, I64Mul, I64DivS,
+        I64DivU, I64RemS, I64RemU, I64And, I64Or, I64Xor, I64Shl, I64ShrS, I64ShrU, I64Rotl,
+        I64Rotr, F32Abs, F32Neg, F32Ceil, F32Floor, F32Trunc, F32Nearest, F32Sqrt, F32Add, F32Sub,
+        F32Mul, F32Div, F32Min, F32Max, F32Copysign, F64Abs, F64Neg, F64Ceil, F64Floor, F64Trunc,
+        F64Nearest, F64Sqrt, F64Add, F64Sub, F64Mul, F64Div, F64Min, F64Max, F64Copysign,
+        I32WrapI64, I32TruncSF32, I32TruncUF32, I32TruncSF64, I32TruncUF64, I64ExtendSI32,
+        I64ExtendUI32, I64TruncSF32, I64TruncUF32, I64TruncSF64, I64TruncUF64, F32ConvertSI32,
+        F32ConvertUI32, F32ConvertSI64, F32ConvertUI64, F32DemoteF64, F64ConvertSI32,
+        F64ConvertUI32, F64ConvertSI64, F64ConvertUI64, F64PromoteF32, I32ReinterpretF32,
+        I64ReinterpretF64, F32ReinterpretI32, F64ReinterpretI64, I32TruncSatSF32, I32TruncSatUF32,
+        I32TruncSatSF64, I32TruncSatUF64, I64TruncSatSF32, I64TruncSatUF32, I64TruncSatSF64,
+        I64TruncSatUF64,
+    ],
+    indices: [
+        GetLocal, SetLocal, TeeLocal, GetGlobal, SetGlobal, I32Load, I64Load, F32Load, F64Load,
+        I32Load8S, I32Load8U, I32Load16S, I32Load16U, I64Load8S, I64Load8U, I64Load16S, I64Load16U,
+        I64Load32S, I64Load32U, I32Store, I64Store, F32Store, F64Store, I32Store8, I32Store16,
+        I64Store8, I64Store16, I64Store32, MemoryAtomicNotify, MemoryAtomicWait32,
+        MemoryAtomicWait64, TableInit, ElemDrop,
+    ],
+    calls: [Call, CallIndirect],
+    branches: [Br, BrIfEqz, BrIfNez],
+    others: [BrTable, Return, MemoryCopy, I32Const, I64Const, F32Const, F64Const],
+}
+
+/// Sets the destination of the branch or `br_table` target referred to by `reloc`.
+pub(super) fn patch_relocation(code: &mut [u8], reloc: Reloc, dst_pc: u32) {
+    let offset = match reloc {
+        Reloc::Br { pc } => match code[pc as usize] {
+            op::Br | op::BrIfEqz | op::BrIfNez => pc as usize + 1,
+            _ => panic!("branch relocation points to a non-branch instruction"),
+        },
+        Reloc::BrTable { pc, idx } => match code[pc as usize] {
+            op::BrTable => pc as usize + BR_TABLE_HEADER_SIZE + idx * TARGET_SIZE,
+            _ => panic!("brtable relocation points to not brtable instruction"),
+        },
+    };
+    code[offset..offset + 4].copy_from_slice(&dst_pc.to_le_bytes());
+}
+
+/// Reads the `br_table` target with the given index from the encoded targets.
+#[inline]
+pub(super) fn br_table_target(targets: &[u8], index: usize) -> Target {
+    read_target(targets, &mut (index * TARGET_SIZE))
+}
+
+fn write_target(code: &mut Vec<u8>, target: Target) {
+    code.extend_from_slice(&target.dst_pc.to_le_bytes());
+    code.extend_from_slice(&target.drop_keep.drop.to_le_bytes());
+    code.extend_from_slice(&target.drop_keep.keep.to_le_bytes());
+}
+
+#[inline]
+fn read_target(code: &[u8], position: &mut usize) -> Target {
+    Target {
+        dst_pc: read_u32(code, position),
+        drop_keep: DropKeep {
+            drop: read_u32(code, position),
+            keep: read_u32(code, position),
+        },
+    }
+}
+
+#[inline]
+fn read_u32(code: &[u8], position: &mut usize) -> u32 {
+    let bytes = code[*position..*position + 4].try_into().unwrap();
+    *position += 4;
+    u32::from_le_bytes(bytes)
+}
+
+#[inline]
+fn read_u64(code: &[u8], position: &mut usize) -> u64 {
+    let bytes = code[*position..*position + 8].try_into().unwrap();
+    *position += 8;
+    u64::from_le_bytes(bytes)
+}
+
+fn write_uleb(code: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            code.push(byte);
+            return;
+        }
+        code.push(byte | 0x80);
+    }
+}
+
+#[inline]
+fn read_uleb(code: &[u8], position: &mut usize) -> u32 {
+    let mut result = 0;
+    let mut shift = 0;
+    loop {
+        let byte = code[*position];
+        *position += 1;
+        result |= u32::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return result;
+        }
+        shift += 7;
+    }
+}
+
+fn write_sleb(code: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        // Arithmetic shift, so negative values end in all ones.
+        value >>= 7;
+        let sign_bit = byte & 0x40 != 0;
+        if (value == 0 && !sign_bit) || (value == -1 && sign_bit) {
+            code.push(byte);
+            return;
+        }
+        code.push(byte | 0x80);
+    }
+}
+
+#[inline]
+fn read_sleb(code: &[u8], position: &mut usize) -> i64 {
+    let mut result = 0;
+    let mut shift = 0;
+    loop {
+        let byte = code[*position];
+        *position += 1;
+        result |= i64::from(byte & 0x7f) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            if shift < 64 && byte & 0x40 != 0 {
+                result |= -1 << shift;
+            }
+            return result;
+        }
+    }
+}
@@ -45,6 +45,17 @@
 //! Functions can either return a result or trap (e.g. there can't be linking error in the middle of the function execution).
 //! This property is ensured by the validation process.
 //!
+//! ## Floating point
+//!
+//! Float arithmetic and conversions use the floating point instructions of the host, which
+//! round to nearest, ties to even, as the spec requires. wasmi relies on Rust's guarantee
+//! of this rounding mode: Rust code assumes the default floating point environment, so
+//! calling into wasmi after changing the rounding mode, e.g. with `fesetround`, is
+//! undefined behavior rather than a way to change the results of wasm code. Embedders
+//! that change the rounding mode must restore the default before executing wasm code.
+//!
+//! The rounding instructions, such as `f32.nearest`, don't depend on the rounding mode.
+//!
 //! # Examples
 //!
 //! ```rust
@@ -111,15 +122,18 @@ extern crate core;
 
 #[cfg(test)]
 extern crate assert_matches;
-#[cfg(test)]
+#[cfg(any(test, feature = "wabt"))]
 extern crate wabt;
 
 use alloc::{
     boxed::Box,
+    collections::BTreeSet,
     string::{String, ToString},
+    sync::Arc,
     vec::Vec,
 };
-use core::fmt;
+use core::{cmp, fmt};
+use parity_wasm::elements::{External, Internal, ResizableLimits};
 #[cfg(feature = "std")]
 use std::error;
 
@@ -136,12 +150,27 @@ extern crate num_traits;
 #[derive(Debug)]
 pub struct Trap {
     kind: TrapKind,
+    #[cfg(feature = "trap_state")]
+    state: Option<Box<TrapState>>,
 }
 
 impl Trap {
     /// Create new trap.
     pub fn new(kind: TrapKind) -> Trap {
-        Trap { kind }
+        Trap {
+            kind,
+            #[cfg(feature = "trap_state")]
+            state: None,
+        }
+    }
+
+    /// Create new trap which carries the state of the function that raised it.
+    #[cfg(feature = "trap_state")]
+    pub(crate) fn with_state(kind: TrapKind, state: TrapState) -> Trap {
+        Trap {
+            kind,
+            state: Some(Box::new(state)),
+        }
     }
 
     /// Returns kind of this trap.
@@ -153,11 +182,20 @@ impl Trap {
     pub fn into_kind(self) -> TrapKind {
         self.kind
     }
+
+    /// Returns the locals and operands of the function that raised this trap.
+    ///
+    /// Only traps raised by wasm instructions carry a state, traps returned by host
+    /// functions or raised while setting up a call don't.
+    #[cfg(feature = "trap_state")]
+    pub fn state(&self) -> Option<&TrapState> {
+        self.state.as_deref()
+    }
 }
 
 impl fmt::Display for Trap {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Trap: {:?}", self.kind)
+        write!(f, "Trap: {}", self.kind)
     }
 }
 
@@ -186,8 +224,39 @@ pub enum TrapKind {
     ///
     /// Since addresses are interpreted as unsigned integers, out of bounds access
     /// can't happen with negative addresses (i.e. they will always wrap).
+    ///
+    /// Loads and stores don't raise this trap in invocations with the non spec compliant
+    /// [`StackRecycler::with_relaxed_memory`] setting.
+    ///
+    /// [`StackRecycler::with_relaxed_memory`]: struct.StackRecycler.html#method.with_relaxed_memory
     MemoryAccessOutOfBounds,
 
+    /// Attempt to load or store from a memory whose buffer was taken out
+    /// with [`MemoryInstance::take_backing`].
+    ///
+    /// [`MemoryInstance::take_backing`]: struct.MemoryInstance.html#method.take_backing
+    MemoryDetached,
+
+    /// Attempt to store to, copy into or grow a memory that was made read-only
+    /// with [`MemoryInstance::set_read_only`].
+    ///
+    /// [`MemoryInstance::set_read_only`]: struct.MemoryInstance.html#method.set_read_only
+    MemoryReadOnly,
+
+    /// Atomic memory access at an address that isn't a multiple of the size of the
+    /// accessed value.
+    UnalignedAtomic,
+
+    /// `memory.atomic.wait32` or `memory.atomic.wait64` on a memory that isn't shared.
+    ExpectedSharedMemory,
+
+    /// `memory.atomic.wait32` or `memory.atomic.wait64` without a timeout found the expected
+    /// value.
+    ///
+    /// Only other threads could change the value and wake the waiting one, so it would wait
+    /// forever.
+    WaitWouldBlockForever,
+
     /// Attempt to access table element at index which
     /// lies outside of bounds.
     ///
@@ -198,6 +267,18 @@ pub enum TrapKind {
     /// can't happen with negative indexes (i.e. they will always wrap).
     TableAccessOutOfBounds,
 
+    /// Attempt to write a table element at index which
+    /// lies outside of bounds.
+    ///
+    /// This typically can happen when `table.init` is executed with a
+    /// destination range that doesn't fit into the table.
+    TableSetOutOfBounds {
+        /// Index of the table.
+        table_index: u32,
+        /// Index of the first element which lies outside of bounds.
+        index: u32,
+    },
+
     /// Attempt to access table element which is uninitialized (i.e. `None`).
     ///
     /// This typically can happen when `call_indirect` is executed.
@@ -218,11 +299,52 @@ pub enum TrapKind {
     /// - trying to truncate NaNs, infinity, or value for which the result is out of range into an integer.
     InvalidConversionToInt,
 
+    /// Attempt to shift an integer by an amount which is not less than its bit width.
+    ///
+    /// Wasm masks shift amounts, so this trap is only raised in invocations with the
+    /// [`StackRecycler::with_strict_shifts`] setting. It helps to catch code relying on the
+    /// masking, e.g. when comparing against engines or languages with different shift
+    /// semantics.
+    ///
+    /// [`StackRecycler::with_strict_shifts`]: struct.StackRecycler.html#method.with_strict_shifts
+    ShiftOutOfRange,
+
+    /// Execution ran past the deadline set by an [`ExecutionDeadline`].
+    ///
+    /// Only raised when the `execution_deadline` feature is enabled. The deadline is
+    /// checked periodically, so execution may overrun it by up to one check interval.
+    ///
+    /// [`ExecutionDeadline`]: struct.ExecutionDeadline.html
+    Timeout,
+
+    /// The invocation tried to call more host functions than
+    /// [`StackRecycler::with_host_call_limit`] allows.
+    ///
+    /// [`StackRecycler::with_host_call_limit`]: struct.StackRecycler.html#method.with_host_call_limit
+    HostCallLimitExceeded {
+        /// The maximum number of host calls per invocation.
+        limit: u32,
+    },
+
     /// Stack overflow.
     ///
     /// This is likely caused by some infinite or very deep recursion.
     /// Extensive inlining might also be the cause of stack overflow.
-    StackOverflow,
+    ///
+    /// Both fields are counted in values if the value stack overflowed, in frames if the
+    /// call stack overflowed, in bytes if a [`NativeStackGuard`] detected the overflow and
+    /// in nested executions if a [`ReentrancyLimit`] was exceeded.
+    /// Comparing them against the configured limits helps to tell apart runaway recursion
+    /// from limits that are simply too tight for the workload.
+    ///
+    /// [`NativeStackGuard`]: struct.NativeStackGuard.html
+    /// [`ReentrancyLimit`]: struct.ReentrancyLimit.html
+    StackOverflow {
+        /// The maximum depth of the stack that overflowed.
+        limit: usize,
+        /// The depth the stack would have reached.
+        depth: usize,
+    },
 
     /// Attempt to invoke a function with mismatching signature.
     ///
@@ -237,6 +359,36 @@ pub enum TrapKind {
     /// [`Signature`]: struct.Signature.html
     UnexpectedSignature,
 
+    /// Executed code is malformed, e.g. it pops more values than there are on the stack
+    /// or refers to a function that doesn't exist.
+    ///
+    /// Code that passed validation never causes this trap. It is only raised when the
+    /// `defensive_checks` feature is enabled, for instance to fuzz the interpreter with
+    /// hand-assembled code. Otherwise malformed code makes the interpreter panic.
+    InvalidCode,
+
+    /// Attempt to read a string from linear memory which is not valid UTF-8.
+    ///
+    /// Typically returned from [`MemoryInstance::read_str`].
+    ///
+    /// [`MemoryInstance::read_str`]: struct.MemoryInstance.html#method.read_str
+    InvalidUtf8,
+
+    /// Attempt to call a host function which is not implemented.
+    ///
+    /// Typically returned from an implementation of [`Externals`] for stub
+    /// host functions. Unlike [`UnexpectedSignature`] this means that the host
+    /// refused to execute the function at runtime.
+    ///
+    /// [`Externals`]: trait.Externals.html
+    /// [`UnexpectedSignature`]: #variant.UnexpectedSignature
+    Unimplemented {
+        /// Name of the module the function is imported from.
+        module_name: String,
+        /// Name of the function within the module.
+        field_name: String,
+    },
+
     /// Error specified by the host.
     ///
     /// Typically returned from an implementation of [`Externals`].
@@ -252,14 +404,73 @@ impl TrapKind {
     }
 }
 
+/// Describes the trap in a way suitable for users.
+///
+/// Where the spec test suite has a message for a trap, the description contains it,
+/// so harnesses can match traps of different engines by their text.
+impl fmt::Display for TrapKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TrapKind::Unreachable => write!(f, "unreachable"),
+            TrapKind::MemoryAccessOutOfBounds => write!(f, "out of bounds memory access"),
+            TrapKind::MemoryDetached => write!(f, "memory access to a detached memory"),
+            TrapKind::MemoryReadOnly => write!(f, "write to a read-only memory"),
+            TrapKind::UnalignedAtomic => write!(f, "unaligned atomic"),
+            TrapKind::ExpectedSharedMemory => write!(f, "expected shared memory"),
+            TrapKind::WaitWouldBlockForever => write!(f, "wait would block forever"),
+            TrapKind::TableAccessOutOfBounds => write!(f, "undefined element"),
+            TrapKind::TableSetOutOfBounds { table_index, index } => write!(
+                f,
+                "out of bounds write to element {} of table {}",
+                index, table_index
+            ),
+            TrapKind::ElemUninitialized => write!(f, "uninitialized element"),
+            TrapKind::DivisionByZero => write!(f, "integer divide by zero"),
+            // Covers both signed division overflow and failed float truncation, which
+            // the spec reports as "integer overflow" and "invalid conversion to integer".
+            TrapKind::InvalidConversionToInt => {
+                write!(f, "integer overflow or invalid conversion to integer")
+            }
+            TrapKind::ShiftOutOfRange => write!(f, "shift amount out of range"),
+            TrapKind::Timeout => write!(f, "execution deadline exceeded"),
+            TrapKind::HostCallLimitExceeded { limit } => {
+                write!(f, "host call limit of {} exceeded", limit)
+            }
+            TrapKind::StackOverflow { limit, depth } => write!(
+                f,
+                "call stack exhausted (depth {} exceeds limit {})",
+                depth, limit
+            ),
+            TrapKind::UnexpectedSignature => write!(f, "indirect call type mismatch"),
+            TrapKind::InvalidCode => write!(f, "invalid code"),
+            TrapKind::InvalidUtf8 => write!(f, "invalid UTF-8"),
+            TrapKind::Unimplemented {
+                ref module_name,
+                ref field_name,
+            } => write!(f, "not implemented: {}.{}", module_name, field_name),
+            TrapKind::Host(ref host_error) => write!(f, "host error: {}", host_error),
+        }
+    }
+}
+
 /// Internal interpreter error.
 #[derive(Debug)]
 pub enum Error {
     /// Module validation error. Might occur only at load time.
     Validation(String),
+    /// The wasm text format could not be parsed.
+    ///
+    /// Returned by [`Module::from_wat`] before any validation happens.
+    ///
+    /// [`Module::from_wat`]: struct.Module.html#method.from_wat
+    Parse(String),
     /// Error while instantiating a module. Might occur when provided
     /// with incorrect exports (i.e. linkage failure).
-    Instantiation(String),
+    ///
+    /// See [`InstantiationError`] for the possible causes.
+    ///
+    /// [`InstantiationError`]: enum.InstantiationError.html
+    Instantiation(InstantiationError),
     /// Function-level error.
     Function(String),
     /// Table-level error.
@@ -270,12 +481,127 @@ pub enum Error {
     Global(String),
     /// Value-level error.
     Value(String),
+    /// A snapshot doesn't fit the instance it is restored into.
+    ///
+    /// Returned by [`ModuleInstance::restore`].
+    ///
+    /// [`ModuleInstance::restore`]: struct.ModuleInstance.html#method.restore
+    Snapshot(String),
     /// Trap.
     Trap(Trap),
     /// Custom embedder error.
     Host(Box<dyn host::HostError>),
 }
 
+/// The cause of a failed instantiation of a module.
+#[derive(Debug)]
+pub enum InstantiationError {
+    /// No value is provided for the import `module.field`.
+    ///
+    /// A [`ModuleImportResolver`] doesn't know the name of the module it resolves, so
+    /// `module` is only filled in once it is used through an [`ImportsBuilder`].
+    ///
+    /// [`ModuleImportResolver`]: trait.ModuleImportResolver.html
+    /// [`ImportsBuilder`]: struct.ImportsBuilder.html
+    MissingImport {
+        /// The name of the module the import is imported from.
+        module: String,
+        /// The name of the imported field.
+        field: String,
+    },
+    /// The value provided for the import `module.field` is of another kind or type than
+    /// the import declares.
+    ImportTypeMismatch {
+        /// The name of the module the import is imported from.
+        module: String,
+        /// The name of the imported field.
+        field: String,
+        /// The kind and type declared by the import.
+        expected: ExternType,
+        /// The kind and type of the provided value.
+        actual: ExternType,
+    },
+    /// The limits of the memory or table provided for the import `module.field` don't
+    /// satisfy the limits declared by the import.
+    LimitsMismatch {
+        /// The name of the module the import is imported from.
+        module: String,
+        /// The name of the imported field.
+        field: String,
+        /// The limits declared by the import.
+        expected: ResizableLimits,
+        /// The limits of the provided memory or table.
+        actual: ResizableLimits,
+    },
+    /// The active element segment with the given index doesn't fit into its table.
+    ElemSegmentDoesNotFit {
+        /// The index of the segment among all element segments of the module.
+        index: u32,
+    },
+    /// The active data segment with the given index doesn't fit into its memory.
+    DataSegmentDoesNotFit {
+        /// The index of the segment among all data segments of the module.
+        index: u32,
+    },
+    /// The `start` function trapped.
+    StartTrapped(Trap),
+    /// Any other cause, e.g. reported by a custom [`ImportResolver`].
+    ///
+    /// [`ImportResolver`]: trait.ImportResolver.html
+    Other(String),
+}
+
+impl fmt::Display for InstantiationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InstantiationError::MissingImport {
+                ref module,
+                ref field,
+            } => write!(f, "import {}.{} not found", module, field),
+            InstantiationError::ImportTypeMismatch {
+                ref module,
+                ref field,
+                ref expected,
+                ref actual,
+            } => write!(
+                f,
+                "import {}.{} expects {:?}, but {:?} was provided",
+                module, field, expected, actual
+            ),
+            InstantiationError::LimitsMismatch {
+                ref module,
+                ref field,
+                ref expected,
+                ref actual,
+            } => write!(
+                f,
+                "import {}.{} expects limits {}, but limits {} were provided",
+                module,
+                field,
+                describe_limits(expected),
+                describe_limits(actual)
+            ),
+            InstantiationError::ElemSegmentDoesNotFit { index } => {
+                write!(f, "element segment {} does not fit", index)
+            }
+            InstantiationError::DataSegmentDoesNotFit { index } => {
+                write!(f, "data segment {} does not fit", index)
+            }
+            InstantiationError::StartTrapped(ref trap) => {
+                write!(f, "start function trapped: {}", trap)
+            }
+            InstantiationError::Other(ref s) => write!(f, "{}", s),
+        }
+    }
+}
+
+fn describe_limits(limits: &ResizableLimits) -> String {
+    match limits.maximum() {
+        Some(maximum) => format!("{}..={}", limits.initial(), maximum),
+        None => format!("{}..", limits.initial()),
+    }
+}
+
 impl Error {
     /// Returns a reference to a [`HostError`] if this `Error` represents some host error.
     ///
@@ -290,6 +616,7 @@ impl Error {
             Error::Host(host_err) => Some(&**host_err),
             Error::Trap(Trap {
                 kind: TrapKind::Host(host_err),
+                ..
             }) => Some(&**host_err),
             _ => None,
         }
@@ -308,6 +635,7 @@ impl Error {
             Error::Host(host_err) => Some(host_err),
             Error::Trap(Trap {
                 kind: TrapKind::Host(host_err),
+                ..
             }) => Some(host_err),
             _ => None,
         }
@@ -326,6 +654,7 @@ impl Error {
             Error::Host(host_err) => Ok(host_err),
             Error::Trap(Trap {
                 kind: TrapKind::Host(host_err),
+                ..
             }) => Ok(host_err),
             other => Err(other),
         }
@@ -337,12 +666,14 @@ impl Into<String> for Error {
     fn into(self) -> String {
         match self {
             Error::Validation(s) => s,
-            Error::Instantiation(s) => s,
+            Error::Instantiation(e) => e.to_string(),
             Error::Function(s) => s,
             Error::Table(s) => s,
             Error::Memory(s) => s,
             Error::Global(s) => s,
             Error::Value(s) => s,
+            Error::Snapshot(s) => s,
+            Error::Parse(s) => s,
             Error::Trap(s) => format!("trap: {:?}", s),
             Error::Host(e) => format!("user: {}", e),
         }
@@ -353,13 +684,15 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::Validation(ref s) => write!(f, "Validation: {}", s),
-            Error::Instantiation(ref s) => write!(f, "Instantiation: {}", s),
+            Error::Instantiation(ref e) => write!(f, "Instantiation: {}", e),
             Error::Function(ref s) => write!(f, "Function: {}", s),
             Error::Table(ref s) => write!(f, "Table: {}", s),
             Error::Memory(ref s) => write!(f, "Memory: {}", s),
             Error::Global(ref s) => write!(f, "Global: {}", s),
             Error::Value(ref s) => write!(f, "Value: {}", s),
-            Error::Trap(ref s) => write!(f, "Trap: {:?}", s),
+            Error::Snapshot(ref s) => write!(f, "Snapshot: {}", s),
+            Error::Parse(ref s) => write!(f, "Parse: {}", s),
+            Error::Trap(ref s) => write!(f, "{}", s),
             Error::Host(ref e) => write!(f, "User: {}", e),
         }
     }
@@ -370,12 +703,15 @@ impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
             Error::Validation(ref s) => s,
-            Error::Instantiation(ref s) => s,
+            Error::Instantiation(InstantiationError::Other(ref s)) => s,
+            Error::Instantiation(_) => "Instantiation error",
             Error::Function(ref s) => s,
             Error::Table(ref s) => s,
             Error::Memory(ref s) => s,
             Error::Global(ref s) => s,
             Error::Value(ref s) => s,
+            Error::Snapshot(ref s) => s,
+            Error::Parse(ref s) => s,
             Error::Trap(_) => "Trap",
             Error::Host(_) => "Host error",
         }
@@ -406,6 +742,12 @@ impl From<Trap> for Error {
     }
 }
 
+impl From<InstantiationError> for Error {
+    fn from(e: InstantiationError) -> Error {
+        Error::Instantiation(e)
+    }
+}
+
 impl From<TrapKind> for Trap {
     fn from(e: TrapKind) -> Trap {
         Trap::new(e)
@@ -418,6 +760,8 @@ impl From<validation::Error> for Error {
     }
 }
 
+#[cfg(feature = "std")]
+mod cache;
 mod func;
 mod global;
 mod host;
@@ -428,6 +772,8 @@ mod module;
 pub mod nan_preserving_float;
 mod prepare;
 mod runner;
+#[cfg(feature = "stepping")]
+mod stepper;
 mod table;
 mod types;
 mod value;
@@ -435,16 +781,40 @@ mod value;
 #[cfg(test)]
 mod tests;
 
-pub use self::func::{FuncInstance, FuncInvocation, FuncRef, ResumableError};
-pub use self::global::{GlobalInstance, GlobalRef};
-pub use self::host::{Externals, HostError, NopExternals, RuntimeArgs};
+#[cfg(feature = "std")]
+pub use self::cache::ModuleCache;
+pub use self::func::{
+    ExecutionStep, FuncInstance, FuncInvocation, FuncRef, PendingHostCall, ResumableError,
+};
+pub use self::global::{ExternRefGlobalInstance, ExternRefGlobalRef, GlobalInstance, GlobalRef};
+pub use self::host::{
+    Caller, Externals, HostError, NopExternals, RuntimeArgs, SeededRandom, SharedExternals, Stdio,
+};
 pub use self::imports::{ImportResolver, ImportsBuilder, ModuleImportResolver};
-pub use self::memory::{MemoryInstance, MemoryRef, LINEAR_MEMORY_PAGE_SIZE};
-pub use self::module::{ExternVal, ModuleInstance, ModuleRef, NotStartedModuleRef};
+pub use self::memory::{DetachedMemory, MemoryInstance, MemoryRef, LINEAR_MEMORY_PAGE_SIZE};
+#[cfg(feature = "import_usage")]
+pub use self::module::UsedImport;
+pub use self::module::{
+    ExternKind, ExternType, ExternVal, InstanceSnapshot, ModuleInstance, ModuleRef,
+    NotStartedModuleRef,
+};
+#[cfg(feature = "trap_state")]
+pub use self::runner::TrapState;
+#[cfg(feature = "execution_deadline")]
+pub use self::runner::{ExecutionDeadline, DEFAULT_DEADLINE_CHECK_INTERVAL};
+#[cfg(feature = "std")]
+pub use self::runner::{NativeStackGuard, ReentrancyLimit};
+#[cfg(feature = "overflow_tracing")]
+pub use self::runner::{OverflowTrace, OverflowingOp, SignedOverflow};
 pub use self::runner::{StackRecycler, DEFAULT_CALL_STACK_LIMIT, DEFAULT_VALUE_STACK_LIMIT};
-pub use self::table::{TableInstance, TableRef};
+#[cfg(feature = "stepping")]
+pub use self::stepper::{StepOutcome, Stepper};
+pub use self::table::{TableElement, TableElementType, TableInstance, TableRef};
 pub use self::types::{GlobalDescriptor, MemoryDescriptor, Signature, TableDescriptor, ValueType};
-pub use self::value::{Error as ValueError, FromRuntimeValue, LittleEndianConvert, RuntimeValue};
+pub use self::value::{
+    Error as ValueError, ExternRef, FromRuntimeValue, LittleEndianConvert, RuntimeValue,
+};
+pub use validation::{DataSegment, ElemSegment, ImportCounts, SegmentOffset};
 
 /// WebAssembly-specific sizes and units.
 pub mod memory_units {
@@ -454,8 +824,51 @@ pub mod memory_units {
 
 /// Deserialized module prepared for instantiation.
 pub struct Module {
-    code_map: Vec<isa::Instructions>,
-    module: parity_wasm::elements::Module,
+    code_map: Vec<prepare::FuncCode>,
+    module: Arc<parity_wasm::elements::Module>,
+}
+
+/// Size and complexity metrics of a [`Module`].
+///
+/// [`Module`]: struct.Module.html
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleStats {
+    /// The number of functions defined by the module, not counting imported ones.
+    pub functions: u32,
+    /// The total number of instructions of all compiled function bodies.
+    pub instructions: u32,
+    /// The maximum operand stack height reached by any function.
+    pub max_stack_height: u32,
+    /// The maximum number of locals, including parameters, of any function.
+    pub max_locals: u32,
+}
+
+/// An import of a [`Module`], see [`Module::imports`].
+///
+/// [`Module`]: struct.Module.html
+/// [`Module::imports`]: struct.Module.html#method.imports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleImport<'a> {
+    /// The name of the module the import is imported from.
+    pub module_name: &'a str,
+    /// The name of the imported field.
+    pub field_name: &'a str,
+    /// The kind of the import.
+    pub kind: ExternKind,
+}
+
+/// An export of a [`Module`], see [`Module::exports`].
+///
+/// [`Module`]: struct.Module.html
+/// [`Module::exports`]: struct.Module.html#method.exports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleExport<'a> {
+    /// The name of the export.
+    pub name: &'a str,
+    /// The kind of the export.
+    pub kind: ExternKind,
+    /// The index of the exported entity in the index space of its kind.
+    pub index: u32,
 }
 
 impl Module {
@@ -492,7 +905,35 @@ impl Module {
     /// }
     /// ```
     pub fn from_parity_wasm_module(module: parity_wasm::elements::Module) -> Result<Module, Error> {
-        let prepare::CompiledModule { code_map, module } = prepare::compile_module(module)?;
+        #[cfg(feature = "source_offsets")]
+        let wasm = parity_wasm::serialize(module.clone())
+            .map_err(|e: parity_wasm::elements::Error| Error::Validation(e.to_string()))?;
+        let (module, code_map) = prepare::compile_module(module)?.into_parts();
+        #[cfg(feature = "source_offsets")]
+        let code_map = prepare::resolve_source_offsets(code_map, &wasm)?;
+
+        Ok(Module::compiled(module, code_map))
+    }
+
+    /// Like [`from_parity_wasm_module`], but only compiles functions when they are first
+    /// called, see [`from_buffer_lazy`].
+    ///
+    /// Only available with the `lazy_compilation` feature.
+    ///
+    /// [`from_parity_wasm_module`]: #method.from_parity_wasm_module
+    /// [`from_buffer_lazy`]: #method.from_buffer_lazy
+    #[cfg(feature = "lazy_compilation")]
+    pub fn from_parity_wasm_module_lazy(
+        module: parity_wasm::elements::Module,
+    ) -> Result<Module, Error> {
+        #[cfg(feature = "source_offsets")]
+        let wasm = parity_wasm::serialize(module.clone())
+            .map_err(|e: parity_wasm::elements::Error| Error::Validation(e.to_string()))?;
+        let (module, code_map) = prepare::compile_module_lazy(
+            module,
+            #[cfg(feature = "source_offsets")]
+            &wasm,
+        )?;
 
         Ok(Module { code_map, module })
     }
@@ -557,6 +998,173 @@ impl Module {
         prepare::deny_floating_point(&self.module).map_err(Into::into)
     }
 
+    /// Returns the number of imports of each kind this module requires.
+    ///
+    /// This allows to check up front whether all required imports can be provided,
+    /// before attempting to instantiate the module.
+    pub fn import_counts(&self) -> ImportCounts {
+        ImportCounts::of(&self.module)
+    }
+
+    /// Returns `true` if this module imports a linear memory.
+    pub fn requires_memory_import(&self) -> bool {
+        self.import_counts().memories > 0
+    }
+
+    /// Returns `true` if this module imports a table.
+    pub fn requires_table_import(&self) -> bool {
+        self.import_counts().tables > 0
+    }
+
+    /// Returns the data segments of this module.
+    ///
+    /// Offsets that are constant are evaluated, offsets which read a global are left as
+    /// they are since the global's value is only known when the module is instantiated.
+    /// This allows e.g. to extract resources embedded into a module without instantiating it.
+    pub fn data_segments(&self) -> impl Iterator<Item = DataSegment<'_>> + '_ {
+        validation::data_segments(&self.module)
+    }
+
+    /// Returns the element segments of this module.
+    ///
+    /// Offsets are evaluated the same way as for [`data_segments`].
+    ///
+    /// [`data_segments`]: #method.data_segments
+    pub fn elem_segments(&self) -> impl Iterator<Item = ElemSegment<'_>> + '_ {
+        validation::elem_segments(&self.module)
+    }
+
+    /// Returns the offsets of the wasm instructions the compiled code of a function was
+    /// generated from, indexed by the position in the compiled code.
+    ///
+    /// The offsets are relative to the start of the code section's contents, which is how
+    /// DWARF debug info for wasm refers to code. Together with [`Caller::position`] this
+    /// maps a call back to the source of the calling function.
+    ///
+    /// `func_index` is an index in the function index space, which includes imported
+    /// functions. Returns `None` if it doesn't refer to a function defined by this module.
+    ///
+    /// Only available with the `source_offsets` feature.
+    ///
+    /// [`Caller::position`]: struct.Caller.html#method.position
+    #[cfg(feature = "source_offsets")]
+    pub fn source_offsets(&self, func_index: u32) -> Option<&[u32]> {
+        let defined_index = func_index.checked_sub(self.import_counts().functions)?;
+        self.code_map
+            .get(defined_index as usize)
+            .map(|code| code.instructions().source_offsets())
+    }
+
+    /// Returns the imports of this module in the order they are declared.
+    pub fn imports(&self) -> impl Iterator<Item = ModuleImport<'_>> + '_ {
+        self.module
+            .import_section()
+            .map(|section| section.entries())
+            .unwrap_or_default()
+            .iter()
+            .map(|entry| ModuleImport {
+                module_name: entry.module(),
+                field_name: entry.field(),
+                kind: match *entry.external() {
+                    External::Function(_) => ExternKind::Func,
+                    External::Table(_) => ExternKind::Table,
+                    External::Memory(_) => ExternKind::Memory,
+                    External::Global(_) => ExternKind::Global,
+                },
+            })
+    }
+
+    /// Returns the exports of this module in the order they are declared.
+    ///
+    /// [`ModuleInstance::exports`] lists them in the same order.
+    ///
+    /// [`ModuleInstance::exports`]: struct.ModuleInstance.html#method.exports
+    pub fn exports(&self) -> impl Iterator<Item = ModuleExport<'_>> + '_ {
+        self.module
+            .export_section()
+            .map(|section| section.entries())
+            .unwrap_or_default()
+            .iter()
+            .map(|entry| {
+                let (kind, index) = match *entry.internal() {
+                    Internal::Function(index) => (ExternKind::Func, index),
+                    Internal::Table(index) => (ExternKind::Table, index),
+                    Internal::Memory(index) => (ExternKind::Memory, index),
+                    Internal::Global(index) => (ExternKind::Global, index),
+                };
+                ModuleExport {
+                    name: entry.field(),
+                    kind,
+                    index,
+                }
+            })
+    }
+
+    /// Returns size and complexity metrics of this module.
+    ///
+    /// The metrics are gathered while validating and compiling the module, so this
+    /// allows to inspect a module without instantiating or running it. Functions of a
+    /// module loaded with [`from_buffer_lazy`] are compiled to gather them.
+    ///
+    /// [`from_buffer_lazy`]: #method.from_buffer_lazy
+    pub fn stats(&self) -> ModuleStats {
+        let mut stats = ModuleStats {
+            functions: self.code_map.len() as u32,
+            ..ModuleStats::default()
+        };
+        for code in self.code_map.iter().map(prepare::FuncCode::instructions) {
+            stats.instructions += code.len();
+            stats.max_stack_height = cmp::max(stats.max_stack_height, code.max_stack_height());
+            stats.max_locals = cmp::max(stats.max_locals, code.locals_count());
+        }
+        stats
+    }
+
+    /// Returns the functions defined by this module that are reachable from the function
+    /// at `func_index` by following calls, including the function itself.
+    ///
+    /// Indices are in the function index space, which includes imported functions, but
+    /// imported functions aren't part of the result. Since the target of `call_indirect` is
+    /// only known at runtime, it is assumed to reach every function of the module's element
+    /// segments. Functions the host places into a table aren't taken into account.
+    ///
+    /// This allows e.g. to find dead code or to instrument only the functions an export
+    /// might execute. Returns `None` if `func_index` doesn't refer to a function defined
+    /// by this module.
+    pub fn reachable_functions(&self, func_index: u32) -> Option<BTreeSet<u32>> {
+        let imported = self.import_counts().functions;
+        let is_defined = |index: u32| (index.wrapping_sub(imported) as usize) < self.code_map.len();
+        if !is_defined(func_index) {
+            return None;
+        }
+
+        let mut reachable = BTreeSet::new();
+        let mut pending = vec![func_index];
+        let mut indirect_targets_added = false;
+        reachable.insert(func_index);
+        while let Some(index) = pending.pop() {
+            let code = self.code_map[(index - imported) as usize].instructions();
+            for instruction in code.iterate_from(0) {
+                let callees: Vec<u32> = match instruction {
+                    isa::Instruction::Call(callee) => vec![callee],
+                    isa::Instruction::CallIndirect(_) if !indirect_targets_added => {
+                        indirect_targets_added = true;
+                        self.elem_segments()
+                            .flat_map(|segment| segment.members.iter().cloned())
+                            .collect()
+                    }
+                    _ => continue,
+                };
+                for callee in callees {
+                    if is_defined(callee) && reachable.insert(callee) {
+                        pending.push(callee);
+                    }
+                }
+            }
+        }
+        Some(reachable)
+    }
+
     /// Create `Module` from a given buffer.
     ///
     /// This function will deserialize wasm module from a given module,
@@ -586,14 +1194,97 @@ impl Module {
     pub fn from_buffer<B: AsRef<[u8]>>(buffer: B) -> Result<Module, Error> {
         let module = parity_wasm::elements::deserialize_buffer(buffer.as_ref())
             .map_err(|e: parity_wasm::elements::Error| Error::Validation(e.to_string()))?;
-        Module::from_parity_wasm_module(module)
+        let (module, code_map) = prepare::compile_module(module)?.into_parts();
+        // Use the offsets in the original binary, its encoding might differ from the
+        // one parity-wasm would produce.
+        #[cfg(feature = "source_offsets")]
+        let code_map = prepare::resolve_source_offsets(code_map, buffer.as_ref())?;
+
+        Ok(Module::compiled(module, code_map))
+    }
+
+    /// Create `Module` from a given buffer, deferring the compilation of every function
+    /// until it is first called.
+    ///
+    /// This speeds up loading modules with many functions of which only a few are called,
+    /// and saves the memory their compiled code would take. The compiled code of a function
+    /// is cached and shared by all instances of the module, also across threads.
+    ///
+    /// All function bodies are still validated up front, so a module is rejected the same
+    /// way as by [`from_buffer`] even if its invalid functions are never called. Parsing
+    /// and validation take most of the time needed to load a module, so the time saved is
+    /// moderate, while the memory saved is proportional to the code that never runs.
+    ///
+    /// Only available with the `lazy_compilation` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` in the same cases as [`from_buffer`].
+    ///
+    /// [`from_buffer`]: #method.from_buffer
+    #[cfg(feature = "lazy_compilation")]
+    pub fn from_buffer_lazy<B: AsRef<[u8]>>(buffer: B) -> Result<Module, Error> {
+        let module = parity_wasm::elements::deserialize_buffer(buffer.as_ref())
+            .map_err(|e: parity_wasm::elements::Error| Error::Validation(e.to_string()))?;
+        let (module, code_map) = prepare::compile_module_lazy(
+            module,
+            #[cfg(feature = "source_offsets")]
+            buffer.as_ref(),
+        )?;
+
+        Ok(Module { code_map, module })
+    }
+
+    /// Create `Module` from the wasm text format.
+    ///
+    /// Parses, validates and prepares the module for instantiation in one go, which is
+    /// mostly convenient for tests and examples. Requires the `wabt` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Parse`] if `source` is not valid text format and otherwise the same
+    /// errors as [`from_buffer`], e.g. [`Error::Validation`] for invalid modules.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use wasmi::{ImportsBuilder, Module, ModuleInstance, NopExternals, RuntimeValue};
+    ///
+    /// let module = Module::from_wat(r#"(module (func (export "answer") (result i32) (i32.const 42)))"#)
+    ///     .expect("failed to load module");
+    /// let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+    ///     .expect("failed to instantiate module")
+    ///     .assert_no_start();
+    /// assert_eq!(
+    ///     instance.invoke_export("answer", &[], &mut NopExternals).unwrap(),
+    ///     Some(RuntimeValue::I32(42)),
+    /// );
+    /// ```
+    ///
+    /// [`Error::Parse`]: enum.Error.html#variant.Parse
+    /// [`Error::Validation`]: enum.Error.html#variant.Validation
+    /// [`from_buffer`]: #method.from_buffer
+    #[cfg(feature = "wabt")]
+    pub fn from_wat(source: &str) -> Result<Module, Error> {
+        let wasm = wabt::wat2wasm(source).map_err(|e| Error::Parse(format!("{:?}", e)))?;
+        Module::from_buffer(wasm)
     }
 
     pub(crate) fn module(&self) -> &parity_wasm::elements::Module {
         &self.module
     }
 
-    pub(crate) fn code(&self) -> &Vec<isa::Instructions> {
+    pub(crate) fn code(&self) -> &[prepare::FuncCode] {
         &self.code_map
     }
+
+    fn compiled(module: parity_wasm::elements::Module, code_map: Vec<isa::Instructions>) -> Module {
+        Module {
+            code_map: code_map
+                .into_iter()
+                .map(prepare::FuncCode::Compiled)
+                .collect(),
+            module: Arc::new(module),
+        }
+    }
 }
@@ -135,6 +135,7 @@ impl ByteBuf {
         Ok(Self { mmap })
     }
 
+    /// Resizes the buffer to `new_len` bytes, zero-filling any bytes past the previous length.
     pub fn realloc(&mut self, new_len: usize) -> Result<(), String> {
         let new_mmap = if new_len == 0 {
             None
@@ -196,4 +197,18 @@ mod tests {
         let mut byte_buf = ByteBuf::new(PAGE_SIZE * 3).unwrap();
         byte_buf.realloc(PAGE_SIZE * 2).unwrap();
     }
+
+    #[test]
+    fn byte_buf_realloc_zero_fills() {
+        let mut byte_buf = ByteBuf::new(PAGE_SIZE).unwrap();
+        byte_buf.as_slice_mut().fill(0xAA);
+
+        // Shrinking and growing again must not resurrect the old contents.
+        byte_buf.realloc(PAGE_SIZE / 2).unwrap();
+        byte_buf.realloc(PAGE_SIZE * 3).unwrap();
+
+        let (old, new) = byte_buf.as_slice().split_at(PAGE_SIZE / 2);
+        assert!(old.iter().all(|&b| b == 0xAA));
+        assert!(new.iter().all(|&b| b == 0));
+    }
 }
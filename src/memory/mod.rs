@@ -1,7 +1,11 @@
 use crate::memory_units::{Bytes, Pages, RoundUpTo};
 use crate::value::LittleEndianConvert;
-use crate::Error;
-use alloc::{rc::Rc, string::ToString, vec::Vec};
+use crate::{Error, Trap, TrapKind};
+use alloc::{
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
 use core::{
     cell::{Cell, Ref, RefCell, RefMut},
     cmp, fmt,
@@ -10,6 +14,9 @@ use core::{
 };
 use parity_wasm::elements::ResizableLimits;
 
+// Every `ByteBuf` backend must return zeroed memory from `new` and zero-fill the bytes
+// added by `realloc`. `MemoryInstance::grow` relies on this to implement `memory.grow`
+// without exposing stale data.
 #[cfg(all(unix, not(feature = "vec_memory")))]
 #[path = "mmap_bytebuf.rs"]
 mod bytebuf;
@@ -27,6 +34,15 @@ use self::bytebuf::ByteBuf;
 /// [`MemoryInstance`]: struct.MemoryInstance.html
 pub const LINEAR_MEMORY_PAGE_SIZE: Bytes = Bytes(65536);
 
+/// Converts `pages` to bytes without overflowing on hosts with a 32-bit `usize`.
+fn byte_size(pages: Pages) -> Result<Bytes, Error> {
+    pages
+        .0
+        .checked_mul(LINEAR_MEMORY_PAGE_SIZE.0)
+        .map(Bytes)
+        .ok_or_else(|| Error::Memory(format!("{} pages can't be addressed on this host", pages.0)))
+}
+
 /// Reference to a memory (See [`MemoryInstance`] for details).
 ///
 /// This reference has a reference-counting semantics.
@@ -43,6 +59,9 @@ impl ::core::ops::Deref for MemoryRef {
     }
 }
 
+/// Hook called with the new size of a memory after it has grown.
+type GrowHook = Rc<dyn Fn(Pages)>;
+
 /// Runtime representation of a linear memory (or `memory` for short).
 ///
 /// A memory is a contiguous, mutable array of raw bytes. Wasm code can load and store values
@@ -64,6 +83,19 @@ pub struct MemoryInstance {
     initial: Pages,
     current_size: Cell<usize>,
     maximum: Option<Pages>,
+    /// Whether the buffer has been taken out by [`MemoryInstance::take_backing`].
+    detached: Cell<bool>,
+    /// Whether the buffer is overwritten with zeros when this memory is dropped.
+    zero_on_drop: Cell<bool>,
+    /// Number of upcoming calls to [`MemoryInstance::grow`] that are forced to fail.
+    #[cfg(feature = "grow_failure_injection")]
+    forced_grow_failures: Cell<u32>,
+    /// Called with the new size after every successful [`MemoryInstance::grow`].
+    grow_hook: RefCell<Option<GrowHook>>,
+    /// Whether wasm code traps when it writes to or grows this memory.
+    read_only: Cell<bool>,
+    /// Whether this memory is shared, see [`MemoryInstance::alloc_shared`].
+    shared: bool,
 }
 
 impl fmt::Debug for MemoryInstance {
@@ -116,39 +148,55 @@ impl MemoryInstance {
     ///
     /// [`LINEAR_MEMORY_PAGE_SIZE`]: constant.LINEAR_MEMORY_PAGE_SIZE.html
     pub fn alloc(initial: Pages, maximum: Option<Pages>) -> Result<MemoryRef, Error> {
-        {
-            use core::convert::TryInto;
-            let initial_u32: u32 = initial.0.try_into().map_err(|_| {
-                Error::Memory(format!("initial ({}) can't be coerced to u32", initial.0))
-            })?;
-            let maximum_u32: Option<u32> = maximum
-                .map(|maximum_pages| {
-                    maximum_pages.0.try_into().map_err(|_| {
-                        Error::Memory(format!(
-                            "maximum ({}) can't be coerced to u32",
-                            maximum_pages.0
-                        ))
-                    })
-                })
-                .transpose()?;
-            validation::validate_memory(initial_u32, maximum_u32).map_err(Error::Memory)?;
-        }
-
+        check_limits(initial, maximum)?;
         let memory = MemoryInstance::new(initial, maximum)?;
         Ok(MemoryRef(Rc::new(memory)))
     }
 
+    /// Allocate a shared memory instance, as declared by `(memory <initial> <maximum> shared)`.
+    ///
+    /// Shared memories are meant to be accessed by several threads at once, which wasmi
+    /// doesn't support. They allow running code of the threads proposal within a single
+    /// thread: `memory.atomic.notify` never finds waiting threads to wake and returns 0, and
+    /// `memory.atomic.wait32/64` returns 1 ("not-equal") right away if the memory doesn't
+    /// hold the expected value. Otherwise no other thread could wake it, so it returns 2
+    /// ("timed-out") right away if a timeout is given and traps with
+    /// [`TrapKind::WaitWouldBlockForever`] if not.
+    ///
+    /// Only available with the `atomics` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` in the same cases as [`alloc`].
+    ///
+    /// [`TrapKind::WaitWouldBlockForever`]: enum.TrapKind.html#variant.WaitWouldBlockForever
+    /// [`alloc`]: #method.alloc
+    #[cfg(feature = "atomics")]
+    pub fn alloc_shared(initial: Pages, maximum: Pages) -> Result<MemoryRef, Error> {
+        check_limits(initial, Some(maximum))?;
+        let mut memory = MemoryInstance::new(initial, Some(maximum))?;
+        memory.shared = true;
+        Ok(MemoryRef(Rc::new(memory)))
+    }
+
     /// Create new linear memory instance.
     fn new(initial: Pages, maximum: Option<Pages>) -> Result<Self, Error> {
         let limits = ResizableLimits::new(initial.0 as u32, maximum.map(|p| p.0 as u32));
 
-        let initial_size: Bytes = initial.into();
+        let initial_size = byte_size(initial)?;
         Ok(MemoryInstance {
             limits,
             buffer: RefCell::new(ByteBuf::new(initial_size.0).map_err(Error::Memory)?),
             initial,
             current_size: Cell::new(initial_size.0),
             maximum,
+            detached: Cell::new(false),
+            zero_on_drop: Cell::new(false),
+            #[cfg(feature = "grow_failure_injection")]
+            forced_grow_failures: Cell::new(0),
+            grow_hook: RefCell::new(None),
+            read_only: Cell::new(false),
+            shared: false,
         })
     }
 
@@ -190,7 +238,7 @@ impl MemoryInstance {
     /// );
     /// ```
     pub fn current_size(&self) -> Pages {
-        Bytes(self.buffer.borrow().len()).round_up_to()
+        Bytes(self.current_size.get()).round_up_to()
     }
 
     /// Get value from memory at given offset.
@@ -217,6 +265,42 @@ impl MemoryInstance {
         Ok(buffer.as_slice_mut()[region.range()].to_vec())
     }
 
+    /// Copy the entire contents of the memory, i.e. [`current_size`] pages.
+    ///
+    /// Useful to inspect guest state after a run or to take a checkpoint that can later be
+    /// restored with [`load`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the memory is detached.
+    ///
+    /// [`current_size`]: #method.current_size
+    /// [`load`]: #method.load
+    pub fn to_vec(&self) -> Result<Vec<u8>, Error> {
+        self.get(0, self.current_size.get())
+    }
+
+    /// Overwrite the entire contents of the memory with `contents`, e.g. a snapshot taken by
+    /// [`to_vec`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the memory is detached or the length of `contents` doesn't match
+    /// the current size of the memory. Memory can't shrink, so a snapshot can't be loaded
+    /// once the memory has grown past it.
+    ///
+    /// [`to_vec`]: #method.to_vec
+    pub fn load(&self, contents: &[u8]) -> Result<(), Error> {
+        if contents.len() != self.current_size.get() {
+            return Err(Error::Memory(format!(
+                "trying to load {} bytes into memory of {} bytes",
+                contents.len(),
+                self.current_size.get()
+            )));
+        }
+        self.set(0, contents)
+    }
+
     /// Copy data from given offset in the memory into `target` slice.
     ///
     /// # Errors
@@ -231,6 +315,69 @@ impl MemoryInstance {
         Ok(())
     }
 
+    /// Copy a UTF-8 string of `len` bytes from memory at given offset.
+    ///
+    /// This is meant for host functions receiving strings from wasm code
+    /// as a pointer and a length.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`MemoryAccessOutOfBounds`] trap if the specified region is out of bounds
+    /// (or [`MemoryDetached`] if the memory is detached) and an [`InvalidUtf8`] trap
+    /// if it doesn't contain valid UTF-8.
+    ///
+    /// [`MemoryAccessOutOfBounds`]: enum.TrapKind.html#variant.MemoryAccessOutOfBounds
+    /// [`MemoryDetached`]: enum.TrapKind.html#variant.MemoryDetached
+    /// [`InvalidUtf8`]: enum.TrapKind.html#variant.InvalidUtf8
+    pub fn read_str(&self, offset: u32, len: u32) -> Result<String, Trap> {
+        let mut buffer = self.buffer.borrow_mut();
+        let region = self
+            .checked_region(&mut buffer, offset as usize, len as usize)
+            .map_err(|_| self.access_trap())?;
+
+        core::str::from_utf8(&buffer.as_slice_mut()[region.range()])
+            .map(ToString::to_string)
+            .map_err(|_| TrapKind::InvalidUtf8.into())
+    }
+
+    /// Compare two regions of `len` bytes starting at `a_ptr` and `b_ptr` for equality.
+    ///
+    /// Every byte of both regions is read (through volatile reads) and the differences
+    /// are accumulated without branching, so the comparison doesn't stop at the first
+    /// mismatch. This is a best-effort measure: neither the compiler nor the hardware
+    /// guarantees constant-time execution.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`MemoryAccessOutOfBounds`] trap if either region is out of bounds
+    /// (or [`MemoryDetached`] if the memory is detached).
+    ///
+    /// [`MemoryAccessOutOfBounds`]: enum.TrapKind.html#variant.MemoryAccessOutOfBounds
+    /// [`MemoryDetached`]: enum.TrapKind.html#variant.MemoryDetached
+    pub fn ct_eq(&self, a_ptr: u32, b_ptr: u32, len: u32) -> Result<bool, Trap> {
+        let buffer = self.buffer.borrow();
+        let (a_region, b_region) = self
+            .checked_region_pair(
+                &buffer,
+                a_ptr as usize,
+                len as usize,
+                b_ptr as usize,
+                len as usize,
+            )
+            .map_err(|_| self.access_trap())?;
+
+        let bytes = buffer.as_slice();
+        let mut diff = 0u8;
+        for (a, b) in bytes[a_region.range()].iter().zip(&bytes[b_region.range()]) {
+            // Volatile reads can't be elided or reordered, which keeps the
+            // optimizer from turning the loop into an early-exit comparison.
+            let (a, b) = unsafe { (core::ptr::read_volatile(a), core::ptr::read_volatile(b)) };
+            diff |= a ^ b;
+        }
+
+        Ok(unsafe { core::ptr::read_volatile(&diff) } == 0)
+    }
+
     /// Copy data in the memory at given offset.
     pub fn set(&self, offset: u32, value: &[u8]) -> Result<(), Error> {
         let mut buffer = self.buffer.borrow_mut();
@@ -256,15 +403,30 @@ impl MemoryInstance {
     /// Increases the size of the linear memory by given number of pages.
     /// Returns previous memory size if succeeds.
     ///
+    /// The newly added pages are always zeroed.
+    ///
     /// # Errors
     ///
-    /// Returns `Err` if attempted to allocate more memory than permited by the limit.
+    /// Returns `Err` if attempted to allocate more memory than permited by the limit,
+    /// or if the new size in bytes doesn't fit into `usize` on this host. The latter
+    /// can only happen on 32-bit hosts when growing to the full 4GiB.
     pub fn grow(&self, additional: Pages) -> Result<Pages, Error> {
+        if self.detached.get() {
+            return Err(Error::Memory("memory is detached".to_string()));
+        }
         let size_before_grow: Pages = self.current_size();
 
         if additional == Pages(0) {
             return Ok(size_before_grow);
         }
+        #[cfg(feature = "grow_failure_injection")]
+        {
+            let forced_failures = self.forced_grow_failures.get();
+            if forced_failures > 0 {
+                self.forced_grow_failures.set(forced_failures - 1);
+                return Err(Error::Memory("simulated grow failure".to_string()));
+            }
+        }
         if additional > Pages(65536) {
             return Err(Error::Memory(
                 "Trying to grow memory by more than 65536 pages".to_string(),
@@ -282,7 +444,7 @@ impl MemoryInstance {
             )));
         }
 
-        let new_buffer_length: Bytes = new_size.into();
+        let new_buffer_length = byte_size(new_size)?;
         self.buffer
             .borrow_mut()
             .realloc(new_buffer_length.0)
@@ -290,6 +452,12 @@ impl MemoryInstance {
 
         self.current_size.set(new_buffer_length.0);
 
+        // The hook is cloned out, so that it may grow this memory itself.
+        let grow_hook = self.grow_hook.borrow().clone();
+        if let Some(grow_hook) = grow_hook {
+            grow_hook(new_size);
+        }
+
         Ok(size_before_grow)
     }
 
@@ -307,6 +475,9 @@ impl MemoryInstance {
         })?;
 
         if end > buffer.len() {
+            if self.detached.get() {
+                return Err(Error::Memory("memory is detached".to_string()));
+            }
             return Err(Error::Memory(format!(
                 "trying to access region [{}..{}] in memory [0..{}]",
                 offset,
@@ -318,9 +489,15 @@ impl MemoryInstance {
         Ok(CheckedRegion { offset, size })
     }
 
+    /// Returns `Err` if the region of `len` bytes at `offset` is out of bounds.
+    pub(crate) fn check_region(&self, offset: usize, len: usize) -> Result<(), Error> {
+        self.checked_region(&mut self.buffer.borrow_mut(), offset, len)
+            .map(|_| ())
+    }
+
     fn checked_region_pair(
         &self,
-        buffer: &mut ByteBuf,
+        buffer: &ByteBuf,
         offset1: usize,
         size1: usize,
         offset2: usize,
@@ -340,6 +517,10 @@ impl MemoryInstance {
             ))
         })?;
 
+        if self.detached.get() && (end1 > buffer.len() || end2 > buffer.len()) {
+            return Err(Error::Memory("memory is detached".to_string()));
+        }
+
         if end1 > buffer.len() {
             return Err(Error::Memory(format!(
                 "trying to access region [{}..{}] in memory [0..{}]",
@@ -381,7 +562,7 @@ impl MemoryInstance {
         let mut buffer = self.buffer.borrow_mut();
 
         let (read_region, write_region) =
-            self.checked_region_pair(&mut buffer, src_offset, len, dst_offset, len)?;
+            self.checked_region_pair(&buffer, src_offset, len, dst_offset, len)?;
 
         unsafe {
             ::core::ptr::copy(
@@ -414,7 +595,7 @@ impl MemoryInstance {
         let mut buffer = self.buffer.borrow_mut();
 
         let (read_region, write_region) =
-            self.checked_region_pair(&mut buffer, src_offset, len, dst_offset, len)?;
+            self.checked_region_pair(&buffer, src_offset, len, dst_offset, len)?;
 
         if read_region.intersects(&write_region) {
             return Err(Error::Memory(
@@ -569,15 +750,266 @@ impl MemoryInstance {
 
         Buffer(self.buffer.borrow_mut())
     }
+
+    /// Takes the buffer backing this memory out of it.
+    ///
+    /// Until the buffer is given back with [`restore_backing`] all accesses to this memory
+    /// fail, i.e. wasm loads and stores trap with [`TrapKind::MemoryDetached`] and growing
+    /// the memory fails. The [current size][`current_size`] is still reported.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the memory is already detached.
+    ///
+    /// [`restore_backing`]: #method.restore_backing
+    /// [`current_size`]: #method.current_size
+    /// [`TrapKind::MemoryDetached`]: enum.TrapKind.html#variant.MemoryDetached
+    pub fn take_backing(&self) -> Result<DetachedMemory, Error> {
+        if self.detached.get() {
+            return Err(Error::Memory("memory is already detached".to_string()));
+        }
+        let empty = ByteBuf::new(0).map_err(Error::Memory)?;
+        let buffer = core::mem::replace(&mut *self.buffer.borrow_mut(), empty);
+        self.detached.set(true);
+        Ok(DetachedMemory { buffer })
+    }
+
+    /// Gives a buffer taken out by [`take_backing`] back to this memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the memory isn't detached or the size of `backing`
+    /// doesn't match the current size of this memory.
+    ///
+    /// [`take_backing`]: #method.take_backing
+    pub fn restore_backing(&self, backing: DetachedMemory) -> Result<(), Error> {
+        if !self.detached.get() {
+            return Err(Error::Memory("memory is not detached".to_string()));
+        }
+        if backing.buffer.len() != self.current_size.get() {
+            return Err(Error::Memory(format!(
+                "trying to restore a buffer of {} bytes into memory of {} bytes",
+                backing.buffer.len(),
+                self.current_size.get()
+            )));
+        }
+        *self.buffer.borrow_mut() = backing.buffer;
+        self.detached.set(false);
+        Ok(())
+    }
+
+    /// Returns `true` if the buffer of this memory was taken out by [`take_backing`].
+    ///
+    /// [`take_backing`]: #method.take_backing
+    pub fn is_detached(&self) -> bool {
+        self.detached.get()
+    }
+
+    /// Sets whether the contents of this memory are overwritten with zeros when it is dropped.
+    ///
+    /// By default the buffer is released as is, so data that wasm code left in it, such as
+    /// secrets it handled, might linger in memory that is later reused by the process.
+    /// With this option set, the buffer is wiped using volatile writes which the compiler
+    /// can't optimize away.
+    ///
+    /// A buffer that is [taken out][`take_backing`] at the time of drop isn't wiped.
+    ///
+    /// [`take_backing`]: #method.take_backing
+    pub fn set_zero_on_drop(&self, zero_on_drop: bool) {
+        self.zero_on_drop.set(zero_on_drop);
+    }
+
+    /// Returns `true` if the contents of this memory are overwritten with zeros when it is dropped.
+    ///
+    /// See [`set_zero_on_drop`].
+    ///
+    /// [`set_zero_on_drop`]: #method.set_zero_on_drop
+    pub fn zero_on_drop(&self) -> bool {
+        self.zero_on_drop.get()
+    }
+
+    /// Forces the next `count` calls to [`grow`] to fail, regardless of the limits.
+    ///
+    /// A failed grow leaves the memory unchanged and makes `memory.grow` return -1 to
+    /// wasm code. This allows to test how wasm code handles running out of memory without
+    /// actually exhausting it. Grows by zero pages always succeed and don't count towards
+    /// `count`. Calling this again replaces the previous count.
+    ///
+    /// [`grow`]: #method.grow
+    #[cfg(feature = "grow_failure_injection")]
+    pub fn fail_next_grows(&self, count: u32) {
+        self.forced_grow_failures.set(count);
+    }
+
+    /// Sets a hook called with the new total number of pages after this memory has grown.
+    ///
+    /// The hook is called for every successful [`grow`], including `memory.grow`
+    /// executed by wasm code, which allows to keep track of memory consumption without
+    /// polling. It isn't called for failed grows or grows by zero pages. Calling this
+    /// again replaces the previous hook.
+    ///
+    /// [`grow`]: #method.grow
+    pub fn set_grow_hook<F: Fn(Pages) + 'static>(&self, hook: F) {
+        *self.grow_hook.borrow_mut() = Some(Rc::new(hook));
+    }
+
+    /// Removes the hook set by [`set_grow_hook`].
+    ///
+    /// [`set_grow_hook`]: #method.set_grow_hook
+    pub fn clear_grow_hook(&self) {
+        *self.grow_hook.borrow_mut() = None;
+    }
+
+    /// Sets whether this memory is read-only for wasm code.
+    ///
+    /// While it is read-only, wasm code that stores to this memory, copies into it or grows
+    /// it traps with [`TrapKind::MemoryReadOnly`]. Loads are unaffected. This allows to run
+    /// computations that must not change the state of an instance without copying its
+    /// memory first.
+    ///
+    /// The methods of this type still modify the memory, so the host can keep preparing it.
+    ///
+    /// [`TrapKind::MemoryReadOnly`]: enum.TrapKind.html#variant.MemoryReadOnly
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.set(read_only);
+    }
+
+    /// Returns `true` if this memory is read-only for wasm code.
+    ///
+    /// See [`set_read_only`].
+    ///
+    /// [`set_read_only`]: #method.set_read_only
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.get()
+    }
+
+    /// Returns `true` if this memory is shared, see [`alloc_shared`].
+    ///
+    /// [`alloc_shared`]: #method.alloc_shared
+    pub fn is_shared(&self) -> bool {
+        self.shared
+    }
+
+    /// Returns the trap for a failed access to this memory.
+    pub(crate) fn access_trap(&self) -> TrapKind {
+        if self.detached.get() {
+            TrapKind::MemoryDetached
+        } else {
+            TrapKind::MemoryAccessOutOfBounds
+        }
+    }
+}
+
+/// Checks that a memory can be allocated with the given limits.
+fn check_limits(initial: Pages, maximum: Option<Pages>) -> Result<(), Error> {
+    use core::convert::TryInto;
+    let initial_u32: u32 = initial
+        .0
+        .try_into()
+        .map_err(|_| Error::Memory(format!("initial ({}) can't be coerced to u32", initial.0)))?;
+    let maximum_u32: Option<u32> = maximum
+        .map(|maximum_pages| {
+            maximum_pages.0.try_into().map_err(|_| {
+                Error::Memory(format!(
+                    "maximum ({}) can't be coerced to u32",
+                    maximum_pages.0
+                ))
+            })
+        })
+        .transpose()?;
+    validation::validate_memory(initial_u32, maximum_u32).map_err(Error::Memory)?;
+    Ok(())
+}
+
+impl MemoryInstance {
+    /// Wipes the buffer if [`zero_on_drop`] is set.
+    ///
+    /// [`zero_on_drop`]: #method.zero_on_drop
+    fn wipe_if_zero_on_drop(&mut self) {
+        if self.zero_on_drop.get() {
+            wipe(self.buffer.get_mut().as_slice_mut());
+        }
+    }
+}
+
+impl Drop for MemoryInstance {
+    fn drop(&mut self) {
+        self.wipe_if_zero_on_drop();
+    }
+}
+
+/// Overwrites `buf` with zeros in a way that can't be elided by the compiler.
+fn wipe(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        unsafe {
+            // Safety Proof:
+            // `byte` comes from a mutable reference and thus is valid and aligned for writes.
+            core::ptr::write_volatile(byte, 0);
+        }
+    }
+    core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+/// Buffer of a linear memory taken out of a [`MemoryInstance`].
+///
+/// See [`MemoryInstance::take_backing`].
+///
+/// [`MemoryInstance`]: struct.MemoryInstance.html
+/// [`MemoryInstance::take_backing`]: struct.MemoryInstance.html#method.take_backing
+pub struct DetachedMemory {
+    buffer: ByteBuf,
+}
+
+impl AsRef<[u8]> for DetachedMemory {
+    fn as_ref(&self) -> &[u8] {
+        self.buffer.as_slice()
+    }
+}
+
+impl AsMut<[u8]> for DetachedMemory {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.buffer.as_slice_mut()
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use super::{MemoryInstance, MemoryRef, LINEAR_MEMORY_PAGE_SIZE};
+    use super::{byte_size, MemoryInstance, MemoryRef, LINEAR_MEMORY_PAGE_SIZE};
     use crate::memory_units::Pages;
     use crate::Error;
+    use crate::TrapKind;
     use alloc::rc::Rc;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn snapshot_and_load() {
+        let mem = MemoryInstance::alloc(Pages(1), None).unwrap();
+        let pattern: Vec<u8> = (0..=255).collect();
+        mem.set(100, &pattern).unwrap();
+
+        let snapshot = mem.to_vec().unwrap();
+        assert_eq!(snapshot.len(), LINEAR_MEMORY_PAGE_SIZE.0);
+        assert_eq!(&snapshot[100..356], &pattern[..]);
+
+        mem.clear(0, 0xAB, LINEAR_MEMORY_PAGE_SIZE.0).unwrap();
+        assert_eq!(&snapshot[100..356], &pattern[..]);
+        assert!(snapshot[..100].iter().all(|&byte| byte == 0));
+
+        mem.load(&snapshot).unwrap();
+        assert_eq!(mem.to_vec().unwrap(), snapshot);
+
+        // The snapshot always covers the current size.
+        mem.grow(Pages(1)).unwrap();
+        let grown = mem.to_vec().unwrap();
+        assert_eq!(grown.len(), 2 * LINEAR_MEMORY_PAGE_SIZE.0);
+        assert_eq!(&grown[..LINEAR_MEMORY_PAGE_SIZE.0], &snapshot[..]);
+        assert!(mem.load(&snapshot).is_err());
+
+        let _backing = mem.take_backing().unwrap();
+        assert!(mem.to_vec().is_err());
+        assert!(mem.load(&grown).is_err());
+    }
 
     #[test]
     fn alloc() {
@@ -623,6 +1055,41 @@ mod tests {
         mem
     }
 
+    #[test]
+    fn grow_by_u32_max_pages() {
+        let mem = MemoryInstance::alloc(Pages(1), None).unwrap();
+        assert!(mem.grow(Pages(u32::MAX as usize)).is_err());
+        assert_eq!(mem.current_size(), Pages(1));
+
+        // The full 4GiB can only be addressed with a 64-bit `usize`.
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(byte_size(Pages(65536)).unwrap().0, 1 << 32);
+        #[cfg(target_pointer_width = "32")]
+        assert!(byte_size(Pages(65536)).is_err());
+        assert_eq!(byte_size(Pages(65535)).unwrap().0, 65535 * 65536);
+    }
+
+    #[test]
+    fn grow_zero_fills_new_pages() {
+        let page_size = LINEAR_MEMORY_PAGE_SIZE.0;
+        let mem = MemoryInstance::alloc(Pages(1), Some(Pages(3))).unwrap();
+        mem.set(0, &vec![0xAA; page_size]).unwrap();
+
+        assert_eq!(mem.grow(Pages(2)).unwrap(), Pages(1));
+        assert_eq!(mem.current_size(), Pages(3));
+
+        // The boundary between the old and the new pages.
+        let boundary = mem.get(page_size as u32 - 4, 8).unwrap();
+        assert_eq!(boundary, &[0xAA, 0xAA, 0xAA, 0xAA, 0, 0, 0, 0]);
+
+        let new_pages = mem.get(page_size as u32, 2 * page_size).unwrap();
+        assert!(new_pages.iter().all(|&b| b == 0));
+
+        // The old contents are preserved.
+        let old_page = mem.get(0, page_size).unwrap();
+        assert!(old_page.iter().all(|&b| b == 0xAA));
+    }
+
     #[test]
     fn copy_overlaps_1() {
         let mem = create_memory(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
@@ -741,6 +1208,76 @@ mod tests {
         assert_eq!(data, [17, 129]);
     }
 
+    #[test]
+    fn read_str() {
+        let mem = MemoryInstance::new(Pages(1), None).unwrap();
+        mem.set(10, "hello, wörld".as_bytes())
+            .expect("memory set should not fail");
+        mem.set(100, &[0x66, 0x6f, 0xff])
+            .expect("memory set should not fail");
+
+        assert_eq!(mem.read_str(10, 13).unwrap(), "hello, wörld");
+        assert_eq!(mem.read_str(10, 0).unwrap(), "");
+
+        // Cuts `ö` in half.
+        let trap = mem.read_str(10, 9).unwrap_err();
+        assert!(matches!(trap.kind(), TrapKind::InvalidUtf8));
+        let trap = mem.read_str(100, 3).unwrap_err();
+        assert!(matches!(trap.kind(), TrapKind::InvalidUtf8));
+
+        let trap = mem.read_str(65530, 10).unwrap_err();
+        assert!(matches!(trap.kind(), TrapKind::MemoryAccessOutOfBounds));
+        let trap = mem.read_str(u32::MAX, 1).unwrap_err();
+        assert!(matches!(trap.kind(), TrapKind::MemoryAccessOutOfBounds));
+    }
+
+    #[test]
+    fn ct_eq() {
+        let mem = MemoryInstance::new(Pages(1), None).unwrap();
+        mem.set(10, b"secret token").unwrap();
+        mem.set(100, b"secret token").unwrap();
+        mem.set(200, b"secret tokeN").unwrap();
+
+        assert!(mem.ct_eq(10, 100, 12).unwrap());
+        assert!(!mem.ct_eq(10, 200, 12).unwrap());
+        assert!(mem.ct_eq(10, 200, 11).unwrap());
+        assert!(mem.ct_eq(10, 200, 0).unwrap());
+
+        let trap = mem.ct_eq(10, 65530, 12).unwrap_err();
+        assert!(matches!(trap.kind(), TrapKind::MemoryAccessOutOfBounds));
+        let trap = mem.ct_eq(65530, 10, 12).unwrap_err();
+        assert!(matches!(trap.kind(), TrapKind::MemoryAccessOutOfBounds));
+        let trap = mem.ct_eq(u32::MAX, 10, 2).unwrap_err();
+        assert!(matches!(trap.kind(), TrapKind::MemoryAccessOutOfBounds));
+    }
+
+    #[test]
+    fn take_and_restore_backing() {
+        let mem = MemoryInstance::new(Pages(1), None).unwrap();
+        mem.set(10, &[1, 2, 3]).unwrap();
+
+        let mut backing = mem.take_backing().unwrap();
+        assert!(mem.is_detached());
+        assert_eq!(&backing.as_ref()[10..13], &[1, 2, 3]);
+        backing.as_mut()[13] = 4;
+
+        // The memory keeps its size, but can't be accessed or grown.
+        assert_eq!(mem.current_size(), Pages(1));
+        assert!(mem.get(10, 1).is_err());
+        assert!(mem.grow(Pages(1)).is_err());
+        assert!(mem.take_backing().is_err());
+
+        // Only a buffer of the right size can be restored.
+        let other = MemoryInstance::new(Pages(2), None).unwrap();
+        assert!(mem.restore_backing(other.take_backing().unwrap()).is_err());
+
+        mem.restore_backing(backing).unwrap();
+        assert!(!mem.is_detached());
+        assert_eq!(mem.get(10, 4).unwrap(), &[1, 2, 3, 4]);
+        assert_eq!(mem.grow(Pages(1)).unwrap(), Pages(1));
+        assert_eq!(mem.get(10, 4).unwrap(), &[1, 2, 3, 4]);
+    }
+
     #[test]
     fn zero_copy() {
         let mem = MemoryInstance::alloc(Pages(1), None).unwrap();
@@ -772,4 +1309,25 @@ mod tests {
             let _ = mem_inner.set(0, &[11, 12, 13]);
         });
     }
+
+    #[test]
+    fn zero_on_drop() {
+        let mut mem = MemoryInstance::new(Pages(1), None).unwrap();
+        mem.set(0, b"secret").unwrap();
+        assert!(!mem.zero_on_drop());
+        mem.wipe_if_zero_on_drop();
+        assert_eq!(mem.get(0, 6).unwrap(), b"secret");
+
+        mem.set_zero_on_drop(true);
+        assert!(mem.zero_on_drop());
+        mem.wipe_if_zero_on_drop();
+        assert_eq!(mem.get(0, 6).unwrap(), [0; 6]);
+    }
+
+    #[test]
+    fn wipe() {
+        let mut buf = *b"secret";
+        super::wipe(&mut buf);
+        assert_eq!(buf, [0; 6]);
+    }
 }
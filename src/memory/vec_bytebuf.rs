@@ -12,6 +12,7 @@ impl ByteBuf {
         Ok(Self { buf })
     }
 
+    /// Resizes the buffer to `new_len` bytes, zero-filling any bytes past the previous length.
     pub fn realloc(&mut self, new_len: usize) -> Result<(), String> {
         self.buf.resize(new_len, 0u8);
         Ok(())
@@ -1,13 +1,16 @@
 use crate::func::{FuncBody, FuncInstance, FuncRef};
 use crate::global::{GlobalInstance, GlobalRef};
-use crate::host::Externals;
+use crate::host::{Externals, NopExternals, SharedExternals};
 use crate::imports::ImportResolver;
 use crate::memory::MemoryRef;
-use crate::memory_units::Pages;
+use crate::memory_units::{Bytes, Pages};
 use crate::runner::StackRecycler;
-use crate::table::TableRef;
+use crate::table::{TableElementType, TableRef};
 use crate::types::{GlobalDescriptor, MemoryDescriptor, TableDescriptor};
-use crate::{Error, MemoryInstance, Module, RuntimeValue, Signature, TableInstance, Trap};
+use crate::{
+    Error, InstantiationError, MemoryInstance, Module, RuntimeValue, Signature, TableInstance,
+    Trap, ValueType,
+};
 use alloc::collections::BTreeMap;
 use alloc::{
     borrow::ToOwned,
@@ -17,9 +20,26 @@ use alloc::{
 };
 use core::cell::{Ref, RefCell};
 use core::fmt;
-use parity_wasm::elements::{External, InitExpr, Instruction, Internal, ResizableLimits, Type};
+use parity_wasm::elements::{
+    External, ImportCountType, ImportEntry, InitExpr, Instruction, Internal, ResizableLimits, Type,
+};
 use validation::{DEFAULT_MEMORY_INDEX, DEFAULT_TABLE_INDEX};
 
+/// Contents of the memories and values of the mutable globals of a [`ModuleInstance`].
+///
+/// Taken by [`ModuleInstance::snapshot`] and restored by [`ModuleInstance::restore`].
+/// Tables are not part of a snapshot.
+///
+/// [`ModuleInstance`]: struct.ModuleInstance.html
+/// [`ModuleInstance::snapshot`]: struct.ModuleInstance.html#method.snapshot
+/// [`ModuleInstance::restore`]: struct.ModuleInstance.html#method.restore
+#[derive(Debug, Clone)]
+pub struct InstanceSnapshot {
+    memories: Vec<Vec<u8>>,
+    /// Values of the globals by index, `None` for immutable ones.
+    globals: Vec<Option<RuntimeValue>>,
+}
+
 /// Reference to a [`ModuleInstance`].
 ///
 /// This reference has a reference-counting semantics.
@@ -128,6 +148,162 @@ impl ExternVal {
             _ => None,
         }
     }
+
+    /// Returns the kind of this external value.
+    pub fn kind(&self) -> ExternKind {
+        match *self {
+            ExternVal::Func(_) => ExternKind::Func,
+            ExternVal::Table(_) => ExternKind::Table,
+            ExternVal::Memory(_) => ExternKind::Memory,
+            ExternVal::Global(_) => ExternKind::Global,
+        }
+    }
+
+    /// Returns the type of this external value.
+    pub fn extern_type(&self) -> ExternType {
+        match *self {
+            ExternVal::Func(ref func) => ExternType::Func(func.signature().clone()),
+            ExternVal::Table(ref table) => ExternType::Table(table.element_type()),
+            ExternVal::Memory(ref memory) => ExternType::Memory {
+                shared: memory.is_shared(),
+            },
+            ExternVal::Global(ref global) => ExternType::Global(global.value_type()),
+        }
+    }
+}
+
+/// Kind of an entity that can be imported or exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternKind {
+    /// A function.
+    Func,
+    /// A table.
+    Table,
+    /// A linear memory.
+    Memory,
+    /// A global.
+    Global,
+}
+
+/// Type of an entity that can be imported or exported.
+///
+/// Limits of tables and memories are not part of the type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExternType {
+    /// A function with the given signature.
+    Func(Signature),
+    /// A table holding elements of the given type.
+    Table(TableElementType),
+    /// A linear memory.
+    Memory {
+        /// Whether the memory is shared, see [`MemoryInstance::alloc_shared`].
+        ///
+        /// [`MemoryInstance::alloc_shared`]: struct.MemoryInstance.html#method.alloc_shared
+        shared: bool,
+    },
+    /// A global holding values of the given type.
+    Global(ValueType),
+}
+
+impl ExternType {
+    /// Returns the kind of entities of this type.
+    pub fn kind(&self) -> ExternKind {
+        match *self {
+            ExternType::Func(_) => ExternKind::Func,
+            ExternType::Table(_) => ExternKind::Table,
+            ExternType::Memory { .. } => ExternKind::Memory,
+            ExternType::Global(_) => ExternKind::Global,
+        }
+    }
+}
+
+/// An import of a module instance that was used during execution.
+///
+/// See [`ModuleInstance::used_imports`].
+///
+/// [`ModuleInstance::used_imports`]: struct.ModuleInstance.html#method.used_imports
+#[cfg(feature = "import_usage")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsedImport {
+    /// The name of the module the import is imported from.
+    pub module_name: String,
+    /// The name of the imported field.
+    pub field_name: String,
+    /// The kind of the import, never [`ExternKind::Table`].
+    ///
+    /// [`ExternKind::Table`]: enum.ExternKind.html#variant.Table
+    pub kind: ExternKind,
+    /// The index of the import in the index space of its kind.
+    pub index: u32,
+}
+
+/// Records the first use of each imported function, global and memory of a module instance.
+#[cfg(feature = "import_usage")]
+#[derive(Debug, Default)]
+struct ImportUsage {
+    /// Imports of each kind by index, imports precede definitions in every index space.
+    funcs: Vec<(UsedImport, bool)>,
+    globals: Vec<(UsedImport, bool)>,
+    memories: Vec<(UsedImport, bool)>,
+    /// The imports in order of their first use.
+    used: Vec<UsedImport>,
+}
+
+#[cfg(feature = "import_usage")]
+impl ImportUsage {
+    fn push(&mut self, module_name: &str, field_name: &str, kind: ExternKind) {
+        let imports = self
+            .imports_mut(kind)
+            .expect("uses of imported tables are not recorded");
+        let import = UsedImport {
+            module_name: module_name.into(),
+            field_name: field_name.into(),
+            kind,
+            index: imports.len() as u32,
+        };
+        imports.push((import, false));
+    }
+
+    fn imports_mut(&mut self, kind: ExternKind) -> Option<&mut Vec<(UsedImport, bool)>> {
+        match kind {
+            ExternKind::Func => Some(&mut self.funcs),
+            ExternKind::Global => Some(&mut self.globals),
+            ExternKind::Memory => Some(&mut self.memories),
+            ExternKind::Table => None,
+        }
+    }
+
+    fn record(&mut self, kind: ExternKind, index: u32) {
+        let import = self
+            .imports_mut(kind)
+            .and_then(|imports| imports.get_mut(index as usize));
+        if let Some((import, used)) = import {
+            if !*used {
+                *used = true;
+                let import = import.clone();
+                self.used.push(import);
+            }
+        }
+    }
+}
+
+/// Key under which signatures are interned while allocating a module instance.
+///
+/// Orders signatures by the discriminants of their value types, so that [`Signature`] and
+/// [`ValueType`] don't have to implement `Ord` just for interning.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct SignatureKey {
+    params: Vec<u8>,
+    return_type: Option<u8>,
+}
+
+impl SignatureKey {
+    fn new(signature: &Signature) -> SignatureKey {
+        SignatureKey {
+            params: signature.params().iter().map(|&ty| ty as u8).collect(),
+            return_type: signature.return_type().map(|ty| ty as u8),
+        }
+    }
 }
 
 /// A module instance is the runtime representation of a [module][`Module`].
@@ -156,20 +332,34 @@ pub struct ModuleInstance {
     signatures: RefCell<Vec<Rc<Signature>>>,
     tables: RefCell<Vec<TableRef>>,
     funcs: RefCell<Vec<FuncRef>>,
+    /// Snapshot of `funcs` shared with the running functions, so that direct calls can be
+    /// resolved by indexing a slice. Rebuilt lazily after functions are added.
+    call_targets: RefCell<Option<Rc<[FuncRef]>>>,
     memories: RefCell<Vec<MemoryRef>>,
     globals: RefCell<Vec<GlobalRef>>,
-    exports: RefCell<BTreeMap<String, ExternVal>>,
+    elem_segments: RefCell<Vec<Vec<FuncRef>>>,
+    /// Exports in the order they are declared in the module.
+    exports: RefCell<Vec<(String, ExternVal)>>,
+    /// Indices into `exports` by name.
+    export_indices: RefCell<BTreeMap<String, usize>>,
+    #[cfg(feature = "import_usage")]
+    import_usage: RefCell<ImportUsage>,
 }
 
 impl ModuleInstance {
-    fn default() -> Self {
+    pub(crate) fn default() -> Self {
         ModuleInstance {
             funcs: RefCell::new(Vec::new()),
+            call_targets: RefCell::new(None),
             signatures: RefCell::new(Vec::new()),
             tables: RefCell::new(Vec::new()),
             memories: RefCell::new(Vec::new()),
             globals: RefCell::new(Vec::new()),
-            exports: RefCell::new(BTreeMap::new()),
+            elem_segments: RefCell::new(Vec::new()),
+            exports: RefCell::new(Vec::new()),
+            export_indices: RefCell::new(BTreeMap::new()),
+            #[cfg(feature = "import_usage")]
+            import_usage: RefCell::new(ImportUsage::default()),
         }
     }
 
@@ -189,30 +379,110 @@ impl ModuleInstance {
         self.funcs.borrow().get(idx as usize).cloned()
     }
 
+    /// Returns the index of `func` in the function index space of this module.
+    pub(crate) fn func_index(&self, func: &FuncRef) -> Option<u32> {
+        self.funcs
+            .borrow()
+            .iter()
+            .position(|f| core::ptr::eq::<FuncInstance>(&**f, &**func))
+            .map(|idx| idx as u32)
+    }
+
     pub(crate) fn signature_by_index(&self, idx: u32) -> Option<Rc<Signature>> {
         self.signatures.borrow().get(idx as usize).cloned()
     }
 
-    fn push_func(&self, func: FuncRef) {
+    /// Calls `f` with the functions of the element segment at the given index.
+    ///
+    /// Active segments and segments dropped by `elem.drop` are empty. Returns `None`
+    /// if there is no segment at the given index.
+    pub(crate) fn with_elem_segment<R, F: FnOnce(&[FuncRef]) -> R>(
+        &self,
+        idx: u32,
+        f: F,
+    ) -> Option<R> {
+        self.elem_segments
+            .borrow()
+            .get(idx as usize)
+            .map(|segment| f(segment))
+    }
+
+    pub(crate) fn drop_elem_segment(&self, idx: u32) {
+        if let Some(segment) = self.elem_segments.borrow_mut().get_mut(idx as usize) {
+            *segment = Vec::new();
+        }
+    }
+
+    fn push_elem_segment(&self, segment: Vec<FuncRef>) {
+        self.elem_segments.borrow_mut().push(segment);
+    }
+
+    pub(crate) fn push_func(&self, func: FuncRef) {
         self.funcs.borrow_mut().push(func);
+        *self.call_targets.borrow_mut() = None;
+    }
+
+    /// Returns all functions of this module, indexed by their function index.
+    ///
+    /// This is used to resolve the targets of direct calls.
+    pub(crate) fn call_targets(&self) -> Rc<[FuncRef]> {
+        self.call_targets
+            .borrow_mut()
+            .get_or_insert_with(|| self.funcs.borrow().as_slice().into())
+            .clone()
     }
 
-    fn push_signature(&self, signature: Rc<Signature>) {
+    pub(crate) fn push_signature(&self, signature: Rc<Signature>) {
         self.signatures.borrow_mut().push(signature)
     }
 
-    fn push_memory(&self, memory: MemoryRef) {
+    pub(crate) fn push_memory(&self, memory: MemoryRef) {
         self.memories.borrow_mut().push(memory)
     }
 
-    fn push_table(&self, table: TableRef) {
+    pub(crate) fn push_table(&self, table: TableRef) {
         self.tables.borrow_mut().push(table)
     }
 
-    fn push_global(&self, global: GlobalRef) {
+    pub(crate) fn push_global(&self, global: GlobalRef) {
         self.globals.borrow_mut().push(global)
     }
 
+    /// Records a use of the import of the given kind and index by the code of this module.
+    ///
+    /// Indices past the imports refer to definitions of this module and are ignored.
+    #[cfg(feature = "import_usage")]
+    pub(crate) fn record_import_use(&self, kind: ExternKind, index: u32) {
+        self.import_usage.borrow_mut().record(kind, index);
+    }
+
+    /// Records a call of `func` by the code of this module, if `func` is imported.
+    #[cfg(feature = "import_usage")]
+    pub(crate) fn record_func_use(&self, func: &FuncRef) {
+        let imported_funcs = self.import_usage.borrow().funcs.len();
+        let index = self.funcs.borrow()[..imported_funcs]
+            .iter()
+            .position(|f| core::ptr::eq::<FuncInstance>(&**f, &**func));
+        if let Some(index) = index {
+            self.record_import_use(ExternKind::Func, index as u32);
+        }
+    }
+
+    /// Returns the imported functions, globals and memories the code of this module
+    /// has used so far, in order of their first use.
+    ///
+    /// Functions count as used once they are called, directly or through a table, globals
+    /// once they are read or written and memories once they are accessed by any memory
+    /// instruction. Uses while instantiating the module, like global imports in the offsets
+    /// of segments, don't count. Imports missing from a representative run are candidates
+    /// for removal.
+    ///
+    /// Only available with the `import_usage` feature.
+    #[cfg(feature = "import_usage")]
+    pub fn used_imports(&self) -> Vec<UsedImport> {
+        self.import_usage.borrow().used.clone()
+    }
+
     /// Access all globals. This is a non-standard API so it's unlikely to be
     /// portable to other engines.
     pub fn globals(&self) -> Ref<Vec<GlobalRef>> {
@@ -220,7 +490,12 @@ impl ModuleInstance {
     }
 
     fn insert_export<N: Into<String>>(&self, name: N, extern_val: ExternVal) {
-        self.exports.borrow_mut().insert(name.into(), extern_val);
+        let name = name.into();
+        let mut exports = self.exports.borrow_mut();
+        self.export_indices
+            .borrow_mut()
+            .insert(name.clone(), exports.len());
+        exports.push((name, extern_val));
     }
 
     fn alloc_module<'i, I: Iterator<Item = &'i ExternVal>>(
@@ -230,8 +505,16 @@ impl ModuleInstance {
         let module = loaded_module.module();
         let instance = ModuleRef(Rc::new(ModuleInstance::default()));
 
+        // Modules often declare the same function type many times. Share one `Signature`
+        // between all of them, so that functions of the same type can be matched by
+        // pointer in `call_indirect`.
+        let mut interned_signatures = BTreeMap::new();
         for &Type::Function(ref ty) in module.type_section().map(|ts| ts.types()).unwrap_or(&[]) {
-            let signature = Rc::new(Signature::from_elements(ty));
+            let signature = Signature::from_elements(ty);
+            let signature = interned_signatures
+                .entry(SignatureKey::new(&signature))
+                .or_insert_with(|| Rc::new(signature))
+                .clone();
             instance.push_signature(signature);
         }
 
@@ -250,9 +533,10 @@ impl ModuleInstance {
                     (Some(import), Some(extern_val)) => (import, extern_val),
                     (None, None) => break,
                     (Some(_), None) | (None, Some(_)) => {
-                        return Err(Error::Instantiation(
+                        return Err(InstantiationError::Other(
                             "extern_vals length is not equal to import section entries".to_owned(),
-                        ));
+                        )
+                        .into());
                     }
                 };
 
@@ -263,39 +547,53 @@ impl ModuleInstance {
                             .expect("Due to validation function type should exists");
                         let actual_fn_type = func.signature();
                         if &*expected_fn_type != actual_fn_type {
-                            return Err(Error::Instantiation(format!(
-								"Expected function with type {:?}, but actual type is {:?} for entry {}",
-								expected_fn_type,
-								actual_fn_type,
-								import.field(),
-							)));
+                            return Err(type_mismatch(&instance, import, extern_val));
                         }
+                        #[cfg(feature = "import_usage")]
+                        instance.import_usage.borrow_mut().push(
+                            import.module(),
+                            import.field(),
+                            ExternKind::Func,
+                        );
                         instance.push_func(func.clone())
                     }
                     (&External::Table(ref tt), &ExternVal::Table(ref table)) => {
-                        match_limits(table.limits(), tt.limits())?;
+                        // Wasm modules can only declare tables of functions so far.
+                        if table.element_type() != TableElementType::FuncRef {
+                            return Err(type_mismatch(&instance, import, extern_val));
+                        }
+                        match_limits(import, table.limits(), tt.limits())?;
                         instance.push_table(table.clone());
                     }
                     (&External::Memory(ref mt), &ExternVal::Memory(ref memory)) => {
-                        match_limits(memory.limits(), mt.limits())?;
+                        match_limits(import, memory.limits(), mt.limits())?;
+                        #[cfg(feature = "atomics")]
+                        {
+                            if memory.is_shared() != mt.limits().shared() {
+                                return Err(type_mismatch(&instance, import, extern_val));
+                            }
+                        }
+                        #[cfg(feature = "import_usage")]
+                        instance.import_usage.borrow_mut().push(
+                            import.module(),
+                            import.field(),
+                            ExternKind::Memory,
+                        );
                         instance.push_memory(memory.clone());
                     }
                     (&External::Global(ref gl), &ExternVal::Global(ref global)) => {
                         if gl.content_type() != global.elements_value_type() {
-                            return Err(Error::Instantiation(format!(
-                                "Expect global with {:?} type, but provided global with {:?} type",
-                                gl.content_type(),
-                                global.value_type(),
-                            )));
+                            return Err(type_mismatch(&instance, import, extern_val));
                         }
+                        #[cfg(feature = "import_usage")]
+                        instance.import_usage.borrow_mut().push(
+                            import.module(),
+                            import.field(),
+                            ExternKind::Global,
+                        );
                         instance.push_global(global.clone());
                     }
-                    (expected_import, actual_extern_val) => {
-                        return Err(Error::Instantiation(format!(
-                            "Expected {:?} type, but provided {:?} extern_val",
-                            expected_import, actual_extern_val
-                        )));
-                    }
+                    _ => return Err(type_mismatch(&instance, import, extern_val)),
                 }
             }
         }
@@ -343,8 +641,18 @@ impl ModuleInstance {
             let initial: Pages = Pages(memory_type.limits().initial() as usize);
             let maximum: Option<Pages> = memory_type.limits().maximum().map(|m| Pages(m as usize));
 
-            let memory = MemoryInstance::alloc(initial, maximum)
-                .expect("Due to validation `initial` and `maximum` should be valid");
+            #[cfg(feature = "atomics")]
+            let memory = if memory_type.limits().shared() {
+                MemoryInstance::alloc_shared(
+                    initial,
+                    maximum.expect("Due to validation shared memory should have maximum"),
+                )
+            } else {
+                MemoryInstance::alloc(initial, maximum)
+            };
+            #[cfg(not(feature = "atomics"))]
+            let memory = MemoryInstance::alloc(initial, maximum);
+            let memory = memory.expect("Due to validation `initial` and `maximum` should be valid");
             instance.push_memory(memory);
         }
 
@@ -405,20 +713,75 @@ impl ModuleInstance {
     pub fn with_externvals<'a, 'i, I: Iterator<Item = &'i ExternVal>>(
         loaded_module: &'a Module,
         extern_vals: I,
+    ) -> Result<NotStartedModuleRef<'a>, Error> {
+        Self::instantiate(loaded_module, extern_vals, Vec::new())
+    }
+
+    fn instantiate<'a, 'i, I: Iterator<Item = &'i ExternVal>>(
+        loaded_module: &'a Module,
+        extern_vals: I,
+        table_contents: Vec<Option<FuncRef>>,
     ) -> Result<NotStartedModuleRef<'a>, Error> {
         let module = loaded_module.module();
 
+        // An imported table might be used by other instances, so don't overwrite it.
+        if !table_contents.is_empty() && module.import_count(ImportCountType::Table) > 0 {
+            return Err(InstantiationError::Other(
+                "initial table contents are given, but the module imports its table".to_string(),
+            )
+            .into());
+        }
+
         let module_ref = ModuleInstance::alloc_module(loaded_module, extern_vals)?;
 
-        for element_segment in module
+        if !table_contents.is_empty() {
+            let table_inst = module_ref
+                .table_by_index(DEFAULT_TABLE_INDEX)
+                .ok_or_else(|| {
+                    InstantiationError::Other(
+                        "initial table contents are given, but the module has no table".to_string(),
+                    )
+                })?;
+            if table_contents.len() > table_inst.current_size() as usize {
+                return Err(InstantiationError::Other(format!(
+                    "initial table contents of {} elements don't fit into table of size {}",
+                    table_contents.len(),
+                    table_inst.current_size()
+                ))
+                .into());
+            }
+            for (index, func) in table_contents.into_iter().enumerate() {
+                table_inst.set(index as u32, func)?;
+            }
+        }
+
+        for (index, element_segment) in module
             .elements_section()
             .map(|es| es.entries())
             .unwrap_or(&[])
+            .iter()
+            .enumerate()
         {
-            let offset = element_segment
-                .offset()
-                .as_ref()
-                .expect("passive segments are rejected due to validation");
+            let offset = match element_segment.offset() {
+                Some(offset) => offset,
+                None => {
+                    // Passive segments are kept around for `table.init`.
+                    let funcs = element_segment
+                        .members()
+                        .iter()
+                        .map(|func_idx| {
+                            module_ref.func_by_index(*func_idx).expect(
+                                "Due to validation funcs from element segments should exists",
+                            )
+                        })
+                        .collect();
+                    module_ref.push_elem_segment(funcs);
+                    continue;
+                }
+            };
+            // Active segments behave as if they were dropped after instantiation.
+            module_ref.push_elem_segment(Vec::new());
+
             let offset_val = match eval_init_expr(offset, &module_ref) {
                 RuntimeValue::I32(v) => v as u32,
                 _ => panic!("Due to validation elem segment offset should evaluate to i32"),
@@ -433,9 +796,10 @@ impl ModuleInstance {
             if offset_val as u64 + element_segment.members().len() as u64
                 > table_inst.current_size() as u64
             {
-                return Err(Error::Instantiation(
-                    "elements segment does not fit".to_string(),
-                ));
+                return Err(InstantiationError::ElemSegmentDoesNotFit {
+                    index: index as u32,
+                }
+                .into());
             }
 
             for (j, func_idx) in element_segment.members().iter().enumerate() {
@@ -447,7 +811,13 @@ impl ModuleInstance {
             }
         }
 
-        for data_segment in module.data_section().map(|ds| ds.entries()).unwrap_or(&[]) {
+        for (index, data_segment) in module
+            .data_section()
+            .map(|ds| ds.entries())
+            .unwrap_or(&[])
+            .iter()
+            .enumerate()
+        {
             let offset = data_segment
                 .offset()
                 .as_ref()
@@ -460,7 +830,11 @@ impl ModuleInstance {
             let memory_inst = module_ref
                 .memory_by_index(DEFAULT_MEMORY_INDEX)
                 .expect("Due to validation default memory should exists");
-            memory_inst.set(offset_val, data_segment.value())?;
+            memory_inst
+                .set(offset_val, data_segment.value())
+                .map_err(|_| InstantiationError::DataSegmentDoesNotFit {
+                    index: index as u32,
+                })?;
         }
 
         Ok(NotStartedModuleRef {
@@ -533,6 +907,86 @@ impl ModuleInstance {
         loaded_module: &'m Module,
         imports: &I,
     ) -> Result<NotStartedModuleRef<'m>, Error> {
+        let extern_vals = Self::resolve_imports(loaded_module, imports)?;
+        Self::with_externvals(loaded_module, extern_vals.iter())
+    }
+
+    /// Instantiate a [module][`Module`] with the initial contents of its table given by the host.
+    ///
+    /// The first `table_contents.len()` elements of the default table are set to `table_contents`
+    /// before element segments are applied, so that element segments can still override
+    /// specific slots. This allows to build a dispatch table in Rust instead of declaring it
+    /// in the module.
+    ///
+    /// See [`new`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` in the same cases as [`new`], or if `table_contents` is non-empty and
+    /// the module has no table, imports its table or `table_contents` doesn't fit into it.
+    /// Imported tables are rejected since they might be used by other instances.
+    ///
+    /// [`Module`]: struct.Module.html
+    /// [`new`]: #method.new
+    pub fn new_with_table<'m, I: ImportResolver>(
+        loaded_module: &'m Module,
+        imports: &I,
+        table_contents: Vec<Option<FuncRef>>,
+    ) -> Result<NotStartedModuleRef<'m>, Error> {
+        let extern_vals = Self::resolve_imports(loaded_module, imports)?;
+        Self::instantiate(loaded_module, extern_vals.iter(), table_contents)
+    }
+
+    /// Instantiate a [module][`Module`] that doesn't import any functions and run its
+    /// `start` function, if any.
+    ///
+    /// Code of such a module can't call into the host, so no [`Externals`] are needed to run
+    /// it. Exports of the returned instance can be invoked with [`invoke_export_pure`].
+    ///
+    /// Tables, memories and globals can still be imported through `imports`; use
+    /// [`ImportsBuilder::default`] if the module doesn't import anything.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the module imports a function, in the same cases as [`new`], or if
+    /// the `start` function traps, see [`InstantiationError::StartTrapped`].
+    ///
+    /// [`Module`]: struct.Module.html
+    /// [`Externals`]: trait.Externals.html
+    /// [`invoke_export_pure`]: #method.invoke_export_pure
+    /// [`ImportsBuilder::default`]: struct.ImportsBuilder.html
+    /// [`new`]: #method.new
+    /// [`InstantiationError::StartTrapped`]: enum.InstantiationError.html#variant.StartTrapped
+    pub fn new_pure<I: ImportResolver>(
+        loaded_module: &Module,
+        imports: &I,
+    ) -> Result<ModuleRef, Error> {
+        let module = loaded_module.module();
+        let func_import = module
+            .import_section()
+            .map(|s| s.entries())
+            .unwrap_or(&[])
+            .iter()
+            .find(|import| matches!(*import.external(), External::Function(_)));
+        if let Some(import) = func_import {
+            return Err(InstantiationError::Other(format!(
+                "module imports function {}.{}, but host functions can't be called without externals",
+                import.module(),
+                import.field()
+            ))
+            .into());
+        }
+
+        let instance = Self::new(loaded_module, imports)?;
+        instance
+            .run_start(&mut NopExternals)
+            .map_err(|trap| InstantiationError::StartTrapped(trap).into())
+    }
+
+    fn resolve_imports<I: ImportResolver>(
+        loaded_module: &Module,
+        imports: &I,
+    ) -> Result<Vec<ExternVal>, Error> {
         let module = loaded_module.module();
 
         let mut extern_vals = Vec::new();
@@ -571,7 +1025,7 @@ impl ModuleInstance {
             extern_vals.push(extern_val);
         }
 
-        Self::with_externvals(loaded_module, extern_vals.iter())
+        Ok(extern_vals)
     }
 
     /// Invoke exported function by a name.
@@ -633,6 +1087,181 @@ impl ModuleInstance {
         FuncInstance::invoke(&func_instance, args, externals).map_err(Error::Trap)
     }
 
+    /// Invoke exported function by a name without any externals.
+    ///
+    /// Meant for instances created by [`new_pure`], which can't call host functions. Should
+    /// the function call a host function anyway, e.g. one placed into an imported table,
+    /// the call traps with [`TrapKind::Unreachable`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`invoke_export`].
+    ///
+    /// [`new_pure`]: #method.new_pure
+    /// [`invoke_export`]: #method.invoke_export
+    /// [`TrapKind::Unreachable`]: enum.TrapKind.html#variant.Unreachable
+    pub fn invoke_export_pure(
+        &self,
+        func_name: &str,
+        args: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, Error> {
+        self.invoke_export(func_name, args, &mut NopExternals)
+    }
+
+    /// Invoke the function with the given index in the function index space of this module,
+    /// whether it is exported or not.
+    ///
+    /// This is mostly useful for tooling and fuzzing, which want to run functions that the
+    /// module doesn't export. The index space starts with the imported functions.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if there is no function with index `func_idx`, if it is a host function,
+    /// if `args` don't match its signature or if it traps.
+    pub fn invoke_index<E: Externals>(
+        &self,
+        func_idx: u32,
+        args: &[RuntimeValue],
+        externals: &mut E,
+    ) -> Result<Option<RuntimeValue>, Error> {
+        let func_instance = self
+            .func_by_index(func_idx)
+            .ok_or_else(|| Error::Function(format!("Module doesn't have function {}", func_idx)))?;
+        if func_instance.body().is_none() {
+            return Err(Error::Function(format!(
+                "Function {} is a host function and can only be called by wasm code",
+                func_idx
+            )));
+        }
+
+        FuncInstance::invoke(&func_instance, args, externals).map_err(Error::Trap)
+    }
+
+    /// Invoke exported function by a name with externals that only need a shared borrow.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`invoke_export`].
+    ///
+    /// [`invoke_export`]: #method.invoke_export
+    pub fn invoke_export_shared<E: SharedExternals>(
+        &self,
+        func_name: &str,
+        args: &[RuntimeValue],
+        externals: &E,
+    ) -> Result<Option<RuntimeValue>, Error> {
+        self.invoke_export(func_name, args, &mut &*externals)
+    }
+
+    /// Invoke exported function by a name and roll back its effects if it fails.
+    ///
+    /// Takes a [`snapshot`] before the invocation and [restores][`restore`] it if the
+    /// invocation returns `Err`, e.g. because it trapped. This leaves the instance in its
+    /// state before the call instead of whatever state the partial execution left it in.
+    /// Copying the memories makes this considerably more expensive than [`invoke_export`]
+    /// for instances with large memories.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`invoke_export`], plus errors of [`snapshot`] and [`restore`].
+    ///
+    /// [`invoke_export`]: #method.invoke_export
+    /// [`snapshot`]: #method.snapshot
+    /// [`restore`]: #method.restore
+    pub fn invoke_export_transactional<E: Externals>(
+        &self,
+        func_name: &str,
+        args: &[RuntimeValue],
+        externals: &mut E,
+    ) -> Result<Option<RuntimeValue>, Error> {
+        let snapshot = self.snapshot()?;
+        self.invoke_export(func_name, args, externals)
+            .or_else(|err| self.restore(&snapshot).and(Err(err)))
+    }
+
+    /// Copy the contents of all memories and the values of all mutable globals.
+    ///
+    /// This includes imported memories and globals, which might be shared with other
+    /// instances.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if a memory is detached.
+    pub fn snapshot(&self) -> Result<InstanceSnapshot, Error> {
+        let memories = self
+            .memories
+            .borrow()
+            .iter()
+            .map(|memory| memory.to_vec())
+            .collect::<Result<_, _>>()?;
+        let globals = self
+            .globals
+            .borrow()
+            .iter()
+            .map(|global| Some(global.get()).filter(|_| global.is_mutable()))
+            .collect();
+        Ok(InstanceSnapshot { memories, globals })
+    }
+
+    /// Restore memories and mutable globals to the state captured by [`snapshot`].
+    ///
+    /// Memories can't shrink, so pages added after the snapshot was taken are kept but
+    /// zeroed.
+    ///
+    /// The snapshot is checked against the instance before anything is written, so on
+    /// error the instance is left untouched.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Snapshot`] if `snapshot` doesn't fit this instance, i.e. it has a
+    /// different number of memories or globals, a memory is smaller than (or detached
+    /// since) the snapshot, or a global is immutable or of another type than its value
+    /// in the snapshot.
+    ///
+    /// [`snapshot`]: #method.snapshot
+    /// [`Error::Snapshot`]: enum.Error.html#variant.Snapshot
+    pub fn restore(&self, snapshot: &InstanceSnapshot) -> Result<(), Error> {
+        let memories = self.memories.borrow();
+        let globals = self.globals.borrow();
+        if memories.len() != snapshot.memories.len() || globals.len() != snapshot.globals.len() {
+            return Err(Error::Snapshot(
+                "snapshot was taken from a different instance".to_string(),
+            ));
+        }
+        for (index, (memory, contents)) in memories.iter().zip(&snapshot.memories).enumerate() {
+            let Bytes(size) = memory.current_size().into();
+            if memory.is_detached() || size < contents.len() {
+                return Err(Error::Snapshot(format!(
+                    "memory {} can't hold the {} bytes of the snapshot",
+                    index,
+                    contents.len()
+                )));
+            }
+        }
+        for (index, (global, value)) in globals.iter().zip(&snapshot.globals).enumerate() {
+            if let Some(value) = *value {
+                if !global.is_mutable() || !value.matches_type(global.value_type()) {
+                    return Err(Error::Snapshot(format!(
+                        "global {} can't be set to {:?}",
+                        index, value
+                    )));
+                }
+            }
+        }
+
+        for (memory, contents) in memories.iter().zip(&snapshot.memories) {
+            let Bytes(size) = memory.current_size().into();
+            memory.set(0, contents)?;
+            memory.zero(contents.len(), size - contents.len())?;
+        }
+        for (global, value) in globals.iter().zip(&snapshot.globals) {
+            if let Some(value) = *value {
+                global.set(value)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Invoke exported function by a name using recycled stacks.
     ///
     /// # Errors
@@ -671,7 +1300,44 @@ impl ModuleInstance {
     ///
     /// Returns `None` if there is no export with such name.
     pub fn export_by_name(&self, name: &str) -> Option<ExternVal> {
-        self.exports.borrow().get(name).cloned()
+        let index = *self.export_indices.borrow().get(name)?;
+        Some(self.exports.borrow()[index].1.clone())
+    }
+
+    /// Returns all exports with their names, in the order they are declared in the module.
+    pub fn exports(&self) -> Vec<(String, ExternVal)> {
+        self.exports.borrow().clone()
+    }
+
+    /// Returns the value of the global exported under `name`.
+    ///
+    /// Returns `None` if there is no such export or it is not a global.
+    pub fn global_value(&self, name: &str) -> Option<RuntimeValue> {
+        self.export_by_name(name)
+            .and_then(|export| export.as_global().map(|global| global.get()))
+    }
+
+    /// Sets the value of the global exported under `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if:
+    ///
+    /// - there are no export with a given name or this export is not a global,
+    /// - the global is immutable,
+    /// - `value` doesn't match the type of the global.
+    pub fn set_global_value(&self, name: &str, value: RuntimeValue) -> Result<(), Error> {
+        let extern_val = self
+            .export_by_name(name)
+            .ok_or_else(|| Error::Global(format!("Module doesn't have export {}", name)))?;
+
+        match extern_val {
+            ExternVal::Global(global) => global.set(value),
+            unexpected => Err(Error::Global(format!(
+                "Export {} is not a global, but {:?}",
+                name, unexpected
+            ))),
+        }
     }
 }
 
@@ -714,6 +1380,11 @@ impl<'a> NotStartedModuleRef<'a> {
 
     /// Executes `start` function (if any) and returns fully instantiated module.
     ///
+    /// Globals, memories and tables, including the contents of data and element segments, are
+    /// fully initialized before the `start` function runs. Both the `start` function and any host
+    /// functions it calls, including ones re-entering exports of this instance, observe the same
+    /// state as exports invoked after this method returns.
+    ///
     /// # Errors
     ///
     /// Returns `Err` if start function traps.
@@ -769,58 +1440,105 @@ impl<'a> NotStartedModuleRef<'a> {
 }
 
 fn eval_init_expr(init_expr: &InitExpr, module: &ModuleInstance) -> RuntimeValue {
-    let code = init_expr.code();
-    debug_assert!(
-        code.len() == 2,
-        "Due to validation `code`.len() should be 2"
-    );
-    match code[0] {
-        Instruction::I32Const(v) => v.into(),
-        Instruction::I64Const(v) => v.into(),
-        Instruction::F32Const(v) => RuntimeValue::decode_f32(v),
-        Instruction::F64Const(v) => RuntimeValue::decode_f64(v),
-        Instruction::GetGlobal(idx) => {
-            let global = module
-                .global_by_index(idx)
-                .expect("Due to validation global should exists in module");
-            global.get()
-        }
-        _ => panic!("Due to validation init should be a const expr"),
+    fn pop(stack: &mut Vec<RuntimeValue>) -> RuntimeValue {
+        stack
+            .pop()
+            .expect("Due to validation operands should be on the stack")
+    }
+
+    let mut stack: Vec<RuntimeValue> = Vec::new();
+    for instruction in init_expr.code() {
+        let value = match *instruction {
+            Instruction::I32Const(v) => v.into(),
+            Instruction::I64Const(v) => v.into(),
+            Instruction::F32Const(v) => RuntimeValue::decode_f32(v),
+            Instruction::F64Const(v) => RuntimeValue::decode_f64(v),
+            Instruction::GetGlobal(idx) => {
+                let global = module
+                    .global_by_index(idx)
+                    .expect("Due to validation global should exists in module");
+                global.get()
+            }
+            Instruction::I32Add | Instruction::I64Add => {
+                let (rhs, lhs) = (pop(&mut stack), pop(&mut stack));
+                lhs.wrapping_add(rhs)
+                    .expect("Due to validation operands should be of the same type")
+            }
+            Instruction::I32Sub | Instruction::I64Sub => {
+                let (rhs, lhs) = (pop(&mut stack), pop(&mut stack));
+                lhs.wrapping_sub(rhs)
+                    .expect("Due to validation operands should be of the same type")
+            }
+            Instruction::I32Mul | Instruction::I64Mul => {
+                let (rhs, lhs) = (pop(&mut stack), pop(&mut stack));
+                lhs.wrapping_mul(rhs)
+                    .expect("Due to validation operands should be of the same type")
+            }
+            Instruction::End => break,
+            _ => panic!("Due to validation init should be a const expr"),
+        };
+        stack.push(value);
     }
+    pop(&mut stack)
 }
 
-fn match_limits(l1: &ResizableLimits, l2: &ResizableLimits) -> Result<(), Error> {
-    if l1.initial() < l2.initial() {
-        return Err(Error::Instantiation(format!(
-            "trying to import with limits l1.initial={} and l2.initial={}",
-            l1.initial(),
-            l2.initial()
-        )));
-    }
-
-    match (l1.maximum(), l2.maximum()) {
-        (_, None) => (),
-        (Some(m1), Some(m2)) if m1 <= m2 => (),
-        _ => {
-            return Err(Error::Instantiation(format!(
-                "trying to import with limits l1.max={:?} and l2.max={:?}",
-                l1.maximum(),
-                l2.maximum()
-            )));
+fn match_limits(
+    import: &ImportEntry,
+    l1: &ResizableLimits,
+    l2: &ResizableLimits,
+) -> Result<(), Error> {
+    let fits = l1.initial() >= l2.initial()
+        && match (l1.maximum(), l2.maximum()) {
+            (_, None) => true,
+            (Some(m1), Some(m2)) => m1 <= m2,
+            (None, Some(_)) => false,
+        };
+    if !fits {
+        return Err(InstantiationError::LimitsMismatch {
+            module: import.module().to_string(),
+            field: import.field().to_string(),
+            expected: *l2,
+            actual: *l1,
         }
+        .into());
     }
-
     Ok(())
 }
 
+/// Returns the error for `extern_val` not matching the type `import` declares in `instance`.
+fn type_mismatch(instance: &ModuleInstance, import: &ImportEntry, extern_val: &ExternVal) -> Error {
+    let expected = match *import.external() {
+        External::Function(fn_type_idx) => ExternType::Func(
+            (*instance
+                .signature_by_index(fn_type_idx)
+                .expect("Due to validation function type should exists"))
+            .clone(),
+        ),
+        // Wasm modules can only declare tables of functions so far.
+        External::Table(_) => ExternType::Table(TableElementType::FuncRef),
+        External::Memory(ref mt) => ExternType::Memory {
+            shared: MemoryDescriptor::from_elements(mt).is_shared(),
+        },
+        External::Global(ref gl) => ExternType::Global(ValueType::from_elements(gl.content_type())),
+    };
+    InstantiationError::ImportTypeMismatch {
+        module: import.module().to_string(),
+        field: import.field().to_string(),
+        expected,
+        actual: extern_val.extern_type(),
+    }
+    .into()
+}
+
 pub fn check_limits(limits: &ResizableLimits) -> Result<(), Error> {
     if let Some(maximum) = limits.maximum() {
         if maximum < limits.initial() {
-            return Err(Error::Instantiation(format!(
+            return Err(InstantiationError::Other(format!(
                 "maximum limit {} is less than minimum {}",
                 maximum,
                 limits.initial()
-            )));
+            ))
+            .into());
         }
     }
 
@@ -831,9 +1549,10 @@ pub fn check_limits(limits: &ResizableLimits) -> Result<(), Error> {
 mod tests {
     use super::{ExternVal, ModuleInstance};
     use crate::func::FuncInstance;
-    use crate::imports::ImportsBuilder;
+    use crate::imports::{ImportsBuilder, ModuleImportResolver};
     use crate::tests::parse_wat;
     use crate::types::{Signature, ValueType};
+    use crate::{Error, InstantiationError};
 
     #[should_panic]
     #[test]
@@ -895,4 +1614,59 @@ mod tests {
         )
         .is_err());
     }
+
+    #[test]
+    fn unsatisfied_memory_import() {
+        struct NoMemory;
+        impl ModuleImportResolver for NoMemory {}
+
+        let module_with_memory_import = parse_wat(
+            r#"
+			(module
+				(import "env" "memory" (memory 1))
+				(func (export "load") (result i32)
+					(i32.load (i32.const 0))))
+			"#,
+        );
+
+        // Module that the memory is imported from is not registered.
+        assert!(matches!(
+            ModuleInstance::new(&module_with_memory_import, &ImportsBuilder::default()),
+            Err(Error::Instantiation(
+                InstantiationError::MissingImport { .. }
+            ))
+        ));
+
+        // Module is registered, but doesn't provide the memory.
+        assert!(matches!(
+            ModuleInstance::new(
+                &module_with_memory_import,
+                &ImportsBuilder::new().with_resolver("env", &NoMemory),
+            ),
+            Err(Error::Instantiation(
+                InstantiationError::MissingImport { .. }
+            ))
+        ));
+
+        // No extern val provided for the memory import.
+        assert!(matches!(
+            ModuleInstance::with_externvals(&module_with_memory_import, [].iter()),
+            Err(Error::Instantiation(InstantiationError::Other(_)))
+        ));
+
+        // Extern val of another kind provided for the memory import.
+        assert!(matches!(
+            ModuleInstance::with_externvals(
+                &module_with_memory_import,
+                [ExternVal::Func(FuncInstance::alloc_host(
+                    Signature::new(&[][..], None),
+                    0
+                ))]
+                .iter(),
+            ),
+            Err(Error::Instantiation(
+                InstantiationError::ImportTypeMismatch { .. }
+            ))
+        ));
+    }
 }
@@ -1,6 +1,9 @@
 use alloc::{string::String, vec::Vec};
+use core::cmp;
 
-use parity_wasm::elements::{BlockType, FuncBody, Instruction};
+#[cfg(feature = "atomics")]
+use parity_wasm::elements::AtomicsInstruction;
+use parity_wasm::elements::{BlockType, BulkInstruction, FuncBody, Instruction};
 
 use crate::isa;
 use validation::func::{
@@ -8,7 +11,7 @@ use validation::func::{
 };
 use validation::stack::StackWithLimit;
 use validation::util::Locals;
-use validation::{Error, FuncValidator};
+use validation::{Error, FuncValidator, DEFAULT_MEMORY_INDEX};
 
 /// Type of block frame.
 #[derive(Debug, Clone, Copy)]
@@ -63,15 +66,26 @@ pub struct Compiler {
     /// A sink used to emit optimized code.
     sink: Sink,
     label_stack: Vec<BlockFrameType>,
+    /// Number of locals, including parameters, of the function being compiled.
+    locals_count: u32,
+    /// Maximum operand stack height seen so far.
+    max_stack_height: u32,
+    /// Index of the next wasm instruction in the function body.
+    #[cfg(feature = "source_offsets")]
+    instruction_index: u32,
 }
 
 impl FuncValidator for Compiler {
     type Output = isa::Instructions;
-    fn new(_ctx: &FunctionValidationContext, body: &FuncBody) -> Self {
+    fn new(ctx: &FunctionValidationContext, body: &FuncBody) -> Self {
         let code_len = body.code().elements().len();
         let mut compiler = Compiler {
             sink: Sink::with_capacity(code_len),
             label_stack: Vec::new(),
+            locals_count: ctx.locals.count(),
+            max_stack_height: 0,
+            #[cfg(feature = "source_offsets")]
+            instruction_index: 0,
         };
 
         // Push implicit frame for the outer function block.
@@ -87,10 +101,23 @@ impl FuncValidator for Compiler {
         ctx: &mut FunctionValidationContext,
         instruction: &Instruction,
     ) -> Result<(), Error> {
-        self.compile_instruction(ctx, instruction)
+        #[cfg(feature = "stack_height_checks")]
+        let stack_height = ctx.value_stack.len() as u32;
+        self.compile_instruction(ctx, instruction)?;
+        #[cfg(feature = "stack_height_checks")]
+        self.sink.ins.record_stack_height(stack_height);
+        #[cfg(feature = "source_offsets")]
+        {
+            self.sink.ins.record_source(self.instruction_index);
+            self.instruction_index += 1;
+        }
+        self.max_stack_height = cmp::max(self.max_stack_height, ctx.value_stack.len() as u32);
+        Ok(())
     }
     fn finish(self) -> Self::Output {
-        self.sink.into_inner()
+        let mut code = self.sink.into_inner();
+        code.set_frame_size(self.locals_count, self.max_stack_height);
+        code
     }
 }
 
@@ -103,6 +130,12 @@ impl Compiler {
         use self::Instruction::*;
 
         match *instruction {
+            Nop => {
+                if cfg!(feature = "preserve_nops") {
+                    self.sink.emit(isa::InstructionInternal::Nop);
+                }
+                context.step(instruction)?;
+            }
             Unreachable => {
                 self.sink.emit(isa::InstructionInternal::Unreachable);
                 context.step(instruction)?;
@@ -133,10 +166,7 @@ impl Compiler {
 
                 self.sink.emit_br_eqz(Target {
                     label: if_not,
-                    drop_keep: isa::DropKeep {
-                        drop: 0,
-                        keep: isa::Keep::None,
-                    },
+                    drop_keep: isa::DropKeep { drop: 0, keep: 0 },
                 });
             }
             Else => {
@@ -160,10 +190,7 @@ impl Compiler {
                 // to the "end_label" (it will be resolved at End).
                 self.sink.emit_br(Target {
                     label: end_label,
-                    drop_keep: isa::DropKeep {
-                        drop: 0,
-                        keep: isa::Keep::None,
-                    },
+                    drop_keep: isa::DropKeep { drop: 0, keep: 0 },
                 });
 
                 // Resolve `if_not` to here so when if condition is unsatisfied control flow
@@ -448,6 +475,43 @@ impl Compiler {
                 context.step(instruction)?;
                 self.sink.emit(isa::InstructionInternal::GrowMemory);
             }
+            Bulk(BulkInstruction::MemoryCopy) => {
+                context.step(instruction)?;
+                self.sink.emit(isa::InstructionInternal::MemoryCopy {
+                    src_mem: DEFAULT_MEMORY_INDEX,
+                    dst_mem: DEFAULT_MEMORY_INDEX,
+                });
+            }
+
+            #[cfg(feature = "atomics")]
+            Atomics(AtomicsInstruction::AtomicWake(ref memarg)) => {
+                context.step(instruction)?;
+                self.sink
+                    .emit(isa::InstructionInternal::MemoryAtomicNotify(memarg.offset));
+            }
+            #[cfg(feature = "atomics")]
+            Atomics(AtomicsInstruction::I32AtomicWait(ref memarg)) => {
+                context.step(instruction)?;
+                self.sink
+                    .emit(isa::InstructionInternal::MemoryAtomicWait32(memarg.offset));
+            }
+            #[cfg(feature = "atomics")]
+            Atomics(AtomicsInstruction::I64AtomicWait(ref memarg)) => {
+                context.step(instruction)?;
+                self.sink
+                    .emit(isa::InstructionInternal::MemoryAtomicWait64(memarg.offset));
+            }
+
+            Bulk(BulkInstruction::TableInit(segment_idx)) => {
+                context.step(instruction)?;
+                self.sink
+                    .emit(isa::InstructionInternal::TableInit(segment_idx));
+            }
+            Bulk(BulkInstruction::TableDrop(segment_idx)) => {
+                context.step(instruction)?;
+                self.sink
+                    .emit(isa::InstructionInternal::ElemDrop(segment_idx));
+            }
 
             I32Const(v) => {
                 context.step(instruction)?;
@@ -989,13 +1053,13 @@ fn compute_drop_keep(
     start_value_stack_height: usize,
 ) -> Result<isa::DropKeep, Error> {
     // Find out how many values we need to keep (copy to the new stack location after the drop).
-    let keep: isa::Keep = match (started_with, block_type) {
+    let keep: u32 = match (started_with, block_type) {
         // A loop doesn't take a value upon a branch. It can return value
         // only via reaching it's closing `End` operator.
-        (StartedWith::Loop, _) => isa::Keep::None,
+        (StartedWith::Loop, _) => 0,
 
-        (_, BlockType::Value(_)) => isa::Keep::Single,
-        (_, BlockType::NoResult) => isa::Keep::None,
+        (_, BlockType::Value(_)) => 1,
+        (_, BlockType::NoResult) => 0,
     };
 
     // Find out how many values we need to discard.
@@ -1011,14 +1075,14 @@ fn compute_drop_keep(
                 start_value_stack_height,
             )));
         }
-        if (actual_value_stack_height as u32 - start_value_stack_height as u32) < keep.count() {
+        if (actual_value_stack_height as u32 - start_value_stack_height as u32) < keep {
             return Err(Error(format!(
                 "Stack underflow detected: asked to keep {:?} values, but there are only {}",
                 keep,
                 actual_value_stack_height as u32 - start_value_stack_height as u32,
             )));
         }
-        (actual_value_stack_height as u32 - start_value_stack_height as u32) - keep.count()
+        (actual_value_stack_height as u32 - start_value_stack_height as u32) - keep
     };
 
     Ok(isa::DropKeep { drop, keep })
@@ -0,0 +1,112 @@
+//! Compilation of function bodies when they are first needed.
+
+use super::compile::Compiler;
+use super::FuncCode;
+use crate::isa;
+use alloc::{sync::Arc, vec::Vec};
+use core::fmt;
+use parity_wasm::elements::Module;
+use std::sync::OnceLock;
+use validation::context::ModuleContext;
+use validation::{
+    func, module_context, validate, Error, PlainValidator, DEFAULT_MAX_NESTING_DEPTH,
+};
+
+/// Everything needed to compile the functions of a module.
+struct LazyModule {
+    module: Arc<Module>,
+    context: ModuleContext,
+    /// Offsets of the wasm instructions of every function body, see `resolve_source_offsets`.
+    #[cfg(feature = "source_offsets")]
+    source_offsets: Vec<Vec<u32>>,
+}
+
+/// A function whose body is compiled when its code is first needed, e.g. when it is
+/// first called.
+///
+/// The compiled code is cached and shared by all instances of the module. If several
+/// threads need the code at the same time, only one of them compiles it while the others
+/// wait for the result.
+pub struct LazyFunc {
+    module: Arc<LazyModule>,
+    /// Index of the function among the functions defined by the module.
+    index: usize,
+    code: OnceLock<isa::Instructions>,
+}
+
+impl LazyFunc {
+    /// Returns the compiled code of the function, compiling it first if needed.
+    pub fn instructions(&self) -> &isa::Instructions {
+        self.code.get_or_init(|| self.compile())
+    }
+
+    /// Whether the code of the function has been compiled already.
+    #[cfg(test)]
+    pub fn is_compiled(&self) -> bool {
+        self.code.get().is_some()
+    }
+
+    fn compile(&self) -> isa::Instructions {
+        let module = &self.module.module;
+        let func = module
+            .function_section()
+            .and_then(|fs| fs.entries().get(self.index))
+            .expect("Lazy functions are only created for defined functions; qed");
+        let body = module
+            .code_section()
+            .and_then(|cs| cs.bodies().get(self.index))
+            .expect("Due to validation every function has a body; qed");
+        #[allow(unused_mut)]
+        let mut code =
+            func::drive::<Compiler>(&self.module.context, func, body, DEFAULT_MAX_NESTING_DEPTH)
+                .expect("Function bodies are validated when the module is loaded; qed");
+        #[cfg(feature = "source_offsets")]
+        code.resolve_source_offsets(&self.module.source_offsets[self.index]);
+        code
+    }
+}
+
+impl fmt::Debug for LazyFunc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LazyFunc")
+            .field("index", &self.index)
+            .field("compiled", &self.code.get().is_some())
+            .finish()
+    }
+}
+
+/// Validate a module and prepare its functions to be compiled when they are first needed.
+///
+/// All function bodies are validated, so invalid functions are rejected even if they are
+/// never called.
+pub fn compile_module_lazy(
+    module: Module,
+    #[cfg(feature = "source_offsets")] wasm: &[u8],
+) -> Result<(Arc<Module>, Vec<FuncCode>), Error> {
+    let (module, ()) = validate::<PlainValidator>(module)?.into_parts();
+    let context = module_context(&module)?;
+    let functions = module
+        .function_section()
+        .map(|fs| fs.entries().len())
+        .unwrap_or(0);
+    #[cfg(feature = "source_offsets")]
+    let source_offsets = super::body_offsets(wasm, functions)?;
+
+    let module = Arc::new(module);
+    let lazy_module = Arc::new(LazyModule {
+        module: module.clone(),
+        context,
+        #[cfg(feature = "source_offsets")]
+        source_offsets,
+    });
+    let code_map = (0..functions)
+        .map(|index| {
+            FuncCode::Lazy(Arc::new(LazyFunc {
+                module: lazy_module.clone(),
+                index,
+                code: OnceLock::new(),
+            }))
+        })
+        .collect();
+    Ok((module, code_map))
+}
@@ -1,20 +1,46 @@
 use crate::isa;
+#[cfg(feature = "lazy_compilation")]
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+#[cfg(feature = "source_offsets")]
+use core::cmp;
 use parity_wasm::elements::Module;
-use validation::{validate_module, Error, Validator};
+use validation::{validate, Error, ValidatedModule, Validator};
 
 #[cfg(feature = "core")]
 use crate::alloc::string::ToString;
 
 mod compile;
+#[cfg(feature = "lazy_compilation")]
+mod lazy;
+
+#[cfg(feature = "lazy_compilation")]
+pub use self::lazy::{compile_module_lazy, LazyFunc};
 
 #[cfg(test)]
 mod tests;
 
-#[derive(Clone)]
-pub struct CompiledModule {
-    pub code_map: Vec<isa::Instructions>,
-    pub module: Module,
+pub type CompiledModule = ValidatedModule<Vec<isa::Instructions>>;
+
+/// The code of a function defined by a module.
+#[derive(Clone, Debug)]
+pub enum FuncCode {
+    /// Code compiled when the module was loaded.
+    Compiled(isa::Instructions),
+    /// Code compiled when it is first needed, shared by all instances of the module.
+    #[cfg(feature = "lazy_compilation")]
+    Lazy(Arc<LazyFunc>),
+}
+
+impl FuncCode {
+    /// Returns the compiled code, compiling it first if needed.
+    pub fn instructions(&self) -> &isa::Instructions {
+        match *self {
+            FuncCode::Compiled(ref code) => code,
+            #[cfg(feature = "lazy_compilation")]
+            FuncCode::Lazy(ref func) => func.instructions(),
+        }
+    }
 }
 
 pub struct WasmiValidation {
@@ -42,8 +68,86 @@ impl Validator for WasmiValidation {
 
 /// Validate a module and compile it to the internal representation.
 pub fn compile_module(module: Module) -> Result<CompiledModule, Error> {
-    let code_map = validate_module::<WasmiValidation>(&module)?;
-    Ok(CompiledModule { code_map, module })
+    validate::<WasmiValidation>(module)
+}
+
+/// Replaces the wasm instruction indices recorded in the compiled code with the offsets
+/// of the wasm instructions in `wasm`, the binary the code was compiled from.
+///
+/// The offsets are relative to the start of the code section's contents, which is how
+/// DWARF debug info for wasm refers to code.
+#[cfg(feature = "source_offsets")]
+pub fn resolve_source_offsets(
+    mut code_map: Vec<isa::Instructions>,
+    wasm: &[u8],
+) -> Result<Vec<isa::Instructions>, Error> {
+    let bodies = body_offsets(wasm, code_map.len())?;
+    for (code, offsets) in code_map.iter_mut().zip(&bodies) {
+        code.resolve_source_offsets(offsets);
+    }
+    Ok(code_map)
+}
+
+/// Returns the offsets of the instructions of every function body in `wasm`, which must
+/// have `functions` bodies.
+#[cfg(feature = "source_offsets")]
+fn body_offsets(wasm: &[u8], functions: usize) -> Result<Vec<Vec<u32>>, Error> {
+    let bodies = instruction_offsets(wasm)
+        .map_err(|e| Error(format!("Failed to locate instructions: {}", e)))?;
+    if bodies.len() != functions {
+        return Err(Error(format!(
+            "Code section has {} function bodies, expected {}",
+            bodies.len(),
+            functions
+        )));
+    }
+    Ok(bodies)
+}
+
+/// Returns the offsets of the instructions of every function body in the code section of
+/// `wasm`, relative to the start of the section's contents.
+///
+/// The module is parsed already, so this only walks the sections and instructions to
+/// learn their encoded sizes, which may not be minimal.
+#[cfg(feature = "source_offsets")]
+fn instruction_offsets(wasm: &[u8]) -> Result<Vec<Vec<u32>>, parity_wasm::elements::Error> {
+    use parity_wasm::elements::{Deserialize, Instruction, Local, VarUint32, VarUint7};
+
+    const CODE_SECTION_ID: u8 = 10;
+
+    // Skip the magic number and the version.
+    let mut reader = wasm.get(8..).unwrap_or_default();
+    while !reader.is_empty() {
+        let id: u8 = VarUint7::deserialize(&mut reader)?.into();
+        let size: u32 = VarUint32::deserialize(&mut reader)?.into();
+        let size = cmp::min(size as usize, reader.len());
+        let (contents, rest) = reader.split_at(size);
+        reader = rest;
+        if id != CODE_SECTION_ID {
+            continue;
+        }
+
+        let mut reader = contents;
+        let offset = |reader: &[u8]| (contents.len() - reader.len()) as u32;
+        let count: u32 = VarUint32::deserialize(&mut reader)?.into();
+        let mut bodies = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let size: u32 = VarUint32::deserialize(&mut reader)?.into();
+            let end = offset(reader) + size;
+            let local_count: u32 = VarUint32::deserialize(&mut reader)?.into();
+            for _ in 0..local_count {
+                Local::deserialize(&mut reader)?;
+            }
+            let mut offsets = Vec::new();
+            while offset(reader) < end {
+                offsets.push(offset(reader));
+                Instruction::deserialize(&mut reader)?;
+            }
+            bodies.push(offsets);
+        }
+        return Ok(bodies);
+    }
+    Ok(Vec::new())
 }
 
 /// Verify that the module doesn't use floating point instructions or types.
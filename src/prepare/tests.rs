@@ -15,7 +15,7 @@ fn validate(wat: &str) -> CompiledModule {
 }
 
 fn compile(module: &CompiledModule) -> (Vec<isa::Instruction>, Vec<u32>) {
-    let code = &module.code_map[0];
+    let code = &module.code_map()[0];
     let mut instructions = Vec::new();
     let mut pcs = Vec::new();
     let mut iter = code.iterate_from(0);
@@ -34,12 +34,7 @@ fn compile(module: &CompiledModule) -> (Vec<isa::Instruction>, Vec<u32>) {
 
 macro_rules! targets {
 	($($target:expr),*) => {
-		crate::isa::BrTargets::from_internal(
-			&[$($target,)*]
-				.iter()
-				.map(|&target| crate::isa::InstructionInternal::BrTableTarget(target))
-				.collect::<Vec<_>>()[..]
-		)
+		crate::isa::BrTargets::from_internal(&crate::isa::encode_br_targets(&[$($target,)*]))
 	};
 }
 
@@ -56,10 +51,7 @@ fn implicit_return_no_value() {
     let (code, _) = compile(&module);
     assert_eq!(
         code,
-        vec![isa::Instruction::Return(isa::DropKeep {
-            drop: 0,
-            keep: isa::Keep::None,
-        })]
+        vec![isa::Instruction::Return(isa::DropKeep { drop: 0, keep: 0 })]
     )
 }
 
@@ -79,10 +71,7 @@ fn implicit_return_with_value() {
         code,
         vec![
             isa::Instruction::I32Const(0),
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 0,
-                keep: isa::Keep::Single,
-            }),
+            isa::Instruction::Return(isa::DropKeep { drop: 0, keep: 1 }),
         ]
     )
 }
@@ -100,10 +89,7 @@ fn implicit_return_param() {
     let (code, _) = compile(&module);
     assert_eq!(
         code,
-        vec![isa::Instruction::Return(isa::DropKeep {
-            drop: 1,
-            keep: isa::Keep::None,
-        }),]
+        vec![isa::Instruction::Return(isa::DropKeep { drop: 1, keep: 0 }),]
     )
 }
 
@@ -123,10 +109,7 @@ fn get_local() {
         code,
         vec![
             isa::Instruction::GetLocal(1),
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 1,
-                keep: isa::Keep::Single,
-            }),
+            isa::Instruction::Return(isa::DropKeep { drop: 1, keep: 1 }),
         ]
     )
 }
@@ -148,14 +131,8 @@ fn explicit_return() {
         code,
         vec![
             isa::Instruction::GetLocal(1),
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 1,
-                keep: isa::Keep::Single,
-            }),
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 1,
-                keep: isa::Keep::Single,
-            }),
+            isa::Instruction::Return(isa::DropKeep { drop: 1, keep: 1 }),
+            isa::Instruction::Return(isa::DropKeep { drop: 1, keep: 1 }),
         ]
     )
 }
@@ -185,10 +162,7 @@ fn add_params() {
             isa::Instruction::GetLocal(2),
             isa::Instruction::GetLocal(2),
             isa::Instruction::I32Add,
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 2,
-                keep: isa::Keep::Single,
-            }),
+            isa::Instruction::Return(isa::DropKeep { drop: 2, keep: 1 }),
         ]
     )
 }
@@ -212,10 +186,7 @@ fn drop_locals() {
         vec![
             isa::Instruction::GetLocal(2),
             isa::Instruction::SetLocal(1),
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 2,
-                keep: isa::Keep::None,
-            }),
+            isa::Instruction::Return(isa::DropKeep { drop: 2, keep: 0 }),
         ]
     )
 }
@@ -243,21 +214,15 @@ fn if_without_else() {
             isa::Instruction::I32Const(1),
             isa::Instruction::BrIfEqz(isa::Target {
                 dst_pc: pcs[4],
-                drop_keep: isa::DropKeep {
-                    drop: 0,
-                    keep: isa::Keep::None,
-                },
+                drop_keep: isa::DropKeep { drop: 0, keep: 0 },
             }),
             isa::Instruction::I32Const(2),
             isa::Instruction::Return(isa::DropKeep {
-                drop: 1,                 // 1 param
-                keep: isa::Keep::Single, // 1 result
+                drop: 1, // 1 param
+                keep: 1, // 1 result
             }),
             isa::Instruction::I32Const(3),
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 1,
-                keep: isa::Keep::Single,
-            }),
+            isa::Instruction::Return(isa::DropKeep { drop: 1, keep: 1 }),
         ]
     )
 }
@@ -288,26 +253,17 @@ fn if_else() {
             isa::Instruction::I32Const(1),
             isa::Instruction::BrIfEqz(isa::Target {
                 dst_pc: pcs[5],
-                drop_keep: isa::DropKeep {
-                    drop: 0,
-                    keep: isa::Keep::None,
-                },
+                drop_keep: isa::DropKeep { drop: 0, keep: 0 },
             }),
             isa::Instruction::I32Const(2),
             isa::Instruction::SetLocal(1),
             isa::Instruction::Br(isa::Target {
                 dst_pc: pcs[7],
-                drop_keep: isa::DropKeep {
-                    drop: 0,
-                    keep: isa::Keep::None,
-                },
+                drop_keep: isa::DropKeep { drop: 0, keep: 0 },
             }),
             isa::Instruction::I32Const(3),
             isa::Instruction::SetLocal(1),
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 1,
-                keep: isa::Keep::None,
-            }),
+            isa::Instruction::Return(isa::DropKeep { drop: 1, keep: 0 }),
         ]
     )
 }
@@ -336,25 +292,16 @@ fn if_else_returns_result() {
             isa::Instruction::I32Const(1),
             isa::Instruction::BrIfEqz(isa::Target {
                 dst_pc: pcs[4],
-                drop_keep: isa::DropKeep {
-                    drop: 0,
-                    keep: isa::Keep::None,
-                },
+                drop_keep: isa::DropKeep { drop: 0, keep: 0 },
             }),
             isa::Instruction::I32Const(2),
             isa::Instruction::Br(isa::Target {
                 dst_pc: pcs[5],
-                drop_keep: isa::DropKeep {
-                    drop: 0,
-                    keep: isa::Keep::None,
-                },
+                drop_keep: isa::DropKeep { drop: 0, keep: 0 },
             }),
             isa::Instruction::I32Const(3),
             isa::Instruction::Drop,
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 0,
-                keep: isa::Keep::None,
-            }),
+            isa::Instruction::Return(isa::DropKeep { drop: 0, keep: 0 }),
         ]
     )
 }
@@ -387,35 +334,25 @@ fn if_else_branch_from_true_branch() {
             isa::Instruction::I32Const(1),
             isa::Instruction::BrIfEqz(isa::Target {
                 dst_pc: pcs[8],
-                drop_keep: isa::DropKeep {
-                    drop: 0,
-                    keep: isa::Keep::None,
-                },
+                drop_keep: isa::DropKeep { drop: 0, keep: 0 },
             }),
             isa::Instruction::I32Const(1),
             isa::Instruction::I32Const(1),
+            // The branch carries the single result of the `if` and there is
+            // nothing below it in the block to drop.
             isa::Instruction::BrIfNez(isa::Target {
                 dst_pc: pcs[9],
-                drop_keep: isa::DropKeep {
-                    drop: 0,
-                    keep: isa::Keep::Single,
-                },
+                drop_keep: isa::DropKeep { drop: 0, keep: 1 },
             }),
             isa::Instruction::Drop,
             isa::Instruction::I32Const(2),
             isa::Instruction::Br(isa::Target {
                 dst_pc: pcs[9],
-                drop_keep: isa::DropKeep {
-                    drop: 0,
-                    keep: isa::Keep::None,
-                },
+                drop_keep: isa::DropKeep { drop: 0, keep: 0 },
             }),
             isa::Instruction::I32Const(3),
             isa::Instruction::Drop,
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 0,
-                keep: isa::Keep::None,
-            }),
+            isa::Instruction::Return(isa::DropKeep { drop: 0, keep: 0 }),
         ]
     )
 }
@@ -448,35 +385,25 @@ fn if_else_branch_from_false_branch() {
             isa::Instruction::I32Const(1),
             isa::Instruction::BrIfEqz(isa::Target {
                 dst_pc: pcs[4],
-                drop_keep: isa::DropKeep {
-                    drop: 0,
-                    keep: isa::Keep::None,
-                },
+                drop_keep: isa::DropKeep { drop: 0, keep: 0 },
             }),
             isa::Instruction::I32Const(1),
             isa::Instruction::Br(isa::Target {
                 dst_pc: pcs[9],
-                drop_keep: isa::DropKeep {
-                    drop: 0,
-                    keep: isa::Keep::None,
-                },
+                drop_keep: isa::DropKeep { drop: 0, keep: 0 },
             }),
             isa::Instruction::I32Const(2),
             isa::Instruction::I32Const(1),
+            // The branch carries the single result of the `if` and there is
+            // nothing below it in the block to drop.
             isa::Instruction::BrIfNez(isa::Target {
                 dst_pc: pcs[9],
-                drop_keep: isa::DropKeep {
-                    drop: 0,
-                    keep: isa::Keep::Single,
-                },
+                drop_keep: isa::DropKeep { drop: 0, keep: 1 },
             }),
             isa::Instruction::Drop,
             isa::Instruction::I32Const(3),
             isa::Instruction::Drop,
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 0,
-                keep: isa::Keep::None,
-            }),
+            isa::Instruction::Return(isa::DropKeep { drop: 0, keep: 0 }),
         ]
     )
 }
@@ -504,17 +431,11 @@ fn loop_() {
             isa::Instruction::I32Const(1),
             isa::Instruction::BrIfNez(isa::Target {
                 dst_pc: 0,
-                drop_keep: isa::DropKeep {
-                    drop: 0,
-                    keep: isa::Keep::None,
-                },
+                drop_keep: isa::DropKeep { drop: 0, keep: 0 },
             }),
             isa::Instruction::I32Const(2),
             isa::Instruction::Drop,
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 0,
-                keep: isa::Keep::None,
-            }),
+            isa::Instruction::Return(isa::DropKeep { drop: 0, keep: 0 }),
         ]
     )
 }
@@ -534,10 +455,7 @@ fn loop_empty() {
     let (code, _) = compile(&module);
     assert_eq!(
         code,
-        vec![isa::Instruction::Return(isa::DropKeep {
-            drop: 0,
-            keep: isa::Keep::None,
-        }),]
+        vec![isa::Instruction::Return(isa::DropKeep { drop: 0, keep: 0 }),]
     )
 }
 
@@ -563,7 +481,7 @@ fn spec_as_br_if_value_cond() {
         )
 	"#,
     );
-    let (code, _) = compile(&module);
+    let (code, pcs) = compile(&module);
     assert_eq!(
         code,
         vec![
@@ -572,33 +490,21 @@ fn spec_as_br_if_value_cond() {
             I32Const(0),
             isa::Instruction::BrTable(targets![
                 isa::Target {
-                    dst_pc: 9,
-                    drop_keep: isa::DropKeep {
-                        drop: 1,
-                        keep: isa::Keep::Single
-                    }
+                    dst_pc: pcs[7],
+                    drop_keep: isa::DropKeep { drop: 1, keep: 1 }
                 },
                 isa::Target {
-                    dst_pc: 9,
-                    drop_keep: isa::DropKeep {
-                        drop: 1,
-                        keep: isa::Keep::Single
-                    }
+                    dst_pc: pcs[7],
+                    drop_keep: isa::DropKeep { drop: 1, keep: 1 }
                 }
             ]),
             BrIfNez(isa::Target {
-                dst_pc: 9,
-                drop_keep: isa::DropKeep {
-                    drop: 0,
-                    keep: isa::Keep::Single
-                }
+                dst_pc: pcs[7],
+                drop_keep: isa::DropKeep { drop: 0, keep: 1 }
             }),
             Drop,
             I32Const(7),
-            Return(isa::DropKeep {
-                drop: 0,
-                keep: isa::Keep::Single
-            })
+            Return(isa::DropKeep { drop: 0, keep: 1 })
         ]
     );
 }
@@ -627,23 +533,14 @@ fn brtable() {
             isa::Instruction::BrTable(targets![
                 isa::Target {
                     dst_pc: 0,
-                    drop_keep: isa::DropKeep {
-                        drop: 0,
-                        keep: isa::Keep::None,
-                    },
+                    drop_keep: isa::DropKeep { drop: 0, keep: 0 },
                 },
                 isa::Target {
                     dst_pc: pcs[2],
-                    drop_keep: isa::DropKeep {
-                        drop: 0,
-                        keep: isa::Keep::None,
-                    },
+                    drop_keep: isa::DropKeep { drop: 0, keep: 0 },
                 }
             ]),
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 0,
-                keep: isa::Keep::None,
-            }),
+            isa::Instruction::Return(isa::DropKeep { drop: 0, keep: 0 }),
         ]
     )
 }
@@ -677,25 +574,16 @@ fn brtable_returns_result() {
             isa::Instruction::BrTable(targets![
                 isa::Target {
                     dst_pc: pcs[3],
-                    drop_keep: isa::DropKeep {
-                        drop: 0,
-                        keep: isa::Keep::Single,
-                    },
+                    drop_keep: isa::DropKeep { drop: 0, keep: 1 },
                 },
                 isa::Target {
                     dst_pc: pcs[4],
-                    drop_keep: isa::DropKeep {
-                        keep: isa::Keep::Single,
-                        drop: 0,
-                    },
+                    drop_keep: isa::DropKeep { keep: 1, drop: 0 },
                 }
             ]),
             isa::Instruction::Unreachable,
             isa::Instruction::Drop,
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 0,
-                keep: isa::Keep::None,
-            }),
+            isa::Instruction::Return(isa::DropKeep { drop: 0, keep: 0 }),
         ]
     )
 }
@@ -725,25 +613,43 @@ fn wabt_example() {
             isa::Instruction::GetLocal(1),
             isa::Instruction::BrIfNez(isa::Target {
                 dst_pc: pcs[4],
-                drop_keep: isa::DropKeep {
-                    drop: 0,
-                    keep: isa::Keep::None,
-                },
+                drop_keep: isa::DropKeep { drop: 0, keep: 0 },
             }),
             isa::Instruction::I32Const(1),
             isa::Instruction::Return(isa::DropKeep {
                 drop: 1, // 1 parameter
-                keep: isa::Keep::Single,
+                keep: 1,
             }),
             isa::Instruction::I32Const(2),
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 1,
-                keep: isa::Keep::Single,
-            }),
-            isa::Instruction::Return(isa::DropKeep {
-                drop: 1,
-                keep: isa::Keep::Single,
-            }),
+            isa::Instruction::Return(isa::DropKeep { drop: 1, keep: 1 }),
+            isa::Instruction::Return(isa::DropKeep { drop: 1, keep: 1 }),
         ]
     )
 }
+
+#[cfg(feature = "stack_height_checks")]
+#[test]
+fn stack_heights() {
+    let module = validate(
+        r#"
+		(module
+			(func (export "call") (param i32) (result i32)
+				i32.const 1
+				block (result i32)
+					get_local 0
+					get_local 0
+					br_if 0
+				end
+				i32.add
+			)
+		)
+	"#,
+    );
+    let (_, pcs) = compile(&module);
+    let code = &module.code_map()[0];
+    let heights: Vec<_> = pcs.iter().map(|&pc| code.stack_height(pc)).collect();
+    assert_eq!(
+        heights,
+        vec![Some(0), Some(1), Some(2), Some(3), Some(2), Some(1)]
+    );
+}
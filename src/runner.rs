@@ -1,18 +1,21 @@
 #![allow(clippy::unnecessary_wraps)]
 
-use crate::func::{FuncInstance, FuncInstanceInternal, FuncRef};
-use crate::host::Externals;
+use crate::func::{ExecutionStep, FuncInstanceInternal, FuncRef, PendingHostCall};
+use crate::host::{Caller, Externals, NopExternals};
 use crate::isa;
-use crate::memory::MemoryRef;
+use crate::memory::{MemoryInstance, MemoryRef};
 use crate::memory_units::Pages;
+#[cfg(feature = "import_usage")]
+use crate::module::ExternKind;
 use crate::module::ModuleRef;
 use crate::nan_preserving_float::{F32, F64};
 use crate::value::{
     ArithmeticOps, ExtendInto, Float, Integer, LittleEndianConvert, RuntimeValue, TransmuteInto,
-    TryTruncateInto, WrapInto,
+    TruncateSaturateInto, TryTruncateInto, WrapInto,
 };
 use crate::{Signature, Trap, TrapKind, ValueType};
-use alloc::{boxed::Box, vec::Vec};
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use core::cmp;
 use core::fmt;
 use core::ops;
 use core::{u32, usize};
@@ -131,6 +134,8 @@ pub enum InstructionOutcome {
     Branch(isa::Target),
     /// Execute function call.
     ExecuteCall(FuncRef),
+    /// `call_indirect` found a function with another signature than the expected one.
+    MismatchedCall(FuncRef, Rc<Signature>),
     /// Return from current function block.
     Return(isa::DropKeep),
 }
@@ -159,6 +164,11 @@ enum RunResult {
     Return,
     /// Function is calling other function.
     NestedCall(FuncRef),
+    /// Function is calling a function with an unexpected signature via `call_indirect`.
+    MismatchedCall(FuncRef, Rc<Signature>),
+    /// Function has used up the instructions it was allowed to execute.
+    #[cfg(feature = "stepping")]
+    Suspended,
 }
 
 /// Function interpreter.
@@ -167,6 +177,19 @@ pub struct Interpreter {
     call_stack: CallStack,
     return_type: Option<ValueType>,
     state: InterpreterState,
+    config: InvocationConfig,
+    /// Instructions left until the [`ExecutionDeadline`] is checked the next time.
+    #[cfg(feature = "execution_deadline")]
+    deadline_countdown: u32,
+    /// Number of host functions called by this invocation so far.
+    host_calls: u32,
+    /// Number of `memory.grow` instructions executed by this invocation so far.
+    memory_grows: u32,
+    /// Instructions left to execute until the execution is suspended by [`step`].
+    ///
+    /// [`step`]: #method.step
+    #[cfg(feature = "stepping")]
+    step_budget: u64,
 }
 
 impl Interpreter {
@@ -175,14 +198,15 @@ impl Interpreter {
         args: &[RuntimeValue],
         mut stack_recycler: Option<&mut StackRecycler>,
     ) -> Result<Interpreter, Trap> {
+        let config = stack_recycler
+            .as_ref()
+            .map_or_else(InvocationConfig::default, |this| this.config);
         let mut value_stack = StackRecycler::recreate_value_stack(&mut stack_recycler);
         for &arg in args {
             let arg = arg.into();
-            value_stack.push(arg).map_err(
-                // There is not enough space for pushing initial arguments.
-                // Weird, but bail out anyway.
-                |_| Trap::from(TrapKind::StackOverflow),
-            )?;
+            // There might not be enough space for pushing initial arguments.
+            // Weird, but bail out anyway.
+            value_stack.push(arg).map_err(Trap::new)?;
         }
 
         let mut call_stack = StackRecycler::recreate_call_stack(&mut stack_recycler);
@@ -196,6 +220,13 @@ impl Interpreter {
             call_stack,
             return_type,
             state: InterpreterState::Initialized,
+            config,
+            #[cfg(feature = "execution_deadline")]
+            deadline_countdown: 0,
+            host_calls: 0,
+            memory_grows: 0,
+            #[cfg(feature = "stepping")]
+            step_budget: u64::MAX,
         })
     }
 
@@ -211,7 +242,153 @@ impl Interpreter {
         assert!(self.state == InterpreterState::Initialized);
 
         self.state = InterpreterState::Started;
-        self.run_interpreter_loop(externals)?;
+        self.run_interpreter_loop(externals, false)
+            .map_err(|trap| self.unwind(trap))?;
+
+        self.take_return_value()
+    }
+
+    /// Starts the execution and runs it until it either finishes or calls a host function.
+    ///
+    /// The host function isn't called, but returned to the caller, which is expected to
+    /// call it and pass its result to [`resume_until_host_call`].
+    ///
+    /// [`resume_until_host_call`]: #method.resume_until_host_call
+    pub fn start_until_host_call(&mut self) -> Result<ExecutionStep, Trap> {
+        // Ensure that the VM has not been executed. This is checked in `FuncInvocation::run_until_host_call`.
+        assert!(self.state == InterpreterState::Initialized);
+
+        self.state = InterpreterState::Started;
+        self.run_until_host_call()
+    }
+
+    /// Resumes the execution after a host call with its `return_val` and runs it until it
+    /// either finishes or calls the next host function.
+    pub fn resume_until_host_call(
+        &mut self,
+        return_val: Option<RuntimeValue>,
+    ) -> Result<ExecutionStep, Trap> {
+        self.prepare_resume(return_val)?;
+        self.run_until_host_call()
+    }
+
+    fn run_until_host_call(&mut self) -> Result<ExecutionStep, Trap> {
+        match self
+            .run_interpreter_loop(&mut NopExternals, true)
+            .map_err(|trap| self.unwind(trap))?
+        {
+            Some(host_call) => Ok(ExecutionStep::HostCall(host_call)),
+            None => self.take_return_value().map(ExecutionStep::Finished),
+        }
+    }
+
+    /// Executes the next instruction of the execution.
+    ///
+    /// Returns `Some` with the return value if the execution finished, and `None` if it
+    /// was suspended before the following instruction. Calls of host functions are made
+    /// as part of the call instruction.
+    #[cfg(feature = "stepping")]
+    pub fn step<'a, E: Externals + 'a>(
+        &mut self,
+        externals: &'a mut E,
+    ) -> Result<Option<Option<RuntimeValue>>, Trap> {
+        self.state = InterpreterState::Started;
+        self.step_budget = 1;
+        let result = self.run_interpreter_loop(externals, false);
+        self.step_budget = u64::MAX;
+        result.map_err(|trap| self.unwind(trap))?;
+
+        if self.call_stack.is_empty() {
+            self.take_return_value().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the number of frames on the call stack.
+    #[cfg(feature = "stepping")]
+    pub fn call_depth(&self) -> usize {
+        self.call_stack.buf.len()
+    }
+
+    /// Returns the function of the innermost frame and the position of the next
+    /// instruction it executes.
+    #[cfg(feature = "stepping")]
+    pub fn current_frame(&self) -> Option<(&FuncRef, u32)> {
+        self.call_stack
+            .buf
+            .last()
+            .map(|frame| (&frame.function, frame.position))
+    }
+
+    /// Abandons the frames of an execution that trapped unless it can be resumed.
+    ///
+    /// The frames of the callers of the trapping function are still on the stacks and
+    /// would otherwise keep their functions and module instances alive for as long as
+    /// this interpreter exists.
+    fn unwind(&mut self, trap: Trap) -> Trap {
+        if !self.state.is_resumable() {
+            self.call_stack.buf.clear();
+            self.value_stack.sp = 0;
+        }
+        trap
+    }
+
+    /// Creates the trap raised by an instruction of the function running in `function_context`.
+    #[cfg(not(feature = "trap_state"))]
+    #[inline(always)]
+    fn trap_at(&self, kind: TrapKind, _: &FunctionContext, _: &[Local]) -> Trap {
+        Trap::new(kind)
+    }
+
+    /// Creates the trap raised by an instruction of the function running in `function_context`
+    /// and records the function's locals and operands in it.
+    #[cfg(feature = "trap_state")]
+    #[cold]
+    fn trap_at(
+        &self,
+        kind: TrapKind,
+        function_context: &FunctionContext,
+        locals: &[Local],
+    ) -> Trap {
+        let local_types = function_context
+            .function
+            .signature()
+            .params()
+            .iter()
+            .cloned()
+            .chain(locals.iter().flat_map(|local| {
+                let value_type = ValueType::from_elements(local.value_type());
+                (0..local.count()).map(move |_| value_type)
+            }))
+            .collect::<Vec<_>>();
+        // With the `defensive_checks` feature, unvalidated code might have trapped after
+        // popping values below the locals, so don't rely on the frame being complete.
+        let locals_start = function_context.value_stack_base - local_types.len();
+        let frame =
+            &self.value_stack.buf[locals_start..cmp::max(self.value_stack.sp, locals_start)];
+        let (local_values, operands) = frame.split_at(cmp::min(local_types.len(), frame.len()));
+        Trap::with_state(
+            kind,
+            TrapState {
+                locals: local_values
+                    .iter()
+                    .zip(local_types)
+                    .map(|(value, ty)| value.with_type(ty))
+                    .collect(),
+                operands: operands.iter().map(|value| value.0).collect(),
+            },
+        )
+    }
+
+    /// Pops the return value of the executed function off the value stack.
+    fn take_return_value(&mut self) -> Result<Option<RuntimeValue>, Trap> {
+        #[cfg(feature = "defensive_checks")]
+        {
+            if self.value_stack.len() != self.return_type.iter().count() {
+                return Err(TrapKind::InvalidCode.into());
+            }
+        }
 
         let opt_return_value = self
             .return_type
@@ -228,6 +405,17 @@ impl Interpreter {
         return_val: Option<RuntimeValue>,
         externals: &'a mut E,
     ) -> Result<Option<RuntimeValue>, Trap> {
+        self.prepare_resume(return_val)?;
+
+        self.run_interpreter_loop(externals, false)
+            .map_err(|trap| self.unwind(trap))?;
+
+        self.take_return_value()
+    }
+
+    /// Leaves the resumable state by pushing the result of the host call the execution
+    /// was suspended at.
+    fn prepare_resume(&mut self, return_val: Option<RuntimeValue>) -> Result<(), Trap> {
         use core::mem::swap;
 
         // Ensure that the VM is resumable. This is checked in `FuncInvocation::resume_execution`.
@@ -241,23 +429,21 @@ impl Interpreter {
                 .push(return_val.into())
                 .map_err(Trap::new)?;
         }
-
-        self.run_interpreter_loop(externals)?;
-
-        let opt_return_value = self
-            .return_type
-            .map(|vt| self.value_stack.pop().with_type(vt));
-
-        // Ensure that stack is empty after the execution. This is guaranteed by the validation properties.
-        assert!(self.value_stack.len() == 0);
-
-        Ok(opt_return_value)
+        Ok(())
     }
 
+    /// Runs the execution until the call stack is empty.
+    ///
+    /// With `yield_at_host_calls` the execution is suspended at the first call of a host
+    /// function, which is returned instead of being called.
     fn run_interpreter_loop<'a, E: Externals + 'a>(
         &mut self,
         externals: &'a mut E,
-    ) -> Result<(), Trap> {
+        yield_at_host_calls: bool,
+    ) -> Result<Option<PendingHostCall>, Trap> {
+        #[cfg(feature = "std")]
+        let _entry = ExecutionEntry::enter()?;
+
         loop {
             let mut function_context = self.call_stack.pop().expect(
                 "on loop entry - not empty; on loop continue - checking for emptiness; qed",
@@ -275,20 +461,51 @@ impl Interpreter {
             }
 
             let function_return = self
-                .do_run_function(&mut function_context, &function_body.code)
-                .map_err(Trap::new)?;
+                .do_run_function(&mut function_context, function_body.code.instructions())
+                .map_err(|kind| self.trap_at(kind, &function_context, &function_body.locals))?;
+
+            let nested_func = match function_return {
+                RunResult::Return => None,
+                RunResult::NestedCall(nested_func) => Some(nested_func),
+                #[cfg(feature = "stepping")]
+                RunResult::Suspended => {
+                    self.call_stack.push(function_context);
+                    return Ok(None);
+                }
+                RunResult::MismatchedCall(found, expected) => {
+                    // Only a fallback of the expected signature keeps the value stack intact.
+                    match externals
+                        .on_signature_mismatch(&expected, &found)
+                        .filter(|fallback| *fallback.signature() == *expected)
+                    {
+                        Some(fallback) => Some(fallback),
+                        None => {
+                            return Err(self.trap_at(
+                                TrapKind::UnexpectedSignature,
+                                &function_context,
+                                &function_body.locals,
+                            ))
+                        }
+                    }
+                }
+            };
 
-            match function_return {
-                RunResult::Return => {
+            #[cfg(feature = "import_usage")]
+            if let Some(ref nested_func) = nested_func {
+                function_context.module.record_func_use(nested_func);
+            }
+
+            match nested_func {
+                None => {
                     if self.call_stack.is_empty() {
                         // This was the last frame in the call stack. This means we
                         // are done executing.
-                        return Ok(());
+                        return Ok(None);
                     }
                 }
-                RunResult::NestedCall(nested_func) => {
+                Some(nested_func) => {
                     if self.call_stack.is_full() {
-                        return Err(TrapKind::StackOverflow.into());
+                        return Err(self.call_stack.overflow().into());
                     }
 
                     match *nested_func.as_internal() {
@@ -297,30 +514,83 @@ impl Interpreter {
                             self.call_stack.push(function_context);
                             self.call_stack.push(nested_context);
                         }
-                        FuncInstanceInternal::Host { ref signature, .. } => {
-                            let args = prepare_function_args(signature, &mut self.value_stack);
+                        FuncInstanceInternal::Host { .. }
+                        | FuncInstanceInternal::HostClosure { .. } => {
+                            let args = prepare_function_args(
+                                nested_func.signature(),
+                                &mut self.value_stack,
+                            );
+                            let caller_module = function_context.module();
+                            let caller_func = function_context.function.clone();
+                            // The position was already advanced past the call instruction.
+                            let call_position = function_context.position - isa::CALL_SIZE;
                             // We push the function context first. If the VM is not resumable, it does no harm. If it is, we then save the context here.
                             self.call_stack.push(function_context);
 
-                            let return_val =
-                                match FuncInstance::invoke(&nested_func, &args, externals) {
-                                    Ok(val) => val,
-                                    Err(trap) => {
-                                        if trap.kind().is_host() {
-                                            self.state = InterpreterState::Resumable(
-                                                nested_func.signature().return_type(),
-                                            );
-                                        }
-                                        return Err(trap);
+                            self.host_calls = self.host_calls.saturating_add(1);
+                            if let Some(limit) = self.config.host_call_limit {
+                                if self.host_calls > limit {
+                                    return Err(TrapKind::HostCallLimitExceeded { limit }.into());
+                                }
+                            }
+
+                            if yield_at_host_calls {
+                                self.state = InterpreterState::Resumable(
+                                    nested_func.signature().return_type(),
+                                );
+                                return Ok(Some(PendingHostCall::new(nested_func, args)));
+                            }
+
+                            // Host functions might re-enter wasm, which recurses on the native stack.
+                            #[cfg(feature = "std")]
+                            NativeStackGuard::check()?;
+
+                            let result = match *nested_func.as_internal() {
+                                FuncInstanceInternal::Host {
+                                    ref host_func_index,
+                                    ..
+                                } => {
+                                    let caller = Caller::new(
+                                        &caller_module,
+                                        &caller_func,
+                                        call_position,
+                                        self.value_stack.headroom(),
+                                    );
+                                    externals.invoke_index_with_caller(
+                                        *host_func_index,
+                                        args.as_slice().into(),
+                                        caller,
+                                    )
+                                }
+                                FuncInstanceInternal::HostClosure { ref closure, .. } => {
+                                    closure(args.as_slice().into())
+                                }
+                                FuncInstanceInternal::Internal { .. } => unreachable!(),
+                            };
+                            let return_val = match result {
+                                Ok(val) => val,
+                                Err(trap) => {
+                                    if trap.kind().is_host() {
+                                        self.state = InterpreterState::Resumable(
+                                            nested_func.signature().return_type(),
+                                        );
                                     }
-                                };
+                                    return Err(trap);
+                                }
+                            };
 
                             // Check if `return_val` matches the signature.
-                            let value_ty = return_val.as_ref().map(|val| val.value_type());
-                            let expected_ty = nested_func.signature().return_type();
-                            if value_ty != expected_ty {
-                                return Err(TrapKind::UnexpectedSignature.into());
-                            }
+                            check_function_result(
+                                nested_func.signature().return_type(),
+                                return_val.as_ref(),
+                            )?;
+
+                            // Let the host apply its policy, which might substitute the value.
+                            let return_val = externals.on_host_result(&nested_func, return_val)?;
+                            check_function_result(
+                                nested_func.signature().return_type(),
+                                return_val.as_ref(),
+                            )?;
 
                             if let Some(return_val) = return_val {
                                 self.value_stack
@@ -342,11 +612,51 @@ impl Interpreter {
         let mut iter = instructions.iterate_from(function_context.position);
 
         loop {
-            let instruction = iter.next().expect(
-                "Ran out of instructions, this should be impossible \
-                 since validation ensures that we either have an explicit \
-                 return or an implicit block `end`.",
-            );
+            #[cfg(feature = "stepping")]
+            {
+                if self.step_budget == 0 {
+                    function_context.position = iter.position();
+                    return Ok(RunResult::Suspended);
+                }
+                self.step_budget -= 1;
+            }
+
+            #[cfg(feature = "execution_deadline")]
+            {
+                if self.deadline_countdown == 0 {
+                    self.deadline_countdown = ExecutionDeadline::check()?;
+                }
+                self.deadline_countdown -= 1;
+            }
+
+            #[cfg(feature = "stack_height_checks")]
+            {
+                let pc = iter.position();
+                if let Some(height) = instructions.stack_height(pc) {
+                    let expected = function_context.value_stack_base + height as usize;
+                    assert_eq!(
+                        self.value_stack.len(),
+                        expected,
+                        "value stack height mismatch before executing instruction at {}",
+                        pc
+                    );
+                }
+            }
+
+            let instruction = match iter.next() {
+                Some(instruction) => instruction,
+                #[cfg(feature = "defensive_checks")]
+                None => return Err(TrapKind::InvalidCode),
+                #[cfg(not(feature = "defensive_checks"))]
+                None => panic!(
+                    "Ran out of instructions, this should be impossible \
+                     since validation ensures that we either have an explicit \
+                     return or an implicit block `end`."
+                ),
+            };
+
+            #[cfg(feature = "defensive_checks")]
+            self.check_instruction(function_context, instructions, &instruction)?;
 
             match self.run_instruction(function_context, &instruction)? {
                 InstructionOutcome::RunNextInstruction => {}
@@ -358,6 +668,10 @@ impl Interpreter {
                     function_context.position = iter.position();
                     return Ok(RunResult::NestedCall(func_ref));
                 }
+                InstructionOutcome::MismatchedCall(found, expected) => {
+                    function_context.position = iter.position();
+                    return Ok(RunResult::MismatchedCall(found, expected));
+                }
                 InstructionOutcome::Return(drop_keep) => {
                     self.value_stack.drop_keep(drop_keep);
                     break;
@@ -368,6 +682,109 @@ impl Interpreter {
         Ok(RunResult::Return)
     }
 
+    /// Checks that `instruction` can be executed without running into any of the
+    /// invariants that are normally guaranteed by validation.
+    ///
+    /// Values on the stack are untyped, so only the number of operands is checked,
+    /// not their types.
+    #[cfg(feature = "defensive_checks")]
+    fn check_instruction(
+        &self,
+        context: &FunctionContext,
+        instructions: &isa::Instructions,
+        instruction: &isa::Instruction,
+    ) -> Result<(), TrapKind> {
+        let check = |condition: bool| {
+            if condition {
+                Ok(())
+            } else {
+                Err(TrapKind::InvalidCode)
+            }
+        };
+        let module = context.module();
+        let len = self.value_stack.len();
+        check(instruction.operand_count() <= len)?;
+        // The height of the stack after the operands are popped.
+        let rest = len - instruction.operand_count();
+        let check_target = |target: &isa::Target| {
+            check(instructions.is_branch_target(target.dst_pc))?;
+            check(target.drop_keep.drop as usize + target.drop_keep.keep as usize <= rest)
+        };
+
+        match *instruction {
+            isa::Instruction::Br(ref target)
+            | isa::Instruction::BrIfEqz(ref target)
+            | isa::Instruction::BrIfNez(ref target) => check_target(target)?,
+            isa::Instruction::BrTable(ref targets) => {
+                check(targets.len() > 0)?;
+                for index in 0..targets.len() {
+                    check_target(&targets.get(index))?;
+                }
+            }
+            isa::Instruction::Return(drop_keep) => {
+                check(drop_keep.drop as usize + drop_keep.keep as usize <= rest)?
+            }
+            isa::Instruction::GetLocal(depth) | isa::Instruction::TeeLocal(depth) => {
+                check(depth >= 1 && depth as usize <= len)?
+            }
+            isa::Instruction::SetLocal(depth) => check(depth >= 1 && depth as usize <= rest)?,
+            isa::Instruction::Call(index) => {
+                let func = module.func_by_index(index).ok_or(TrapKind::InvalidCode)?;
+                check(func.signature().params().len() <= rest)?;
+            }
+            isa::Instruction::CallIndirect(index) => {
+                let signature = module
+                    .signature_by_index(index)
+                    .ok_or(TrapKind::InvalidCode)?;
+                check(signature.params().len() <= rest)?;
+                check(module.table_by_index(DEFAULT_TABLE_INDEX).is_some())?;
+            }
+            isa::Instruction::GetGlobal(index) => check(module.global_by_index(index).is_some())?,
+            isa::Instruction::SetGlobal(index) => {
+                let global = module.global_by_index(index).ok_or(TrapKind::InvalidCode)?;
+                check(global.is_mutable())?;
+            }
+            isa::Instruction::I32Load(_)
+            | isa::Instruction::I64Load(_)
+            | isa::Instruction::F32Load(_)
+            | isa::Instruction::F64Load(_)
+            | isa::Instruction::I32Load8S(_)
+            | isa::Instruction::I32Load8U(_)
+            | isa::Instruction::I32Load16S(_)
+            | isa::Instruction::I32Load16U(_)
+            | isa::Instruction::I64Load8S(_)
+            | isa::Instruction::I64Load8U(_)
+            | isa::Instruction::I64Load16S(_)
+            | isa::Instruction::I64Load16U(_)
+            | isa::Instruction::I64Load32S(_)
+            | isa::Instruction::I64Load32U(_)
+            | isa::Instruction::I32Store(_)
+            | isa::Instruction::I64Store(_)
+            | isa::Instruction::F32Store(_)
+            | isa::Instruction::F64Store(_)
+            | isa::Instruction::I32Store8(_)
+            | isa::Instruction::I32Store16(_)
+            | isa::Instruction::I64Store8(_)
+            | isa::Instruction::I64Store16(_)
+            | isa::Instruction::I64Store32(_)
+            | isa::Instruction::CurrentMemory
+            | isa::Instruction::GrowMemory
+            | isa::Instruction::MemoryAtomicNotify(_)
+            | isa::Instruction::MemoryAtomicWait32(_)
+            | isa::Instruction::MemoryAtomicWait64(_) => check(context.memory.is_some())?,
+            isa::Instruction::MemoryCopy { src_mem, dst_mem } => {
+                check(module.memory_by_index(src_mem).is_some())?;
+                check(module.memory_by_index(dst_mem).is_some())?;
+            }
+            isa::Instruction::TableInit(index) => {
+                check(module.table_by_index(DEFAULT_TABLE_INDEX).is_some())?;
+                check(module.with_elem_segment(index, |_| ()).is_some())?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
     #[inline(always)]
     fn run_instruction(
         &mut self,
@@ -375,6 +792,7 @@ impl Interpreter {
         instruction: &isa::Instruction,
     ) -> Result<InstructionOutcome, TrapKind> {
         match instruction {
+            isa::Instruction::Nop => Ok(InstructionOutcome::RunNextInstruction),
             isa::Instruction::Unreachable => self.run_unreachable(context),
 
             isa::Instruction::Br(target) => self.run_br(context, *target),
@@ -448,6 +866,21 @@ impl Interpreter {
 
             isa::Instruction::CurrentMemory => self.run_current_memory(context),
             isa::Instruction::GrowMemory => self.run_grow_memory(context),
+            isa::Instruction::MemoryCopy { src_mem, dst_mem } => {
+                self.run_memory_copy(context, *src_mem, *dst_mem)
+            }
+            isa::Instruction::MemoryAtomicNotify(offset) => {
+                self.run_memory_atomic_notify(context, *offset)
+            }
+            isa::Instruction::MemoryAtomicWait32(offset) => {
+                self.run_memory_atomic_wait::<i32>(context, *offset)
+            }
+            isa::Instruction::MemoryAtomicWait64(offset) => {
+                self.run_memory_atomic_wait::<i64>(context, *offset)
+            }
+
+            isa::Instruction::TableInit(index) => self.run_table_init(context, *index),
+            isa::Instruction::ElemDrop(index) => self.run_elem_drop(context, *index),
 
             isa::Instruction::I32Const(val) => self.run_const((*val).into()),
             isa::Instruction::I64Const(val) => self.run_const((*val).into()),
@@ -586,6 +1019,15 @@ impl Interpreter {
             isa::Instruction::I64ReinterpretF64 => self.run_reinterpret::<F64, i64>(),
             isa::Instruction::F32ReinterpretI32 => self.run_reinterpret::<i32, F32>(),
             isa::Instruction::F64ReinterpretI64 => self.run_reinterpret::<i64, F64>(),
+
+            isa::Instruction::I32TruncSatSF32 => self.run_trunc_to_int_sat::<F32, i32, i32>(),
+            isa::Instruction::I32TruncSatUF32 => self.run_trunc_to_int_sat::<F32, u32, i32>(),
+            isa::Instruction::I32TruncSatSF64 => self.run_trunc_to_int_sat::<F64, i32, i32>(),
+            isa::Instruction::I32TruncSatUF64 => self.run_trunc_to_int_sat::<F64, u32, i32>(),
+            isa::Instruction::I64TruncSatSF32 => self.run_trunc_to_int_sat::<F32, i64, i64>(),
+            isa::Instruction::I64TruncSatUF32 => self.run_trunc_to_int_sat::<F32, u64, i64>(),
+            isa::Instruction::I64TruncSatSF64 => self.run_trunc_to_int_sat::<F64, i64, i64>(),
+            isa::Instruction::I64TruncSatUF64 => self.run_trunc_to_int_sat::<F64, u64, i64>(),
         }
     }
 
@@ -640,8 +1082,9 @@ impl Interpreter {
         func_idx: u32,
     ) -> Result<InstructionOutcome, TrapKind> {
         let func = context
-            .module()
-            .func_by_index(func_idx)
+            .call_targets
+            .get(func_idx as usize)
+            .cloned()
             .expect("Due to validation func should exists");
         Ok(InstructionOutcome::ExecuteCall(func))
     }
@@ -668,8 +1111,16 @@ impl Interpreter {
                 .signature_by_index(signature_idx)
                 .expect("Due to validation type should exists");
 
-            if &*required_function_type != actual_function_type {
-                return Err(TrapKind::UnexpectedSignature);
+            // Functions of the same module share interned signatures, so most calls are
+            // resolved by the pointer comparison. Imported functions need the full one.
+            if !core::ptr::eq(&*required_function_type, actual_function_type)
+                && &*required_function_type != actual_function_type
+            {
+                // Let the embedder decide whether to trap, see `Externals::on_signature_mismatch`.
+                return Ok(InstructionOutcome::MismatchedCall(
+                    func_ref,
+                    required_function_type,
+                ));
             }
         }
 
@@ -713,6 +1164,8 @@ impl Interpreter {
         context: &mut FunctionContext,
         index: u32,
     ) -> Result<InstructionOutcome, TrapKind> {
+        #[cfg(feature = "import_usage")]
+        context.module.record_import_use(ExternKind::Global, index);
         let global = context
             .module()
             .global_by_index(index)
@@ -728,6 +1181,8 @@ impl Interpreter {
         index: u32,
     ) -> Result<InstructionOutcome, TrapKind> {
         let val = self.value_stack.pop();
+        #[cfg(feature = "import_usage")]
+        context.module.record_import_use(ExternKind::Global, index);
         let global = context
             .module()
             .global_by_index(index)
@@ -748,17 +1203,61 @@ impl Interpreter {
         T: LittleEndianConvert,
     {
         let raw_address = self.value_stack.pop_as();
-        let address = effective_address(offset, raw_address)?;
-        let m = context
-            .memory()
-            .expect("Due to validation memory should exists");
-        let n: T = m
-            .get_value(address)
-            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+        let n: T = load(
+            context.memory(),
+            raw_address,
+            offset,
+            self.config.relaxed_memory,
+        )?;
         self.value_stack.push(n.into())?;
         Ok(InstructionOutcome::RunNextInstruction)
     }
 
+    fn run_memory_atomic_notify(
+        &mut self,
+        context: &mut FunctionContext,
+        offset: u32,
+    ) -> Result<InstructionOutcome, TrapKind> {
+        let _count: u32 = self.value_stack.pop_as();
+        let raw_address = self.value_stack.pop_as();
+        atomic_address::<i32>(context.memory(), raw_address, offset)?;
+        // There is only a single thread, so nobody is ever waiting to be woken.
+        self.value_stack.push(RuntimeValueInternal::from(0i32))?;
+        Ok(InstructionOutcome::RunNextInstruction)
+    }
+
+    fn run_memory_atomic_wait<T>(
+        &mut self,
+        context: &mut FunctionContext,
+        offset: u32,
+    ) -> Result<InstructionOutcome, TrapKind>
+    where
+        T: FromRuntimeValueInternal + LittleEndianConvert + PartialEq,
+    {
+        let timeout: i64 = self.value_stack.pop_as();
+        let expected: T = self.value_stack.pop_as();
+        let raw_address = self.value_stack.pop_as();
+        let memory = context.memory();
+        if !memory.is_shared() {
+            return Err(TrapKind::ExpectedSharedMemory);
+        }
+        let address = atomic_address::<T>(memory, raw_address, offset)?;
+        let actual: T = memory
+            .get_value(address)
+            .map_err(|_| memory.access_trap())?;
+        // Values only change while waiting if another thread changes them, so waiting
+        // either ends right away or never. See `MemoryInstance::alloc_shared`.
+        let result: i32 = if actual != expected {
+            1 // not-equal
+        } else if timeout >= 0 {
+            2 // timed-out
+        } else {
+            return Err(TrapKind::WaitWouldBlockForever);
+        };
+        self.value_stack.push(result.into())?;
+        Ok(InstructionOutcome::RunNextInstruction)
+    }
+
     fn run_load_extend<T, U>(
         &mut self,
         context: &mut FunctionContext,
@@ -770,13 +1269,12 @@ impl Interpreter {
         T: LittleEndianConvert,
     {
         let raw_address = self.value_stack.pop_as();
-        let address = effective_address(offset, raw_address)?;
-        let m = context
-            .memory()
-            .expect("Due to validation memory should exists");
-        let v: T = m
-            .get_value(address)
-            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+        let v: T = load(
+            context.memory(),
+            raw_address,
+            offset,
+            self.config.relaxed_memory,
+        )?;
         let stack_value: U = v.extend_into();
         self.value_stack
             .push(stack_value.into())
@@ -795,13 +1293,13 @@ impl Interpreter {
     {
         let stack_value = self.value_stack.pop_as::<T>();
         let raw_address = self.value_stack.pop_as::<u32>();
-        let address = effective_address(offset, raw_address)?;
-
-        let m = context
-            .memory()
-            .expect("Due to validation memory should exists");
-        m.set_value(address, stack_value)
-            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+        store(
+            context.memory(),
+            raw_address,
+            offset,
+            stack_value,
+            self.config.relaxed_memory,
+        )?;
         Ok(InstructionOutcome::RunNextInstruction)
     }
 
@@ -818,12 +1316,13 @@ impl Interpreter {
         let stack_value: T = <_>::from_runtime_value_internal(self.value_stack.pop());
         let stack_value = stack_value.wrap_into();
         let raw_address = self.value_stack.pop_as::<u32>();
-        let address = effective_address(offset, raw_address)?;
-        let m = context
-            .memory()
-            .expect("Due to validation memory should exists");
-        m.set_value(address, stack_value)
-            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+        store(
+            context.memory(),
+            raw_address,
+            offset,
+            stack_value,
+            self.config.relaxed_memory,
+        )?;
         Ok(InstructionOutcome::RunNextInstruction)
     }
 
@@ -831,9 +1330,7 @@ impl Interpreter {
         &mut self,
         context: &mut FunctionContext,
     ) -> Result<InstructionOutcome, TrapKind> {
-        let m = context
-            .memory()
-            .expect("Due to validation memory should exists");
+        let m = context.memory();
         let s = m.current_size().0;
         self.value_stack.push(RuntimeValueInternal(s as _))?;
         Ok(InstructionOutcome::RunNextInstruction)
@@ -844,20 +1341,161 @@ impl Interpreter {
         context: &mut FunctionContext,
     ) -> Result<InstructionOutcome, TrapKind> {
         let pages: u32 = self.value_stack.pop_as();
-        let m = context
-            .memory()
-            .expect("Due to validation memory should exists");
+        let m = context.memory();
+        if m.is_read_only() {
+            return Err(TrapKind::MemoryReadOnly);
+        }
+        self.memory_grows = self.memory_grows.saturating_add(1);
+        if let Some(limit) = self.config.memory_grow_limit {
+            if self.memory_grows > limit {
+                self.value_stack.push(RuntimeValueInternal(u32::MAX as _))?;
+                return Ok(InstructionOutcome::RunNextInstruction);
+            }
+        }
         let m = match m.grow(Pages(pages as usize)) {
-            Ok(Pages(new_size)) => new_size as u32,
+            Ok(Pages(size_before_grow)) => size_before_grow as u32,
             Err(_) => u32::MAX, // Returns -1 (or 0xFFFFFFFF) in case of error.
         };
         self.value_stack.push(RuntimeValueInternal(m as _))?;
         Ok(InstructionOutcome::RunNextInstruction)
     }
 
-    fn run_const(&mut self, val: RuntimeValue) -> Result<InstructionOutcome, TrapKind> {
+    fn run_memory_copy(
+        &mut self,
+        context: &mut FunctionContext,
+        src_mem: u32,
+        dst_mem: u32,
+    ) -> Result<InstructionOutcome, TrapKind> {
+        let len: u32 = self.value_stack.pop_as();
+        let src: u32 = self.value_stack.pop_as();
+        let dst: u32 = self.value_stack.pop_as();
+        let module = context.module();
+        #[cfg(feature = "import_usage")]
+        {
+            module.record_import_use(ExternKind::Memory, src_mem);
+            module.record_import_use(ExternKind::Memory, dst_mem);
+        }
+        let src_memory = module
+            .memory_by_index(src_mem)
+            .expect("Due to validation memory should exists");
+        let dst_memory = module
+            .memory_by_index(dst_mem)
+            .expect("Due to validation memory should exists");
+        if dst_memory.is_read_only() {
+            return Err(TrapKind::MemoryReadOnly);
+        }
+        let access_trap = |_| {
+            if src_memory.is_detached() {
+                src_memory.access_trap()
+            } else {
+                dst_memory.access_trap()
+            }
+        };
+
+        // Copies within the same memory might overlap and are handled by `transfer`.
+        #[cfg(not(feature = "execution_deadline"))]
+        MemoryInstance::transfer(
+            &src_memory,
+            src as usize,
+            &dst_memory,
+            dst as usize,
+            len as usize,
+        )
+        .map_err(access_trap)?;
+
+        // Large copies are made in chunks, so that the deadline can interrupt them. Both
+        // regions are checked up front, so that only interrupted copies have partial effects.
+        #[cfg(feature = "execution_deadline")]
+        {
+            let (src, dst, len) = (src as usize, dst as usize, len as usize);
+            src_memory.check_region(src, len).map_err(access_trap)?;
+            dst_memory.check_region(dst, len).map_err(access_trap)?;
+            let mut copied = 0;
+            while copied < len {
+                if copied > 0 {
+                    ExecutionDeadline::check()?;
+                }
+                let chunk = cmp::min(len - copied, MEMORY_COPY_CHUNK_SIZE);
+                // Copy the chunks back to front if the destination might overlap the end of
+                // the source, like `memmove`.
+                let offset = if dst > src {
+                    len - copied - chunk
+                } else {
+                    copied
+                };
+                MemoryInstance::transfer(
+                    &src_memory,
+                    src + offset,
+                    &dst_memory,
+                    dst + offset,
+                    chunk,
+                )
+                .map_err(access_trap)?;
+                copied += chunk;
+            }
+        }
+        Ok(InstructionOutcome::RunNextInstruction)
+    }
+
+    fn run_table_init(
+        &mut self,
+        context: &mut FunctionContext,
+        segment_idx: u32,
+    ) -> Result<InstructionOutcome, TrapKind> {
+        let (dst, src, len) = self.value_stack.pop_triple();
+        let dst: u32 = <_>::from_runtime_value_internal(dst);
+        let src: u32 = <_>::from_runtime_value_internal(src);
+        let len: u32 = <_>::from_runtime_value_internal(len);
+        let table = context
+            .module()
+            .table_by_index(DEFAULT_TABLE_INDEX)
+            .expect("Due to validation table should exists");
+        // Only the copied range of the segment is cloned, and the segment is no longer
+        // borrowed while the table is written.
+        let funcs = context
+            .module()
+            .with_elem_segment(segment_idx, |segment| {
+                src.checked_add(len)
+                    .and_then(|end| segment.get(src as usize..end as usize))
+                    .map(<[FuncRef]>::to_vec)
+            })
+            .expect("Due to validation element segment should exists")
+            .ok_or(TrapKind::TableAccessOutOfBounds)?;
+        let table_size = table.current_size();
+        if dst as u64 + len as u64 > table_size as u64 {
+            return Err(TrapKind::TableSetOutOfBounds {
+                table_index: DEFAULT_TABLE_INDEX,
+                index: cmp::max(dst, table_size),
+            });
+        }
+        for (offset, func) in funcs.into_iter().enumerate() {
+            let index = dst + offset as u32;
+            table
+                .set(index, Some(func))
+                .map_err(|_| TrapKind::TableSetOutOfBounds {
+                    table_index: DEFAULT_TABLE_INDEX,
+                    index,
+                })?;
+        }
+        Ok(InstructionOutcome::RunNextInstruction)
+    }
+
+    fn run_elem_drop(
+        &mut self,
+        context: &mut FunctionContext,
+        segment_idx: u32,
+    ) -> Result<InstructionOutcome, TrapKind> {
+        context.module().drop_elem_segment(segment_idx);
+        Ok(InstructionOutcome::RunNextInstruction)
+    }
+
+    /// Pushes a constant onto the value stack.
+    ///
+    /// Float constants are stored in the instruction as their bit patterns, so
+    /// they can be pushed as is without a round trip through [`RuntimeValue`].
+    fn run_const(&mut self, val: RuntimeValueInternal) -> Result<InstructionOutcome, TrapKind> {
         self.value_stack
-            .push(val.into())
+            .push(val)
             .map_err(Into::into)
             .map(|_| InstructionOutcome::RunNextInstruction)
     }
@@ -867,14 +1505,13 @@ impl Interpreter {
         T: FromRuntimeValueInternal,
         F: FnOnce(T, T) -> bool,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let v = if f(left, right) {
-            RuntimeValueInternal(1)
-        } else {
-            RuntimeValueInternal(0)
-        };
-        self.value_stack.push(v)?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        self.run_binop(|left: T, right: T| {
+            Ok(if f(left, right) {
+                RuntimeValueInternal(1)
+            } else {
+                RuntimeValueInternal(0)
+            })
+        })
     }
 
     fn run_eqz<T>(&mut self) -> Result<InstructionOutcome, TrapKind>
@@ -930,6 +1567,16 @@ impl Interpreter {
         self.run_relop(|left: T, right: T| left >= right)
     }
 
+    fn run_binop<T, U, F>(&mut self, f: F) -> Result<InstructionOutcome, TrapKind>
+    where
+        F: FnOnce(T, T) -> Result<U, TrapKind>,
+        T: FromRuntimeValueInternal,
+        RuntimeValueInternal: From<U>,
+    {
+        self.value_stack.apply_binop(f)?;
+        Ok(InstructionOutcome::RunNextInstruction)
+    }
+
     fn run_unop<T, U, F>(&mut self, f: F) -> Result<InstructionOutcome, TrapKind>
     where
         F: FnOnce(T) -> U,
@@ -969,34 +1616,40 @@ impl Interpreter {
     fn run_add<T>(&mut self) -> Result<InstructionOutcome, TrapKind>
     where
         RuntimeValueInternal: From<T>,
-        T: ArithmeticOps<T> + FromRuntimeValueInternal,
+        RuntimeValue: From<T>,
+        T: ArithmeticOps<T> + FromRuntimeValueInternal + Copy,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let v = left.add(right);
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        self.run_binop(|left: T, right: T| {
+            #[cfg(feature = "overflow_tracing")]
+            OverflowTrace::check(OverflowingOp::Add, left, right);
+            Ok(left.add(right))
+        })
     }
 
     fn run_sub<T>(&mut self) -> Result<InstructionOutcome, TrapKind>
     where
         RuntimeValueInternal: From<T>,
-        T: ArithmeticOps<T> + FromRuntimeValueInternal,
+        RuntimeValue: From<T>,
+        T: ArithmeticOps<T> + FromRuntimeValueInternal + Copy,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let v = left.sub(right);
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        self.run_binop(|left: T, right: T| {
+            #[cfg(feature = "overflow_tracing")]
+            OverflowTrace::check(OverflowingOp::Sub, left, right);
+            Ok(left.sub(right))
+        })
     }
 
     fn run_mul<T>(&mut self) -> Result<InstructionOutcome, TrapKind>
     where
         RuntimeValueInternal: From<T>,
-        T: ArithmeticOps<T> + FromRuntimeValueInternal,
+        RuntimeValue: From<T>,
+        T: ArithmeticOps<T> + FromRuntimeValueInternal + Copy,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let v = left.mul(right);
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        self.run_binop(|left: T, right: T| {
+            #[cfg(feature = "overflow_tracing")]
+            OverflowTrace::check(OverflowingOp::Mul, left, right);
+            Ok(left.mul(right))
+        })
     }
 
     fn run_div<T, U>(&mut self) -> Result<InstructionOutcome, TrapKind>
@@ -1005,12 +1658,11 @@ impl Interpreter {
         T: TransmuteInto<U> + FromRuntimeValueInternal,
         U: ArithmeticOps<U> + TransmuteInto<T>,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let (left, right) = (left.transmute_into(), right.transmute_into());
-        let v = left.div(right)?;
-        let v = v.transmute_into();
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        self.run_binop(|left: T, right: T| {
+            let (left, right): (U, U) = (left.transmute_into(), right.transmute_into());
+            let v: T = left.div(right)?.transmute_into();
+            Ok(v)
+        })
     }
 
     fn run_rem<T, U>(&mut self) -> Result<InstructionOutcome, TrapKind>
@@ -1019,12 +1671,11 @@ impl Interpreter {
         T: TransmuteInto<U> + FromRuntimeValueInternal,
         U: Integer<U> + TransmuteInto<T>,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let (left, right) = (left.transmute_into(), right.transmute_into());
-        let v = left.rem(right)?;
-        let v = v.transmute_into();
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        self.run_binop(|left: T, right: T| {
+            let (left, right): (U, U) = (left.transmute_into(), right.transmute_into());
+            let v: T = left.rem(right)?.transmute_into();
+            Ok(v)
+        })
     }
 
     fn run_and<T>(&mut self) -> Result<InstructionOutcome, TrapKind>
@@ -1032,10 +1683,7 @@ impl Interpreter {
         RuntimeValueInternal: From<<T as ops::BitAnd>::Output>,
         T: ops::BitAnd<T> + FromRuntimeValueInternal,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let v = left.bitand(right);
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        self.run_binop(|left: T, right: T| Ok(left.bitand(right)))
     }
 
     fn run_or<T>(&mut self) -> Result<InstructionOutcome, TrapKind>
@@ -1043,10 +1691,7 @@ impl Interpreter {
         RuntimeValueInternal: From<<T as ops::BitOr>::Output>,
         T: ops::BitOr<T> + FromRuntimeValueInternal,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let v = left.bitor(right);
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        self.run_binop(|left: T, right: T| Ok(left.bitor(right)))
     }
 
     fn run_xor<T>(&mut self) -> Result<InstructionOutcome, TrapKind>
@@ -1054,36 +1699,33 @@ impl Interpreter {
         RuntimeValueInternal: From<<T as ops::BitXor>::Output>,
         T: ops::BitXor<T> + FromRuntimeValueInternal,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let v = left.bitxor(right);
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        self.run_binop(|left: T, right: T| Ok(left.bitxor(right)))
     }
 
     fn run_shl<T>(&mut self, mask: T) -> Result<InstructionOutcome, TrapKind>
     where
         RuntimeValueInternal: From<<T as ops::Shl<T>>::Output>,
-        T: ops::Shl<T> + ops::BitAnd<T, Output = T> + FromRuntimeValueInternal,
+        T: ops::Shl<T> + ops::BitAnd<T, Output = T> + Copy + PartialEq + FromRuntimeValueInternal,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let v = left.shl(right & mask);
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        let strict = self.config.strict_shifts;
+        self.run_binop(|left: T, right: T| Ok(left.shl(checked_shift(right, mask, strict)?)))
     }
 
     fn run_shr<T, U>(&mut self, mask: U) -> Result<InstructionOutcome, TrapKind>
     where
         RuntimeValueInternal: From<T>,
         T: TransmuteInto<U> + FromRuntimeValueInternal,
-        U: ops::Shr<U> + ops::BitAnd<U, Output = U>,
+        U: ops::Shr<U> + ops::BitAnd<U, Output = U> + Copy + PartialEq,
         <U as ops::Shr<U>>::Output: TransmuteInto<T>,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let (left, right) = (left.transmute_into(), right.transmute_into());
-        let v = left.shr(right & mask);
-        let v = v.transmute_into();
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        let strict = self.config.strict_shifts;
+        self.run_binop(|left: T, right: T| {
+            let (left, right): (U, U) = (left.transmute_into(), right.transmute_into());
+            let v: T = left
+                .shr(checked_shift(right, mask, strict)?)
+                .transmute_into();
+            Ok(v)
+        })
     }
 
     fn run_rotl<T>(&mut self) -> Result<InstructionOutcome, TrapKind>
@@ -1091,10 +1733,7 @@ impl Interpreter {
         RuntimeValueInternal: From<T>,
         T: Integer<T> + FromRuntimeValueInternal,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let v = left.rotl(right);
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        self.run_binop(|left: T, right: T| Ok(left.rotl(right)))
     }
 
     fn run_rotr<T>(&mut self) -> Result<InstructionOutcome, TrapKind>
@@ -1102,10 +1741,7 @@ impl Interpreter {
         RuntimeValueInternal: From<T>,
         T: Integer<T> + FromRuntimeValueInternal,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let v = left.rotr(right);
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        self.run_binop(|left: T, right: T| Ok(left.rotr(right)))
     }
 
     fn run_abs<T>(&mut self) -> Result<InstructionOutcome, TrapKind>
@@ -1169,10 +1805,7 @@ impl Interpreter {
         RuntimeValueInternal: From<T>,
         T: Float<T> + FromRuntimeValueInternal,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let v = left.min(right);
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        self.run_binop(|left: T, right: T| Ok(left.min(right)))
     }
 
     fn run_max<T>(&mut self) -> Result<InstructionOutcome, TrapKind>
@@ -1180,10 +1813,7 @@ impl Interpreter {
         RuntimeValueInternal: From<T>,
         T: Float<T> + FromRuntimeValueInternal,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let v = left.max(right);
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        self.run_binop(|left: T, right: T| Ok(left.max(right)))
     }
 
     fn run_copysign<T>(&mut self) -> Result<InstructionOutcome, TrapKind>
@@ -1191,10 +1821,7 @@ impl Interpreter {
         RuntimeValueInternal: From<T>,
         T: Float<T> + FromRuntimeValueInternal,
     {
-        let (left, right) = self.value_stack.pop_pair_as::<T>();
-        let v = left.copysign(right);
-        self.value_stack.push(v.into())?;
-        Ok(InstructionOutcome::RunNextInstruction)
+        self.run_binop(|left: T, right: T| Ok(left.copysign(right)))
     }
 
     fn run_wrap<T, U>(&mut self) -> Result<InstructionOutcome, TrapKind>
@@ -1219,6 +1846,20 @@ impl Interpreter {
             .map(|_| InstructionOutcome::RunNextInstruction)
     }
 
+    fn run_trunc_to_int_sat<T, U, V>(&mut self) -> Result<InstructionOutcome, TrapKind>
+    where
+        RuntimeValueInternal: From<V>,
+        T: TruncateSaturateInto<U> + FromRuntimeValueInternal,
+        U: TransmuteInto<V>,
+    {
+        let v = self.value_stack.pop_as::<T>();
+
+        let v: V = v.truncate_saturate_into().transmute_into();
+        self.value_stack.push(v.into())?;
+
+        Ok(InstructionOutcome::RunNextInstruction)
+    }
+
     fn run_extend<T, U, V>(&mut self) -> Result<InstructionOutcome, TrapKind>
     where
         RuntimeValueInternal: From<V>,
@@ -1256,23 +1897,32 @@ struct FunctionContext {
     pub function: FuncRef,
     pub module: ModuleRef,
     pub memory: Option<MemoryRef>,
+    /// Functions of the module, indexed by the immediate of `Call` instructions.
+    pub call_targets: Rc<[FuncRef]>,
     /// Current instruction position.
     pub position: u32,
+    /// Height of the value stack including arguments and locals of the function.
+    #[cfg(any(feature = "stack_height_checks", feature = "trap_state"))]
+    pub value_stack_base: usize,
 }
 
 impl FunctionContext {
     pub fn new(function: FuncRef) -> Self {
         let module = match function.as_internal() {
 			FuncInstanceInternal::Internal { module, .. } => module.upgrade().expect("module deallocated"),
-			FuncInstanceInternal::Host { .. } => panic!("Host functions can't be called as internally defined functions; Thus FunctionContext can be created only with internally defined functions; qed"),
+			FuncInstanceInternal::Host { .. } | FuncInstanceInternal::HostClosure { .. } => panic!("Host functions can't be called as internally defined functions; Thus FunctionContext can be created only with internally defined functions; qed"),
 		};
         let memory = module.memory_by_index(DEFAULT_MEMORY_INDEX);
+        let call_targets = module.call_targets();
         FunctionContext {
             is_initialized: false,
             function,
             module: ModuleRef(module),
             memory,
+            call_targets,
             position: 0,
+            #[cfg(any(feature = "stack_height_checks", feature = "trap_state"))]
+            value_stack_base: 0,
         }
     }
 
@@ -1291,6 +1941,11 @@ impl FunctionContext {
 
         value_stack.extend(num_locals)?;
 
+        #[cfg(any(feature = "stack_height_checks", feature = "trap_state"))]
+        {
+            self.value_stack_base = value_stack.len();
+        }
+
         self.is_initialized = true;
         Ok(())
     }
@@ -1299,8 +1954,15 @@ impl FunctionContext {
         self.module.clone()
     }
 
-    pub fn memory(&self) -> Option<&MemoryRef> {
-        self.memory.as_ref()
+    pub fn memory(&self) -> &MemoryRef {
+        #[cfg(feature = "import_usage")]
+        self.module
+            .record_import_use(ExternKind::Memory, DEFAULT_MEMORY_INDEX);
+        // Validation rejects memory instructions in modules without a memory, and
+        // instantiation fails if an imported memory isn't provided.
+        self.memory
+            .as_ref()
+            .expect("Due to validation memory should exists")
     }
 }
 
@@ -1317,18 +1979,94 @@ fn effective_address(address: u32, offset: u32) -> Result<u32, TrapKind> {
     }
 }
 
+/// Returns `address` plus `offset` for an atomic access to a `T` in `memory`.
+///
+/// Traps if the accessed bytes are out of bounds or if the address isn't a multiple of the
+/// size of `T`.
+fn atomic_address<T>(memory: &MemoryRef, address: u32, offset: u32) -> Result<u32, TrapKind> {
+    let address = effective_address(offset, address)?;
+    let size = core::mem::size_of::<T>();
+    memory
+        .check_region(address as usize, size)
+        .map_err(|_| memory.access_trap())?;
+    if address as usize & (size - 1) != 0 {
+        return Err(TrapKind::UnalignedAtomic);
+    }
+    Ok(address)
+}
+
+/// Loads a value from `address` plus `offset` in `memory`.
+///
+/// With `relaxed`, out of bounds loads yield zero instead of trapping.
+fn load<T: LittleEndianConvert>(
+    memory: &MemoryRef,
+    address: u32,
+    offset: u32,
+    relaxed: bool,
+) -> Result<T, TrapKind> {
+    let result = effective_address(offset, address)
+        .and_then(|address| memory.get_value(address).map_err(|_| memory.access_trap()));
+    match result {
+        Err(TrapKind::MemoryAccessOutOfBounds) if relaxed => {
+            let zero = [0u8; 8];
+            Ok(T::from_little_endian(&zero[..core::mem::size_of::<T>()])
+                .expect("all loadable types fit into 8 bytes"))
+        }
+        result => result,
+    }
+}
+
+/// Stores `value` to `address` plus `offset` in `memory`.
+///
+/// Traps if `memory` is read-only. With `relaxed`, out of bounds stores are ignored
+/// instead of trapping.
+fn store<T: LittleEndianConvert>(
+    memory: &MemoryRef,
+    address: u32,
+    offset: u32,
+    value: T,
+    relaxed: bool,
+) -> Result<(), TrapKind> {
+    if memory.is_read_only() {
+        return Err(TrapKind::MemoryReadOnly);
+    }
+    let result = effective_address(offset, address).and_then(|address| {
+        memory
+            .set_value(address, value)
+            .map_err(|_| memory.access_trap())
+    });
+    match result {
+        Err(TrapKind::MemoryAccessOutOfBounds) if relaxed => Ok(()),
+        result => result,
+    }
+}
+
+/// Masks a shift amount to the bit width of the shifted value.
+///
+/// With `strict`, traps instead if the amount doesn't fit into the bit width.
+#[inline(always)]
+fn checked_shift<T>(amount: T, mask: T, strict: bool) -> Result<T, TrapKind>
+where
+    T: ops::BitAnd<T, Output = T> + Copy + PartialEq,
+{
+    let masked = amount & mask;
+    if strict && masked != amount {
+        return Err(TrapKind::ShiftOutOfRange);
+    }
+    Ok(masked)
+}
+
 fn prepare_function_args(
     signature: &Signature,
     caller_stack: &mut ValueStack,
 ) -> Vec<RuntimeValue> {
-    let mut out = signature
-        .params()
+    let params = signature.params();
+    caller_stack
+        .pop_slice(params.len())
         .iter()
-        .rev()
-        .map(|&param_ty| caller_stack.pop().with_type(param_ty))
-        .collect::<Vec<RuntimeValue>>();
-    out.reverse();
-    out
+        .zip(params)
+        .map(|(&value, &param_ty)| value.with_type(param_ty))
+        .collect()
 }
 
 pub fn check_function_args(signature: &Signature, args: &[RuntimeValue]) -> Result<(), Trap> {
@@ -1339,8 +2077,8 @@ pub fn check_function_args(signature: &Signature, args: &[RuntimeValue]) -> Resu
     if signature
         .params()
         .iter()
-        .zip(args.iter().map(|param_value| param_value.value_type()))
-        .any(|(expected_type, actual_type)| &actual_type != expected_type)
+        .zip(args)
+        .any(|(&expected_type, arg)| !arg.matches_type(expected_type))
     {
         return Err(TrapKind::UnexpectedSignature.into());
     }
@@ -1348,6 +2086,22 @@ pub fn check_function_args(signature: &Signature, args: &[RuntimeValue]) -> Resu
     Ok(())
 }
 
+pub fn check_function_result(
+    return_type: Option<ValueType>,
+    return_val: Option<&RuntimeValue>,
+) -> Result<(), Trap> {
+    let matches = match (return_val, return_type) {
+        (Some(val), Some(expected_type)) => val.matches_type(expected_type),
+        (None, None) => true,
+        _ => false,
+    };
+    if !matches {
+        return Err(TrapKind::UnexpectedSignature.into());
+    }
+
+    Ok(())
+}
+
 #[derive(Debug)]
 struct ValueStack {
     buf: Box<[RuntimeValueInternal]>,
@@ -1358,13 +2112,22 @@ struct ValueStack {
 impl ValueStack {
     #[inline]
     fn drop_keep(&mut self, drop_keep: isa::DropKeep) {
-        if drop_keep.keep == isa::Keep::Single {
-            let top = *self.top();
-            *self.pick_mut(drop_keep.drop as usize + 1) = top;
+        let drop = drop_keep.drop as usize;
+        match drop_keep.keep {
+            0 => {}
+            // By far the most common case, avoid the overhead of a block move.
+            1 => {
+                let top = *self.top();
+                *self.pick_mut(drop + 1) = top;
+            }
+            keep => {
+                let keep_start = self.sp - keep as usize;
+                self.buf.copy_within(keep_start..self.sp, keep_start - drop);
+            }
         }
 
         let cur_stack_len = self.len();
-        self.sp = cur_stack_len - drop_keep.drop as usize;
+        self.sp = cur_stack_len - drop;
     }
 
     #[inline]
@@ -1377,14 +2140,21 @@ impl ValueStack {
         T::from_runtime_value_internal(value)
     }
 
+    /// Replaces the top two values with the result of applying `f` to them.
+    ///
+    /// Cheaper than popping both values and pushing the result, since the slot of
+    /// the left operand is reused and no bounds check for a push is required.
     #[inline]
-    fn pop_pair_as<T>(&mut self) -> (T, T)
+    fn apply_binop<T, U, F>(&mut self, f: F) -> Result<(), TrapKind>
     where
         T: FromRuntimeValueInternal,
+        RuntimeValueInternal: From<U>,
+        F: FnOnce(T, T) -> Result<U, TrapKind>,
     {
-        let right = self.pop_as();
-        let left = self.pop_as();
-        (left, right)
+        let right = self.pop_as::<T>();
+        let left = self.pick_mut(1);
+        *left = f(T::from_runtime_value_internal(*left), right)?.into();
+        Ok(())
     }
 
     #[inline]
@@ -1421,19 +2191,29 @@ impl ValueStack {
         self.buf[self.sp]
     }
 
+    /// Pops the top `len` values, returning them in the order they were pushed.
+    #[inline]
+    fn pop_slice(&mut self, len: usize) -> &[RuntimeValueInternal] {
+        self.sp -= len;
+        &self.buf[self.sp..self.sp + len]
+    }
+
     #[inline]
     fn push(&mut self, value: RuntimeValueInternal) -> Result<(), TrapKind> {
-        let cell = self.buf.get_mut(self.sp).ok_or(TrapKind::StackOverflow)?;
+        let cell = match self.buf.get_mut(self.sp) {
+            Some(cell) => cell,
+            None => return Err(self.overflow(1)),
+        };
         *cell = value;
         self.sp += 1;
         Ok(())
     }
 
     fn extend(&mut self, len: usize) -> Result<(), TrapKind> {
-        let cells = self
-            .buf
-            .get_mut(self.sp..self.sp + len)
-            .ok_or(TrapKind::StackOverflow)?;
+        let cells = match self.buf.get_mut(self.sp..self.sp + len) {
+            Some(cells) => cells,
+            None => return Err(self.overflow(len)),
+        };
         for cell in cells {
             *cell = Default::default();
         }
@@ -1441,10 +2221,25 @@ impl ValueStack {
         Ok(())
     }
 
+    /// The trap for failing to push `count` more values.
+    #[cold]
+    fn overflow(&self, count: usize) -> TrapKind {
+        TrapKind::StackOverflow {
+            limit: self.buf.len(),
+            depth: self.sp + count,
+        }
+    }
+
     #[inline]
     fn len(&self) -> usize {
         self.sp
     }
+
+    /// Number of values that can still be pushed onto the stack.
+    #[inline]
+    fn headroom(&self) -> usize {
+        self.buf.len() - self.sp
+    }
 }
 
 struct CallStack {
@@ -1468,14 +2263,429 @@ impl CallStack {
     fn is_full(&self) -> bool {
         self.buf.len() + 1 >= self.limit
     }
+
+    /// The trap for failing to push a frame while the frame of the caller is popped.
+    fn overflow(&self) -> TrapKind {
+        TrapKind::StackOverflow {
+            limit: self.limit,
+            depth: self.buf.len() + 2,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    /// The native stack pointer at the time the innermost [`NativeStackGuard`]
+    /// was created and the number of bytes that may be used beyond it.
+    static NATIVE_STACK_LIMIT: core::cell::Cell<Option<(usize, usize)>> =
+        const { core::cell::Cell::new(None) };
+}
+
+/// Limits the native stack usage of wasm code re-entered from host functions.
+///
+/// The interpreter loop itself doesn't recurse on the native stack. However, a host
+/// function that calls back into wasm does, and untrusted code can abuse such callbacks
+/// to exhaust the native stack, which aborts the whole process.
+///
+/// While a guard is alive, every call from wasm to a host function on the current thread
+/// probes the native stack pointer and traps with [`TrapKind::StackOverflow`] once it lies
+/// more than `max_usage` bytes away from where the guard was created.
+///
+/// # Platform limitations
+///
+/// - The stack pointer is approximated by the address of a local variable, so the
+///   measurement is only accurate up to the size of a few stack frames. Leave a margin
+///   between `max_usage` and the actual size of the thread's stack.
+/// - The limit is tracked per thread and only applies to the thread which created the guard.
+/// - Only the host call boundary is checked, stack used by the host function itself is not.
+/// - Not available without the `std` feature.
+///
+/// [`TrapKind::StackOverflow`]: enum.TrapKind.html#variant.StackOverflow
+#[cfg(feature = "std")]
+pub struct NativeStackGuard {
+    previous: Option<(usize, usize)>,
+}
+
+#[cfg(feature = "std")]
+impl NativeStackGuard {
+    /// Allows up to `max_usage` bytes of the native stack to be used, measured from the
+    /// caller's stack frame, until the returned guard is dropped.
+    pub fn new(max_usage: usize) -> NativeStackGuard {
+        let base = approximate_stack_pointer();
+        let previous = NATIVE_STACK_LIMIT.with(|limit| limit.replace(Some((base, max_usage))));
+        NativeStackGuard { previous }
+    }
+
+    fn check() -> Result<(), TrapKind> {
+        match NATIVE_STACK_LIMIT.with(|limit| limit.get()) {
+            Some((base, max_usage)) => {
+                // The stack might grow either way depending on the platform.
+                let current = approximate_stack_pointer();
+                let used = current.abs_diff(base);
+                if used > max_usage {
+                    return Err(TrapKind::StackOverflow {
+                        limit: max_usage,
+                        depth: used,
+                    });
+                }
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for NativeStackGuard {
+    fn drop(&mut self) {
+        NATIVE_STACK_LIMIT.with(|limit| limit.set(self.previous));
+    }
+}
+
+#[cfg(feature = "std")]
+#[inline(never)]
+fn approximate_stack_pointer() -> usize {
+    let probe = 0u8;
+    &probe as *const u8 as usize
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    /// The number of wasm executions currently running on the thread and the maximum
+    /// reentrancy depth set by the innermost [`ReentrancyLimit`].
+    static REENTRANCY: core::cell::Cell<(usize, Option<usize>)> =
+        const { core::cell::Cell::new((0, None)) };
+}
+
+/// Limits how deeply host functions may re-enter wasm execution.
+///
+/// Calls between wasm functions don't recurse on the native stack, but a host function
+/// calling back into wasm, which calls the host function again, does. Unlike
+/// [`NativeStackGuard`], which measures the stack usage, this guard counts the wasm
+/// executions nested into each other on the current thread.
+///
+/// While a guard is alive, wasm execution can be re-entered at most `max_depth` times, i.e.
+/// at most `max_depth + 1` executions (the outermost one included) may run on the thread at
+/// once. Entering another one traps with [`TrapKind::StackOverflow`]. Guards can be nested, dropping one restores the previous
+/// limit. Not available without the `std` feature.
+///
+/// [`NativeStackGuard`]: struct.NativeStackGuard.html
+/// [`TrapKind::StackOverflow`]: enum.TrapKind.html#variant.StackOverflow
+#[cfg(feature = "std")]
+pub struct ReentrancyLimit {
+    previous: Option<usize>,
+}
+
+#[cfg(feature = "std")]
+impl ReentrancyLimit {
+    /// Allows wasm execution to be re-entered at most `max_depth` times until the
+    /// returned guard is dropped.
+    pub fn new(max_depth: usize) -> ReentrancyLimit {
+        let previous = REENTRANCY.with(|current| {
+            let (depth, previous) = current.get();
+            current.set((depth, Some(max_depth)));
+            previous
+        });
+        ReentrancyLimit { previous }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for ReentrancyLimit {
+    fn drop(&mut self) {
+        REENTRANCY.with(|current| current.set((current.get().0, self.previous)));
+    }
+}
+
+/// Counts a running wasm execution towards the reentrancy depth until dropped.
+#[cfg(feature = "std")]
+struct ExecutionEntry;
+
+#[cfg(feature = "std")]
+impl ExecutionEntry {
+    fn enter() -> Result<ExecutionEntry, TrapKind> {
+        REENTRANCY.with(|current| {
+            let (depth, max_depth) = current.get();
+            if let Some(max_depth) = max_depth {
+                if depth > max_depth {
+                    return Err(TrapKind::StackOverflow {
+                        limit: max_depth,
+                        depth,
+                    });
+                }
+            }
+            current.set((depth + 1, max_depth));
+            Ok(ExecutionEntry)
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for ExecutionEntry {
+    fn drop(&mut self) {
+        REENTRANCY.with(|current| {
+            let (depth, max_depth) = current.get();
+            current.set((depth - 1, max_depth));
+        });
+    }
+}
+
+/// The locals and operands of a function at the time it raised a trap.
+///
+/// See [`Trap::state`].
+///
+/// [`Trap::state`]: struct.Trap.html#method.state
+#[cfg(feature = "trap_state")]
+#[derive(Debug, Clone)]
+pub struct TrapState {
+    locals: Vec<RuntimeValue>,
+    operands: Vec<u64>,
+}
+
+#[cfg(feature = "trap_state")]
+impl TrapState {
+    /// Returns the parameters followed by the declared locals of the function.
+    pub fn locals(&self) -> &[RuntimeValue] {
+        &self.locals
+    }
+
+    /// Returns the number of values on the function's operand stack.
+    pub fn operand_count(&self) -> usize {
+        self.operands.len()
+    }
+
+    /// Returns the operand at `depth` counted from the top of the operand stack,
+    /// interpreted as a value of type `value_type`.
+    ///
+    /// The interpreter doesn't keep track of the types of operands, so it is up to the
+    /// caller to know them, e.g. from the instructions leading up to the trap.
+    pub fn operand(&self, depth: usize, value_type: ValueType) -> Option<RuntimeValue> {
+        let index = self.operands.len().checked_sub(depth + 1)?;
+        Some(RuntimeValueInternal(self.operands[index]).with_type(value_type))
+    }
+}
+
+/// Default number of instructions executed between two checks of an [`ExecutionDeadline`].
+#[cfg(feature = "execution_deadline")]
+pub const DEFAULT_DEADLINE_CHECK_INTERVAL: u32 = 10_000;
+
+/// Number of bytes `memory.copy` copies between two checks of an [`ExecutionDeadline`].
+#[cfg(feature = "execution_deadline")]
+const MEMORY_COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+#[cfg(feature = "execution_deadline")]
+std::thread_local! {
+    /// The deadline set by the innermost [`ExecutionDeadline`] and the number of
+    /// instructions executed between two checks against it.
+    static EXECUTION_DEADLINE: core::cell::Cell<Option<(std::time::Instant, u32)>> =
+        const { core::cell::Cell::new(None) };
+}
+
+/// Bounds the wall-clock time spent executing wasm code on the current thread.
+///
+/// While a guard is alive, the interpreter compares the current time against the
+/// deadline every `check_interval` instructions and traps with [`TrapKind::Timeout`]
+/// once it has passed. Reading the clock is comparatively expensive, so the interval
+/// trades the precision of the deadline for execution speed.
+///
+/// The deadline is tracked per thread, applies to every execution on the thread while
+/// the guard is alive and doesn't include time spent in host functions until wasm code
+/// resumes. Guards can be nested, dropping one restores the previous deadline.
+///
+/// Large `memory.copy` instructions check the deadline between chunks of the copy as well.
+/// An interrupted copy traps like any other instruction, with the chunks copied so far
+/// remaining visible in the memory.
+///
+/// Only available with the `execution_deadline` feature.
+///
+/// [`TrapKind::Timeout`]: enum.TrapKind.html#variant.Timeout
+#[cfg(feature = "execution_deadline")]
+pub struct ExecutionDeadline {
+    previous: Option<(std::time::Instant, u32)>,
+}
+
+#[cfg(feature = "execution_deadline")]
+impl ExecutionDeadline {
+    /// Allows wasm code to run for `timeout` from now, checking the deadline every
+    /// [`DEFAULT_DEADLINE_CHECK_INTERVAL`] instructions.
+    ///
+    /// [`DEFAULT_DEADLINE_CHECK_INTERVAL`]: constant.DEFAULT_DEADLINE_CHECK_INTERVAL.html
+    pub fn new(timeout: std::time::Duration) -> ExecutionDeadline {
+        Self::with_check_interval(timeout, DEFAULT_DEADLINE_CHECK_INTERVAL)
+    }
+
+    /// Allows wasm code to run for `timeout` from now, checking the deadline every
+    /// `check_interval` instructions.
+    ///
+    /// # Panics
+    ///
+    /// If `check_interval` is zero.
+    pub fn with_check_interval(
+        timeout: std::time::Duration,
+        check_interval: u32,
+    ) -> ExecutionDeadline {
+        assert!(check_interval > 0, "check interval must not be zero");
+        let deadline = std::time::Instant::now() + timeout;
+        let previous =
+            EXECUTION_DEADLINE.with(|current| current.replace(Some((deadline, check_interval))));
+        ExecutionDeadline { previous }
+    }
+
+    /// Returns the number of instructions to execute before checking again.
+    #[cold]
+    fn check() -> Result<u32, TrapKind> {
+        match EXECUTION_DEADLINE.with(|current| current.get()) {
+            Some((deadline, check_interval)) => {
+                if std::time::Instant::now() >= deadline {
+                    return Err(TrapKind::Timeout);
+                }
+                Ok(check_interval)
+            }
+            None => Ok(DEFAULT_DEADLINE_CHECK_INTERVAL),
+        }
+    }
+}
+
+#[cfg(feature = "execution_deadline")]
+impl Drop for ExecutionDeadline {
+    fn drop(&mut self) {
+        EXECUTION_DEADLINE.with(|current| current.set(self.previous));
+    }
+}
+
+/// An arithmetic operation whose signed overflow is reported to an [`OverflowTrace`].
+#[cfg(feature = "overflow_tracing")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowingOp {
+    /// `i32.add` or `i64.add`.
+    Add,
+    /// `i32.sub` or `i64.sub`.
+    Sub,
+    /// `i32.mul` or `i64.mul`.
+    Mul,
+}
+
+/// An integer operation executed by wasm code whose result overflowed the signed range
+/// and was wrapped.
+#[cfg(feature = "overflow_tracing")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignedOverflow {
+    /// The operation.
+    pub op: OverflowingOp,
+    /// The left operand, either an `I32` or an `I64`.
+    pub lhs: RuntimeValue,
+    /// The right operand, of the same type as `lhs`.
+    pub rhs: RuntimeValue,
+}
+
+#[cfg(feature = "overflow_tracing")]
+impl SignedOverflow {
+    /// The wasm text format name of the overflowing instruction, e.g. `i32.add`.
+    pub fn opcode(&self) -> &'static str {
+        match (self.lhs.value_type(), self.op) {
+            (ValueType::I64, OverflowingOp::Add) => "i64.add",
+            (ValueType::I64, OverflowingOp::Sub) => "i64.sub",
+            (ValueType::I64, OverflowingOp::Mul) => "i64.mul",
+            (_, OverflowingOp::Add) => "i32.add",
+            (_, OverflowingOp::Sub) => "i32.sub",
+            (_, OverflowingOp::Mul) => "i32.mul",
+        }
+    }
+}
+
+/// The hook installed by the innermost [`OverflowTrace`].
+#[cfg(feature = "overflow_tracing")]
+type OverflowHook = alloc::boxed::Box<dyn FnMut(&SignedOverflow)>;
+
+#[cfg(feature = "overflow_tracing")]
+std::thread_local! {
+    static OVERFLOW_HOOK: core::cell::RefCell<Option<OverflowHook>> =
+        const { core::cell::RefCell::new(None) };
+}
+
+/// Reports integer additions, subtractions and multiplications overflowing the signed
+/// range to a hook, as a debugging aid for guests relying on wrapping arithmetic.
+///
+/// The overflowing operations still produce their wrapped result as wasm requires.
+///
+/// The hook is installed per thread and called for every execution on the thread while
+/// the guard is alive. Guards can be nested, dropping one restores the previous hook.
+///
+/// Only available with the `overflow_tracing` feature.
+#[cfg(feature = "overflow_tracing")]
+pub struct OverflowTrace {
+    previous: Option<OverflowHook>,
+}
+
+#[cfg(feature = "overflow_tracing")]
+impl OverflowTrace {
+    /// Calls `hook` for every signed overflow until the guard is dropped.
+    pub fn new<F: FnMut(&SignedOverflow) + 'static>(hook: F) -> OverflowTrace {
+        let previous =
+            OVERFLOW_HOOK.with(|current| current.replace(Some(alloc::boxed::Box::new(hook))));
+        OverflowTrace { previous }
+    }
+
+    #[inline(always)]
+    fn check<T>(op: OverflowingOp, lhs: T, rhs: T)
+    where
+        T: ArithmeticOps<T> + Copy,
+        RuntimeValue: From<T>,
+    {
+        if lhs.overflows_signed(op, rhs) {
+            Self::report(SignedOverflow {
+                op,
+                lhs: lhs.into(),
+                rhs: rhs.into(),
+            });
+        }
+    }
+
+    #[cold]
+    fn report(overflow: SignedOverflow) {
+        // The hook is taken out while it runs, so that it may run wasm code itself.
+        let hook = OVERFLOW_HOOK.with(|current| current.borrow_mut().take());
+        if let Some(mut hook) = hook {
+            hook(&overflow);
+            OVERFLOW_HOOK.with(|current| {
+                let mut current = current.borrow_mut();
+                if current.is_none() {
+                    *current = Some(hook);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(feature = "overflow_tracing")]
+impl Drop for OverflowTrace {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        OVERFLOW_HOOK.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Settings of the invocations made with a [`StackRecycler`].
+#[derive(Debug, Clone, Copy, Default)]
+struct InvocationConfig {
+    relaxed_memory: bool,
+    strict_shifts: bool,
+    host_call_limit: Option<u32>,
+    memory_grow_limit: Option<u32>,
 }
 
 /// Used to recycle stacks instead of allocating them repeatedly.
+///
+/// Also carries the settings of the invocations using it, e.g.
+/// [`with_relaxed_memory`]. All of them are off by default.
+///
+/// [`with_relaxed_memory`]: #method.with_relaxed_memory
 pub struct StackRecycler {
     value_stack_buf: Option<Box<[RuntimeValueInternal]>>,
     value_stack_limit: usize,
     call_stack_buf: Option<Vec<FunctionContext>>,
     call_stack_limit: usize,
+    config: InvocationConfig,
 }
 
 impl StackRecycler {
@@ -1488,9 +2698,63 @@ impl StackRecycler {
             value_stack_limit,
             call_stack_buf: None,
             call_stack_limit,
+            config: InvocationConfig::default(),
         }
     }
 
+    /// Makes out of bounds loads yield zero and out of bounds stores do nothing instead
+    /// of trapping with [`TrapKind::MemoryAccessOutOfBounds`] in invocations using this
+    /// recycler.
+    ///
+    /// WARNING: This is NOT spec compliant and lets buggy or malicious code carry on with
+    /// garbage instead of stopping it. Only meant for differential testing and fuzzing
+    /// against engines that don't trap identically, never enable it in production.
+    ///
+    /// [`TrapKind::MemoryAccessOutOfBounds`]: enum.TrapKind.html#variant.MemoryAccessOutOfBounds
+    pub fn with_relaxed_memory(mut self, relaxed_memory: bool) -> Self {
+        self.config.relaxed_memory = relaxed_memory;
+        self
+    }
+
+    /// Makes integer shifts by amounts not less than the bit width trap with
+    /// [`TrapKind::ShiftOutOfRange`] instead of masking the amount as required by the spec
+    /// in invocations using this recycler.
+    ///
+    /// Useful for differential testing against engines or languages with different shift
+    /// semantics, to catch code relying on the masking.
+    ///
+    /// [`TrapKind::ShiftOutOfRange`]: enum.TrapKind.html#variant.ShiftOutOfRange
+    pub fn with_strict_shifts(mut self, strict_shifts: bool) -> Self {
+        self.config.strict_shifts = strict_shifts;
+        self
+    }
+
+    /// Makes invocations using this recycler trap with [`TrapKind::HostCallLimitExceeded`]
+    /// before calling more than `max_calls` host functions.
+    ///
+    /// Host calls tend to be far more expensive than wasm instructions, so a guest calling
+    /// host functions in a loop can hog the host even if its execution is otherwise
+    /// bounded. Wasm code re-entered from a host function is a separate invocation, which
+    /// only has a limit if it is made with a recycler that sets one.
+    ///
+    /// [`TrapKind::HostCallLimitExceeded`]: enum.TrapKind.html#variant.HostCallLimitExceeded
+    pub fn with_host_call_limit(mut self, max_calls: u32) -> Self {
+        self.config.host_call_limit = Some(max_calls);
+        self
+    }
+
+    /// Makes every `memory.grow` instruction beyond the first `max_grows` of an invocation
+    /// using this recycler fail as if the memory couldn't be grown, i.e. return -1 without
+    /// trapping.
+    ///
+    /// A guest growing its memory by a page at a time causes far more reallocations than
+    /// its final size suggests, so a size limit alone doesn't bound the allocation churn.
+    /// Instructions that failed count as well. Memories grown by the host aren't counted.
+    pub fn with_memory_grow_limit(mut self, max_grows: u32) -> Self {
+        self.config.memory_grow_limit = Some(max_grows);
+        self
+    }
+
     /// Clears any values left on the stack to avoid
     /// leaking them to future export invocations.
     ///
@@ -0,0 +1,162 @@
+use crate::func::{FuncInstanceInternal, FuncRef, ResumableError};
+use crate::runner::{check_function_args, Interpreter};
+use crate::{Externals, RuntimeValue};
+
+/// The state an execution reached after a step of a [`Stepper`].
+///
+/// [`Stepper`]: struct.Stepper.html
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepOutcome {
+    /// The execution is paused before the next instruction, see [`Stepper::position`].
+    ///
+    /// [`Stepper::position`]: struct.Stepper.html#method.position
+    Paused,
+    /// The execution has finished with the given return value.
+    Finished(Option<RuntimeValue>),
+}
+
+/// Executes the code of a function one step at a time, e.g. for a debugger.
+///
+/// The execution starts paused before the first instruction of the function. Each step
+/// runs it until it reaches another instruction that matches the kind of the step:
+///
+/// - [`step_into`] executes a single instruction, stopping at the first instruction of a
+///   called function.
+/// - [`step_over`] stops at the next instruction of the current function, executing the
+///   functions it calls entirely.
+/// - [`step_out`] stops once the current function has returned to its caller.
+///
+/// Steps never stop within a host function, calls of host functions are made through the
+/// passed [`Externals`]. All kinds of steps stop in the caller if the current function
+/// returns, and finish the execution if there is no caller left.
+///
+/// Once the execution has finished or trapped, further steps return a `NotResumable` error.
+///
+/// [`step_into`]: #method.step_into
+/// [`step_over`]: #method.step_over
+/// [`step_out`]: #method.step_out
+/// [`Externals`]: trait.Externals.html
+pub struct Stepper {
+    interpreter: Interpreter,
+    finished: bool,
+}
+
+impl Stepper {
+    /// Prepares the execution of `func` with `args`, paused before its first instruction.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Trap` error if `args` don't match the signature of `func`, and a
+    /// `NotResumable` error if `func` is a host function, which has no code to step through.
+    pub fn new(func: &FuncRef, args: &[RuntimeValue]) -> Result<Stepper, ResumableError> {
+        check_function_args(func.signature(), args)?;
+        match *func.as_internal() {
+            FuncInstanceInternal::Internal { .. } => Ok(Stepper {
+                interpreter: Interpreter::new(func, args, None)?,
+                finished: false,
+            }),
+            FuncInstanceInternal::Host { .. } | FuncInstanceInternal::HostClosure { .. } => {
+                Err(ResumableError::NotResumable)
+            }
+        }
+    }
+
+    /// Returns the number of wasm functions on the call stack of the paused execution,
+    /// or 0 once the execution has finished.
+    pub fn depth(&self) -> usize {
+        self.interpreter.call_depth()
+    }
+
+    /// Returns the function the paused execution is in.
+    pub fn function(&self) -> Option<&FuncRef> {
+        self.interpreter
+            .current_frame()
+            .map(|(function, _)| function)
+    }
+
+    /// Returns the position of the instruction the execution is paused before, in the
+    /// code of [`function`].
+    ///
+    /// The position is in the same units as [`Caller::position`].
+    ///
+    /// [`function`]: #method.function
+    /// [`Caller::position`]: struct.Caller.html#method.position
+    pub fn position(&self) -> Option<u32> {
+        self.interpreter
+            .current_frame()
+            .map(|(_, position)| position)
+    }
+
+    /// Executes the next instruction.
+    ///
+    /// If the instruction calls a wasm function, the execution pauses before the first
+    /// instruction of the called function.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Trap` error if the instruction traps, and a `NotResumable` error if the
+    /// execution has already finished or trapped.
+    pub fn step_into<E: Externals>(
+        &mut self,
+        externals: &mut E,
+    ) -> Result<StepOutcome, ResumableError> {
+        if self.finished {
+            return Err(ResumableError::NotResumable);
+        }
+        match self.interpreter.step(externals) {
+            Ok(None) => Ok(StepOutcome::Paused),
+            Ok(Some(return_value)) => {
+                self.finished = true;
+                Ok(StepOutcome::Finished(return_value))
+            }
+            Err(trap) => {
+                self.finished = true;
+                Err(trap.into())
+            }
+        }
+    }
+
+    /// Executes the next instruction, including all functions it calls.
+    ///
+    /// # Errors
+    ///
+    /// See [`step_into`].
+    ///
+    /// [`step_into`]: #method.step_into
+    pub fn step_over<E: Externals>(
+        &mut self,
+        externals: &mut E,
+    ) -> Result<StepOutcome, ResumableError> {
+        // Frames are only pushed on top of the current one, so the execution is back in
+        // the current frame or one of its callers as soon as the depth doesn't exceed the
+        // current one.
+        let depth = self.depth();
+        loop {
+            match self.step_into(externals)? {
+                StepOutcome::Paused if self.depth() > depth => {}
+                outcome => return Ok(outcome),
+            }
+        }
+    }
+
+    /// Executes the rest of the current function and pauses in its caller, right after
+    /// the call.
+    ///
+    /// # Errors
+    ///
+    /// See [`step_into`].
+    ///
+    /// [`step_into`]: #method.step_into
+    pub fn step_out<E: Externals>(
+        &mut self,
+        externals: &mut E,
+    ) -> Result<StepOutcome, ResumableError> {
+        let depth = self.depth();
+        loop {
+            match self.step_into(externals)? {
+                StepOutcome::Paused if self.depth() >= depth => {}
+                outcome => return Ok(outcome),
+            }
+        }
+    }
+}
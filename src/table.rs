@@ -1,9 +1,11 @@
 use crate::func::FuncRef;
 use crate::module::check_limits;
+use crate::value::ExternRef;
 use crate::Error;
 use alloc::{rc::Rc, vec::Vec};
 use core::cell::RefCell;
 use core::fmt;
+use core::ops::Range;
 use core::u32;
 use parity_wasm::elements::ResizableLimits;
 
@@ -23,37 +25,70 @@ impl ::core::ops::Deref for TableRef {
     }
 }
 
+/// The type of the elements a table holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableElementType {
+    /// Untyped functions, i.e. `funcref`.
+    FuncRef,
+    /// Opaque host references, i.e. `externref`.
+    ExternRef,
+}
+
+/// An element stored in a table.
+#[derive(Debug, Clone)]
+pub enum TableElement {
+    /// A function.
+    Func(FuncRef),
+    /// A reference to a host value.
+    Extern(ExternRef),
+}
+
+impl TableElement {
+    /// Returns the type of this element.
+    pub fn element_type(&self) -> TableElementType {
+        match *self {
+            TableElement::Func(_) => TableElementType::FuncRef,
+            TableElement::Extern(_) => TableElementType::ExternRef,
+        }
+    }
+}
+
 /// Runtime representation of a table.
 ///
-/// A table is a array of untyped functions. It allows wasm code to call functions
-/// indirectly through a dynamic index into a table. For example, this allows emulating function
-/// pointers by way of table indices.
+/// A table is an array of references of a single [type][`TableElementType`]. Tables of untyped
+/// functions allow wasm code to call functions indirectly through a dynamic index into a table.
+/// For example, this allows emulating function pointers by way of table indices.
 ///
 /// Table is created with an initial size but can be grown dynamically via [`grow`] method.
 /// Growth can be limited by an optional maximum size.
 ///
-/// In future, a table might be extended to be able to hold not only functions but different types.
+/// Tables of `externref` can only be created and accessed by the host for now, since
+/// wasm modules can't declare them yet.
 ///
 /// [`grow`]: #method.grow
+/// [`TableElementType`]: enum.TableElementType.html
 ///
 pub struct TableInstance {
     /// Table limits.
     limits: ResizableLimits,
+    /// The type of the elements.
+    element_type: TableElementType,
     /// Table memory buffer.
-    buffer: RefCell<Vec<Option<FuncRef>>>,
+    buffer: RefCell<Vec<Option<TableElement>>>,
 }
 
 impl fmt::Debug for TableInstance {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("TableInstance")
             .field("limits", &self.limits)
+            .field("element_type", &self.element_type)
             .field("buffer.len", &self.buffer.borrow().len())
             .finish()
     }
 }
 
 impl TableInstance {
-    /// Allocate a table instance.
+    /// Allocate a table instance of untyped functions.
     ///
     /// The table allocated with initial size, specified by `initial_size`.
     /// Maximum size can be specified by `maximum_size`.
@@ -64,18 +99,43 @@ impl TableInstance {
     ///
     /// Returns `Err` if `initial_size` is greater than `maximum_size`.
     pub fn alloc(initial_size: u32, maximum_size: Option<u32>) -> Result<TableRef, Error> {
-        let table = TableInstance::new(ResizableLimits::new(initial_size, maximum_size))?;
+        TableInstance::alloc_typed(initial_size, maximum_size, TableElementType::FuncRef)
+    }
+
+    /// Allocate a table instance holding elements of the given type.
+    ///
+    /// See [`alloc`] for details.
+    ///
+    /// [`alloc`]: #method.alloc
+    pub fn alloc_typed(
+        initial_size: u32,
+        maximum_size: Option<u32>,
+        element_type: TableElementType,
+    ) -> Result<TableRef, Error> {
+        let table = TableInstance::new(
+            ResizableLimits::new(initial_size, maximum_size),
+            element_type,
+        )?;
         Ok(TableRef(Rc::new(table)))
     }
 
-    fn new(limits: ResizableLimits) -> Result<TableInstance, Error> {
+    fn new(
+        limits: ResizableLimits,
+        element_type: TableElementType,
+    ) -> Result<TableInstance, Error> {
         check_limits(&limits)?;
         Ok(TableInstance {
             buffer: RefCell::new(vec![None; limits.initial() as usize]),
+            element_type,
             limits,
         })
     }
 
+    /// Returns the type of the elements this table holds.
+    pub fn element_type(&self) -> TableElementType {
+        self.element_type
+    }
+
     /// Return table limits.
     pub(crate) fn limits(&self) -> &ResizableLimits {
         &self.limits
@@ -96,37 +156,76 @@ impl TableInstance {
         self.buffer.borrow().len() as u32
     }
 
-    /// Increases the size of the table by given number of elements.
+    /// Increases the size of the table by `by` elements, setting them to `init`.
+    ///
+    /// Returns the previous size of the table, like `memory.grow` does for memories.
+    /// This is also what the `table.grow` instruction is based on.
     ///
     /// # Errors
     ///
-    /// Returns `Err` if tried to allocate more elements than permited by limit.
-    pub fn grow(&self, by: u32) -> Result<(), Error> {
+    /// Returns `Err` if the new size would exceed the maximum size of the table or this
+    /// table doesn't hold functions. The table is left unchanged in that case.
+    pub fn grow(&self, by: u32, init: Option<FuncRef>) -> Result<u32, Error> {
+        self.check_element_type(TableElementType::FuncRef)?;
+        self.grow_element(by, init.map(TableElement::Func))
+    }
+
+    /// Increases the size of the table by `by` elements, setting them to `init`.
+    ///
+    /// See [`grow`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the new size would exceed the maximum size of the table or `init` is
+    /// not of the element type of this table. The table is left unchanged in that case.
+    ///
+    /// [`grow`]: #method.grow
+    pub fn grow_element(&self, by: u32, init: Option<TableElement>) -> Result<u32, Error> {
+        if let Some(ref init) = init {
+            self.check_element_type(init.element_type())?;
+        }
         let mut buffer = self.buffer.borrow_mut();
+        let current_size = buffer.len() as u32;
         let maximum_size = self.maximum_size().unwrap_or(u32::MAX);
-        let new_size = self
-            .current_size()
+        let new_size = current_size
             .checked_add(by)
-            .and_then(|new_size| {
-                if maximum_size < new_size {
-                    None
-                } else {
-                    Some(new_size)
-                }
-            })
+            .filter(|&new_size| new_size <= maximum_size)
             .ok_or_else(|| {
                 Error::Table(format!(
                     "Trying to grow table by {} items when there are already {} items",
-                    by,
-                    self.current_size(),
+                    by, current_size,
                 ))
             })?;
-        buffer.resize(new_size as usize, None);
-        Ok(())
+        buffer.resize(new_size as usize, init);
+        Ok(current_size)
     }
 
-    /// Get the specific value in the table
+    /// Get the specific function in the table.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `offset` is out of bounds or this table doesn't hold functions.
     pub fn get(&self, offset: u32) -> Result<Option<FuncRef>, Error> {
+        self.check_element_type(TableElementType::FuncRef)?;
+        match self.get_element(offset)? {
+            Some(TableElement::Func(func)) => Ok(Some(func)),
+            Some(TableElement::Extern(_)) => unreachable!("element type was checked above"),
+            None => Ok(None),
+        }
+    }
+
+    /// Set the table element to the specified function.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `offset` is out of bounds or this table doesn't hold functions.
+    pub fn set(&self, offset: u32, value: Option<FuncRef>) -> Result<(), Error> {
+        self.check_element_type(TableElementType::FuncRef)?;
+        self.set_element(offset, value.map(TableElement::Func))
+    }
+
+    /// Get the specific element in the table.
+    pub fn get_element(&self, offset: u32) -> Result<Option<TableElement>, Error> {
         let buffer = self.buffer.borrow();
         let buffer_len = buffer.len();
         let table_elem = buffer.get(offset as usize).cloned().ok_or_else(|| {
@@ -138,8 +237,16 @@ impl TableInstance {
         Ok(table_elem)
     }
 
-    /// Set the table element to the specified function.
-    pub fn set(&self, offset: u32, value: Option<FuncRef>) -> Result<(), Error> {
+    /// Set the table element to the specified value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `offset` is out of bounds or `value` is not of the element type
+    /// of this table.
+    pub fn set_element(&self, offset: u32, value: Option<TableElement>) -> Result<(), Error> {
+        if let Some(ref value) = value {
+            self.check_element_type(value.element_type())?;
+        }
         let mut buffer = self.buffer.borrow_mut();
         let buffer_len = buffer.len();
         let table_elem = buffer.get_mut(offset as usize).ok_or_else(|| {
@@ -151,4 +258,80 @@ impl TableInstance {
         *table_elem = value;
         Ok(())
     }
+
+    /// Set `len` elements starting at `offset` to the specified function.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the range is out of bounds or this table doesn't hold functions.
+    /// The table is left unchanged in that case.
+    pub fn fill(&self, offset: u32, value: Option<FuncRef>, len: u32) -> Result<(), Error> {
+        self.check_element_type(TableElementType::FuncRef)?;
+        self.fill_element(offset, value.map(TableElement::Func), len)
+    }
+
+    /// Set `len` elements starting at `offset` to the specified value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the range is out of bounds or `value` is not of the element type
+    /// of this table. The table is left unchanged in that case.
+    pub fn fill_element(
+        &self,
+        offset: u32,
+        value: Option<TableElement>,
+        len: u32,
+    ) -> Result<(), Error> {
+        if let Some(ref value) = value {
+            self.check_element_type(value.element_type())?;
+        }
+        let mut buffer = self.buffer.borrow_mut();
+        let range = checked_range(buffer.len(), offset, len)?;
+        for elem in &mut buffer[range] {
+            *elem = value.clone();
+        }
+        Ok(())
+    }
+
+    /// Copy `len` elements starting at `src` to the elements starting at `dst`.
+    ///
+    /// Semantically equivalent to `memmove`, i.e. the ranges may overlap.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if either range is out of bounds. The table is left unchanged in that case.
+    pub fn copy(&self, dst: u32, src: u32, len: u32) -> Result<(), Error> {
+        let mut buffer = self.buffer.borrow_mut();
+        let src_range = checked_range(buffer.len(), src, len)?;
+        checked_range(buffer.len(), dst, len)?;
+        // Elements aren't `Copy`, so take the source range out before overwriting it.
+        let elements = buffer[src_range].to_vec();
+        for (elem, value) in buffer[dst as usize..].iter_mut().zip(elements) {
+            *elem = value;
+        }
+        Ok(())
+    }
+
+    fn check_element_type(&self, element_type: TableElementType) -> Result<(), Error> {
+        if self.element_type != element_type {
+            return Err(Error::Table(format!(
+                "trying to access {:?} elements of a table holding {:?} elements",
+                element_type, self.element_type
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the range of `len` elements starting at `offset` if it lies within a table
+/// of `table_len` elements.
+fn checked_range(table_len: usize, offset: u32, len: u32) -> Result<Range<usize>, Error> {
+    let end = offset as u64 + len as u64;
+    if end > table_len as u64 {
+        return Err(Error::Table(format!(
+            "trying to access table items [{}..{}] when there are only {} items",
+            offset, end, table_len
+        )));
+    }
+    Ok(offset as usize..end as usize)
 }
@@ -0,0 +1,475 @@
+//! Assembling modules straight from interpreter instructions.
+//!
+//! This bypasses both the text format and the validator, which makes it possible to
+//! exercise the interpreter with exactly the code under test. Since the code isn't
+//! validated, it is up to the test to make sure that it is well formed.
+
+// Not every part of the assembler is used with every set of features.
+#![allow(dead_code)]
+
+use crate::func::{FuncBody, FuncInstance};
+use crate::isa::{self, DropKeep, Instruction};
+use crate::module::{ModuleInstance, ModuleRef};
+use crate::prepare::FuncCode;
+use crate::{
+    FuncRef, GlobalRef, MemoryRef, NopExternals, RuntimeValue, Signature, TableRef, TrapKind,
+    ValueType,
+};
+use alloc::{rc::Rc, vec::Vec};
+use parity_wasm::elements::Local;
+
+/// Builds a module instance out of functions given as raw [`isa::Instruction`]s.
+///
+/// Functions, signatures, memories, tables and globals are assigned indices in the
+/// order they are added.
+pub struct ModuleAssembler {
+    instance: ModuleRef,
+}
+
+impl ModuleAssembler {
+    pub fn new() -> Self {
+        ModuleAssembler {
+            instance: ModuleRef(Rc::new(ModuleInstance::default())),
+        }
+    }
+
+    /// Adds a function with the given `locals` (not including parameters) and body.
+    ///
+    /// The body has to end with an explicit `Return`.
+    pub fn with_func(
+        self,
+        signature: Signature,
+        locals: &[ValueType],
+        instructions: Vec<Instruction>,
+    ) -> Self {
+        let mut code = isa::Instructions::with_capacity(instructions.len());
+        for instruction in instructions {
+            code.push_instruction(instruction);
+        }
+        let body = FuncBody {
+            locals: locals
+                .iter()
+                .map(|ty| Local::new(1, ty.into_elements()))
+                .collect(),
+            code: FuncCode::Compiled(code),
+        };
+        let func =
+            FuncInstance::alloc_internal(Rc::downgrade(&self.instance.0), Rc::new(signature), body);
+        self.instance.push_func(func);
+        self
+    }
+
+    /// Adds an already allocated function, e.g. a host function.
+    pub fn with_func_ref(self, func: FuncRef) -> Self {
+        self.instance.push_func(func);
+        self
+    }
+
+    /// Adds a signature to be referred to by `CallIndirect`.
+    pub fn with_signature(self, signature: Signature) -> Self {
+        self.instance.push_signature(Rc::new(signature));
+        self
+    }
+
+    pub fn with_memory(self, memory: MemoryRef) -> Self {
+        self.instance.push_memory(memory);
+        self
+    }
+
+    pub fn with_table(self, table: TableRef) -> Self {
+        self.instance.push_table(table);
+        self
+    }
+
+    pub fn with_global(self, global: GlobalRef) -> Self {
+        self.instance.push_global(global);
+        self
+    }
+
+    /// Returns the assembled module.
+    ///
+    /// The module has to be kept alive while its functions are invoked.
+    pub fn build(self) -> ModuleRef {
+        self.instance
+    }
+}
+
+fn ret(drop: u32) -> Instruction<'static> {
+    Instruction::Return(DropKeep { drop, keep: 1 })
+}
+
+#[test]
+fn assembled_func() {
+    let module = ModuleAssembler::new()
+        .with_func(
+            Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+            &[],
+            vec![
+                Instruction::GetLocal(2),
+                Instruction::GetLocal(2),
+                Instruction::I32DivS,
+                ret(2),
+            ],
+        )
+        .build();
+    let div = module.func_by_index(0).unwrap();
+    let call = |a: i32, b: i32| {
+        FuncInstance::invoke(
+            &div,
+            &[RuntimeValue::I32(a), RuntimeValue::I32(b)],
+            &mut NopExternals,
+        )
+    };
+
+    assert_eq!(call(7, 2).unwrap(), Some(RuntimeValue::I32(3)));
+    match call(1, 0) {
+        Err(trap) => assert!(matches!(trap.kind(), TrapKind::DivisionByZero)),
+        other => panic!("expected division by zero trap, got {:?}", other),
+    }
+}
+
+#[test]
+fn assembled_trunc_sat() {
+    let module = ModuleAssembler::new()
+        .with_func(
+            Signature::new(&[ValueType::F64][..], Some(ValueType::I32)),
+            &[],
+            vec![
+                Instruction::GetLocal(1),
+                Instruction::I32TruncSatUF64,
+                ret(1),
+            ],
+        )
+        .build();
+    let trunc = module.func_by_index(0).unwrap();
+    let call = |v: f64| {
+        FuncInstance::invoke(&trunc, &[RuntimeValue::F64(v.into())], &mut NopExternals).unwrap()
+    };
+
+    // `u32::MAX` is reinterpreted as `-1` when pushed as an `i32`.
+    assert_eq!(call(5e9), Some(RuntimeValue::I32(-1)));
+    assert_eq!(call(-1.5), Some(RuntimeValue::I32(0)));
+    assert_eq!(call(f64::NAN), Some(RuntimeValue::I32(0)));
+    assert_eq!(call(42.9), Some(RuntimeValue::I32(42)));
+}
+
+#[test]
+fn assembled_call() {
+    let module = ModuleAssembler::new()
+        .with_func(
+            Signature::new(&[ValueType::I64][..], Some(ValueType::I64)),
+            &[ValueType::I64],
+            vec![
+                // local = param * 2
+                Instruction::GetLocal(2),
+                Instruction::I64Const(2),
+                Instruction::I64Mul,
+                Instruction::SetLocal(1),
+                // square(local)
+                Instruction::GetLocal(1),
+                Instruction::Call(1),
+                ret(2),
+            ],
+        )
+        .with_func(
+            Signature::new(&[ValueType::I64][..], Some(ValueType::I64)),
+            &[],
+            vec![
+                Instruction::GetLocal(1),
+                Instruction::GetLocal(2),
+                Instruction::I64Mul,
+                ret(1),
+            ],
+        )
+        .build();
+    let func = module.func_by_index(0).unwrap();
+
+    assert_eq!(
+        FuncInstance::invoke(&func, &[RuntimeValue::I64(3)], &mut NopExternals).unwrap(),
+        Some(RuntimeValue::I64(36))
+    );
+}
+
+// Branch targets are byte offsets with the compact bytecode.
+#[cfg(not(feature = "compact_bytecode"))]
+#[test]
+fn assembled_branch_keeping_multiple_values() {
+    use crate::isa::Target;
+
+    let target = |dst_pc, drop, keep| Target {
+        dst_pc,
+        drop_keep: DropKeep { drop, keep },
+    };
+    // Roughly what branching out of a `(block (result i32 i32))` with results `2` and `3`
+    // compiles to, when the block left `10` and `20` on the stack. The branch has to move
+    // the results down over the leftovers, keeping their order. The code is assembled by
+    // hand since parity-wasm can't parse block types with multiple results.
+    let module = ModuleAssembler::new()
+        .with_func(
+            Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
+            &[],
+            vec![
+                Instruction::I32Const(1),
+                Instruction::I32Const(10),
+                Instruction::I32Const(20),
+                Instruction::I32Const(2),
+                Instruction::I32Const(3),
+                Instruction::GetLocal(6),
+                Instruction::BrIfNez(target(8, 2, 2)),
+                Instruction::Br(target(11, 1, 3)),
+                // 1 - (2 - 3)
+                Instruction::I32Sub,
+                Instruction::I32Sub,
+                ret(1),
+                // 1 - (20 - (2 - 3)), keeping three values only drops `10`.
+                Instruction::I32Sub,
+                Instruction::I32Sub,
+                Instruction::I32Sub,
+                ret(1),
+            ],
+        )
+        .build();
+    let func = module.func_by_index(0).unwrap();
+    let call = |cond: i32| {
+        FuncInstance::invoke(&func, &[RuntimeValue::I32(cond)], &mut NopExternals).unwrap()
+    };
+
+    assert_eq!(call(1), Some(RuntimeValue::I32(2)));
+    assert_eq!(call(0), Some(RuntimeValue::I32(-20)));
+}
+
+#[test]
+fn memory_copy_between_memories() {
+    use crate::memory_units::Pages;
+    use crate::MemoryInstance;
+
+    let dst = MemoryInstance::alloc(Pages(1), None).unwrap();
+    let src = MemoryInstance::alloc(Pages(1), None).unwrap();
+    src.set(65530, &[1, 2, 3, 4, 5, 6]).unwrap();
+    let module = ModuleAssembler::new()
+        .with_memory(dst.clone())
+        .with_memory(src.clone())
+        .with_func(
+            Signature::new(&[ValueType::I32, ValueType::I32, ValueType::I32][..], None),
+            &[],
+            vec![
+                Instruction::GetLocal(3),
+                Instruction::GetLocal(3),
+                Instruction::GetLocal(3),
+                Instruction::MemoryCopy {
+                    src_mem: 1,
+                    dst_mem: 0,
+                },
+                Instruction::Return(DropKeep { drop: 3, keep: 0 }),
+            ],
+        )
+        .build();
+    let copy = module.func_by_index(0).unwrap();
+    let copy = |dst: i32, src: i32, len: i32| {
+        FuncInstance::invoke(
+            &copy,
+            &[
+                RuntimeValue::I32(dst),
+                RuntimeValue::I32(src),
+                RuntimeValue::I32(len),
+            ],
+            &mut NopExternals,
+        )
+    };
+
+    copy(0, 65530, 6).unwrap();
+    assert_eq!(dst.get(0, 6).unwrap(), [1, 2, 3, 4, 5, 6]);
+    assert_eq!(src.get(0, 6).unwrap(), [0; 6]);
+
+    // Bounds are checked against each memory separately.
+    src.grow(Pages(1)).unwrap();
+    match copy(65534, 65530, 4) {
+        Err(trap) => assert!(matches!(trap.kind(), TrapKind::MemoryAccessOutOfBounds)),
+        other => panic!("expected out of bounds trap, got {:?}", other),
+    }
+    match copy(0, 131070, 4) {
+        Err(trap) => assert!(matches!(trap.kind(), TrapKind::MemoryAccessOutOfBounds)),
+        other => panic!("expected out of bounds trap, got {:?}", other),
+    }
+    copy(0, 131068, 4).unwrap();
+    assert_eq!(dst.get(0, 6).unwrap(), [0, 0, 0, 0, 5, 6]);
+}
+
+/// Feeds random instruction sequences to the interpreter and checks that it never panics.
+#[cfg(feature = "defensive_checks")]
+#[test]
+fn defensive_checks_missing_memory() {
+    let module = ModuleAssembler::new()
+        .with_func(
+            Signature::new(&[][..], Some(ValueType::I32)),
+            &[],
+            vec![Instruction::I32Const(0), Instruction::I32Load(0), ret(0)],
+        )
+        .with_func(
+            Signature::new(&[][..], Some(ValueType::I32)),
+            &[],
+            vec![Instruction::CurrentMemory, ret(0)],
+        )
+        .build();
+
+    for index in 0..2 {
+        let func = module.func_by_index(index).unwrap();
+        match FuncInstance::invoke(&func, &[], &mut NopExternals) {
+            Err(trap) => assert!(matches!(trap.kind(), TrapKind::InvalidCode)),
+            other => panic!("expected invalid code trap, got {:?}", other),
+        }
+    }
+}
+
+#[cfg(feature = "defensive_checks")]
+#[test]
+fn defensive_checks_random_code() {
+    extern crate std;
+
+    use crate::isa::{encode_br_targets, BrTargets, CodeUnit, Target};
+    use crate::memory_units::Pages;
+    use crate::{GlobalInstance, MemoryInstance, StackRecycler, TableInstance};
+    use rand::{Rng, SeedableRng, StdRng};
+    use std::println;
+
+    /// Change to reproduce a failure seen with another seed.
+    const SEED: usize = 0x5eed;
+
+    const TYPES: [ValueType; 4] = [
+        ValueType::I32,
+        ValueType::I64,
+        ValueType::F32,
+        ValueType::F64,
+    ];
+
+    fn random_target<R: Rng>(rng: &mut R, pc: u32) -> Target {
+        Target {
+            // Only branch forward so that the code always terminates.
+            dst_pc: rng.gen_range(pc + 1, pc + 8),
+            drop_keep: DropKeep {
+                drop: rng.gen_range(0, 4),
+                keep: rng.gen_range(0, 3),
+            },
+        }
+    }
+
+    /// A random instruction which owns the targets of a `BrTable`.
+    enum RandomInstruction {
+        Plain(Instruction<'static>),
+        BrTable(Vec<CodeUnit>),
+    }
+
+    impl RandomInstruction {
+        fn instruction(&self) -> Instruction<'_> {
+            match *self {
+                RandomInstruction::Plain(ref instruction) => instruction.clone(),
+                RandomInstruction::BrTable(ref targets) => {
+                    Instruction::BrTable(BrTargets::from_internal(targets))
+                }
+            }
+        }
+    }
+
+    fn random_instruction<R: Rng>(rng: &mut R, func: u32, pc: u32) -> RandomInstruction {
+        let instruction = match rng.gen_range(0, 24) {
+            0 => Instruction::GetLocal(rng.gen_range(0, 6)),
+            1 => Instruction::SetLocal(rng.gen_range(0, 6)),
+            2 => Instruction::TeeLocal(rng.gen_range(0, 6)),
+            3 => Instruction::Br(random_target(rng, pc)),
+            4 => Instruction::BrIfEqz(random_target(rng, pc)),
+            5 => Instruction::BrIfNez(random_target(rng, pc)),
+            6 => {
+                let count = rng.gen_range(0, 4);
+                let targets: Vec<Target> = (0..count).map(|_| random_target(rng, pc)).collect();
+                return RandomInstruction::BrTable(encode_br_targets(&targets));
+            }
+            7 => Instruction::Return(random_target(rng, pc).drop_keep),
+            // Only call functions defined later so that calls don't recurse.
+            8 => Instruction::Call(rng.gen_range(func + 1, 4)),
+            9 => Instruction::CallIndirect(rng.gen_range(0, 2)),
+            10 => Instruction::Drop,
+            11 => Instruction::Select,
+            12 => Instruction::GetGlobal(rng.gen_range(0, 3)),
+            13 => Instruction::SetGlobal(rng.gen_range(0, 3)),
+            14 => Instruction::I32Load(rng.gen_range(0, 8)),
+            15 => Instruction::I64Store(rng.gen_range(0, 8)),
+            16 => Instruction::GrowMemory,
+            17 => Instruction::TableInit(0),
+            18 => Instruction::I32Const(rng.gen_range(-2, 3)),
+            19 => Instruction::I64Const(rng.gen_range(-2, 3)),
+            20 => Instruction::I32DivS,
+            21 => Instruction::I64Add,
+            22 => Instruction::F64Sqrt,
+            _ => Instruction::Unreachable,
+        };
+        RandomInstruction::Plain(instruction)
+    }
+
+    /// Returns by how much `instruction` advances the pc of the code it is pushed to.
+    #[cfg(not(feature = "compact_bytecode"))]
+    fn encoded_len(instruction: &Instruction) -> u32 {
+        match *instruction {
+            Instruction::BrTable(ref targets) => 1 + targets.len(),
+            _ => 1,
+        }
+    }
+
+    /// Returns by how much `instruction` advances the pc of the code it is pushed to.
+    ///
+    /// Branch targets are byte offsets with the compact bytecode, so this is the size of
+    /// the encoded instruction.
+    #[cfg(feature = "compact_bytecode")]
+    fn encoded_len(instruction: &Instruction) -> u32 {
+        let mut code = crate::isa::Instructions::with_capacity(1);
+        code.push_instruction(instruction.clone());
+        code.current_pc()
+    }
+
+    println!("seed: {:#x}", SEED);
+    let mut rng = StdRng::from_seed(&[SEED][..]);
+    let mut stack_recycler = StackRecycler::with_limits(4096, 64);
+    for _ in 0..1000 {
+        let mut assembler = ModuleAssembler::new()
+            .with_memory(MemoryInstance::alloc(Pages(1), None).unwrap())
+            .with_table(TableInstance::alloc(2, None).unwrap())
+            .with_global(GlobalInstance::alloc(RuntimeValue::I32(0), false))
+            .with_global(GlobalInstance::alloc(RuntimeValue::I64(0), true));
+        for func in 0..3 {
+            let params: Vec<ValueType> = (0..rng.gen_range(0, 3))
+                .map(|_| *rng.choose(&TYPES).unwrap())
+                .collect();
+            let result = if rng.gen() {
+                Some(*rng.choose(&TYPES).unwrap())
+            } else {
+                None
+            };
+            let signature = Signature::new(params, result);
+            let locals: Vec<ValueType> = (0..rng.gen_range(0, 3))
+                .map(|_| *rng.choose(&TYPES).unwrap())
+                .collect();
+            let mut code = Vec::new();
+            let mut pc = 0;
+            for _ in 0..rng.gen_range(1, 16) {
+                let instruction = random_instruction(&mut rng, func, pc);
+                pc += encoded_len(&instruction.instruction());
+                code.push(instruction);
+            }
+            let code = code.iter().map(RandomInstruction::instruction).collect();
+            assembler = assembler
+                .with_signature(signature.clone())
+                .with_func(signature, &locals, code);
+        }
+        let module = assembler.build();
+        let func = module.func_by_index(0).unwrap();
+        let args: Vec<RuntimeValue> = func
+            .signature()
+            .params()
+            .iter()
+            .map(|&ty| RuntimeValue::default(ty))
+            .collect();
+
+        // Malformed code either traps or happens to execute successfully.
+        let _ =
+            FuncInstance::invoke_with_stack(&func, &args, &mut NopExternals, &mut stack_recycler);
+    }
+}
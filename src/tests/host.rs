@@ -5,11 +5,16 @@ use super::parse_wat;
 use crate::memory_units::Pages;
 use crate::types::ValueType;
 use crate::{
-    Error, Externals, FuncInstance, FuncRef, HostError, ImportsBuilder, MemoryDescriptor,
-    MemoryInstance, MemoryRef, ModuleImportResolver, ModuleInstance, ModuleRef, ResumableError,
-    RuntimeArgs, RuntimeValue, Signature, TableDescriptor, TableInstance, TableRef, Trap, TrapKind,
+    Caller, Error, ExternRef, ExternRefGlobalInstance, ExternType, Externals, FuncInstance,
+    FuncRef, GlobalDescriptor, GlobalInstance, GlobalRef, HostError, ImportsBuilder,
+    InstantiationError, MemoryDescriptor, MemoryInstance, MemoryRef, ModuleImportResolver,
+    ModuleInstance, ModuleRef, NopExternals, ResumableError, RuntimeArgs, RuntimeValue,
+    SeededRandom, SharedExternals, Signature, Stdio, TableDescriptor, TableElement,
+    TableElementType, TableInstance, TableRef, Trap, TrapKind,
 };
 use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use std::println;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -204,18 +209,18 @@ impl ModuleImportResolver for TestHost {
             "recurse" => RECURSE_FUNC_INDEX,
             "trap_sub" => TRAP_SUB_FUNC_INDEX,
             _ => {
-                return Err(Error::Instantiation(format!(
+                return Err(Error::Instantiation(InstantiationError::Other(format!(
                     "Export {} not found",
                     field_name
-                )));
+                ))));
             }
         };
 
         if !self.check_signature(index, signature) {
-            return Err(Error::Instantiation(format!(
+            return Err(Error::Instantiation(InstantiationError::Other(format!(
                 "Export `{}` doesnt match expected type {:?}",
                 field_name, signature
-            )));
+            ))));
         }
 
         Ok(FuncInstance::alloc_host(signature.clone(), index))
@@ -226,10 +231,55 @@ impl ModuleImportResolver for TestHost {
         field_name: &str,
         _memory_type: &MemoryDescriptor,
     ) -> Result<MemoryRef, Error> {
-        Err(Error::Instantiation(format!(
+        Err(Error::Instantiation(InstantiationError::Other(format!(
             "Export {} not found",
             field_name
-        )))
+        ))))
+    }
+}
+
+/// Externals forwarding every host call together with its [`Caller`] to a closure.
+///
+/// Panics if a host function is invoked without a caller.
+struct CallerHost<F>(F);
+
+impl<F> CallerHost<F>
+where
+    F: FnMut(usize, RuntimeArgs, Caller) -> Result<Option<RuntimeValue>, Trap>,
+{
+    fn new(f: F) -> Self {
+        CallerHost(f)
+    }
+}
+
+impl<F> Externals for CallerHost<F>
+where
+    F: FnMut(usize, RuntimeArgs, Caller) -> Result<Option<RuntimeValue>, Trap>,
+{
+    fn invoke_index(
+        &mut self,
+        _index: usize,
+        _args: RuntimeArgs,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        panic!("host functions should always be invoked with a caller");
+    }
+
+    fn invoke_index_with_caller(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+        caller: Caller,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        (self.0)(index, args, caller)
+    }
+}
+
+/// Resolves every imported function to a host function with index 0.
+struct AnyHostFunc;
+
+impl ModuleImportResolver for AnyHostFunc {
+    fn resolve_func(&self, _field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+        Ok(FuncInstance::alloc_host(signature.clone(), 0))
     }
 }
 
@@ -264,6 +314,43 @@ fn call_host_func() {
     );
 }
 
+#[test]
+fn call_host_and_internal_funcs() {
+    // Direct calls are resolved through the same table for imported and internal functions.
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "sub" (func $sub (param i32 i32) (result i32)))
+
+	(func $double (param i32) (result i32)
+		(i32.add (get_local 0) (get_local 0))
+	)
+	(func (export "test") (result i32)
+		(call $double
+			(call $sub
+				(call $double (i32.const 5))
+				(i32.const 3)
+			)
+		)
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    assert_eq!(
+        instance
+            .invoke_export("test", &[], &mut env)
+            .expect("Failed to invoke 'test' function",),
+        Some(RuntimeValue::I32(14))
+    );
+}
+
 #[test]
 fn resume_call_host_func() {
     let module = parse_wat(
@@ -307,6 +394,164 @@ fn resume_call_host_func() {
     );
 }
 
+#[test]
+fn run_until_host_call() {
+    use crate::ExecutionStep;
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "sub" (func $sub (param i32 i32) (result i32)))
+
+	(func (export "test") (result i32)
+		(call $sub
+			(call $sub
+				(i32.const 5)
+				(i32.const 7)
+			)
+			(i32.const 3)
+		)
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let export = instance.export_by_name("test").unwrap();
+    let func_instance = export.as_func().unwrap();
+
+    let mut invocation = FuncInstance::invoke_resumable(func_instance, &[][..]).unwrap();
+    let host_call = match invocation.run_until_host_call().unwrap() {
+        ExecutionStep::HostCall(host_call) => host_call,
+        step => panic!("expected a host call, got {:?}", step),
+    };
+    assert_eq!(
+        host_call.args(),
+        [RuntimeValue::I32(5), RuntimeValue::I32(7)]
+    );
+    assert!(invocation.is_resumable());
+    assert!(matches!(
+        invocation.run_until_host_call(),
+        Err(ResumableError::AlreadyStarted)
+    ));
+
+    // Service the call on behalf of the guest.
+    let result = FuncInstance::invoke(host_call.func(), host_call.args(), &mut env).unwrap();
+    let host_call = match invocation.resume_until_host_call(result).unwrap() {
+        ExecutionStep::HostCall(host_call) => host_call,
+        step => panic!("expected a host call, got {:?}", step),
+    };
+    assert_eq!(
+        host_call.args(),
+        [RuntimeValue::I32(-2), RuntimeValue::I32(3)]
+    );
+
+    let result = FuncInstance::invoke(host_call.func(), host_call.args(), &mut env).unwrap();
+    assert!(matches!(
+        invocation.resume_until_host_call(result).unwrap(),
+        ExecutionStep::Finished(Some(RuntimeValue::I32(-5)))
+    ));
+    assert!(!invocation.is_resumable());
+}
+
+#[cfg(feature = "import_usage")]
+#[test]
+fn used_imports_records_called_host_funcs() {
+    use crate::{ExternKind, UsedImport};
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "inc_mem" (func $inc_mem (param i32)))
+	(import "env" "sub" (func $sub (param i32 i32) (result i32)))
+
+	(func (export "test") (result i32)
+		(call $sub
+			(call $sub
+				(i32.const 5)
+				(i32.const 7)
+			)
+			(i32.const 3)
+		)
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    assert_eq!(instance.used_imports(), []);
+
+    assert_eq!(
+        instance
+            .invoke_export("test", &[], &mut env)
+            .expect("Failed to invoke 'test' function"),
+        Some(RuntimeValue::I32(-5))
+    );
+    assert_eq!(
+        instance.used_imports(),
+        [UsedImport {
+            module_name: "env".into(),
+            field_name: "sub".into(),
+            kind: ExternKind::Func,
+            index: 1,
+        }]
+    );
+}
+
+#[test]
+fn invoke_non_exported_func_by_index() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "sub" (func $sub (param i32 i32) (result i32)))
+
+	(func $twice_minus (param i32 i32) (result i32)
+		(call $sub
+			(i32.add (get_local 0) (get_local 0))
+			(get_local 1)
+		)
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    assert_eq!(
+        instance
+            .invoke_index(1, &[RuntimeValue::I32(5), RuntimeValue::I32(3)], &mut env)
+            .expect("Failed to invoke function 1"),
+        Some(RuntimeValue::I32(7))
+    );
+
+    // The imported host function, a missing index and bad arguments are rejected.
+    assert!(matches!(
+        instance.invoke_index(0, &[RuntimeValue::I32(5), RuntimeValue::I32(3)], &mut env),
+        Err(Error::Function(_))
+    ));
+    assert!(matches!(
+        instance.invoke_index(2, &[], &mut env),
+        Err(Error::Function(_))
+    ));
+    assert!(matches!(
+        instance.invoke_index(1, &[RuntimeValue::I32(5)], &mut env),
+        Err(Error::Trap(_))
+    ));
+}
+
 #[test]
 fn resume_call_host_func_type_mismatch() {
     fn resume_with_val(val: Option<RuntimeValue>) {
@@ -398,6 +643,170 @@ fn host_err() {
     assert_eq!(error_with_code.error_code, 228);
 }
 
+#[test]
+fn unimplemented_host_func() {
+    struct StubExternals;
+
+    impl Externals for StubExternals {
+        fn invoke_index(
+            &mut self,
+            _index: usize,
+            _args: RuntimeArgs,
+        ) -> Result<Option<RuntimeValue>, Trap> {
+            Err(TrapKind::Unimplemented {
+                module_name: "env".into(),
+                field_name: "foo".into(),
+            }
+            .into())
+        }
+    }
+
+    impl ModuleImportResolver for StubExternals {
+        fn resolve_func(&self, _field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+            Ok(FuncInstance::alloc_host(signature.clone(), 0))
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "foo" (func $foo (result i32)))
+
+	(func (export "test") (result i32)
+		(call $foo)
+	)
+)
+"#,
+    );
+
+    let instance = ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &StubExternals),
+    )
+    .expect("Failed to instantiate module")
+    .assert_no_start();
+
+    let error = instance
+        .invoke_export("test", &[], &mut StubExternals)
+        .expect_err("`test` expected to return error");
+    match error {
+        Error::Trap(ref trap) => {
+            assert!(matches!(
+                trap.kind(),
+                TrapKind::Unimplemented { module_name, field_name }
+                    if module_name == "env" && field_name == "foo"
+            ));
+            assert_eq!(trap.to_string(), "Trap: not implemented: env.foo");
+        }
+        error => panic!("Expected trap, got {:?}", error),
+    }
+}
+
+#[test]
+fn host_func_with_caller() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "print" (func $print (param i32 i32)))
+	(memory 1)
+	(data (i32.const 16) "Hello, world!")
+	(global (export "count") (mut i32) (i32.const 0))
+
+	(func (export "test") (result i32)
+		(call $print (i32.const 16) (i32.const 5))
+		(call $print (i32.const 23) (i32.const 6))
+		(get_global 0)
+	)
+)
+"#,
+    );
+
+    let mut printed = Vec::new();
+    let mut env = CallerHost::new(|_index, args, caller| {
+        let ptr: u32 = args.nth_checked(0)?;
+        let len: u32 = args.nth_checked(1)?;
+        let memory = caller.memory().expect("caller has a memory");
+        let bytes = memory
+            .get(ptr, len as usize)
+            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+        printed.push(String::from_utf8(bytes).unwrap());
+
+        let count: i32 = caller.get_global("count").unwrap().try_into().unwrap();
+        caller
+            .set_global("count", RuntimeValue::I32(count + 1))
+            .unwrap();
+        assert!(caller.stack_headroom() > 0);
+        Ok(None)
+    });
+    let instance = ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &AnyHostFunc),
+    )
+    .expect("Failed to instantiate module")
+    .assert_no_start();
+
+    assert_eq!(
+        instance
+            .invoke_export("test", &[], &mut env)
+            .expect("Failed to invoke 'test' function"),
+        Some(RuntimeValue::I32(2))
+    );
+    assert_eq!(printed, ["Hello", "world!"]);
+}
+
+#[test]
+fn host_func_caller_position() {
+    use crate::isa::Instruction;
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "record" (func $record))
+
+	(func (export "test")
+		(call $record)
+		(block
+			(call $record)
+		)
+		(call $record)
+	)
+)
+"#,
+    );
+
+    let mut call_sites = Vec::new();
+    let mut env = CallerHost::new(|_index, _args, caller| {
+        call_sites.push((caller.func_index(), caller.position()));
+        Ok(None)
+    });
+    let instance = ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &AnyHostFunc),
+    )
+    .expect("Failed to instantiate module")
+    .assert_no_start();
+
+    instance
+        .invoke_export("test", &[], &mut env)
+        .expect("Failed to invoke 'test' function");
+
+    // Collect the positions of all `call` instructions in the compiled body.
+    let func = instance.func_by_index(1).unwrap();
+    let body = func.body().unwrap();
+    let mut iter = body.code.instructions().iterate_from(0);
+    let mut expected = Vec::new();
+    loop {
+        let position = iter.position();
+        match iter.next() {
+            Some(Instruction::Call(_)) => expected.push((Some(1), position)),
+            Some(_) => {}
+            None => break,
+        }
+    }
+    assert_eq!(expected.len(), 3);
+    assert_eq!(call_sites, expected);
+}
+
 #[test]
 fn modify_mem_with_host_funcs() {
     let module = parse_wat(
@@ -484,40 +893,164 @@ fn pull_internal_mem_from_module() {
 }
 
 #[test]
-fn recursion() {
-    let module = parse_wat(
+fn call_func_imported_from_other_instance() {
+    let callee_module = parse_wat(
         r#"
 (module
-	;; Import 'recurse' function. Upon a call it will call back inside
-	;; this module, namely to function 'recursive' defined below.
-	(import "env" "recurse" (func $recurse (param i64) (result i64)))
-
-	;; Note that we import same function but with different type signature
-	;; this is possible since 'recurse' is a host function and it is defined
-	;; to be polymorphic.
-	(import "env" "recurse" (func (param f32) (result f32)))
-
-	(func (export "recursive") (param i64) (result i64)
-		;; return arg_0 + 42;
-		(i64.add
-			(get_local 0)
-			(i64.const 42)
+	(memory 1 1)
+	(data (i32.const 0) "\0a")
+	(global $g (mut i32) (i32.const 100))
+
+	;; Returns mem[0] + $g of *this* module, then bumps $g.
+	(func (export "get") (param i32) (result i32)
+		(set_global $g
+			(i32.add (get_global $g) (get_local 0))
+		)
+		(i32.add
+			(i32.load8_u (i32.const 0))
+			(get_global $g)
 		)
 	)
+)
+"#,
+    );
+    let caller_module = parse_wat(
+        r#"
+(module
+	(import "callee" "get" (func $get (param i32) (result i32)))
 
-	(func (export "test") (result i64)
-		(call $recurse (i64.const 321))
+	(memory 1 1)
+	(data (i32.const 0) "\14")
+	(global $g i32 (i32.const 1000))
+
+	(func (export "test") (result i32)
+		(i32.add
+			(call $get (i32.const 1))
+			;; Must see the caller's memory and globals after the call returns.
+			(i32.add
+				(i32.load8_u (i32.const 0))
+				(get_global $g)
+			)
+		)
 	)
 )
 "#,
     );
 
-    let mut env = TestHost::new();
-
-    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
-        .expect("Failed to instantiate module")
+    let callee = ModuleInstance::new(&callee_module, &ImportsBuilder::default())
+        .expect("Failed to instantiate callee module")
         .assert_no_start();
-
+    let caller = ModuleInstance::new(
+        &caller_module,
+        &ImportsBuilder::new().with_resolver("callee", &callee),
+    )
+    .expect("Failed to instantiate caller module")
+    .assert_no_start();
+
+    // 10 + 101 from the callee, 20 + 1000 from the caller.
+    assert_eq!(
+        caller
+            .invoke_export("test", &[], &mut NopExternals)
+            .unwrap(),
+        Some(RuntimeValue::I32(1131))
+    );
+    // The callee's global was updated, not the caller's.
+    assert_eq!(
+        caller
+            .invoke_export("test", &[], &mut NopExternals)
+            .unwrap(),
+        Some(RuntimeValue::I32(1132))
+    );
+}
+
+#[test]
+fn import_func_from_other_instance_with_wrong_signature() {
+    let callee_module = parse_wat(
+        r#"
+(module
+	(func (export "get") (result i32)
+		(i32.const 0)
+	)
+)
+"#,
+    );
+    let caller_module = parse_wat(
+        r#"
+(module
+	(import "callee" "get" (func (result i64)))
+)
+"#,
+    );
+
+    let callee = ModuleInstance::new(&callee_module, &ImportsBuilder::default())
+        .expect("Failed to instantiate callee module")
+        .assert_no_start();
+    let result = ModuleInstance::new(
+        &caller_module,
+        &ImportsBuilder::new().with_resolver("callee", &callee),
+    );
+    assert!(matches!(
+        result,
+        Err(Error::Instantiation(InstantiationError::ImportTypeMismatch { ref module, ref field, .. }))
+            if module == "callee" && field == "get"
+    ));
+
+    // The instance rejects the function itself when resolving it directly, outside of
+    // instantiation.
+    let expected = Signature::new(&[][..], Some(ValueType::I64));
+    match callee.resolve_func("get", &expected) {
+        Err(Error::Instantiation(InstantiationError::ImportTypeMismatch {
+            field,
+            expected: ExternType::Func(expected_signature),
+            actual: ExternType::Func(actual_signature),
+            ..
+        })) => {
+            assert_eq!(field, "get");
+            assert_eq!(expected_signature, expected);
+            assert_eq!(
+                actual_signature,
+                Signature::new(&[][..], Some(ValueType::I32))
+            );
+        }
+        other => panic!("expected an import type mismatch, got {:?}", other),
+    }
+}
+
+#[test]
+fn recursion() {
+    let module = parse_wat(
+        r#"
+(module
+	;; Import 'recurse' function. Upon a call it will call back inside
+	;; this module, namely to function 'recursive' defined below.
+	(import "env" "recurse" (func $recurse (param i64) (result i64)))
+
+	;; Note that we import same function but with different type signature
+	;; this is possible since 'recurse' is a host function and it is defined
+	;; to be polymorphic.
+	(import "env" "recurse" (func (param f32) (result f32)))
+
+	(func (export "recursive") (param i64) (result i64)
+		;; return arg_0 + 42;
+		(i64.add
+			(get_local 0)
+			(i64.const 42)
+		)
+	)
+
+	(func (export "test") (result i64)
+		(call $recurse (i64.const 321))
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
     // Put instance into the env, because $recurse function expects
     // attached module instance.
     env.instance = Some(instance.clone());
@@ -531,6 +1064,333 @@ fn recursion() {
     );
 }
 
+#[cfg(feature = "std")]
+#[test]
+fn native_stack_exhaustion_by_reentrance() {
+    use crate::{NativeStackGuard, StackRecycler};
+
+    /// Host which calls back into the "reenter" export on every call, forever.
+    struct ReenteringHost {
+        instance: Option<ModuleRef>,
+        depth: usize,
+    }
+
+    impl Externals for ReenteringHost {
+        fn invoke_index(
+            &mut self,
+            _index: usize,
+            _args: RuntimeArgs,
+        ) -> Result<Option<RuntimeValue>, Trap> {
+            self.depth += 1;
+            let func = self
+                .instance
+                .as_ref()
+                .expect("instance is attached before the call")
+                .export_by_name("reenter")
+                .and_then(|export| export.as_func().cloned())
+                .expect("'reenter' export is a function");
+            // Use small stacks so that only the native stack is exhausted.
+            let mut stack_recycler = StackRecycler::with_limits(1024, 16);
+            FuncInstance::invoke_with_stack(&func, &[], self, &mut stack_recycler)?;
+            Ok(None)
+        }
+    }
+
+    impl ModuleImportResolver for ReenteringHost {
+        fn resolve_func(&self, _field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+            Ok(FuncInstance::alloc_host(signature.clone(), 0))
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "call_host" (func $call_host))
+	(func (export "reenter")
+		(call $call_host)
+	)
+)
+"#,
+    );
+
+    let mut env = ReenteringHost {
+        instance: None,
+        depth: 0,
+    };
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    env.instance = Some(instance.clone());
+
+    let _guard = NativeStackGuard::new(256 * 1024);
+    let error = instance
+        .invoke_export("reenter", &[], &mut env)
+        .expect_err("infinite reentrance should trap");
+    assert!(matches!(
+        error,
+        Error::Trap(ref trap) if matches!(trap.kind(), TrapKind::StackOverflow { .. })
+    ));
+    assert!(env.depth > 1);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn reentrancy_limit() {
+    use crate::ReentrancyLimit;
+
+    /// Host which calls back into the "reenter" export while `remaining` is not zero.
+    struct ReenteringHost {
+        instance: Option<ModuleRef>,
+        calls: usize,
+        remaining: usize,
+    }
+
+    impl Externals for ReenteringHost {
+        fn invoke_index(
+            &mut self,
+            _index: usize,
+            _args: RuntimeArgs,
+        ) -> Result<Option<RuntimeValue>, Trap> {
+            self.calls += 1;
+            if self.remaining == 0 {
+                return Ok(None);
+            }
+            self.remaining -= 1;
+            let instance = self
+                .instance
+                .clone()
+                .expect("instance is attached before the call");
+            match instance.invoke_export("reenter", &[], self) {
+                Ok(_) => Ok(None),
+                Err(Error::Trap(trap)) => Err(trap),
+                Err(err) => panic!("unexpected error: {:?}", err),
+            }
+        }
+    }
+
+    impl ModuleImportResolver for ReenteringHost {
+        fn resolve_func(&self, _field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+            Ok(FuncInstance::alloc_host(signature.clone(), 0))
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "call_host" (func $call_host))
+	(func (export "reenter")
+		(call $call_host)
+	)
+)
+"#,
+    );
+
+    let mut env = ReenteringHost {
+        instance: None,
+        calls: 0,
+        remaining: 3,
+    };
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    env.instance = Some(instance.clone());
+
+    let _guard = ReentrancyLimit::new(3);
+
+    // Re-entering up to the limit is fine.
+    instance
+        .invoke_export("reenter", &[], &mut env)
+        .expect("reentrance up to the limit should succeed");
+    assert_eq!(env.calls, 4);
+
+    // The fourth reentrance traps, no matter how much native stack is left.
+    env.calls = 0;
+    env.remaining = usize::MAX;
+    let error = instance
+        .invoke_export("reenter", &[], &mut env)
+        .expect_err("reentrance beyond the limit should trap");
+    assert!(matches!(
+        error,
+        Error::Trap(ref trap)
+            if matches!(trap.kind(), TrapKind::StackOverflow { limit: 3, depth: 4 })
+    ));
+    assert_eq!(env.calls, 4);
+
+    // The depth is back to zero after the trap.
+    env.calls = 0;
+    env.remaining = 3;
+    instance
+        .invoke_export("reenter", &[], &mut env)
+        .expect("reentrance up to the limit should succeed");
+
+    // Exactly at the boundary: with a limit of zero only the outermost execution may run.
+    let _inner_guard = ReentrancyLimit::new(0);
+    env.calls = 0;
+    env.remaining = 0;
+    instance
+        .invoke_export("reenter", &[], &mut env)
+        .expect("the outermost execution doesn't count as a reentrance");
+    assert_eq!(env.calls, 1);
+
+    env.calls = 0;
+    env.remaining = 1;
+    let error = instance
+        .invoke_export("reenter", &[], &mut env)
+        .expect_err("the first reentrance should trap");
+    assert!(matches!(
+        error,
+        Error::Trap(ref trap)
+            if matches!(trap.kind(), TrapKind::StackOverflow { limit: 0, depth: 1 })
+    ));
+    assert_eq!(env.calls, 1);
+}
+
+#[test]
+fn host_call_limit() {
+    use crate::StackRecycler;
+
+    struct CountingExternals {
+        calls: u32,
+    }
+
+    impl Externals for CountingExternals {
+        fn invoke_index(
+            &mut self,
+            _index: usize,
+            _args: RuntimeArgs,
+        ) -> Result<Option<RuntimeValue>, Trap> {
+            self.calls += 1;
+            Ok(None)
+        }
+    }
+
+    impl ModuleImportResolver for CountingExternals {
+        fn resolve_func(&self, _field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+            Ok(FuncInstance::alloc_host(signature.clone(), 0))
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "ping" (func $ping))
+	(func (export "spam") (param $n i32)
+		(block
+			(loop
+				(br_if 1 (i32.eqz (get_local $n)))
+				(call $ping)
+				(set_local $n (i32.sub (get_local $n) (i32.const 1)))
+				(br 0)
+			)
+		)
+	)
+)
+"#,
+    );
+    let mut externals = CountingExternals { calls: 0 };
+    let instance = ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &externals),
+    )
+    .expect("Failed to instantiate module")
+    .assert_no_start();
+
+    let mut stack_recycler = StackRecycler::default().with_host_call_limit(5);
+    let mut spam = |n: i32, externals: &mut CountingExternals| {
+        instance.invoke_export_with_stack(
+            "spam",
+            &[RuntimeValue::I32(n)],
+            externals,
+            &mut stack_recycler,
+        )
+    };
+
+    // The limit applies to each invocation separately.
+    for _ in 0..2 {
+        spam(5, &mut externals).expect("calls up to the limit should succeed");
+    }
+    assert_eq!(externals.calls, 10);
+
+    externals.calls = 0;
+    let error = spam(1000, &mut externals).expect_err("calls beyond the limit should trap");
+    assert!(matches!(
+        error,
+        Error::Trap(ref trap)
+            if matches!(trap.kind(), TrapKind::HostCallLimitExceeded { limit: 5 })
+    ));
+    assert_eq!(externals.calls, 5);
+
+    // Other invocations aren't affected.
+    externals.calls = 0;
+    instance
+        .invoke_export("spam", &[RuntimeValue::I32(1000)], &mut externals)
+        .expect("calls are unlimited by default");
+    assert_eq!(externals.calls, 1000);
+}
+
+#[test]
+fn memory_grow_limit() {
+    use crate::StackRecycler;
+
+    let module = parse_wat(
+        r#"
+(module
+	(memory (export "mem") 0)
+	;; Grows the memory by a page `$n` times and returns how many grows succeeded.
+	(func (export "grow") (param $n i32) (result i32)
+		(local $grown i32)
+		(block
+			(loop
+				(br_if 1 (i32.eqz (get_local $n)))
+				(if (i32.ne (grow_memory (i32.const 1)) (i32.const -1))
+					(then (set_local $grown (i32.add (get_local $grown) (i32.const 1))))
+				)
+				(set_local $n (i32.sub (get_local $n) (i32.const 1)))
+				(br 0)
+			)
+		)
+		(get_local $grown)
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let memory = instance
+        .export_by_name("mem")
+        .and_then(|export| export.as_memory().cloned())
+        .unwrap();
+    let mut stack_recycler = StackRecycler::default().with_memory_grow_limit(3);
+    let mut grow = |n: i32| {
+        instance
+            .invoke_export_with_stack(
+                "grow",
+                &[RuntimeValue::I32(n)],
+                &mut NopExternals,
+                &mut stack_recycler,
+            )
+            .expect("failed grows don't trap")
+    };
+
+    // The limit applies to each invocation separately.
+    assert_eq!(grow(10), Some(RuntimeValue::I32(3)));
+    assert_eq!(grow(2), Some(RuntimeValue::I32(2)));
+    assert_eq!(memory.current_size(), Pages(5));
+
+    // The host can still grow the memory.
+    memory.grow(Pages(1)).unwrap();
+
+    // Other invocations aren't affected.
+    assert_eq!(
+        instance
+            .invoke_export("grow", &[RuntimeValue::I32(10)], &mut NopExternals)
+            .unwrap(),
+        Some(RuntimeValue::I32(10))
+    );
+    assert_eq!(memory.current_size(), Pages(16));
+}
+
 #[test]
 fn defer_providing_externals() {
     const INC_FUNC_INDEX: usize = 0;
@@ -546,16 +1406,16 @@ fn defer_providing_externals() {
     impl ModuleImportResolver for HostImportResolver {
         fn resolve_func(&self, field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
             if field_name != "inc" {
-                return Err(Error::Instantiation(format!(
+                return Err(Error::Instantiation(InstantiationError::Other(format!(
                     "Export {} not found",
                     field_name
-                )));
+                ))));
             }
             if signature.params() != [ValueType::I32] || signature.return_type() != None {
-                return Err(Error::Instantiation(format!(
+                return Err(Error::Instantiation(InstantiationError::Other(format!(
                     "Export `{}` doesnt match expected type {:?}",
                     field_name, signature
-                )));
+                ))));
             }
 
             Ok(FuncInstance::alloc_host(signature.clone(), INC_FUNC_INDEX))
@@ -569,10 +1429,10 @@ fn defer_providing_externals() {
             if field_name == "mem" {
                 Ok(self.mem.clone())
             } else {
-                Err(Error::Instantiation(format!(
+                Err(Error::Instantiation(InstantiationError::Other(format!(
                     "Export {} not found",
                     field_name
-                )))
+                ))))
             }
         }
     }
@@ -675,10 +1535,10 @@ fn two_envs_one_externals() {
                 "ordinary" => ORDINARY_FUNC_INDEX,
                 "privileged" => PRIVILEGED_FUNC_INDEX,
                 _ => {
-                    return Err(Error::Instantiation(format!(
+                    return Err(Error::Instantiation(InstantiationError::Other(format!(
                         "Export {} not found",
                         field_name
-                    )));
+                    ))));
                 }
             };
 
@@ -691,15 +1551,15 @@ fn two_envs_one_externals() {
             let index = match field_name {
                 "ordinary" => ORDINARY_FUNC_INDEX,
                 "privileged" => {
-                    return Err(Error::Instantiation(
+                    return Err(Error::Instantiation(InstantiationError::Other(
                         "'priveleged' can be imported only in privileged context".into(),
-                    ));
+                    )));
                 }
                 _ => {
-                    return Err(Error::Instantiation(format!(
+                    return Err(Error::Instantiation(InstantiationError::Other(format!(
                         "Export {} not found",
                         field_name
-                    )));
+                    ))));
                 }
             };
 
@@ -802,10 +1662,10 @@ fn dynamically_add_host_func() {
             let index = match field_name {
                 "add_func" => ADD_FUNC_FUNC_INDEX,
                 _ => {
-                    return Err(Error::Instantiation(format!(
+                    return Err(Error::Instantiation(InstantiationError::Other(format!(
                         "Export {} not found",
                         field_name
-                    )));
+                    ))));
                 }
             };
             Ok(FuncInstance::alloc_host(signature.clone(), index))
@@ -819,10 +1679,10 @@ fn dynamically_add_host_func() {
             if field_name == "table" {
                 Ok(self.table.clone())
             } else {
-                Err(Error::Instantiation(format!(
+                Err(Error::Instantiation(InstantiationError::Other(format!(
                     "Export {} not found",
                     field_name
-                )))
+                ))))
             }
         }
     }
@@ -865,3 +1725,1192 @@ fn dynamically_add_host_func() {
         Some(RuntimeValue::I32(2))
     );
 }
+
+#[test]
+fn call_host_closure() {
+    use core::cell::Cell;
+    use std::rc::Rc;
+
+    struct ClosureResolver {
+        add: FuncRef,
+    }
+
+    impl ModuleImportResolver for ClosureResolver {
+        fn resolve_func(&self, field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+            if field_name != "add" {
+                return Err(Error::Instantiation(InstantiationError::Other(format!(
+                    "Export {} not found",
+                    field_name
+                ))));
+            }
+            if signature != self.add.signature() {
+                return Err(Error::Instantiation(InstantiationError::Other(format!(
+                    "Export {} has a bad signature",
+                    field_name
+                ))));
+            }
+            Ok(self.add.clone())
+        }
+    }
+
+    let calls = Rc::new(Cell::new(0));
+    let resolver = ClosureResolver {
+        add: FuncInstance::alloc_host_closure(
+            Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+            {
+                let calls = Rc::clone(&calls);
+                move |args: RuntimeArgs| {
+                    calls.set(calls.get() + 1);
+                    let a: i32 = args.nth_checked(0)?;
+                    let b: i32 = args.nth_checked(1)?;
+                    Ok(Some(RuntimeValue::I32(a + b)))
+                }
+            },
+        ),
+    };
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "add" (func $add (param i32 i32) (result i32)))
+	(func (export "test") (result i32)
+		(call $add (i32.const 2) (call $add (i32.const 3) (i32.const 4)))
+	)
+)
+"#,
+    );
+
+    let instance = ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &resolver),
+    )
+    .expect("Failed to instantiate module")
+    .assert_no_start();
+
+    // The closure is called without any externals to dispatch to.
+    assert_eq!(
+        instance
+            .invoke_export("test", &[], &mut NopExternals)
+            .expect("Failed to invoke 'test' function"),
+        Some(RuntimeValue::I32(9))
+    );
+    assert_eq!(calls.get(), 2);
+
+    // The closure can be invoked directly as well.
+    assert_eq!(
+        FuncInstance::invoke(
+            &resolver.add,
+            &[RuntimeValue::I32(1), RuntimeValue::I32(2)],
+            &mut NopExternals
+        )
+        .unwrap(),
+        Some(RuntimeValue::I32(3))
+    );
+    assert_eq!(calls.get(), 3);
+}
+
+#[test]
+fn host_call_argument_order() {
+    use core::cell::RefCell;
+    use std::rc::Rc;
+
+    struct RecordingResolver {
+        record: FuncRef,
+    }
+
+    impl ModuleImportResolver for RecordingResolver {
+        fn resolve_func(
+            &self,
+            _field_name: &str,
+            _signature: &Signature,
+        ) -> Result<FuncRef, Error> {
+            Ok(self.record.clone())
+        }
+    }
+
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let resolver = RecordingResolver {
+        record: FuncInstance::alloc_host_closure(
+            Signature::new(
+                &[
+                    ValueType::I32,
+                    ValueType::I64,
+                    ValueType::F32,
+                    ValueType::F64,
+                ][..],
+                None,
+            ),
+            {
+                let calls = Rc::clone(&calls);
+                move |args: RuntimeArgs| {
+                    calls.borrow_mut().push(args.as_ref().to_vec());
+                    Ok(None)
+                }
+            },
+        ),
+    };
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "record" (func $record (param i32 i64 f32 f64)))
+	(func (export "test") (param i32)
+		(call $record (get_local 0) (i64.const -2) (f32.const 3.5) (f64.const 4.25))
+		;; Values below the arguments stay on the stack.
+		(i32.add
+			(get_local 0)
+			(block (result i32)
+				(call $record (i32.const 5) (i64.const 6) (f32.const 7) (f64.const 8))
+				(i32.const 1)
+			)
+		)
+		(call $record (i32.const 9) (i64.const 10) (f32.const 11) (f64.const 12))
+		(drop)
+	)
+)
+"#,
+    );
+
+    let instance = ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &resolver),
+    )
+    .expect("Failed to instantiate module")
+    .assert_no_start();
+    instance
+        .invoke_export("test", &[RuntimeValue::I32(1)], &mut NopExternals)
+        .expect("Failed to invoke 'test' function");
+
+    let call = |a: i32, b: i64, c: f32, d: f64| {
+        [
+            RuntimeValue::I32(a),
+            RuntimeValue::I64(b),
+            RuntimeValue::F32(c.into()),
+            RuntimeValue::F64(d.into()),
+        ]
+    };
+    assert_eq!(
+        *calls.borrow(),
+        [
+            call(1, -2, 3.5, 4.25),
+            call(5, 6, 7.0, 8.0),
+            call(9, 10, 11.0, 12.0)
+        ]
+    );
+}
+
+#[test]
+fn host_result_policy() {
+    struct PolicyExternals;
+
+    impl Externals for PolicyExternals {
+        fn invoke_index(
+            &mut self,
+            _index: usize,
+            args: RuntimeArgs,
+        ) -> Result<Option<RuntimeValue>, Trap> {
+            let value: i32 = args.nth_checked(0)?;
+            Ok(Some(RuntimeValue::I32(value)))
+        }
+
+        fn on_host_result(
+            &mut self,
+            _func: &FuncRef,
+            result: Option<RuntimeValue>,
+        ) -> Result<Option<RuntimeValue>, Trap> {
+            match result {
+                // Negative values are rejected, large ones clamped.
+                Some(RuntimeValue::I32(value)) if value < 0 => {
+                    Err(TrapKind::Host(Box::new(HostErrorWithCode { error_code: 1 })).into())
+                }
+                Some(RuntimeValue::I32(value)) if value > 100 => Ok(Some(RuntimeValue::I32(100))),
+                // A value of the wrong type can't be substituted.
+                Some(RuntimeValue::I32(13)) => Ok(Some(RuntimeValue::I64(13))),
+                result => Ok(result),
+            }
+        }
+    }
+
+    struct PolicyResolver;
+
+    impl ModuleImportResolver for PolicyResolver {
+        fn resolve_func(&self, _field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+            Ok(FuncInstance::alloc_host(signature.clone(), 0))
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "identity" (func $identity (param i32) (result i32)))
+	(func (export "test") (param i32) (result i32)
+		(i32.add (call $identity (get_local 0)) (i32.const 1))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &PolicyResolver),
+    )
+    .expect("Failed to instantiate module")
+    .assert_no_start();
+    let test =
+        |value| instance.invoke_export("test", &[RuntimeValue::I32(value)], &mut PolicyExternals);
+
+    assert_eq!(test(5).unwrap(), Some(RuntimeValue::I32(6)));
+    assert_eq!(test(500).unwrap(), Some(RuntimeValue::I32(101)));
+    let error = test(-5).expect_err("negative results should trap");
+    let error_with_code = error
+        .as_host_error()
+        .expect("Expected host error")
+        .downcast_ref::<HostErrorWithCode>()
+        .expect("Failed to downcast to expected error type");
+    assert_eq!(error_with_code.error_code, 1);
+    match test(13) {
+        Err(Error::Trap(trap)) => {
+            assert!(matches!(trap.kind(), TrapKind::UnexpectedSignature))
+        }
+        result => panic!("expected a signature trap, got {:?}", result),
+    }
+}
+
+#[test]
+fn table_fill_and_copy() {
+    use crate::func::FuncInstanceInternal;
+
+    let table = TableInstance::alloc(8, None).unwrap();
+    let func = |index| FuncInstance::alloc_host(Signature::new(&[][..], None), index);
+    let host_index = |elem: Option<FuncRef>| {
+        elem.map(|func| match *func.as_internal() {
+            FuncInstanceInternal::Host {
+                host_func_index, ..
+            } => host_func_index,
+            _ => panic!("unexpected table element"),
+        })
+    };
+    let contents = |table: &TableRef| {
+        (0..table.current_size())
+            .map(|index| host_index(table.get(index).unwrap()))
+            .collect::<Vec<_>>()
+    };
+
+    table.fill(1, Some(func(7)), 3).unwrap();
+    table.set(4, Some(func(9))).unwrap();
+    assert_eq!(
+        contents(&table),
+        [None, Some(7), Some(7), Some(7), Some(9), None, None, None]
+    );
+
+    // Overlapping ranges are copied as if through a temporary buffer, both ways.
+    table.copy(2, 1, 4).unwrap();
+    assert_eq!(
+        contents(&table),
+        [
+            None,
+            Some(7),
+            Some(7),
+            Some(7),
+            Some(7),
+            Some(9),
+            None,
+            None
+        ]
+    );
+    table.copy(0, 2, 4).unwrap();
+    assert_eq!(
+        contents(&table),
+        [
+            Some(7),
+            Some(7),
+            Some(7),
+            Some(9),
+            Some(7),
+            Some(9),
+            None,
+            None
+        ]
+    );
+
+    // Clearing a range and empty ranges at the very end are fine.
+    table.fill(0, None, 2).unwrap();
+    table.fill(8, Some(func(1)), 0).unwrap();
+    table.copy(8, 0, 0).unwrap();
+    assert_eq!(
+        contents(&table),
+        [None, None, Some(7), Some(9), Some(7), Some(9), None, None]
+    );
+
+    // Out of bounds ranges fail without touching the table.
+    assert!(table.fill(6, Some(func(1)), 3).is_err());
+    assert!(table.fill(u32::MAX, Some(func(1)), 2).is_err());
+    assert!(table.copy(0, 6, 3).is_err());
+    assert!(table.copy(6, 0, 3).is_err());
+    assert_eq!(
+        contents(&table),
+        [None, None, Some(7), Some(9), Some(7), Some(9), None, None]
+    );
+
+    // Functions can't be filled into a table of externrefs.
+    let externs = TableInstance::alloc_typed(2, None, TableElementType::ExternRef).unwrap();
+    assert!(externs.fill(0, Some(func(1)), 1).is_err());
+}
+
+#[test]
+fn table_grow() {
+    use crate::func::FuncInstanceInternal;
+
+    let table = TableInstance::alloc(1, Some(4)).unwrap();
+    let func = FuncInstance::alloc_host(Signature::new(&[][..], None), 3);
+    let host_index = |elem: Option<FuncRef>| {
+        elem.map(|func| match *func.as_internal() {
+            FuncInstanceInternal::Host {
+                host_func_index, ..
+            } => host_func_index,
+            _ => panic!("unexpected table element"),
+        })
+    };
+
+    // Growing returns the previous size and initializes the new elements.
+    assert_eq!(table.grow(2, Some(func)).unwrap(), 1);
+    assert_eq!(table.current_size(), 3);
+    assert_eq!(table.maximum_size(), Some(4));
+    let contents: Vec<_> = (0..3)
+        .map(|index| host_index(table.get(index).unwrap()))
+        .collect();
+    assert_eq!(contents, [None, Some(3), Some(3)]);
+
+    // Up to the maximum, but not beyond it.
+    assert!(table.grow(2, None).is_err());
+    assert_eq!(table.current_size(), 3);
+    assert_eq!(table.grow(1, None).unwrap(), 3);
+    assert_eq!(table.grow(0, None).unwrap(), 4);
+    assert!(table.grow(1, None).is_err());
+    assert!(table.get(3).unwrap().is_none());
+
+    // Without a declared maximum the size is only limited by the index space.
+    let table = TableInstance::alloc(0, None).unwrap();
+    assert_eq!(table.grow(16, None).unwrap(), 0);
+    assert!(table.grow(u32::MAX, None).is_err());
+
+    // Elements have to match the element type of the table.
+    let externs = TableInstance::alloc_typed(0, None, TableElementType::ExternRef).unwrap();
+    let extern_ref = TableElement::Extern(ExternRef::new(7u32));
+    assert!(externs.grow(1, None).is_err());
+    assert_eq!(externs.grow_element(1, Some(extern_ref)).unwrap(), 0);
+    assert!(table
+        .grow_element(1, externs.get_element(0).unwrap())
+        .is_err());
+    assert_eq!(table.current_size(), 16);
+}
+
+#[test]
+fn externref_table() {
+    let table = TableInstance::alloc_typed(2, None, TableElementType::ExternRef).unwrap();
+    assert_eq!(table.element_type(), TableElementType::ExternRef);
+
+    let host_value = ExternRef::new(String::from("host value"));
+    table
+        .set_element(1, Some(TableElement::Extern(host_value.clone())))
+        .unwrap();
+
+    assert!(table.get_element(0).unwrap().is_none());
+    match table.get_element(1).unwrap() {
+        Some(TableElement::Extern(extern_ref)) => {
+            assert!(extern_ref.ptr_eq(&host_value));
+            assert_eq!(
+                extern_ref.downcast_ref::<String>().map(String::as_str),
+                Some("host value")
+            );
+        }
+        other => panic!("expected an externref, got {:?}", other),
+    }
+
+    // Functions can't be stored in or read from a table of externrefs.
+    let func = FuncInstance::alloc_host(Signature::new(&[][..], None), 0);
+    assert!(table.set(0, Some(func.clone())).is_err());
+    assert!(table
+        .set_element(0, Some(TableElement::Func(func)))
+        .is_err());
+    assert!(table.get(1).is_err());
+
+    // A table of externrefs can't be used where a module expects a table of functions,
+    // so `call_indirect` never sees one.
+    struct TableResolver {
+        table: TableRef,
+    }
+
+    impl ModuleImportResolver for TableResolver {
+        fn resolve_table(
+            &self,
+            _field_name: &str,
+            _table_type: &TableDescriptor,
+        ) -> Result<TableRef, Error> {
+            Ok(self.table.clone())
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "table" (table 2 anyfunc))
+	(type $t (func))
+	(func (export "call") (param i32)
+		(call_indirect (type $t) (get_local 0))
+	)
+)
+"#,
+    );
+    let resolver = TableResolver { table };
+    match ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &resolver),
+    ) {
+        Err(Error::Instantiation(InstantiationError::ImportTypeMismatch { .. })) => {}
+        Err(err) => panic!("expected an import type mismatch, got {:?}", err),
+        Ok(_) => panic!("expected instantiation to fail"),
+    }
+}
+
+#[test]
+fn externref_global() {
+    let host_value = ExternRef::new(String::from("host value"));
+    let global = ExternRefGlobalInstance::alloc(None, true);
+    assert!(global.get().is_none());
+
+    // The host gets back the same handle it stored.
+    global.set(Some(host_value.clone())).unwrap();
+    let extern_ref = global.get().unwrap();
+    assert!(extern_ref.ptr_eq(&host_value));
+    assert_eq!(
+        extern_ref.downcast_ref::<String>().map(String::as_str),
+        Some("host value")
+    );
+    global.set(None).unwrap();
+    assert!(global.get().is_none());
+
+    // Immutable globals can't be changed.
+    let immutable = ExternRefGlobalInstance::alloc(Some(host_value.clone()), false);
+    assert!(!immutable.is_mutable());
+    assert!(immutable.set(None).is_err());
+    assert!(immutable.get().unwrap().ptr_eq(&host_value));
+}
+
+#[test]
+fn signature_mismatch_fallback() {
+    const SENTINEL: i32 = -1;
+
+    /// Redirects mismatched indirect calls to a host function returning `SENTINEL`,
+    /// optionally with the wrong signature.
+    struct FallbackExternals {
+        fallback_signature: Option<Signature>,
+        mismatches: usize,
+    }
+
+    impl Externals for FallbackExternals {
+        fn invoke_index(
+            &mut self,
+            index: usize,
+            args: RuntimeArgs,
+        ) -> Result<Option<RuntimeValue>, Trap> {
+            assert_eq!(index, 0);
+            assert_eq!(args.as_ref(), [RuntimeValue::I32(7)]);
+            Ok(Some(RuntimeValue::I32(SENTINEL)))
+        }
+
+        fn on_signature_mismatch(
+            &mut self,
+            expected: &Signature,
+            found: &FuncRef,
+        ) -> Option<FuncRef> {
+            assert_eq!(
+                *expected,
+                Signature::new(&[ValueType::I32][..], Some(ValueType::I32))
+            );
+            assert_eq!(
+                *found.signature(),
+                Signature::new(&[][..], Some(ValueType::I32))
+            );
+            self.mismatches += 1;
+            self.fallback_signature
+                .clone()
+                .map(|signature| FuncInstance::alloc_host(signature, 0))
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(type $t (func (param i32) (result i32)))
+	(table 2 anyfunc)
+	(func $double (type $t) (i32.mul (get_local 0) (i32.const 2)))
+	(func $answer (result i32) (i32.const 42))
+	(elem (i32.const 0) $double $answer)
+	(func (export "call") (param i32 i32) (result i32)
+		(i32.add
+			(call_indirect (type $t) (get_local 1) (get_local 0))
+			(i32.const 100))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    fn call<E: Externals>(
+        instance: &ModuleRef,
+        index: i32,
+        externals: &mut E,
+    ) -> Result<Option<RuntimeValue>, Error> {
+        instance.invoke_export(
+            "call",
+            &[RuntimeValue::I32(index), RuntimeValue::I32(7)],
+            externals,
+        )
+    }
+    let is_mismatch = |result: Result<Option<RuntimeValue>, Error>| {
+        matches!(
+            result,
+            Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::UnexpectedSignature)
+        )
+    };
+
+    let mut externals = FallbackExternals {
+        fallback_signature: Some(Signature::new(&[ValueType::I32][..], Some(ValueType::I32))),
+        mismatches: 0,
+    };
+    // Matching calls don't consult the hook.
+    assert_eq!(
+        call(&instance, 0, &mut externals).unwrap(),
+        Some(RuntimeValue::I32(114))
+    );
+    assert_eq!(externals.mismatches, 0);
+    // The mismatched call is redirected and execution continues in the caller.
+    assert_eq!(
+        call(&instance, 1, &mut externals).unwrap(),
+        Some(RuntimeValue::I32(SENTINEL + 100))
+    );
+    assert_eq!(externals.mismatches, 1);
+
+    // A fallback with the wrong signature can't be called in place of the expected one.
+    let mut externals = FallbackExternals {
+        fallback_signature: Some(Signature::new(&[][..], Some(ValueType::I32))),
+        mismatches: 0,
+    };
+    assert!(is_mismatch(call(&instance, 1, &mut externals)));
+    assert_eq!(externals.mismatches, 1);
+
+    // By default mismatches trap as the spec demands.
+    assert!(is_mismatch(call(&instance, 1, &mut NopExternals)));
+}
+
+#[test]
+fn instantiate_with_host_table() {
+    /// Dispatches to host functions that multiply their argument by `index + 1`.
+    struct Dispatch;
+
+    impl Externals for Dispatch {
+        fn invoke_index(
+            &mut self,
+            index: usize,
+            args: RuntimeArgs,
+        ) -> Result<Option<RuntimeValue>, Trap> {
+            let a: i32 = args.nth_checked(0)?;
+            Ok(Some(RuntimeValue::I32(a * (index as i32 + 1))))
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(type $t (func (param i32) (result i32)))
+	(table 4 anyfunc)
+	(func $negate (type $t) (i32.sub (i32.const 0) (get_local 0)))
+	(elem (i32.const 1) $negate)
+	(func (export "call") (param i32 i32) (result i32)
+		(call_indirect (type $t) (get_local 1) (get_local 0))
+	)
+)
+"#,
+    );
+    let signature = Signature::new(&[ValueType::I32][..], Some(ValueType::I32));
+    let table_contents = vec![
+        Some(FuncInstance::alloc_host(signature.clone(), 0)),
+        Some(FuncInstance::alloc_host(signature.clone(), 1)),
+        Some(FuncInstance::alloc_host(signature, 2)),
+    ];
+    let instance =
+        ModuleInstance::new_with_table(&module, &ImportsBuilder::default(), table_contents)
+            .unwrap()
+            .assert_no_start();
+    let call = |index: i32| {
+        instance.invoke_export(
+            "call",
+            &[RuntimeValue::I32(index), RuntimeValue::I32(7)],
+            &mut Dispatch,
+        )
+    };
+
+    assert_eq!(call(0).unwrap(), Some(RuntimeValue::I32(7)));
+    // The element segment overrides the host function in slot 1.
+    assert_eq!(call(1).unwrap(), Some(RuntimeValue::I32(-7)));
+    assert_eq!(call(2).unwrap(), Some(RuntimeValue::I32(21)));
+    assert!(matches!(
+        call(3),
+        Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::ElemUninitialized)
+    ));
+
+    // The contents must fit into the table.
+    let signature = Signature::new(&[ValueType::I32][..], Some(ValueType::I32));
+    let too_many = vec![Some(FuncInstance::alloc_host(signature, 0)); 5];
+    assert!(matches!(
+        ModuleInstance::new_with_table(&module, &ImportsBuilder::default(), too_many),
+        Err(Error::Instantiation(_))
+    ));
+
+    // Imported tables are left alone, since other instances might use them.
+    struct TableImport(TableRef);
+
+    impl ModuleImportResolver for TableImport {
+        fn resolve_table(
+            &self,
+            _field_name: &str,
+            _table_type: &TableDescriptor,
+        ) -> Result<TableRef, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "table" (table 4 anyfunc))
+)
+"#,
+    );
+    let table = TableInstance::alloc(4, None).unwrap();
+    let resolver = TableImport(table.clone());
+    let imports = ImportsBuilder::new().with_resolver("env", &resolver);
+    let signature = Signature::new(&[ValueType::I32][..], Some(ValueType::I32));
+    let contents = vec![Some(FuncInstance::alloc_host(signature, 0))];
+    assert!(matches!(
+        ModuleInstance::new_with_table(&module, &imports, contents),
+        Err(Error::Instantiation(_))
+    ));
+    assert!(table.get(0).unwrap().is_none());
+}
+
+#[test]
+fn seeded_random_get() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "wasi_snapshot_preview1" "random_get" (func $random_get (param i32 i32) (result i32)))
+	(memory (export "memory") 1)
+	(func (export "fill") (param i32 i32) (result i32)
+		(call $random_get (get_local 0) (get_local 1))
+	)
+)
+"#,
+    );
+
+    let run = |seed: u64| {
+        let mut random = SeededRandom::new(seed);
+        let mut host = CallerHost::new(|_index, args, caller| random.random_get(args, &caller));
+        let instance = ModuleInstance::new(
+            &module,
+            &ImportsBuilder::new().with_resolver("wasi_snapshot_preview1", &AnyHostFunc),
+        )
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+        let mut fill = |ptr: i32, len: i32| {
+            instance
+                .invoke_export(
+                    "fill",
+                    &[RuntimeValue::I32(ptr), RuntimeValue::I32(len)],
+                    &mut host,
+                )
+                .map_err(|err| format!("{:?}", err))
+        };
+        assert_eq!(fill(0, 13), Ok(Some(RuntimeValue::I32(0))));
+        assert_eq!(fill(16, 13), Ok(Some(RuntimeValue::I32(0))));
+        assert!(fill(65530, 13).is_err());
+
+        let memory = instance
+            .export_by_name("memory")
+            .and_then(|export| export.as_memory().cloned())
+            .unwrap();
+        (memory.get(0, 13).unwrap(), memory.get(16, 13).unwrap())
+    };
+
+    let (first, second) = run(42);
+    assert_ne!(first, second);
+    assert_eq!(run(42), (first.clone(), second));
+    assert_ne!(run(7).0, first);
+}
+
+#[test]
+fn stdio_capture() {
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    const FD_READ: usize = 0;
+    const FD_WRITE: usize = 1;
+
+    struct StdioImports;
+
+    impl ModuleImportResolver for StdioImports {
+        fn resolve_func(&self, field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+            let index = match field_name {
+                "fd_read" => FD_READ,
+                "fd_write" => FD_WRITE,
+                _ => {
+                    return Err(Error::Instantiation(InstantiationError::Other(format!(
+                        "unknown export {}",
+                        field_name
+                    ))))
+                }
+            };
+            Ok(FuncInstance::alloc_host(signature.clone(), index))
+        }
+    }
+
+    // Two iovecs at offset 0 point to "hello, " and "world" at offset 16,
+    // a third one at offset 32 points to an 8 byte read buffer at offset 48.
+    // At offset 72 a valid iovec is followed by one that is out of bounds.
+    let module = parse_wat(
+        r#"
+(module
+	(import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+	(import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+	(memory (export "memory") 1)
+	(data (i32.const 0) "\10\00\00\00\07\00\00\00\17\00\00\00\05\00\00\00")
+	(data (i32.const 16) "hello, world")
+	(data (i32.const 32) "\30\00\00\00\08\00\00\00")
+	(data (i32.const 72) "\30\00\00\00\08\00\00\00\00\00\01\00\00\00\00\40")
+	(func (export "write") (param i32 i32 i32) (result i32)
+		(call $fd_write (get_local 0) (get_local 1) (get_local 2) (i32.const 64))
+	)
+	(func (export "read") (param i32 i32 i32) (result i32)
+		(call $fd_read (get_local 0) (get_local 1) (get_local 2) (i32.const 64))
+	)
+)
+"#,
+    );
+
+    let stdout = Rc::new(RefCell::new(Vec::new()));
+    let stderr = Rc::new(RefCell::new(Vec::new()));
+    let mut stdin: &'static [u8] = b"input";
+    let mut stdio = Stdio::new()
+        .with_stdin(move |buf| {
+            let len = buf.len().min(stdin.len());
+            buf[..len].copy_from_slice(&stdin[..len]);
+            stdin = &stdin[len..];
+            len
+        })
+        .with_stdout({
+            let stdout = Rc::clone(&stdout);
+            move |bytes| stdout.borrow_mut().extend_from_slice(bytes)
+        })
+        .with_stderr({
+            let stderr = Rc::clone(&stderr);
+            move |bytes| stderr.borrow_mut().extend_from_slice(bytes)
+        });
+    let mut host = CallerHost::new(|index, args, caller| match index {
+        FD_READ => stdio.fd_read(args, &caller),
+        FD_WRITE => stdio.fd_write(args, &caller),
+        _ => panic!("unexpected host function index {}", index),
+    });
+    let instance = ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("wasi_snapshot_preview1", &StdioImports),
+    )
+    .expect("Failed to instantiate module")
+    .assert_no_start();
+    let memory = instance
+        .export_by_name("memory")
+        .and_then(|export| export.as_memory().cloned())
+        .unwrap();
+    let mut invoke = |name: &str, args: &[RuntimeValue]| {
+        instance
+            .invoke_export(name, args, &mut host)
+            .map_err(|err| format!("{:?}", err))
+    };
+    let ok = Ok(Some(RuntimeValue::I32(0)));
+    let badf = Ok(Some(RuntimeValue::I32(8)));
+    let inval = Ok(Some(RuntimeValue::I32(28)));
+
+    assert_eq!(
+        invoke(
+            "write",
+            &[
+                RuntimeValue::I32(1),
+                RuntimeValue::I32(0),
+                RuntimeValue::I32(2)
+            ]
+        ),
+        ok
+    );
+    assert_eq!(memory.get_value::<u32>(64).unwrap(), 12);
+    assert_eq!(
+        invoke(
+            "write",
+            &[
+                RuntimeValue::I32(2),
+                RuntimeValue::I32(8),
+                RuntimeValue::I32(1)
+            ]
+        ),
+        ok
+    );
+    assert_eq!(memory.get_value::<u32>(64).unwrap(), 5);
+    assert_eq!(
+        invoke(
+            "write",
+            &[
+                RuntimeValue::I32(3),
+                RuntimeValue::I32(0),
+                RuntimeValue::I32(2)
+            ]
+        ),
+        badf
+    );
+    assert!(invoke(
+        "write",
+        &[
+            RuntimeValue::I32(1),
+            RuntimeValue::I32(65532),
+            RuntimeValue::I32(1)
+        ]
+    )
+    .is_err());
+    // None of the buffers is written if one of them is out of bounds.
+    assert!(invoke(
+        "write",
+        &[
+            RuntimeValue::I32(1),
+            RuntimeValue::I32(72),
+            RuntimeValue::I32(2)
+        ]
+    )
+    .is_err());
+    // More iovecs than WASI's `IOV_MAX` are rejected before looking at any of them.
+    assert_eq!(
+        invoke(
+            "write",
+            &[
+                RuntimeValue::I32(1),
+                RuntimeValue::I32(0),
+                RuntimeValue::I32(1025)
+            ]
+        ),
+        inval
+    );
+    assert_eq!(&stdout.borrow()[..], b"hello, world");
+    assert_eq!(&stderr.borrow()[..], b"world");
+
+    let read = |fd: i32, iovs: i32, iovs_len: i32| {
+        [
+            RuntimeValue::I32(fd),
+            RuntimeValue::I32(iovs),
+            RuntimeValue::I32(iovs_len),
+        ]
+    };
+    // The second iovec claims 1 GiB at offset 64 KiB, which must trap before any input
+    // is consumed or buffered.
+    assert!(invoke("read", &read(0, 72, 2)).is_err());
+    assert!(invoke("read", &read(0, 80, 1)).is_err());
+    assert_eq!(invoke("read", &read(0, 32, 1)), ok);
+    assert_eq!(memory.get_value::<u32>(64).unwrap(), 5);
+    assert_eq!(memory.get(48, 5).unwrap(), b"input");
+    assert_eq!(invoke("read", &read(0, 32, 1)), ok);
+    assert_eq!(memory.get_value::<u32>(64).unwrap(), 0);
+    assert_eq!(invoke("read", &read(1, 32, 1)), badf);
+    assert_eq!(invoke("read", &read(0, 0, 1025)), inval);
+}
+
+#[test]
+fn start_observes_initialized_instance() {
+    struct ObservingImports;
+
+    impl ModuleImportResolver for ObservingImports {
+        fn resolve_func(&self, field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+            AnyHostFunc.resolve_func(field_name, signature)
+        }
+
+        fn resolve_global(
+            &self,
+            _field_name: &str,
+            _global_type: &GlobalDescriptor,
+        ) -> Result<GlobalRef, Error> {
+            Ok(GlobalInstance::alloc(RuntimeValue::I32(40), false))
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "observe" (func $observe))
+	(import "env" "base" (global $base i32))
+	(global $g (export "g") i32 (get_global $base))
+	(global $seen (export "seen") (mut i32) (i32.const 0))
+	(memory 1)
+	(data (i32.const 0) "\01\02")
+	(table 1 anyfunc)
+	(elem (i32.const 0) $answer)
+	(func $answer (result i32)
+		(i32.const 42)
+	)
+	(func (export "indirect") (result i32)
+		(call_indirect (result i32) (i32.const 0))
+	)
+	(func $start
+		(set_global $seen
+			(i32.add
+				(get_global $g)
+				(i32.load8_u (i32.const 1))
+			)
+		)
+		(call $observe)
+	)
+	(start $start)
+)
+"#,
+    );
+
+    let mut observed = None;
+    let mut host = CallerHost::new(|_index, _args, caller| {
+        let global = caller.get_global("g").expect("exported global `g`");
+        let data = caller.memory().unwrap().get(0, 2).unwrap();
+        // Re-enter the instance that is being started.
+        let indirect = caller
+            .module()
+            .invoke_export("indirect", &[], &mut NopExternals)
+            .expect("exports are callable from the start function");
+        observed = Some((global, data, indirect));
+        Ok(None)
+    });
+    let instance = ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &ObservingImports),
+    )
+    .expect("Failed to instantiate module")
+    .run_start(&mut host)
+    .expect("Failed to run start function");
+
+    assert_eq!(
+        instance
+            .export_by_name("seen")
+            .and_then(|export| export.as_global().map(|global| global.get())),
+        Some(RuntimeValue::I32(42))
+    );
+    assert_eq!(
+        observed,
+        Some((
+            RuntimeValue::I32(40),
+            vec![1, 2],
+            Some(RuntimeValue::I32(42))
+        ))
+    );
+}
+
+#[test]
+fn shared_externals() {
+    use core::cell::Cell;
+
+    const INC: usize = 0;
+    const REENTER: usize = 1;
+
+    struct CountingHost {
+        incs: Cell<u32>,
+        reentries: Cell<u32>,
+    }
+
+    impl SharedExternals for CountingHost {
+        fn invoke_index(
+            &self,
+            _index: usize,
+            _args: RuntimeArgs,
+        ) -> Result<Option<RuntimeValue>, Trap> {
+            panic!("host functions should always be invoked with a caller");
+        }
+
+        fn invoke_index_with_caller(
+            &self,
+            index: usize,
+            args: RuntimeArgs,
+            caller: Caller,
+        ) -> Result<Option<RuntimeValue>, Trap> {
+            match index {
+                INC => {
+                    let by: u32 = args.nth_checked(0)?;
+                    self.incs.set(self.incs.get() + by);
+                    Ok(Some(RuntimeValue::I32(self.incs.get() as i32)))
+                }
+                REENTER => {
+                    self.reentries.set(self.reentries.get() + 1);
+                    // Re-enter the calling instance with the same host state.
+                    caller
+                        .module()
+                        .invoke_export_shared("inc", &[RuntimeValue::I32(10)], self)
+                        .map_err(|err| match err {
+                            Error::Trap(trap) => trap,
+                            other => panic!("unexpected error {:?}", other),
+                        })
+                }
+                _ => panic!("unexpected host function index {}", index),
+            }
+        }
+    }
+
+    impl ModuleImportResolver for CountingHost {
+        fn resolve_func(&self, field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+            let index = match field_name {
+                "inc" => INC,
+                "reenter" => REENTER,
+                _ => {
+                    return Err(Error::Instantiation(InstantiationError::Other(format!(
+                        "unknown export {}",
+                        field_name
+                    ))))
+                }
+            };
+            Ok(FuncInstance::alloc_host(signature.clone(), index))
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "inc" (func $inc (param i32) (result i32)))
+	(import "env" "reenter" (func $reenter (result i32)))
+	(func (export "inc") (param i32) (result i32)
+		(call $inc (get_local 0))
+	)
+	(func (export "reenter") (result i32)
+		(call $reenter)
+	)
+)
+"#,
+    );
+
+    let host = CountingHost {
+        incs: Cell::new(0),
+        reentries: Cell::new(0),
+    };
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &host))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let result = instance
+        .invoke_export_shared("inc", &[RuntimeValue::I32(1)], &host)
+        .unwrap();
+    assert_eq!(result, Some(RuntimeValue::I32(1)));
+
+    // The host state can be read while it is shared with the interpreter.
+    let observer = &host;
+    let reenter = instance
+        .export_by_name("reenter")
+        .and_then(|export| export.as_func().cloned())
+        .unwrap();
+    let result = FuncInstance::invoke_shared(&reenter, &[], &host).unwrap();
+    assert_eq!(result, Some(RuntimeValue::I32(11)));
+    assert_eq!((observer.incs.get(), observer.reentries.get()), (11, 1));
+
+    // A shared reference can also be passed to the `&mut E` entry points.
+    let result = instance
+        .invoke_export("inc", &[RuntimeValue::I32(2)], &mut &host)
+        .unwrap();
+    assert_eq!(result, Some(RuntimeValue::I32(13)));
+}
+
+#[cfg(feature = "stepping")]
+#[test]
+fn stepper() {
+    use crate::isa::CALL_SIZE;
+    use crate::{StepOutcome, Stepper};
+
+    let module = parse_wat(
+        r#"
+(module
+	(func $inner (export "inner") (result i32)
+		(i32.add (i32.const 1) (i32.const 2))
+	)
+	(func (export "main") (result i32)
+		(i32.add (call $inner) (i32.const 10))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let export = |name: &str| {
+        instance
+            .export_by_name(name)
+            .and_then(|export| export.as_func().cloned())
+            .unwrap()
+    };
+    let (main, inner) = (export("main"), export("inner"));
+    let is_in = |stepper: &Stepper, func: &FuncRef| {
+        core::ptr::eq::<FuncInstance>(&**stepper.function().unwrap(), &**func)
+    };
+
+    // Step into the call, through the callee and out of it again.
+    let mut stepper = Stepper::new(&main, &[]).unwrap();
+    assert!(is_in(&stepper, &main));
+    assert_eq!((stepper.depth(), stepper.position()), (1, Some(0)));
+    assert_eq!(
+        stepper.step_into(&mut NopExternals).unwrap(),
+        StepOutcome::Paused
+    );
+    assert!(is_in(&stepper, &inner));
+    assert_eq!((stepper.depth(), stepper.position()), (2, Some(0)));
+    assert_eq!(
+        stepper.step_over(&mut NopExternals).unwrap(),
+        StepOutcome::Paused
+    );
+    assert!(is_in(&stepper, &inner));
+    assert_eq!(stepper.depth(), 2);
+    assert_ne!(stepper.position(), Some(0));
+    assert_eq!(
+        stepper.step_out(&mut NopExternals).unwrap(),
+        StepOutcome::Paused
+    );
+    assert!(is_in(&stepper, &main));
+    assert_eq!((stepper.depth(), stepper.position()), (1, Some(CALL_SIZE)));
+    let outcome = loop {
+        match stepper.step_over(&mut NopExternals).unwrap() {
+            StepOutcome::Paused => assert!(is_in(&stepper, &main)),
+            outcome => break outcome,
+        }
+    };
+    assert_eq!(outcome, StepOutcome::Finished(Some(RuntimeValue::I32(13))));
+    assert_eq!((stepper.depth(), stepper.position()), (0, None));
+    assert!(matches!(
+        stepper.step_into(&mut NopExternals),
+        Err(ResumableError::NotResumable)
+    ));
+
+    // Stepping over the call executes the callee entirely.
+    let mut stepper = Stepper::new(&main, &[]).unwrap();
+    assert_eq!(
+        stepper.step_over(&mut NopExternals).unwrap(),
+        StepOutcome::Paused
+    );
+    assert!(is_in(&stepper, &main));
+    assert_eq!((stepper.depth(), stepper.position()), (1, Some(CALL_SIZE)));
+
+    // Stepping out of the outermost function finishes the execution.
+    assert_eq!(
+        stepper.step_out(&mut NopExternals).unwrap(),
+        StepOutcome::Finished(Some(RuntimeValue::I32(13)))
+    );
+
+    // Mismatching arguments and host functions are rejected.
+    let mut stepper = Stepper::new(&main, &[RuntimeValue::I32(0)]);
+    assert!(matches!(stepper, Err(ResumableError::Trap(_))));
+    let host = FuncInstance::alloc_host(Signature::new(&[][..], None), 0);
+    stepper = Stepper::new(&host, &[]);
+    assert!(matches!(stepper, Err(ResumableError::NotResumable)));
+}
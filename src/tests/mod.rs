@@ -1,5 +1,6 @@
-use crate::Module;
+use crate::{ImportCounts, Module, ModuleStats};
 
+mod assembler;
 mod host;
 mod wasm;
 
@@ -38,6 +39,2787 @@ fn unsigned_to_runtime_value() {
     );
 }
 
+#[test]
+fn runtime_value_matches_type() {
+    use super::{RuntimeValue, ValueType};
+
+    let types = [
+        ValueType::I32,
+        ValueType::I64,
+        ValueType::F32,
+        ValueType::F64,
+    ];
+    for &value_type in &types {
+        let value = RuntimeValue::default(value_type);
+        for &other_type in &types {
+            assert_eq!(value.matches_type(other_type), value_type == other_type);
+        }
+    }
+}
+
+#[test]
+fn signature_equality() {
+    use crate::{Signature, ValueType};
+    use alloc::vec::Vec;
+
+    let sig = Signature::new(&[ValueType::I32, ValueType::I64][..], Some(ValueType::F32));
+
+    // Borrowed and owned parameter lists compare equal.
+    assert_eq!(
+        sig,
+        Signature::new(vec![ValueType::I32, ValueType::I64], Some(ValueType::F32))
+    );
+
+    // The order of the parameters matters.
+    assert_ne!(
+        sig,
+        Signature::new(&[ValueType::I64, ValueType::I32][..], Some(ValueType::F32))
+    );
+    // So do the number of parameters and the return type.
+    assert_ne!(
+        sig,
+        Signature::new(&[ValueType::I32][..], Some(ValueType::F32))
+    );
+    assert_ne!(
+        sig,
+        Signature::new(&[ValueType::I32, ValueType::I64][..], None)
+    );
+    assert_ne!(
+        sig,
+        Signature::new(&[ValueType::I32, ValueType::I64][..], Some(ValueType::F64))
+    );
+
+    assert_eq!(
+        Signature::new(&[][..], None),
+        Signature::new(Vec::new(), None)
+    );
+}
+
+#[test]
+fn module_import_counts() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "f" (func))
+	(import "env" "g" (func (param i32)))
+	(import "env" "memory" (memory 1))
+	(import "env" "global" (global i32))
+)
+"#,
+    );
+    assert_eq!(
+        module.import_counts(),
+        ImportCounts {
+            functions: 2,
+            tables: 0,
+            memories: 1,
+            globals: 1,
+        }
+    );
+    assert!(module.requires_memory_import());
+    assert!(!module.requires_table_import());
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "table" (table 1 anyfunc))
+	(memory 1)
+)
+"#,
+    );
+    assert!(!module.requires_memory_import());
+    assert!(module.requires_table_import());
+}
+
+#[test]
+fn module_stats() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "f" (func))
+	;; Compiles to the same code as the `add_params` compile test.
+	(func (param i32) (param i32) (result i32)
+		get_local 0
+		get_local 1
+		i32.add
+	)
+	(func (param i32)
+		(local i64 i64 i64)
+		i32.const 1
+		i32.const 2
+		i32.const 3
+		drop
+		drop
+		drop
+	)
+)
+"#,
+    );
+    assert_eq!(
+        module.stats(),
+        ModuleStats {
+            functions: 2,
+            // 4 instructions for the first function, 7 for the second one.
+            instructions: 11,
+            max_stack_height: 3,
+            max_locals: 4,
+        }
+    );
+}
+
+#[test]
+fn reachable_functions() {
+    use alloc::vec::Vec;
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "log" (func $log))
+	(table 1 anyfunc)
+	(elem (i32.const 0) $indirect)
+	(func $main (export "main")
+		(call $helper)
+	)
+	(func $helper
+		(call $leaf)
+		(call $log)
+		(call $leaf)
+	)
+	(func $leaf)
+	(func $dead
+		(call $helper)
+	)
+	(func $dispatch (export "dispatch")
+		(call_indirect (i32.const 0))
+	)
+	(func $indirect)
+)
+"#,
+    );
+    let reachable = |index: u32| {
+        module
+            .reachable_functions(index)
+            .map(|functions| functions.into_iter().collect::<Vec<_>>())
+    };
+
+    assert_eq!(reachable(1), Some(vec![1, 2, 3]));
+    assert_eq!(reachable(4), Some(vec![2, 3, 4]));
+    // Indirect calls may reach any function of the element segments.
+    assert_eq!(reachable(5), Some(vec![5, 6]));
+    // Imported and non-existent functions have no code to analyze.
+    assert_eq!(reachable(0), None);
+    assert_eq!(reachable(7), None);
+}
+
+#[test]
+fn host_func_result_type_mismatch() {
+    use super::{
+        Externals, FuncInstance, ImportsBuilder, ModuleImportResolver, ModuleInstance, RuntimeArgs,
+        RuntimeValue, Signature, Trap, TrapKind, ValueType,
+    };
+
+    struct WrongResultHost;
+
+    impl Externals for WrongResultHost {
+        fn invoke_index(
+            &mut self,
+            _index: usize,
+            _args: RuntimeArgs,
+        ) -> Result<Option<RuntimeValue>, Trap> {
+            Ok(Some(RuntimeValue::I64(0)))
+        }
+    }
+
+    impl ModuleImportResolver for WrongResultHost {
+        fn resolve_func(
+            &self,
+            _field_name: &str,
+            signature: &Signature,
+        ) -> Result<super::FuncRef, Error> {
+            Ok(FuncInstance::alloc_host(signature.clone(), 0))
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "f" (func $f (param i32) (result i32)))
+	(func (export "test") (result i32)
+		(call $f (i32.const 0))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &WrongResultHost),
+    )
+    .unwrap()
+    .assert_no_start();
+
+    // The host returns an `i64` where an `i32` is expected.
+    let error = instance
+        .invoke_export("test", &[], &mut WrongResultHost)
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        Error::Trap(ref trap) if matches!(trap.kind(), TrapKind::UnexpectedSignature)
+    ));
+
+    // Arguments are checked against the signature in the same way.
+    let func = FuncInstance::alloc_host(Signature::new(&[ValueType::I32][..], None), 0);
+    let trap = FuncInstance::invoke(
+        &func,
+        &[RuntimeValue::F32(0f32.into())],
+        &mut WrongResultHost,
+    )
+    .unwrap_err();
+    assert!(matches!(trap.kind(), TrapKind::UnexpectedSignature));
+}
+
+/// Executes the functions from the `if_else_branch_from_*` compilation tests
+/// to check the `drop`/`keep` of branches out of `if`/`else` arms dynamically.
+#[test]
+fn if_else_branch_drop_keep() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue};
+
+    let module = parse_wat(
+        r#"
+(module
+	(func (export "from_true") (param i32 i32) (result i32)
+		i32.const 10
+		get_local 0
+		if (result i32)
+			i32.const 1
+			get_local 1
+			br_if 0
+			drop
+			i32.const 2
+		else
+			i32.const 3
+		end
+		i32.add
+	)
+	(func (export "from_false") (param i32 i32) (result i32)
+		i32.const 10
+		get_local 0
+		if (result i32)
+			i32.const 1
+		else
+			i32.const 2
+			get_local 1
+			br_if 0
+			drop
+			i32.const 3
+		end
+		i32.add
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let call = |name: &str, cond: i32, br_cond: i32| {
+        instance
+            .invoke_export(
+                name,
+                &[RuntimeValue::I32(cond), RuntimeValue::I32(br_cond)],
+                &mut NopExternals,
+            )
+            .unwrap()
+    };
+
+    assert_eq!(call("from_true", 1, 1), Some(RuntimeValue::I32(11)));
+    assert_eq!(call("from_true", 1, 0), Some(RuntimeValue::I32(12)));
+    assert_eq!(call("from_true", 0, 1), Some(RuntimeValue::I32(13)));
+    assert_eq!(call("from_false", 1, 1), Some(RuntimeValue::I32(11)));
+    assert_eq!(call("from_false", 0, 1), Some(RuntimeValue::I32(12)));
+    assert_eq!(call("from_false", 0, 0), Some(RuntimeValue::I32(13)));
+}
+
+#[test]
+fn exported_global_values() {
+    use super::{ImportsBuilder, ModuleInstance, RuntimeValue};
+
+    let module = parse_wat(
+        r#"
+(module
+	(global (export "answer") i32 (i32.const 42))
+	(global (export "counter") (mut i64) (i64.const 0))
+	(func (export "func"))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+
+    assert_eq!(instance.global_value("answer"), Some(RuntimeValue::I32(42)));
+    assert_eq!(instance.global_value("counter"), Some(RuntimeValue::I64(0)));
+    assert_eq!(instance.global_value("func"), None);
+    assert_eq!(instance.global_value("missing"), None);
+
+    assert!(instance
+        .set_global_value("counter", RuntimeValue::I64(7))
+        .is_ok());
+    assert_eq!(instance.global_value("counter"), Some(RuntimeValue::I64(7)));
+
+    // Immutable globals, mismatching types and non-global exports are rejected.
+    for (name, value) in [
+        ("answer", RuntimeValue::I32(1)),
+        ("counter", RuntimeValue::I32(1)),
+        ("func", RuntimeValue::I32(1)),
+        ("missing", RuntimeValue::I32(1)),
+    ] {
+        match instance.set_global_value(name, value) {
+            Err(Error::Global(_)) => {}
+            other => panic!("expected a global error for {}, got {:?}", name, other),
+        }
+    }
+    assert_eq!(instance.global_value("answer"), Some(RuntimeValue::I32(42)));
+    assert_eq!(instance.global_value("counter"), Some(RuntimeValue::I64(7)));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn module_cache() {
+    use super::ModuleCache;
+    use std::sync::Arc;
+
+    let wasm = wabt::wat2wasm(r#"(module (func (export "f")))"#).unwrap();
+    let other = wabt::wat2wasm(r#"(module (func (export "g")))"#).unwrap();
+    let cache = Arc::new(ModuleCache::new());
+
+    let first = cache.validate_cached(&wasm).unwrap();
+    let second = cache.validate_cached(wasm.clone()).unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!((cache.hits(), cache.misses(), cache.len()), (1, 1, 1));
+
+    let third = cache.validate_cached(&other).unwrap();
+    assert!(!Arc::ptr_eq(&first, &third));
+    assert_eq!((cache.hits(), cache.misses(), cache.len()), (1, 2, 2));
+
+    // Invalid modules are not cached.
+    assert!(cache.validate_cached(&wasm[..wasm.len() - 1]).is_err());
+    assert_eq!(cache.len(), 2);
+
+    // The cache can be shared between threads.
+    let shared = {
+        let cache = Arc::clone(&cache);
+        let wasm = wasm.clone();
+        std::thread::spawn(move || cache.validate_cached(&wasm).unwrap())
+            .join()
+            .unwrap()
+    };
+    assert!(Arc::ptr_eq(&first, &shared));
+
+    cache.clear();
+    assert!(cache.is_empty());
+    assert!(!Arc::ptr_eq(&first, &cache.validate_cached(&wasm).unwrap()));
+}
+
+#[test]
+fn trap_messages() {
+    use super::{HostError, Trap, TrapKind};
+    use alloc::boxed::Box;
+    use alloc::string::{String, ToString};
+    use core::fmt;
+
+    #[derive(Debug)]
+    struct Exit(i32);
+
+    impl fmt::Display for Exit {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "exited with {}", self.0)
+        }
+    }
+
+    impl HostError for Exit {}
+
+    let cases: [(TrapKind, &str); 21] = [
+        (TrapKind::Unreachable, "unreachable"),
+        (
+            TrapKind::MemoryAccessOutOfBounds,
+            "out of bounds memory access",
+        ),
+        (
+            TrapKind::MemoryDetached,
+            "memory access to a detached memory",
+        ),
+        (TrapKind::MemoryReadOnly, "write to a read-only memory"),
+        (TrapKind::UnalignedAtomic, "unaligned atomic"),
+        (TrapKind::ExpectedSharedMemory, "expected shared memory"),
+        (TrapKind::WaitWouldBlockForever, "wait would block forever"),
+        (TrapKind::TableAccessOutOfBounds, "undefined element"),
+        (
+            TrapKind::TableSetOutOfBounds {
+                table_index: 0,
+                index: 4,
+            },
+            "out of bounds write to element 4 of table 0",
+        ),
+        (TrapKind::ElemUninitialized, "uninitialized element"),
+        (TrapKind::DivisionByZero, "integer divide by zero"),
+        (
+            TrapKind::InvalidConversionToInt,
+            "integer overflow or invalid conversion to integer",
+        ),
+        (TrapKind::ShiftOutOfRange, "shift amount out of range"),
+        (TrapKind::Timeout, "execution deadline exceeded"),
+        (
+            TrapKind::HostCallLimitExceeded { limit: 10 },
+            "host call limit of 10 exceeded",
+        ),
+        (
+            TrapKind::StackOverflow {
+                limit: 64,
+                depth: 65,
+            },
+            "call stack exhausted (depth 65 exceeds limit 64)",
+        ),
+        (TrapKind::UnexpectedSignature, "indirect call type mismatch"),
+        (TrapKind::InvalidCode, "invalid code"),
+        (TrapKind::InvalidUtf8, "invalid UTF-8"),
+        (
+            TrapKind::Unimplemented {
+                module_name: String::from("env"),
+                field_name: String::from("foo"),
+            },
+            "not implemented: env.foo",
+        ),
+        (
+            TrapKind::Host(Box::new(Exit(3))),
+            "host error: exited with 3",
+        ),
+    ];
+    for (kind, message) in cases {
+        assert_eq!(kind.to_string(), message);
+        let trap = Trap::new(kind);
+        assert_eq!(trap.to_string(), alloc::format!("Trap: {}", message));
+        assert_eq!(
+            Error::Trap(trap).to_string(),
+            alloc::format!("Trap: {}", message)
+        );
+    }
+}
+
+#[test]
+fn stack_overflow_reports_limit_and_depth() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, StackRecycler, TrapKind};
+
+    let module = parse_wat(
+        r#"
+(module
+	(func $recurse (export "recurse")
+		(call $recurse)
+	)
+	(func (export "push") (result i32)
+		(i32.add (i32.const 1)
+			(i32.add (i32.const 2)
+				(i32.add (i32.const 3)
+					(i32.add (i32.const 4) (i32.const 5)))))
+	)
+	(func (export "locals")
+		(local i64 i64 i64 i64 i64 i64)
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    // Limit the value stack to 4 values and the call stack to 10 frames.
+    let value_size = core::mem::size_of::<u64>();
+    let overflow = |name: &str| {
+        let mut stack_recycler = StackRecycler::with_limits(4 * value_size, 10);
+        match instance.invoke_export_with_stack(name, &[], &mut NopExternals, &mut stack_recycler) {
+            Err(Error::Trap(trap)) => match trap.into_kind() {
+                TrapKind::StackOverflow { limit, depth } => (limit, depth),
+                other => panic!("expected a stack overflow, got {:?}", other),
+            },
+            other => panic!("expected a stack overflow, got {:?}", other),
+        }
+    };
+
+    assert_eq!(overflow("recurse"), (10, 11));
+    assert_eq!(overflow("push"), (4, 5));
+    assert_eq!(overflow("locals"), (4, 6));
+}
+
+#[test]
+fn transactional_invoke_rolls_back_traps() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue};
+
+    let module = parse_wat(
+        r#"
+(module
+	(memory (export "memory") 1)
+	(global (export "counter") (mut i32) (i32.const 0))
+	(func (export "write") (param i32)
+		(i32.store8 (i32.const 0) (get_local 0))
+		(set_global 0 (i32.add (get_global 0) (i32.const 1)))
+	)
+	(func (export "write_then_trap") (param i32)
+		(i32.store8 (i32.const 0) (get_local 0))
+		(set_global 0 (i32.add (get_global 0) (i32.const 1)))
+		(drop (grow_memory (i32.const 1)))
+		(i32.store8 (i32.const 65536) (get_local 0))
+		(unreachable)
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let memory = instance
+        .export_by_name("memory")
+        .and_then(|export| export.as_memory().cloned())
+        .unwrap();
+    let state = || {
+        (
+            memory.get(0, 1).unwrap()[0],
+            instance.global_value("counter"),
+        )
+    };
+
+    instance
+        .invoke_export_transactional("write", &[RuntimeValue::I32(7)], &mut NopExternals)
+        .unwrap();
+    assert_eq!(state(), (7, Some(RuntimeValue::I32(1))));
+
+    // A trapping call is rolled back, pages it added are kept but zeroed.
+    assert!(instance
+        .invoke_export_transactional(
+            "write_then_trap",
+            &[RuntimeValue::I32(9)],
+            &mut NopExternals
+        )
+        .is_err());
+    assert_eq!(state(), (7, Some(RuntimeValue::I32(1))));
+    assert_eq!(memory.current_size().0, 2);
+    assert_eq!(memory.get(65536, 1).unwrap(), [0]);
+
+    // Without a transaction the partial effects remain.
+    assert!(instance
+        .invoke_export(
+            "write_then_trap",
+            &[RuntimeValue::I32(9)],
+            &mut NopExternals
+        )
+        .is_err());
+    assert_eq!(state(), (9, Some(RuntimeValue::I32(2))));
+
+    // Snapshots can also be restored on demand.
+    let snapshot = instance.snapshot().unwrap();
+    instance
+        .invoke_export("write", &[RuntimeValue::I32(3)], &mut NopExternals)
+        .unwrap();
+    assert_eq!(state(), (3, Some(RuntimeValue::I32(3))));
+    instance.restore(&snapshot).unwrap();
+    assert_eq!(state(), (9, Some(RuntimeValue::I32(2))));
+
+    let other = ModuleInstance::new(&parse_wat("(module)"), &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    assert!(matches!(other.restore(&snapshot), Err(Error::Snapshot(_))));
+}
+
+#[test]
+fn restore_rejects_mismatched_snapshot_without_changes() {
+    use super::{ImportsBuilder, ModuleInstance, RuntimeValue};
+
+    let instantiate = |wat: &str| {
+        ModuleInstance::new(&parse_wat(wat), &ImportsBuilder::default())
+            .unwrap()
+            .assert_no_start()
+    };
+    let source = instantiate(
+        r#"
+(module
+	(memory (export "memory") 2)
+	(global (export "counter") (mut i32) (i32.const 5))
+	(data (i32.const 0) "\07")
+)
+"#,
+    );
+    let snapshot = source.snapshot().unwrap();
+
+    // The memory is big enough but the global has another type.
+    let wrong_global = instantiate(
+        r#"
+(module
+	(memory (export "memory") 2)
+	(global (export "counter") (mut i64) (i64.const 1))
+	(data (i32.const 0) "\01")
+)
+"#,
+    );
+    // The global fits but the memory is smaller than the snapshot.
+    let small_memory = instantiate(
+        r#"
+(module
+	(memory (export "memory") 1)
+	(global (export "counter") (mut i32) (i32.const 1))
+	(data (i32.const 0) "\01")
+)
+"#,
+    );
+
+    for (instance, counter) in [
+        (&wrong_global, RuntimeValue::I64(1)),
+        (&small_memory, RuntimeValue::I32(1)),
+    ]
+    .iter()
+    {
+        match instance.restore(&snapshot) {
+            Err(Error::Snapshot(_)) => {}
+            other => panic!("expected a snapshot error, got {:?}", other),
+        }
+        let memory = instance
+            .export_by_name("memory")
+            .and_then(|export| export.as_memory().cloned())
+            .unwrap();
+        assert_eq!(memory.get(0, 1).unwrap(), [1]);
+        assert_eq!(instance.global_value("counter"), Some(*counter));
+    }
+}
+
+#[test]
+fn trap_in_nested_call_unwinds_frames() {
+    use super::{
+        FuncInstance, ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue, StackRecycler,
+        TrapKind,
+    };
+    use alloc::rc::Rc;
+
+    let module = parse_wat(
+        r#"
+(module
+	(func (export "trap") (param i32) (result i32)
+		(i32.add (get_local 0) (call $first (get_local 0)))
+	)
+	(func $first (param i32) (result i32)
+		(i32.add (get_local 0) (call $second (get_local 0)))
+	)
+	(func $second (param i32) (result i32)
+		(unreachable)
+	)
+	(func $depth (export "depth") (param i32) (result i32)
+		(if (result i32) (i32.eqz (get_local 0))
+			(then (i32.const 0))
+			(else (i32.add (i32.const 1) (call $depth (i32.sub (get_local 0) (i32.const 1)))))
+		)
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+
+    // Stale frames of the trapped call would make the recursion overflow the call stack.
+    let mut stack_recycler = StackRecycler::with_limits(4096, 8);
+    for _ in 0..3 {
+        match instance.invoke_export_with_stack(
+            "trap",
+            &[RuntimeValue::I32(1)],
+            &mut NopExternals,
+            &mut stack_recycler,
+        ) {
+            Err(Error::Trap(trap)) => assert!(matches!(trap.kind(), TrapKind::Unreachable)),
+            other => panic!("expected a trap, got {:?}", other),
+        }
+        let depth = instance.invoke_export_with_stack(
+            "depth",
+            &[RuntimeValue::I32(6)],
+            &mut NopExternals,
+            &mut stack_recycler,
+        );
+        assert_eq!(depth.unwrap(), Some(RuntimeValue::I32(6)));
+    }
+
+    // A trapped invocation releases the frames of its callers.
+    let trap = instance
+        .export_by_name("trap")
+        .and_then(|export| export.as_func().cloned())
+        .unwrap();
+    let strong_count = Rc::strong_count(&instance.0);
+    let mut invocation =
+        FuncInstance::invoke_resumable(&trap, &[RuntimeValue::I32(1)][..]).unwrap();
+    assert!(invocation.start_execution(&mut NopExternals).is_err());
+    assert!(!invocation.is_resumable());
+    assert_eq!(Rc::strong_count(&instance.0), strong_count);
+    assert!(invocation
+        .resume_execution(None, &mut NopExternals)
+        .is_err());
+}
+
+#[test]
+fn multi_value_results_are_rejected() {
+    // A type section declaring `(func (result i32 i64))`.
+    let wasm = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x06, 0x01, 0x60, 0x00, 0x02, 0x7f,
+        0x7e,
+    ];
+    match Module::from_buffer(&wasm[..]) {
+        Err(Error::Validation(_)) => {}
+        other => panic!("expected a validation error, got {:?}", other.err()),
+    }
+}
+
+#[cfg(feature = "wabt")]
+#[test]
+fn module_from_wat() {
+    assert!(Module::from_wat("(module (func (export \"f\")))").is_ok());
+    match Module::from_wat("(module (func") {
+        Err(Error::Parse(_)) => {}
+        other => panic!("expected a parse error, got {:?}", other.err()),
+    }
+    match Module::from_wat("(module (func (result i32)))") {
+        Err(Error::Validation(_)) => {}
+        other => panic!("expected a validation error, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn signed_remainder_of_min_by_minus_one() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue, TrapKind};
+
+    let module = parse_wat(
+        r#"
+(module
+	(func (export "i32.rem_s") (param i32 i32) (result i32)
+		(i32.rem_s (get_local 0) (get_local 1))
+	)
+	(func (export "i32.div_s") (param i32 i32) (result i32)
+		(i32.div_s (get_local 0) (get_local 1))
+	)
+	(func (export "i64.rem_s") (param i64 i64) (result i64)
+		(i64.rem_s (get_local 0) (get_local 1))
+	)
+	(func (export "i64.rem_u") (param i64 i64) (result i64)
+		(i64.rem_u (get_local 0) (get_local 1))
+	)
+	(func (export "i64.div_s") (param i64 i64) (result i64)
+		(i64.div_s (get_local 0) (get_local 1))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let invoke = |name: &str, left: RuntimeValue, right: RuntimeValue| match instance.invoke_export(
+        name,
+        &[left, right],
+        &mut NopExternals,
+    ) {
+        Ok(result) => Ok(result.unwrap()),
+        Err(Error::Trap(trap)) => Err(trap.into_kind()),
+        Err(err) => panic!("unexpected error {:?}", err),
+    };
+    let i32 = RuntimeValue::I32;
+    let i64 = RuntimeValue::I64;
+
+    // Unlike division, the remainder of the most negative value by -1 doesn't overflow.
+    assert_eq!(
+        invoke("i32.rem_s", i32(i32::MIN), i32(-1)).ok(),
+        Some(i32(0))
+    );
+    assert_eq!(
+        invoke("i64.rem_s", i64(i64::MIN), i64(-1)).ok(),
+        Some(i64(0))
+    );
+    assert!(matches!(
+        invoke("i32.div_s", i32(i32::MIN), i32(-1)),
+        Err(TrapKind::InvalidConversionToInt)
+    ));
+    assert!(matches!(
+        invoke("i64.div_s", i64(i64::MIN), i64(-1)),
+        Err(TrapKind::InvalidConversionToInt)
+    ));
+
+    // The sign of the result follows the dividend.
+    assert_eq!(invoke("i32.rem_s", i32(-7), i32(2)).ok(), Some(i32(-1)));
+    assert_eq!(invoke("i32.rem_s", i32(7), i32(-2)).ok(), Some(i32(1)));
+    assert_eq!(invoke("i64.rem_u", i64(-1), i64(-1)).ok(), Some(i64(0)));
+    assert_eq!(
+        invoke("i64.rem_u", i64(i64::MIN), i64(-1)).ok(),
+        Some(i64(i64::MIN))
+    );
+
+    for (name, left, zero) in [
+        ("i32.rem_s", i32(i32::MIN), i32(0)),
+        ("i32.div_s", i32(1), i32(0)),
+        ("i64.rem_s", i64(i64::MIN), i64(0)),
+        ("i64.rem_u", i64(1), i64(0)),
+        ("i64.div_s", i64(1), i64(0)),
+    ] {
+        assert!(matches!(
+            invoke(name, left, zero),
+            Err(TrapKind::DivisionByZero)
+        ));
+    }
+}
+
+#[test]
+fn grow_memory_without_maximum() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue};
+
+    let module = parse_wat(
+        r#"
+(module
+	(memory 1)
+	(func (export "grow") (param i32) (result i32)
+		(grow_memory (get_local 0))
+	)
+	(func (export "size") (result i32)
+		(current_memory)
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let grow = |pages: i32| {
+        instance
+            .invoke_export("grow", &[RuntimeValue::I32(pages)], &mut NopExternals)
+            .unwrap()
+    };
+    let size = || {
+        instance
+            .invoke_export("size", &[], &mut NopExternals)
+            .unwrap()
+    };
+
+    // Growing returns the size before growing.
+    assert_eq!(grow(2), Some(RuntimeValue::I32(1)));
+    assert_eq!(grow(0), Some(RuntimeValue::I32(3)));
+    assert_eq!(size(), Some(RuntimeValue::I32(3)));
+
+    // Without a declared maximum the memory is limited to 65536 pages.
+    assert_eq!(grow(65534), Some(RuntimeValue::I32(-1)));
+    assert_eq!(grow(-1), Some(RuntimeValue::I32(-1)));
+    assert_eq!(size(), Some(RuntimeValue::I32(3)));
+
+    // Growing a `Vec` based memory to 4GiB is too slow for a unit test.
+    #[cfg(all(target_pointer_width = "64", not(feature = "vec_memory")))]
+    {
+        assert_eq!(grow(65533), Some(RuntimeValue::I32(3)));
+        assert_eq!(size(), Some(RuntimeValue::I32(65536)));
+        assert_eq!(grow(1), Some(RuntimeValue::I32(-1)));
+    }
+}
+
+#[cfg(feature = "grow_failure_injection")]
+#[test]
+fn forced_grow_memory_failure() {
+    use super::{ExternVal, ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue};
+    use crate::memory_units::Pages;
+
+    let module = parse_wat(
+        r#"
+(module
+	(memory (export "memory") 1)
+	;; Returns 1 if the memory could be grown and 0 if the fallback was taken.
+	(func (export "try_grow") (result i32)
+		(if (result i32) (i32.eq (grow_memory (i32.const 1)) (i32.const -1))
+			(then (i32.const 0))
+			(else (i32.const 1))
+		)
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let memory = match instance.export_by_name("memory") {
+        Some(ExternVal::Memory(memory)) => memory,
+        _ => panic!("expected a memory export"),
+    };
+    let try_grow = || {
+        instance
+            .invoke_export("try_grow", &[], &mut NopExternals)
+            .unwrap()
+    };
+
+    memory.fail_next_grows(2);
+    // Grows by zero pages neither fail nor use up a forced failure.
+    assert_eq!(memory.grow(Pages(0)).unwrap(), Pages(1));
+    assert_eq!(try_grow(), Some(RuntimeValue::I32(0)));
+    assert_eq!(try_grow(), Some(RuntimeValue::I32(0)));
+    assert_eq!(memory.current_size().0, 1);
+
+    assert_eq!(try_grow(), Some(RuntimeValue::I32(1)));
+    assert_eq!(memory.current_size().0, 2);
+}
+
+#[test]
+fn grow_hook_reports_new_size() {
+    use super::{ExternVal, ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue};
+    use crate::memory_units::Pages;
+    use alloc::rc::Rc;
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+
+    let module = parse_wat(
+        r#"
+(module
+	(memory (export "memory") 1 5)
+	(func (export "grow") (param i32) (result i32)
+		(grow_memory (get_local 0))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let memory = match instance.export_by_name("memory") {
+        Some(ExternVal::Memory(memory)) => memory,
+        _ => panic!("expected a memory export"),
+    };
+    let grow = |pages: i32| {
+        instance
+            .invoke_export("grow", &[RuntimeValue::I32(pages)], &mut NopExternals)
+            .unwrap()
+    };
+    let reported = Rc::new(RefCell::new(Vec::new()));
+    {
+        let reported = reported.clone();
+        memory.set_grow_hook(move |new_size| reported.borrow_mut().push(new_size));
+    }
+
+    assert_eq!(grow(2), Some(RuntimeValue::I32(1)));
+    assert_eq!(*reported.borrow(), [Pages(3)]);
+
+    // Failed grows and grows by zero pages don't change the size and aren't reported.
+    assert_eq!(grow(3), Some(RuntimeValue::I32(-1)));
+    assert_eq!(grow(0), Some(RuntimeValue::I32(3)));
+    #[cfg(feature = "grow_failure_injection")]
+    {
+        memory.fail_next_grows(1);
+        assert!(memory.grow(Pages(1)).is_err());
+    }
+    assert_eq!(*reported.borrow(), [Pages(3)]);
+
+    // Grows by the host are reported as well.
+    assert_eq!(memory.grow(Pages(1)).unwrap(), Pages(3));
+    assert_eq!(*reported.borrow(), [Pages(3), Pages(4)]);
+
+    memory.clear_grow_hook();
+    assert_eq!(grow(1), Some(RuntimeValue::I32(4)));
+    assert_eq!(reported.borrow().len(), 2);
+}
+
+#[test]
+fn load_from_detached_memory() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue, TrapKind};
+
+    let module = parse_wat(
+        r#"
+(module
+	(memory (export "mem") 1)
+	(data (i32.const 0) "\2a")
+	(func (export "load") (result i32)
+		(i32.load8_u (i32.const 0))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let memory = instance
+        .export_by_name("mem")
+        .unwrap()
+        .as_memory()
+        .unwrap()
+        .clone();
+    let load = || instance.invoke_export("load", &[], &mut NopExternals);
+
+    let backing = memory.take_backing().unwrap();
+    assert!(matches!(
+        load(),
+        Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::MemoryDetached)
+    ));
+
+    memory.restore_backing(backing).unwrap();
+    assert_eq!(load().unwrap(), Some(RuntimeValue::I32(42)));
+}
+
+#[test]
+fn store_to_read_only_memory() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue, TrapKind};
+    use crate::memory_units::Pages;
+
+    let module = parse_wat(
+        r#"
+(module
+	(memory (export "mem") 1)
+	(func (export "store") (param i32)
+		(i32.store8 (i32.const 0) (get_local 0))
+	)
+	(func (export "load") (result i32)
+		(i32.load8_u (i32.const 0))
+	)
+	(func (export "grow") (result i32)
+		(grow_memory (i32.const 1))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let memory = instance
+        .export_by_name("mem")
+        .unwrap()
+        .as_memory()
+        .unwrap()
+        .clone();
+    let invoke =
+        |name: &str, args: &[RuntimeValue]| instance.invoke_export(name, args, &mut NopExternals);
+    let is_read_only_trap = |result: Result<Option<RuntimeValue>, Error>| {
+        matches!(
+            result,
+            Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::MemoryReadOnly)
+        )
+    };
+
+    memory.set_read_only(true);
+    assert!(memory.is_read_only());
+    assert!(is_read_only_trap(invoke("store", &[RuntimeValue::I32(7)])));
+    assert!(is_read_only_trap(invoke("grow", &[])));
+    assert_eq!(memory.current_size(), Pages(1));
+
+    // Loads still work and the host can still write.
+    memory.set(0, &[3]).unwrap();
+    assert_eq!(invoke("load", &[]).unwrap(), Some(RuntimeValue::I32(3)));
+
+    memory.set_read_only(false);
+    invoke("store", &[RuntimeValue::I32(7)]).unwrap();
+    assert_eq!(invoke("load", &[]).unwrap(), Some(RuntimeValue::I32(7)));
+    assert_eq!(invoke("grow", &[]).unwrap(), Some(RuntimeValue::I32(1)));
+}
+
+#[test]
+fn float_rounding_is_ties_to_even() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue};
+
+    // Each function returns the bits of a result that would differ with any rounding mode
+    // other than round to nearest, ties to even.
+    let module = parse_wat(
+        r#"
+(module
+	(func (export "div") (result i32)
+		(i32.reinterpret/f32 (f32.div (f32.const 1) (f32.const 3)))
+	)
+	(func (export "sqrt") (result i64)
+		(i64.reinterpret/f64 (f64.sqrt (f64.const 2)))
+	)
+	(func (export "add_tie") (result i64)
+		(i64.reinterpret/f64 (f64.add (f64.const 1) (f64.const 0x1p-53)))
+	)
+	(func (export "add_tie_up") (result i64)
+		(i64.reinterpret/f64 (f64.add (f64.const 1) (f64.const 0x1.8p-52)))
+	)
+	(func (export "convert_tie") (result i32)
+		(i32.reinterpret/f32 (f32.convert_s/i32 (i32.const 16777217)))
+	)
+	(func (export "convert_tie_up") (result i32)
+		(i32.reinterpret/f32 (f32.convert_u/i64 (i64.const 16777219)))
+	)
+	(func (export "demote_tie") (result i32)
+		(i32.reinterpret/f32 (f32.demote/f64 (f64.const 0x1.000001p+0)))
+	)
+	(func (export "demote_tie_up") (result i32)
+		(i32.reinterpret/f32 (f32.demote/f64 (f64.const 0x1.000003p+0)))
+	)
+	(func (export "nearest") (result i32)
+		(i32.reinterpret/f32 (f32.nearest (f32.const -2.5)))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let invoke = |name: &str| {
+        instance
+            .invoke_export(name, &[], &mut NopExternals)
+            .unwrap()
+            .unwrap()
+    };
+
+    assert_eq!(invoke("div"), RuntimeValue::I32(0x3eaa_aaab));
+    assert_eq!(invoke("sqrt"), RuntimeValue::I64(0x3ff6_a09e_667f_3bcd));
+    assert_eq!(invoke("add_tie"), RuntimeValue::I64(0x3ff0_0000_0000_0000));
+    assert_eq!(
+        invoke("add_tie_up"),
+        RuntimeValue::I64(0x3ff0_0000_0000_0002)
+    );
+    assert_eq!(invoke("convert_tie"), RuntimeValue::I32(0x4b80_0000));
+    assert_eq!(invoke("convert_tie_up"), RuntimeValue::I32(0x4b80_0002));
+    assert_eq!(invoke("demote_tie"), RuntimeValue::I32(0x3f80_0000));
+    assert_eq!(invoke("demote_tie_up"), RuntimeValue::I32(0x3f80_0002));
+    assert_eq!(invoke("nearest"), RuntimeValue::I32(0xc000_0000_u32 as i32));
+}
+
+#[test]
+fn float_const_bits_preserved() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue};
+
+    let module = parse_wat(
+        r#"
+(module
+	(func (export "f32") (result i32)
+		(i32.reinterpret/f32 (f32.const -nan:0x200000))
+	)
+	(func (export "f64") (result i64)
+		(i64.reinterpret/f64 (f64.const -0x1.8p+1))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+
+    assert_eq!(
+        instance
+            .invoke_export("f32", &[], &mut NopExternals)
+            .unwrap(),
+        Some(RuntimeValue::I32(0xffa0_0000_u32 as i32))
+    );
+    assert_eq!(
+        instance
+            .invoke_export("f64", &[], &mut NopExternals)
+            .unwrap(),
+        Some(RuntimeValue::I64((-3.0f64).to_bits() as i64))
+    );
+}
+
+#[test]
+fn nan_payloads_round_trip() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue};
+    use crate::nan_preserving_float::F64;
+
+    let module = parse_wat(
+        r#"
+(module
+	(global $f32 (export "f32") f32 (f32.const nan:0x000001))
+	(global $f64 (export "f64") f64 (f64.const -nan:0x4000000000000))
+	(func (export "const_f32") (result i32)
+		(i32.reinterpret/f32 (f32.const -nan:0x7fffff))
+	)
+	(func (export "global_f32") (result i32)
+		(i32.reinterpret/f32 (get_global $f32))
+	)
+	(func (export "global_f64") (result i64)
+		(i64.reinterpret/f64 (get_global $f64))
+	)
+	(func (export "param_f64") (param f64) (result i64)
+		(i64.reinterpret/f64 (get_local 0))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let invoke = |name: &str, args: &[RuntimeValue]| {
+        instance
+            .invoke_export(name, args, &mut NopExternals)
+            .unwrap()
+    };
+
+    assert_eq!(
+        invoke("const_f32", &[]),
+        Some(RuntimeValue::I32(0xffff_ffff_u32 as i32))
+    );
+    assert_eq!(
+        invoke("global_f32", &[]),
+        Some(RuntimeValue::I32(0x7f80_0001))
+    );
+    assert_eq!(
+        invoke("global_f64", &[]),
+        Some(RuntimeValue::I64(0xfff4_0000_0000_0000_u64 as i64))
+    );
+    assert_eq!(
+        invoke(
+            "param_f64",
+            &[RuntimeValue::F64(F64::from_bits(0x7ff0_0000_0000_0001))]
+        ),
+        Some(RuntimeValue::I64(0x7ff0_0000_0000_0001))
+    );
+
+    // Globals hand out the exact bits to the host as well.
+    match instance.global_value("f32") {
+        Some(RuntimeValue::F32(value)) => assert_eq!(value.to_bits(), 0x7f80_0001),
+        other => panic!("expected an f32, got {:?}", other),
+    }
+    assert!(matches!(
+        instance.global_value("f64"),
+        Some(RuntimeValue::F64(value)) if value.to_bits() == 0xfff4_0000_0000_0000
+    ));
+}
+
+#[test]
+fn table_init_from_passive_elem_segment() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue, TrapKind};
+    use alloc::string::ToString;
+
+    let wasm_binary = wabt::wat2wasm(
+        r#"
+(module
+	(type $t (func (result i32)))
+	(table 4 anyfunc)
+	(func $a (result i32) (i32.const 1))
+	(func $b (result i32) (i32.const 2))
+	(elem (i32.const 0) $a $b)
+	(func (export "init") (param i32 i32 i32)
+		(table.init 0 (get_local 0) (get_local 1) (get_local 2))
+	)
+	(func (export "drop")
+		(elem.drop 0)
+	)
+	(func (export "call") (param i32) (result i32)
+		(call_indirect (type $t) (get_local 0))
+	)
+)
+"#,
+    )
+    .expect("Failed to parse wat source");
+    // The text format can't express a passive segment in the binary encoding
+    // understood by `parity-wasm`, so turn the active segment into a passive one.
+    let mut module: parity_wasm::elements::Module =
+        parity_wasm::deserialize_buffer(&wasm_binary).unwrap();
+    let segment = &mut module.elements_section_mut().unwrap().entries_mut()[0];
+    *segment.offset_mut() = None;
+    segment.set_passive(true);
+    let module = Module::from_parity_wasm_module(module).unwrap();
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let call =
+        |index: i32| instance.invoke_export("call", &[RuntimeValue::I32(index)], &mut NopExternals);
+    let init = |dst: i32, src: i32, len: i32| {
+        instance.invoke_export(
+            "init",
+            &[
+                RuntimeValue::I32(dst),
+                RuntimeValue::I32(src),
+                RuntimeValue::I32(len),
+            ],
+            &mut NopExternals,
+        )
+    };
+
+    // A passive segment doesn't touch the table during instantiation.
+    assert!(matches!(
+        call(0),
+        Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::ElemUninitialized)
+    ));
+
+    init(2, 0, 2).unwrap();
+    assert_eq!(call(2).unwrap(), Some(RuntimeValue::I32(1)));
+    assert_eq!(call(3).unwrap(), Some(RuntimeValue::I32(2)));
+
+    // Out of bounds accesses to either the segment or the table trap.
+    assert!(matches!(
+        init(0, 1, 2),
+        Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::TableAccessOutOfBounds)
+    ));
+    match init(3, 0, 2) {
+        Err(Error::Trap(ref trap)) => {
+            assert!(matches!(
+                trap.kind(),
+                TrapKind::TableSetOutOfBounds {
+                    table_index: 0,
+                    index: 4
+                }
+            ));
+            assert_eq!(
+                trap.to_string(),
+                "Trap: out of bounds write to element 4 of table 0"
+            );
+        }
+        other => panic!("expected table set trap, got {:?}", other),
+    }
+    assert!(matches!(
+        init(5, 0, 0),
+        Err(Error::Trap(ref trap)) if matches!(
+            trap.kind(),
+            TrapKind::TableSetOutOfBounds { table_index: 0, index: 5 }
+        )
+    ));
+
+    // Reading a table element out of bounds is still reported as an access trap.
+    assert!(matches!(
+        call(4),
+        Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::TableAccessOutOfBounds)
+    ));
+
+    // A dropped segment can no longer be used to initialize the table.
+    instance
+        .invoke_export("drop", &[], &mut NopExternals)
+        .unwrap();
+    assert!(matches!(
+        init(0, 0, 1),
+        Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::TableAccessOutOfBounds)
+    ));
+    assert!(matches!(
+        call(0),
+        Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::ElemUninitialized)
+    ));
+}
+
+#[test]
+fn extended_const_init_exprs() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue};
+    use parity_wasm::elements::{InitExpr, Instruction};
+
+    let wasm_binary = wabt::wat2wasm(
+        r#"
+(module
+	(memory 1)
+	(global $g i32 (i32.const 0))
+	(data (i32.const 0) "\2a")
+	(func (export "global") (result i32)
+		(get_global $g)
+	)
+	(func (export "load") (param i32) (result i32)
+		(i32.load8_u (get_local 0))
+	)
+)
+"#,
+    )
+    .expect("Failed to parse wat source");
+    // The version of the text format parser we use doesn't know about extended
+    // constant expressions, so patch them into the binary.
+    let mut module: parity_wasm::elements::Module =
+        parity_wasm::deserialize_buffer(&wasm_binary).unwrap();
+    *module.global_section_mut().unwrap().entries_mut()[0].init_expr_mut() = InitExpr::new(vec![
+        Instruction::I32Const(1),
+        Instruction::I32Const(2),
+        Instruction::I32Add,
+        Instruction::End,
+    ]);
+    *module.data_section_mut().unwrap().entries_mut()[0].offset_mut() = Some(InitExpr::new(vec![
+        Instruction::I32Const(10),
+        Instruction::I32Const(3),
+        Instruction::I32Mul,
+        Instruction::I32Const(20),
+        Instruction::I32Sub,
+        Instruction::End,
+    ]));
+    let module = Module::from_parity_wasm_module(module).unwrap();
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    assert_eq!(
+        instance
+            .invoke_export("global", &[], &mut NopExternals)
+            .unwrap(),
+        Some(RuntimeValue::I32(3))
+    );
+    assert_eq!(
+        instance
+            .invoke_export("load", &[RuntimeValue::I32(10)], &mut NopExternals)
+            .unwrap(),
+        Some(RuntimeValue::I32(42))
+    );
+}
+
+#[test]
+fn memory_copy_within_memory() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue, TrapKind};
+    use parity_wasm::elements::{BulkInstruction, Instruction, Instructions};
+
+    let wasm_binary = wabt::wat2wasm(
+        r#"
+(module
+	(memory (export "mem") 1)
+	(data (i32.const 0) "\00\01\02\03\04\05\06\07\08\09")
+	(func (export "copy") (param i32 i32 i32))
+)
+"#,
+    )
+    .expect("Failed to parse wat source");
+    // Text format parsers and `parity-wasm` disagree on the binary encoding of
+    // `memory.copy`, so put the instruction into the body directly.
+    let mut module: parity_wasm::elements::Module =
+        parity_wasm::deserialize_buffer(&wasm_binary).unwrap();
+    *module.code_section_mut().unwrap().bodies_mut()[0].code_mut() = Instructions::new(vec![
+        Instruction::GetLocal(0),
+        Instruction::GetLocal(1),
+        Instruction::GetLocal(2),
+        Instruction::Bulk(BulkInstruction::MemoryCopy),
+        Instruction::End,
+    ]);
+    let module = Module::from_parity_wasm_module(module).unwrap();
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let memory = instance
+        .export_by_name("mem")
+        .unwrap()
+        .as_memory()
+        .unwrap()
+        .clone();
+    let copy = |dst: i32, src: i32, len: i32| {
+        instance.invoke_export(
+            "copy",
+            &[
+                RuntimeValue::I32(dst),
+                RuntimeValue::I32(src),
+                RuntimeValue::I32(len),
+            ],
+            &mut NopExternals,
+        )
+    };
+
+    // Overlapping ranges are copied as if through an intermediate buffer.
+    copy(4, 0, 6).unwrap();
+    assert_eq!(memory.get(0, 10).unwrap(), [0, 1, 2, 3, 0, 1, 2, 3, 4, 5]);
+    copy(0, 4, 6).unwrap();
+    assert_eq!(memory.get(0, 10).unwrap(), [0, 1, 2, 3, 4, 5, 2, 3, 4, 5]);
+
+    // Copying zero bytes right at the end of the memory is fine, one byte more isn't.
+    copy(65536, 0, 0).unwrap();
+    assert!(matches!(
+        copy(65536, 0, 1),
+        Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::MemoryAccessOutOfBounds)
+    ));
+    assert!(matches!(
+        copy(0, 65530, 7),
+        Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::MemoryAccessOutOfBounds)
+    ));
+}
+
+#[test]
+fn nops() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue};
+    use crate::isa::Instruction;
+    use alloc::vec::Vec;
+
+    let module = parse_wat(
+        r#"
+(module
+	(func (export "add") (result i32)
+		i32.const 1
+		nop
+		i32.const 2
+		nop
+		i32.add
+	)
+)
+"#,
+    );
+
+    let code = module.code()[0].instructions();
+    let mut iter = code.iterate_from(0);
+    let mut nop_positions = Vec::new();
+    loop {
+        let position = iter.position();
+        match iter.next() {
+            Some(Instruction::Nop) => nop_positions.push(position),
+            Some(_) => {}
+            None => break,
+        }
+    }
+    // With the `nop`s preserved, straight-line code lines up with the original instructions.
+    #[cfg(all(feature = "preserve_nops", not(feature = "compact_bytecode")))]
+    assert_eq!(nop_positions, [1, 3]);
+    // Unless positions are byte offsets, the `i32.const`s take two bytes each.
+    #[cfg(all(feature = "preserve_nops", feature = "compact_bytecode"))]
+    assert_eq!(nop_positions, [2, 5]);
+    #[cfg(not(feature = "preserve_nops"))]
+    assert!(nop_positions.is_empty());
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    assert_eq!(
+        instance
+            .invoke_export("add", &[], &mut NopExternals)
+            .unwrap(),
+        Some(RuntimeValue::I32(3))
+    );
+}
+
+#[test]
+fn shift_by_more_than_bit_width() {
+    use super::{
+        ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue, StackRecycler, TrapKind,
+    };
+
+    let module = parse_wat(
+        r#"
+(module
+	(func (export "shl") (param i32 i32) (result i32)
+		(i32.shl (get_local 0) (get_local 1))
+	)
+	(func (export "shr_u") (param i32 i32) (result i32)
+		(i32.shr_u (get_local 0) (get_local 1))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let mut masking = StackRecycler::default();
+    let mut strict = StackRecycler::default().with_strict_shifts(true);
+    let shift = |name: &str, value: i32, amount: i32, stack_recycler: &mut StackRecycler| {
+        instance.invoke_export_with_stack(
+            name,
+            &[RuntimeValue::I32(value), RuntimeValue::I32(amount)],
+            &mut NopExternals,
+            stack_recycler,
+        )
+    };
+
+    for stack_recycler in &mut [&mut masking, &mut strict] {
+        assert_eq!(
+            shift("shl", 1, 31, stack_recycler).unwrap(),
+            Some(RuntimeValue::I32(i32::MIN))
+        );
+        assert_eq!(
+            shift("shr_u", 8, 3, stack_recycler).unwrap(),
+            Some(RuntimeValue::I32(1))
+        );
+    }
+
+    assert_eq!(
+        shift("shl", 1, 33, &mut masking).unwrap(),
+        Some(RuntimeValue::I32(2))
+    );
+    assert_eq!(
+        shift("shr_u", 8, 33, &mut masking).unwrap(),
+        Some(RuntimeValue::I32(4))
+    );
+    for &(name, amount) in &[("shl", 33), ("shr_u", 33), ("shl", -1)] {
+        assert!(matches!(
+            shift(name, 1, amount, &mut strict),
+            Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::ShiftOutOfRange)
+        ));
+    }
+}
+
+#[cfg(feature = "execution_deadline")]
+#[test]
+fn execution_deadline_interrupts_infinite_loop() {
+    use super::{ExecutionDeadline, ImportsBuilder, ModuleInstance, NopExternals, TrapKind};
+    use std::time::{Duration, Instant};
+
+    let module = parse_wat(
+        r#"
+(module
+	(func (export "spin")
+		(loop $continue
+			(br $continue)
+		)
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let started = Instant::now();
+    let result = {
+        let _deadline = ExecutionDeadline::with_check_interval(Duration::from_millis(10), 100);
+        instance.invoke_export("spin", &[], &mut NopExternals)
+    };
+    assert!(matches!(
+        result,
+        Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::Timeout)
+    ));
+    // Generous slack for loaded machines, the point is that the loop was interrupted at all.
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+/// Instantiates a module with a memory of `pages` pages exporting `body` as "copy",
+/// a function taking the destination, source and length of a `memory.copy`.
+#[cfg(feature = "execution_deadline")]
+fn memory_copy_instance(
+    pages: u32,
+    body: Vec<parity_wasm::elements::Instruction>,
+) -> (super::ModuleRef, super::MemoryRef) {
+    use super::{ImportsBuilder, ModuleInstance};
+    use parity_wasm::elements::Instructions;
+
+    let wasm_binary = wabt::wat2wasm(format!(
+        r#"
+(module
+	(memory (export "mem") {})
+	(func (export "copy") (param i32 i32 i32))
+)
+"#,
+        pages
+    ))
+    .expect("Failed to parse wat source");
+    // See `memory_copy_within_memory` for why the body is replaced.
+    let mut module: parity_wasm::elements::Module =
+        parity_wasm::deserialize_buffer(&wasm_binary).unwrap();
+    *module.code_section_mut().unwrap().bodies_mut()[0].code_mut() = Instructions::new(body);
+    let module = Module::from_parity_wasm_module(module).unwrap();
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let memory = instance
+        .export_by_name("mem")
+        .unwrap()
+        .as_memory()
+        .unwrap()
+        .clone();
+    (instance, memory)
+}
+
+#[cfg(feature = "execution_deadline")]
+#[test]
+fn execution_deadline_copies_large_regions_in_chunks() {
+    use super::{NopExternals, RuntimeValue};
+    use parity_wasm::elements::{BulkInstruction, Instruction};
+
+    let (instance, memory) = memory_copy_instance(
+        4,
+        vec![
+            Instruction::GetLocal(0),
+            Instruction::GetLocal(1),
+            Instruction::GetLocal(2),
+            Instruction::Bulk(BulkInstruction::MemoryCopy),
+            Instruction::End,
+        ],
+    );
+    let contents: Vec<u8> = (0..4 * 65536).map(|i: u32| (i % 251) as u8).collect();
+
+    // Overlapping copies spanning several chunks behave like `memmove` in both directions.
+    for &(dst, src, len) in &[(1000, 0, 200_000), (0, 1000, 200_000)] {
+        memory.set(0, &contents).unwrap();
+        instance
+            .invoke_export(
+                "copy",
+                &[
+                    RuntimeValue::I32(dst),
+                    RuntimeValue::I32(src),
+                    RuntimeValue::I32(len),
+                ],
+                &mut NopExternals,
+            )
+            .unwrap();
+        let mut expected = contents.clone();
+        expected.copy_within(src as usize..(src + len) as usize, dst as usize);
+        assert!(memory.to_vec().unwrap() == expected);
+    }
+}
+
+#[cfg(feature = "execution_deadline")]
+#[test]
+fn execution_deadline_interrupts_large_memory_copy() {
+    use super::{ExecutionDeadline, NopExternals, RuntimeValue, TrapKind};
+    use parity_wasm::elements::{BlockType, BulkInstruction, Instruction};
+    use std::time::{Duration, Instant};
+
+    // Copies the same megabyte over and over again.
+    let (instance, _memory) = memory_copy_instance(
+        32,
+        vec![
+            Instruction::Loop(BlockType::NoResult),
+            Instruction::GetLocal(0),
+            Instruction::GetLocal(1),
+            Instruction::GetLocal(2),
+            Instruction::Bulk(BulkInstruction::MemoryCopy),
+            Instruction::Br(0),
+            Instruction::End,
+            Instruction::End,
+        ],
+    );
+
+    let started = Instant::now();
+    let result = {
+        // Instructions only check the deadline when the execution starts, so only the
+        // chunks of the copies can observe it passing.
+        let _deadline = ExecutionDeadline::with_check_interval(Duration::from_millis(10), u32::MAX);
+        instance.invoke_export(
+            "copy",
+            &[
+                RuntimeValue::I32(0),
+                RuntimeValue::I32(1 << 20),
+                RuntimeValue::I32(1 << 20),
+            ],
+            &mut NopExternals,
+        )
+    };
+    assert!(matches!(
+        result,
+        Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::Timeout)
+    ));
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[cfg(feature = "overflow_tracing")]
+#[test]
+fn overflow_trace_reports_wrapping_arithmetic() {
+    use super::{
+        ImportsBuilder, ModuleInstance, NopExternals, OverflowTrace, OverflowingOp, RuntimeValue,
+        SignedOverflow,
+    };
+    use crate::nan_preserving_float::F32;
+    use alloc::rc::Rc;
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+
+    let module = parse_wat(
+        r#"
+(module
+	(func (export "i32.add") (param i32 i32) (result i32)
+		(i32.add (get_local 0) (get_local 1)))
+	(func (export "i64.mul") (param i64 i64) (result i64)
+		(i64.mul (get_local 0) (get_local 1)))
+	(func (export "f32.add") (param f32 f32) (result f32)
+		(f32.add (get_local 0) (get_local 1)))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let overflows = Rc::new(RefCell::new(Vec::new()));
+    let _trace = {
+        let overflows = overflows.clone();
+        OverflowTrace::new(move |overflow: &SignedOverflow| overflows.borrow_mut().push(*overflow))
+    };
+    let invoke = |name: &str, args: &[RuntimeValue]| {
+        instance
+            .invoke_export(name, args, &mut NopExternals)
+            .expect("Failed to invoke")
+    };
+
+    // Operations in range aren't reported.
+    assert_eq!(
+        invoke("i32.add", &[RuntimeValue::I32(-5), RuntimeValue::I32(3)]),
+        Some(RuntimeValue::I32(-2))
+    );
+    assert_eq!(
+        invoke(
+            "f32.add",
+            &[
+                RuntimeValue::F32(F32::from_float(f32::MAX)),
+                RuntimeValue::F32(F32::from_float(f32::MAX))
+            ]
+        ),
+        Some(RuntimeValue::F32(F32::from_float(f32::INFINITY)))
+    );
+    assert!(overflows.borrow().is_empty());
+
+    // The overflowing add is reported, but still wraps.
+    assert_eq!(
+        invoke(
+            "i32.add",
+            &[RuntimeValue::I32(i32::MAX), RuntimeValue::I32(1)]
+        ),
+        Some(RuntimeValue::I32(i32::MIN))
+    );
+    assert_eq!(
+        invoke(
+            "i64.mul",
+            &[RuntimeValue::I64(i64::MIN), RuntimeValue::I64(-1)]
+        ),
+        Some(RuntimeValue::I64(i64::MIN))
+    );
+    let overflows = overflows.borrow();
+    assert_eq!(
+        *overflows,
+        [
+            SignedOverflow {
+                op: OverflowingOp::Add,
+                lhs: RuntimeValue::I32(i32::MAX),
+                rhs: RuntimeValue::I32(1),
+            },
+            SignedOverflow {
+                op: OverflowingOp::Mul,
+                lhs: RuntimeValue::I64(i64::MIN),
+                rhs: RuntimeValue::I64(-1),
+            },
+        ]
+    );
+    assert_eq!(overflows[0].opcode(), "i32.add");
+    assert_eq!(overflows[1].opcode(), "i64.mul");
+}
+
+#[cfg(feature = "trap_state")]
+#[test]
+fn trap_records_locals_and_operands() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue, TrapKind, ValueType};
+
+    let module = parse_wat(
+        r#"
+(module
+	(func $inner (param i32) (result i32)
+		(local i64)
+		(set_local 1 (i64.const -7))
+		(f32.const 1.5)
+		(i32.const 42)
+		(unreachable)
+	)
+	(func (export "test") (result i32)
+		(call $inner (i32.const 3))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let trap = match instance.invoke_export("test", &[], &mut NopExternals) {
+        Err(Error::Trap(trap)) => trap,
+        other => panic!("expected a trap, got {:?}", other),
+    };
+    assert!(matches!(trap.kind(), TrapKind::Unreachable));
+    let state = trap
+        .state()
+        .expect("trap raised by wasm should carry a state");
+    assert_eq!(
+        state.locals(),
+        &[RuntimeValue::I32(3), RuntimeValue::I64(-7)]
+    );
+    assert_eq!(state.operand_count(), 2);
+    assert_eq!(
+        state.operand(0, ValueType::I32),
+        Some(RuntimeValue::I32(42))
+    );
+    assert_eq!(
+        state.operand(1, ValueType::F32),
+        Some(RuntimeValue::F32(1.5.into()))
+    );
+    assert_eq!(state.operand(2, ValueType::I32), None);
+}
+
+#[cfg(feature = "compact_bytecode")]
+#[test]
+fn compact_bytecode_round_trip() {
+    use crate::isa::{encode_br_targets, BrTargets, DropKeep, Instruction, Instructions, Target};
+
+    let target = |dst_pc, drop| Target {
+        dst_pc,
+        drop_keep: DropKeep { drop, keep: 1 },
+    };
+    let targets = encode_br_targets(&[target(0, 0), target(u32::MAX, u32::MAX)]);
+    let instructions = [
+        Instruction::I32Const(0),
+        Instruction::I32Const(-1),
+        Instruction::I32Const(i32::MIN),
+        Instruction::I32Const(i32::MAX),
+        Instruction::I64Const(-64),
+        Instruction::I64Const(64),
+        Instruction::I64Const(i64::MIN),
+        Instruction::I64Const(i64::MAX),
+        Instruction::F32Const(u32::MAX),
+        Instruction::F64Const(u64::MAX),
+        Instruction::GetLocal(u32::MAX),
+        Instruction::I32Load(128),
+        Instruction::Call(7),
+        Instruction::CallIndirect(u32::MAX),
+        Instruction::Br(target(3, 1)),
+        Instruction::BrTable(BrTargets::from_internal(&targets)),
+        Instruction::Return(DropKeep { drop: 300, keep: 0 }),
+        Instruction::MemoryCopy {
+            src_mem: 0,
+            dst_mem: 200,
+        },
+        Instruction::I32Add,
+        Instruction::Unreachable,
+    ];
+    let mut code = Instructions::with_capacity(instructions.len());
+    for instruction in &instructions {
+        code.push_instruction(instruction.clone());
+    }
+
+    // Each `br_table` target counts as an instruction.
+    assert_eq!(code.len() as usize, instructions.len() + 2);
+    assert_eq!(code.iterate_from(0).collect::<Vec<_>>(), instructions);
+}
+
+#[test]
+fn identical_signatures_are_interned() {
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue, TrapKind};
+
+    let module = parse_wat(
+        r#"
+(module
+	(type $a (func (param i32) (result i32)))
+	(type $b (func (param i32) (result i32)))
+	(type $c (func (result i32)))
+	(table 2 anyfunc)
+	(func $double (export "double") (type $a) (i32.mul (get_local 0) (i32.const 2)))
+	(func $negate (export "negate") (type $b) (i32.sub (i32.const 0) (get_local 0)))
+	(elem (i32.const 0) $double $negate)
+	(func (export "call") (param i32 i32) (result i32)
+		(call_indirect (type $b) (get_local 1) (get_local 0))
+	)
+	(func (export "call_mismatched") (param i32) (result i32)
+		(call_indirect (type $c) (get_local 0))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let func = |name: &str| {
+        instance
+            .export_by_name(name)
+            .unwrap()
+            .as_func()
+            .unwrap()
+            .clone()
+    };
+
+    // Both types are stored once, so the functions share the very same signature.
+    assert!(core::ptr::eq(
+        func("double").signature(),
+        func("negate").signature()
+    ));
+    assert!(!core::ptr::eq(
+        func("double").signature(),
+        func("call").signature()
+    ));
+
+    let call = |index: i32, arg: i32| {
+        instance.invoke_export(
+            "call",
+            &[RuntimeValue::I32(index), RuntimeValue::I32(arg)],
+            &mut NopExternals,
+        )
+    };
+    assert_eq!(call(0, 21).unwrap(), Some(RuntimeValue::I32(42)));
+    assert_eq!(call(1, 21).unwrap(), Some(RuntimeValue::I32(-21)));
+    assert!(matches!(
+        instance.invoke_export("call_mismatched", &[RuntimeValue::I32(0)], &mut NopExternals),
+        Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::UnexpectedSignature)
+    ));
+}
+
+#[test]
+fn out_of_bounds_loads_and_stores() {
+    use super::{
+        ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue, StackRecycler, TrapKind,
+    };
+
+    let module = parse_wat(
+        r#"
+(module
+	(memory 1)
+	(data (i32.const 1) "\2a")
+	(func (export "load") (param i32) (result i32)
+		(i32.load8_u offset=1 (get_local 0))
+	)
+	(func (export "store") (param i32)
+		(i64.store (get_local 0) (i64.const -1))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let mut strict = StackRecycler::default();
+    let mut relaxed = StackRecycler::default().with_relaxed_memory(true);
+    let invoke = |name: &str, address: i32, stack_recycler: &mut StackRecycler| {
+        instance.invoke_export_with_stack(
+            name,
+            &[RuntimeValue::I32(address)],
+            &mut NopExternals,
+            stack_recycler,
+        )
+    };
+
+    for stack_recycler in &mut [&mut strict, &mut relaxed] {
+        assert_eq!(
+            invoke("load", 0, stack_recycler).unwrap(),
+            Some(RuntimeValue::I32(42))
+        );
+        assert_eq!(invoke("store", 65536 - 8, stack_recycler).unwrap(), None);
+    }
+
+    let out_of_bounds = |result: Result<Option<RuntimeValue>, Error>| {
+        matches!(
+            result,
+            Err(Error::Trap(ref trap)) if matches!(trap.kind(), TrapKind::MemoryAccessOutOfBounds)
+        )
+    };
+    assert!(out_of_bounds(invoke("load", 65535, &mut strict)));
+    // The effective address overflows.
+    assert!(out_of_bounds(invoke("load", u32::MAX as i32, &mut strict)));
+    assert!(out_of_bounds(invoke("store", 65536 - 7, &mut strict)));
+
+    assert_eq!(
+        invoke("load", 65535, &mut relaxed).unwrap(),
+        Some(RuntimeValue::I32(0))
+    );
+    assert_eq!(
+        invoke("load", u32::MAX as i32, &mut relaxed).unwrap(),
+        Some(RuntimeValue::I32(0))
+    );
+    // A partially out of bounds store is dropped completely.
+    assert_eq!(invoke("store", 65536 - 7, &mut relaxed).unwrap(), None);
+    assert_eq!(
+        instance
+            .memory_by_index(0)
+            .unwrap()
+            .get(65536 - 7, 7)
+            .unwrap(),
+        [0xff; 7]
+    );
+
+    // Invocations without a recycler are strict.
+    assert!(out_of_bounds(instance.invoke_export(
+        "load",
+        &[RuntimeValue::I32(65535)],
+        &mut NopExternals
+    )));
+}
+
+#[test]
+fn module_data_segments() {
+    use crate::SegmentOffset;
+    use alloc::vec::Vec;
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "base" (global $base i32))
+	(memory 1)
+	(data (i32.const 16) "resource")
+	(data (get_global $base) "\01\02")
+)
+"#,
+    );
+
+    let segments = module.data_segments().collect::<Vec<_>>();
+    assert_eq!(segments.len(), 2);
+    assert_eq!(segments[0].offset, Some(SegmentOffset::Const(16)));
+    assert_eq!(segments[0].data, b"resource");
+    assert!(matches!(
+        segments[1].offset,
+        Some(SegmentOffset::Unevaluated(_))
+    ));
+    assert_eq!(segments[1].data, [1, 2]);
+    assert_eq!(module.elem_segments().count(), 0);
+}
+
+#[test]
+fn pure_module_runs_without_externals() {
+    use super::{ImportsBuilder, InstantiationError, ModuleInstance, RuntimeValue};
+
+    let module = parse_wat(
+        r#"
+(module
+	(global $offset (mut i32) (i32.const 0))
+	(func $start
+		(set_global $offset (i32.const 10))
+	)
+	(start $start)
+	(func (export "muladd") (param i32 i32) (result i32)
+		(i32.add
+			(i32.mul (get_local 0) (get_local 1))
+			(get_global $offset)
+		)
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new_pure(&module, &ImportsBuilder::default()).unwrap();
+    assert_eq!(
+        instance
+            .invoke_export_pure("muladd", &[RuntimeValue::I32(6), RuntimeValue::I32(7)])
+            .unwrap(),
+        Some(RuntimeValue::I32(52))
+    );
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "log" (func (param i32)))
+)
+"#,
+    );
+    match ModuleInstance::new_pure(&module, &ImportsBuilder::default()) {
+        Err(Error::Instantiation(InstantiationError::Other(message))) => {
+            assert!(message.contains("env.log"))
+        }
+        result => panic!("expected an instantiation error, got {:?}", result),
+    }
+}
+
+#[test]
+fn runtime_value_arithmetic_matches_interpreter() {
+    use super::{ImportsBuilder, ModuleInstance, RuntimeValue, Trap, TrapKind};
+
+    let module = parse_wat(
+        r#"
+(module
+	(func (export "i32.add") (param i32 i32) (result i32) (i32.add (get_local 0) (get_local 1)))
+	(func (export "i32.sub") (param i32 i32) (result i32) (i32.sub (get_local 0) (get_local 1)))
+	(func (export "i32.mul") (param i32 i32) (result i32) (i32.mul (get_local 0) (get_local 1)))
+	(func (export "i32.div_s") (param i32 i32) (result i32) (i32.div_s (get_local 0) (get_local 1)))
+	(func (export "i32.div_u") (param i32 i32) (result i32) (i32.div_u (get_local 0) (get_local 1)))
+	(func (export "i32.rem_s") (param i32 i32) (result i32) (i32.rem_s (get_local 0) (get_local 1)))
+	(func (export "i32.rem_u") (param i32 i32) (result i32) (i32.rem_u (get_local 0) (get_local 1)))
+	(func (export "i64.add") (param i64 i64) (result i64) (i64.add (get_local 0) (get_local 1)))
+	(func (export "i64.div_s") (param i64 i64) (result i64) (i64.div_s (get_local 0) (get_local 1)))
+	(func (export "f32.add") (param f32 f32) (result f32) (f32.add (get_local 0) (get_local 1)))
+	(func (export "f64.div") (param f64 f64) (result f64) (f64.div (get_local 0) (get_local 1)))
+)
+"#,
+    );
+    let instance = ModuleInstance::new_pure(&module, &ImportsBuilder::default()).unwrap();
+
+    type Op = fn(RuntimeValue, RuntimeValue) -> Result<RuntimeValue, TrapKind>;
+    let ops: [(&str, Op); 7] = [
+        ("add", RuntimeValue::wrapping_add),
+        ("sub", RuntimeValue::wrapping_sub),
+        ("mul", RuntimeValue::wrapping_mul),
+        ("div_s", RuntimeValue::checked_div),
+        ("div_u", RuntimeValue::checked_div_u),
+        ("rem_s", RuntimeValue::checked_rem),
+        ("rem_u", RuntimeValue::checked_rem_u),
+    ];
+    let check = |name: &str, op: Op, a: RuntimeValue, b: RuntimeValue| {
+        let interpreted = instance
+            .invoke_export_pure(name, &[a, b])
+            .map(|result| result.expect("all functions return a value"))
+            .map_err(|err| match err {
+                Error::Trap(trap) => Trap::into_kind(trap),
+                err => panic!("unexpected error: {:?}", err),
+            });
+        // Compare the formatted results, NaNs aren't equal to themselves but their payloads must match.
+        match (op(a, b), interpreted) {
+            (Ok(host), Ok(interpreted)) => assert_eq!(
+                alloc::format!("{}", host),
+                alloc::format!("{}", interpreted),
+                "{} {} {}",
+                name,
+                a,
+                b
+            ),
+            (Err(host), Err(interpreted)) => assert_eq!(
+                alloc::format!("{}", host),
+                alloc::format!("{}", interpreted),
+                "{} {} {}",
+                name,
+                a,
+                b
+            ),
+            (host, interpreted) => panic!(
+                "{} {} {}: host computed {:?}, interpreter {:?}",
+                name, a, b, host, interpreted
+            ),
+        }
+    };
+
+    let i32_values = [0, 1, -1, 7, -7, i32::MAX, i32::MIN];
+    for &(op_name, op) in &ops {
+        for &a in &i32_values {
+            for &b in &i32_values {
+                let name = alloc::format!("i32.{}", op_name);
+                check(&name, op, RuntimeValue::I32(a), RuntimeValue::I32(b));
+            }
+        }
+    }
+
+    // `i32::MAX + 1` wraps around just like in wasm code.
+    assert_eq!(
+        RuntimeValue::I32(i32::MAX)
+            .wrapping_add(RuntimeValue::I32(1))
+            .unwrap(),
+        RuntimeValue::I32(i32::MIN)
+    );
+
+    // Operands of different or unsupported types are rejected instead of panicking.
+    for &(_, op) in &ops {
+        assert!(matches!(
+            op(RuntimeValue::I32(1), RuntimeValue::I64(1)),
+            Err(TrapKind::UnexpectedSignature)
+        ));
+    }
+    for &(_, op) in &ops[4..] {
+        assert!(matches!(
+            op(
+                RuntimeValue::F32(1f32.into()),
+                RuntimeValue::F32(1f32.into())
+            ),
+            Err(TrapKind::UnexpectedSignature)
+        ));
+    }
+
+    for &a in &[i64::MAX, i64::MIN, -1, 3] {
+        for &b in &[i64::MAX, 1, 0, -1] {
+            check(
+                "i64.add",
+                ops[0].1,
+                RuntimeValue::I64(a),
+                RuntimeValue::I64(b),
+            );
+            check(
+                "i64.div_s",
+                ops[3].1,
+                RuntimeValue::I64(a),
+                RuntimeValue::I64(b),
+            );
+        }
+    }
+
+    for &(a, b) in &[(0.1f32, 0.2f32), (f32::MAX, f32::MAX), (-0.0, 0.0)] {
+        check(
+            "f32.add",
+            ops[0].1,
+            RuntimeValue::F32(a.into()),
+            RuntimeValue::F32(b.into()),
+        );
+    }
+    for &(a, b) in &[(1.0f64, 3.0f64), (1.0, 0.0), (0.0, 0.0), (-1.0, 0.0)] {
+        check(
+            "f64.div",
+            ops[3].1,
+            RuntimeValue::F64(a.into()),
+            RuntimeValue::F64(b.into()),
+        );
+    }
+}
+
+#[cfg(feature = "source_offsets")]
+#[test]
+fn source_offsets_map_compiled_code_to_wasm() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "log" (func $log (param i32)))
+	(func (result i32)
+		(i32.add (i32.const 1) (i32.const 2))
+	)
+	(func (param i32) (local i32)
+		(block
+			(br_if 0 (get_local 0))
+			(call $log (i32.const 1))
+		)
+		(br_table 0 0 (get_local 0))
+	)
+)
+"#,
+    );
+    assert_eq!(module.source_offsets(0), None);
+    assert_eq!(module.source_offsets(3), None);
+    for (func_index, code) in (1..).zip(module.code()) {
+        let offsets = module.source_offsets(func_index).unwrap();
+        assert_eq!(offsets.len(), code.instructions().current_pc() as usize);
+        assert!(offsets.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+    // The code section starts with the number of bodies, the size of the first body
+    // and its number of locals.
+    let offsets = module.source_offsets(1).unwrap();
+    assert_eq!(offsets.first(), Some(&3));
+    // `i32.const 1`, `i32.const 2` and `i32.add` precede the final `end`.
+    assert_eq!(offsets.last(), Some(&8));
+
+    // `i32.const 1` with its immediate padded to 5 bytes.
+    let wasm = [
+        // Magic number and version.
+        &[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00][..],
+        // Type section with `[] -> [i32]`.
+        &[0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f],
+        // Function section.
+        &[0x03, 0x02, 0x01, 0x00],
+        // Code section.
+        &[
+            0x0a, 0x0a, 0x01, 0x08, 0x00, 0x41, 0x81, 0x80, 0x80, 0x80, 0x00, 0x0b,
+        ],
+    ]
+    .concat();
+    let module = Module::from_buffer(&wasm).unwrap();
+    let offsets = module.source_offsets(0).unwrap();
+    assert_eq!(offsets.first(), Some(&3));
+    assert_eq!(offsets.last(), Some(&9));
+
+    // Modules which aren't loaded from a binary use the encoding of parity-wasm.
+    let module = Module::from_parity_wasm_module(module.module().clone()).unwrap();
+    let offsets = module.source_offsets(0).unwrap();
+    assert_eq!(offsets.first(), Some(&3));
+    assert_eq!(offsets.last(), Some(&5));
+}
+
+#[test]
+fn imports_and_exports_in_declaration_order() {
+    use super::{ExternKind, ImportsBuilder, ModuleExport, ModuleImport, ModuleInstance};
+    use alloc::vec::Vec;
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "zeta" (func (param i32)))
+	(import "host" "memory" (memory 1))
+	(import "env" "alpha" (global i32))
+	(import "env" "beta" (func))
+)
+"#,
+    );
+    let import = |module_name, field_name, kind| ModuleImport {
+        module_name,
+        field_name,
+        kind,
+    };
+    assert_eq!(
+        module.imports().collect::<Vec<_>>(),
+        [
+            import("env", "zeta", ExternKind::Func),
+            import("host", "memory", ExternKind::Memory),
+            import("env", "alpha", ExternKind::Global),
+            import("env", "beta", ExternKind::Func),
+        ]
+    );
+    assert_eq!(module.exports().count(), 0);
+
+    let module = parse_wat(
+        r#"
+(module
+	(func $first)
+	(func $second)
+	(table 1 anyfunc)
+	(global $g i32 (i32.const 0))
+	(export "zulu" (func $second))
+	(export "mike" (global $g))
+	(export "alpha" (func $first))
+	(export "table" (table 0))
+)
+"#,
+    );
+    let export = |name, kind, index| ModuleExport { name, kind, index };
+    assert_eq!(
+        module.exports().collect::<Vec<_>>(),
+        [
+            export("zulu", ExternKind::Func, 1),
+            export("mike", ExternKind::Global, 0),
+            export("alpha", ExternKind::Func, 0),
+            export("table", ExternKind::Table, 0),
+        ]
+    );
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let exports: Vec<_> = instance
+        .exports()
+        .into_iter()
+        .map(|(name, extern_val)| (name, extern_val.kind()))
+        .collect();
+    assert_eq!(
+        exports,
+        [
+            ("zulu".into(), ExternKind::Func),
+            ("mike".into(), ExternKind::Global),
+            ("alpha".into(), ExternKind::Func),
+            ("table".into(), ExternKind::Table),
+        ]
+    );
+    assert!(instance
+        .export_by_name("mike")
+        .unwrap()
+        .as_global()
+        .is_some());
+    assert!(instance.export_by_name("bravo").is_none());
+}
+
+#[test]
+fn instantiation_error_causes() {
+    use super::{
+        ExternType, ImportsBuilder, InstantiationError, ModuleInstance, Signature, TrapKind,
+        ValueType,
+    };
+    use parity_wasm::elements::ResizableLimits;
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "log" (func (param i32)))
+)
+"#,
+    );
+    match ModuleInstance::new(&module, &ImportsBuilder::default()) {
+        Err(Error::Instantiation(InstantiationError::MissingImport { module, field })) => {
+            assert_eq!((module.as_str(), field.as_str()), ("env", "log"))
+        }
+        result => panic!("expected a missing import, got {:?}", result.err()),
+    }
+
+    let exporting = parse_wat(
+        r#"
+(module
+	(func (export "log") (param i64))
+)
+"#,
+    );
+    let exporting = ModuleInstance::new(&exporting, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    match ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &exporting),
+    ) {
+        Err(Error::Instantiation(InstantiationError::ImportTypeMismatch {
+            module,
+            field,
+            expected,
+            actual,
+        })) => {
+            assert_eq!((module.as_str(), field.as_str()), ("env", "log"));
+            assert_eq!(
+                expected,
+                ExternType::Func(Signature::new(&[ValueType::I32][..], None))
+            );
+            assert_eq!(
+                actual,
+                ExternType::Func(Signature::new(&[ValueType::I64][..], None))
+            );
+        }
+        result => panic!("expected an import type mismatch, got {:?}", result.err()),
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "memory" (memory 2 4))
+)
+"#,
+    );
+    let exporting = parse_wat(
+        r#"
+(module
+	(memory (export "memory") 3)
+)
+"#,
+    );
+    let exporting = ModuleInstance::new(&exporting, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    match ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &exporting),
+    ) {
+        Err(Error::Instantiation(InstantiationError::LimitsMismatch {
+            module,
+            field,
+            expected,
+            actual,
+        })) => {
+            assert_eq!((module.as_str(), field.as_str()), ("env", "memory"));
+            assert_eq!(expected, ResizableLimits::new(2, Some(4)));
+            assert_eq!(actual, ResizableLimits::new(3, None));
+        }
+        result => panic!("expected a limits mismatch, got {:?}", result.err()),
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(table 2 anyfunc)
+	(func $f)
+	(elem (i32.const 0) $f)
+	(elem (i32.const 1) $f $f)
+)
+"#,
+    );
+    match ModuleInstance::new(&module, &ImportsBuilder::default()) {
+        Err(Error::Instantiation(InstantiationError::ElemSegmentDoesNotFit { index })) => {
+            assert_eq!(index, 1)
+        }
+        result => panic!("expected an element segment error, got {:?}", result.err()),
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(memory 1)
+	(data (i32.const 0) "fits")
+	(data (i32.const 65534) "overflows")
+)
+"#,
+    );
+    match ModuleInstance::new(&module, &ImportsBuilder::default()) {
+        Err(Error::Instantiation(InstantiationError::DataSegmentDoesNotFit { index })) => {
+            assert_eq!(index, 1)
+        }
+        result => panic!("expected a data segment error, got {:?}", result.err()),
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(func $start unreachable)
+	(start $start)
+)
+"#,
+    );
+    match ModuleInstance::new_pure(&module, &ImportsBuilder::default()) {
+        Err(Error::Instantiation(InstantiationError::StartTrapped(trap))) => {
+            assert!(matches!(trap.kind(), TrapKind::Unreachable))
+        }
+        result => panic!("expected a trapping start function, got {:?}", result.err()),
+    }
+}
+
+#[cfg(feature = "lazy_compilation")]
+#[test]
+fn lazy_compilation() {
+    use super::prepare::FuncCode;
+    use super::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue};
+
+    let wasm = wabt::wat2wasm(
+        r#"
+(module
+	(func $double (param i32) (result i32)
+		(i32.mul (get_local 0) (i32.const 2)))
+	(func $unused (param i32) (result i32)
+		(i32.add (get_local 0) (i32.const 1)))
+	(func (export "main") (param i32) (result i32)
+		(call $double (get_local 0)))
+)
+"#,
+    )
+    .unwrap();
+    let module = Module::from_buffer_lazy(&wasm).unwrap();
+    let is_compiled = |index: usize| match module.code()[index] {
+        FuncCode::Lazy(ref func) => func.is_compiled(),
+        FuncCode::Compiled(_) => panic!("expected lazily compiled code"),
+    };
+    assert!(!(0..3).any(is_compiled));
+
+    // Instances of the module may run on several threads, each compiling on first use.
+    std::thread::scope(|scope| {
+        for input in 0..4 {
+            let module = &module;
+            scope.spawn(move || {
+                let instance = ModuleInstance::new(module, &ImportsBuilder::default())
+                    .unwrap()
+                    .assert_no_start();
+                let result = instance
+                    .invoke_export("main", &[RuntimeValue::I32(input)], &mut NopExternals)
+                    .unwrap();
+                assert_eq!(result, Some(RuntimeValue::I32(input * 2)));
+            });
+        }
+    });
+    assert!(is_compiled(0) && !is_compiled(1) && is_compiled(2));
+
+    let eager = Module::from_buffer(&wasm).unwrap();
+    for index in 0..3 {
+        assert_eq!(
+            module.code()[index].instructions().current_pc(),
+            eager.code()[index].instructions().current_pc()
+        );
+        #[cfg(feature = "source_offsets")]
+        assert_eq!(
+            module.source_offsets(index as u32),
+            eager.source_offsets(index as u32)
+        );
+    }
+
+    // Functions are validated up front even if they are never called.
+    let wasm = wabt::Wat2Wasm::new()
+        .validate(false)
+        .convert(
+            r#"
+(module
+	(func (export "main"))
+	(func $never_called (result i32)
+		(i64.const 0))
+)
+"#,
+        )
+        .unwrap();
+    assert!(matches!(
+        Module::from_buffer_lazy(&wasm),
+        Err(Error::Validation(_))
+    ));
+}
+
+#[cfg(feature = "atomics")]
+#[test]
+fn atomic_wait_and_notify_in_a_single_thread() {
+    use super::memory_units::Pages;
+    use super::{
+        ImportsBuilder, InstantiationError, MemoryInstance, MemoryRef, ModuleImportResolver,
+        ModuleInstance, NopExternals, RuntimeValue, TrapKind,
+    };
+    use crate::types::MemoryDescriptor;
+
+    struct SharedMemory(MemoryRef);
+
+    impl ModuleImportResolver for SharedMemory {
+        fn resolve_memory(
+            &self,
+            _field_name: &str,
+            _: &MemoryDescriptor,
+        ) -> Result<MemoryRef, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "memory" (memory 1 1 shared))
+	(func (export "notify") (param i32) (result i32)
+		(memory.atomic.notify (local.get 0) (i32.const 1)))
+	(func (export "wait32") (param i32 i32 i64) (result i32)
+		(memory.atomic.wait32 (local.get 0) (local.get 1) (local.get 2)))
+	(func (export "wait64") (param i32 i64 i64) (result i32)
+		(memory.atomic.wait64 (local.get 0) (local.get 1) (local.get 2)))
+)
+"#,
+    );
+    let memory = MemoryInstance::alloc_shared(Pages(1), Pages(1)).unwrap();
+    assert!(memory.is_shared());
+    memory.set_value(0, 42i32).unwrap();
+    memory.set_value(8, 42i64).unwrap();
+    let resolver = SharedMemory(memory);
+    let instance = ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &resolver),
+    )
+    .unwrap()
+    .assert_no_start();
+    let invoke =
+        |name, args: &[RuntimeValue]| instance.invoke_export(name, args, &mut NopExternals);
+    let trap_kind = |result: Result<_, Error>| match result {
+        Err(Error::Trap(trap)) => trap.into_kind(),
+        result => panic!("expected a trap, got {:?}", result),
+    };
+    let wait32 = |address, expected, timeout| {
+        invoke(
+            "wait32",
+            &[
+                RuntimeValue::I32(address),
+                RuntimeValue::I32(expected),
+                RuntimeValue::I64(timeout),
+            ],
+        )
+    };
+    let wait64 = |address, expected, timeout| {
+        invoke(
+            "wait64",
+            &[
+                RuntimeValue::I32(address),
+                RuntimeValue::I64(expected),
+                RuntimeValue::I64(timeout),
+            ],
+        )
+    };
+
+    // Nobody is waiting to be woken.
+    assert_eq!(
+        invoke("notify", &[RuntimeValue::I32(0)]).unwrap(),
+        Some(RuntimeValue::I32(0))
+    );
+
+    // Not-equal, timed-out, or blocked forever.
+    assert_eq!(wait32(0, 7, -1).unwrap(), Some(RuntimeValue::I32(1)));
+    assert_eq!(wait64(8, 7, -1).unwrap(), Some(RuntimeValue::I32(1)));
+    assert_eq!(wait32(0, 42, 0).unwrap(), Some(RuntimeValue::I32(2)));
+    assert_eq!(wait64(8, 42, 1_000).unwrap(), Some(RuntimeValue::I32(2)));
+    assert!(matches!(
+        trap_kind(wait32(0, 42, -1)),
+        TrapKind::WaitWouldBlockForever
+    ));
+
+    // Accesses must be naturally aligned and in bounds.
+    assert!(matches!(
+        trap_kind(invoke("notify", &[RuntimeValue::I32(2)])),
+        TrapKind::UnalignedAtomic
+    ));
+    assert!(matches!(
+        trap_kind(wait32(1, 42, 0)),
+        TrapKind::UnalignedAtomic
+    ));
+    assert!(matches!(
+        trap_kind(wait64(4, 42, 0)),
+        TrapKind::UnalignedAtomic
+    ));
+    assert!(matches!(
+        trap_kind(wait64(65536 - 4, 0, 0)),
+        TrapKind::MemoryAccessOutOfBounds
+    ));
+    assert!(matches!(
+        trap_kind(invoke("notify", &[RuntimeValue::I32(65536)])),
+        TrapKind::MemoryAccessOutOfBounds
+    ));
+
+    // A shared memory can't be satisfied with an unshared one.
+    let resolver = SharedMemory(MemoryInstance::alloc(Pages(1), Some(Pages(1))).unwrap());
+    assert!(matches!(
+        ModuleInstance::new(
+            &module,
+            &ImportsBuilder::new().with_resolver("env", &resolver)
+        ),
+        Err(Error::Instantiation(
+            InstantiationError::ImportTypeMismatch { .. }
+        ))
+    ));
+
+    // Only waiting requires a shared memory.
+    let module = parse_wat(
+        r#"
+(module
+	(memory 1)
+	(func (export "notify") (result i32)
+		(memory.atomic.notify (i32.const 0) (i32.const 1)))
+	(func (export "wait32") (result i32)
+		(memory.atomic.wait32 (i32.const 0) (i32.const 1) (i64.const 0)))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    assert_eq!(
+        instance
+            .invoke_export("notify", &[], &mut NopExternals)
+            .unwrap(),
+        Some(RuntimeValue::I32(0))
+    );
+    assert!(matches!(
+        trap_kind(instance.invoke_export("wait32", &[], &mut NopExternals)),
+        TrapKind::ExpectedSharedMemory
+    ));
+
+    // Atomic accesses must declare their natural alignment.
+    let wasm = wabt::wat2wasm(
+        r#"
+(module
+	(memory 1 1 shared)
+	(func (result i32)
+		(memory.atomic.notify align=2 (i32.const 0) (i32.const 1)))
+)
+"#,
+    )
+    .unwrap();
+    assert!(matches!(
+        Module::from_buffer(&wasm),
+        Err(Error::Validation(_))
+    ));
+}
+
 pub fn parse_wat(source: &str) -> Module {
     let wasm_binary = wabt::wat2wasm(source).expect("Failed to parse wat source");
     Module::from_buffer(wasm_binary).expect("Failed to load parsed module")
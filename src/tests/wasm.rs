@@ -3,9 +3,10 @@ extern crate std;
 
 use crate::memory_units::Pages;
 use crate::{
-    Error, FuncRef, GlobalDescriptor, GlobalInstance, GlobalRef, ImportsBuilder, MemoryDescriptor,
-    MemoryInstance, MemoryRef, Module, ModuleImportResolver, ModuleInstance, NopExternals,
-    RuntimeValue, Signature, TableDescriptor, TableInstance, TableRef,
+    Error, FuncRef, GlobalDescriptor, GlobalInstance, GlobalRef, ImportsBuilder,
+    InstantiationError, MemoryDescriptor, MemoryInstance, MemoryRef, Module, ModuleImportResolver,
+    ModuleInstance, NopExternals, RuntimeValue, Signature, TableDescriptor, TableInstance,
+    TableRef,
 };
 use alloc::vec::Vec;
 use std::fs::File;
@@ -30,9 +31,9 @@ impl Env {
 
 impl ModuleImportResolver for Env {
     fn resolve_func(&self, _field_name: &str, _func_type: &Signature) -> Result<FuncRef, Error> {
-        Err(Error::Instantiation(
+        Err(Error::Instantiation(InstantiationError::Other(
             "env module doesn't provide any functions".into(),
-        ))
+        )))
     }
 
     fn resolve_global(
@@ -43,10 +44,10 @@ impl ModuleImportResolver for Env {
         match field_name {
             "tableBase" => Ok(self.table_base.clone()),
             "memoryBase" => Ok(self.memory_base.clone()),
-            _ => Err(Error::Instantiation(format!(
+            _ => Err(Error::Instantiation(InstantiationError::Other(format!(
                 "env module doesn't provide global '{}'",
                 field_name
-            ))),
+            )))),
         }
     }
 
@@ -57,10 +58,10 @@ impl ModuleImportResolver for Env {
     ) -> Result<MemoryRef, Error> {
         match field_name {
             "memory" => Ok(self.memory.clone()),
-            _ => Err(Error::Instantiation(format!(
+            _ => Err(Error::Instantiation(InstantiationError::Other(format!(
                 "env module doesn't provide memory '{}'",
                 field_name
-            ))),
+            )))),
         }
     }
 
@@ -71,10 +72,10 @@ impl ModuleImportResolver for Env {
     ) -> Result<TableRef, Error> {
         match field_name {
             "table" => Ok(self.table.clone()),
-            _ => Err(Error::Instantiation(format!(
+            _ => Err(Error::Instantiation(InstantiationError::Other(format!(
                 "env module doesn't provide table '{}'",
                 field_name
-            ))),
+            )))),
         }
     }
 }
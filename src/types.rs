@@ -9,10 +9,12 @@ use parity_wasm::elements::{
 /// Signature of a function consists of zero or more parameter [types][type] and zero or one return [type].
 ///
 /// Two signatures are considered equal if they have equal list of parameters and equal return types.
+/// The order of the parameters matters, and it doesn't matter whether the parameters are borrowed
+/// or owned.
 ///
 /// [type]: enum.ValueType.html
 /// [function]: struct.FuncInstance.html
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Signature {
     params: Cow<'static, [ValueType]>,
     return_type: Option<ValueType>,
@@ -78,7 +80,7 @@ impl Signature {
 /// See [`RuntimeValue`] for details.
 ///
 /// [`RuntimeValue`]: enum.RuntimeValue.html
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum ValueType {
     /// 32-bit signed or unsigned integer.
     I32,
@@ -181,6 +183,7 @@ impl TableDescriptor {
 pub struct MemoryDescriptor {
     initial: u32,
     maximum: Option<u32>,
+    shared: bool,
 }
 
 impl MemoryDescriptor {
@@ -188,6 +191,11 @@ impl MemoryDescriptor {
         MemoryDescriptor {
             initial: memory_type.limits().initial(),
             maximum: memory_type.limits().maximum(),
+            #[cfg(feature = "atomics")]
+            shared: memory_type.limits().shared(),
+            // Shared memories are rejected by validation without the `atomics` feature.
+            #[cfg(not(feature = "atomics"))]
+            shared: false,
         }
     }
 
@@ -200,4 +208,11 @@ impl MemoryDescriptor {
     pub fn maximum(&self) -> Option<u32> {
         self.maximum
     }
+
+    /// Returns whether the requested memory is shared, see [`MemoryInstance::alloc_shared`].
+    ///
+    /// [`MemoryInstance::alloc_shared`]: struct.MemoryInstance.html#method.alloc_shared
+    pub fn is_shared(&self) -> bool {
+        self.shared
+    }
 }
@@ -1,7 +1,11 @@
 use crate::nan_preserving_float::{F32, F64};
+#[cfg(feature = "overflow_tracing")]
+use crate::runner::OverflowingOp;
 use crate::types::ValueType;
 use crate::TrapKind;
-use core::{f32, i32, i64, u32, u64};
+use alloc::rc::Rc;
+use core::any::Any;
+use core::{f32, fmt, i32, i64, u32, u64};
 
 /// Error for `LittleEndianConvert`
 #[derive(Debug)]
@@ -29,6 +33,36 @@ pub enum RuntimeValue {
     F64(F64),
 }
 
+/// An opaque reference to a host value, i.e. a value of the `externref` type.
+///
+/// Wasm code can't inspect the referenced value, it can only pass the reference around.
+/// Cloning an `ExternRef` creates another reference to the same value.
+#[derive(Clone)]
+pub struct ExternRef(Rc<dyn Any>);
+
+impl ExternRef {
+    /// Creates a reference to the given host value.
+    pub fn new<T: Any>(value: T) -> ExternRef {
+        ExternRef(Rc::new(value))
+    }
+
+    /// Returns the referenced value if it is of type `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.0.downcast_ref()
+    }
+
+    /// Returns `true` if both references point to the same value.
+    pub fn ptr_eq(&self, other: &ExternRef) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for ExternRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ExternRef({:p})", Rc::as_ptr(&self.0))
+    }
+}
+
 /// Trait for creating value from a [`RuntimeValue`].
 ///
 /// Typically each implementation can create a value from the specific type.
@@ -63,6 +97,19 @@ pub trait TryTruncateInto<T, E> {
     fn try_truncate_into(self) -> Result<T, E>;
 }
 
+/// Convert one type to another by rounding to the nearest integer towards zero,
+/// saturating at the bounds of the target type.
+///
+/// Unlike [`TryTruncateInto`] this never fails: values out of range are clamped to the
+/// minimum or maximum of the target type and NaN is converted to zero.
+///
+/// [`TryTruncateInto`]: trait.TryTruncateInto.html
+pub trait TruncateSaturateInto<T> {
+    /// Convert one type to another by rounding to the nearest integer towards zero,
+    /// saturating at the bounds of the target type.
+    fn truncate_saturate_into(self) -> T;
+}
+
 /// Convert one type to another by extending with leading zeroes.
 pub trait ExtendInto<T> {
     /// Convert one type to another by extending with leading zeroes.
@@ -96,6 +143,9 @@ pub trait ArithmeticOps<T> {
     fn mul(self, other: T) -> T;
     /// Divide two values.
     fn div(self, other: T) -> Result<T, TrapKind>;
+    /// Whether `op` overflows the signed range for the two values.
+    #[cfg(feature = "overflow_tracing")]
+    fn overflows_signed(self, op: OverflowingOp, other: T) -> bool;
 }
 
 /// Integer value.
@@ -138,6 +188,32 @@ pub trait Float<T>: ArithmeticOps<T> {
     fn copysign(self, other: T) -> T;
 }
 
+macro_rules! arithmetic_op {
+    ($lhs: expr, $rhs: expr, $op: ident) => {
+        match ($lhs, $rhs) {
+            (RuntimeValue::I32(a), RuntimeValue::I32(b)) => Ok(ArithmeticOps::$op(a, b).into()),
+            (RuntimeValue::I64(a), RuntimeValue::I64(b)) => Ok(ArithmeticOps::$op(a, b).into()),
+            (RuntimeValue::F32(a), RuntimeValue::F32(b)) => Ok(ArithmeticOps::$op(a, b).into()),
+            (RuntimeValue::F64(a), RuntimeValue::F64(b)) => Ok(ArithmeticOps::$op(a, b).into()),
+            _ => Err(TrapKind::UnexpectedSignature),
+        }
+    };
+}
+
+macro_rules! unsigned_integer_op {
+    ($lhs: expr, $rhs: expr, $op: ident) => {
+        match ($lhs, $rhs) {
+            (RuntimeValue::I32(a), RuntimeValue::I32(b)) => (a as u32)
+                .$op(b as u32)
+                .map(|v| RuntimeValue::I32(v as i32)),
+            (RuntimeValue::I64(a), RuntimeValue::I64(b)) => (a as u64)
+                .$op(b as u64)
+                .map(|v| RuntimeValue::I64(v as i64)),
+            _ => Err(TrapKind::UnexpectedSignature),
+        }
+    };
+}
+
 impl RuntimeValue {
     /// Creates new default value of given type.
     pub fn default(value_type: ValueType) -> Self {
@@ -169,6 +245,13 @@ impl RuntimeValue {
         }
     }
 
+    /// Returns `true` if this value is of the given [`ValueType`].
+    ///
+    /// [`ValueType`]: enum.ValueType.html
+    pub fn matches_type(&self, value_type: ValueType) -> bool {
+        self.value_type() == value_type
+    }
+
     /// Returns `T` if this particular [`RuntimeValue`] contains
     /// appropriate type.
     ///
@@ -179,6 +262,164 @@ impl RuntimeValue {
     pub fn try_into<T: FromRuntimeValue>(self) -> Option<T> {
         FromRuntimeValue::from_runtime_value(self)
     }
+
+    /// Adds two values the way `i32.add`, `i64.add`, `f32.add` and `f64.add` do,
+    /// i.e. wrapping around for integers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`UnexpectedSignature`] trap if the values are of different types.
+    ///
+    /// [`UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn wrapping_add(self, other: RuntimeValue) -> Result<RuntimeValue, TrapKind> {
+        arithmetic_op!(self, other, add)
+    }
+
+    /// Subtracts `other` from this value the way the `sub` instructions do, i.e. wrapping
+    /// around for integers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`UnexpectedSignature`] trap if the values are of different types.
+    ///
+    /// [`UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn wrapping_sub(self, other: RuntimeValue) -> Result<RuntimeValue, TrapKind> {
+        arithmetic_op!(self, other, sub)
+    }
+
+    /// Multiplies two values the way the `mul` instructions do, i.e. wrapping around
+    /// for integers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`UnexpectedSignature`] trap if the values are of different types.
+    ///
+    /// [`UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn wrapping_mul(self, other: RuntimeValue) -> Result<RuntimeValue, TrapKind> {
+        arithmetic_op!(self, other, mul)
+    }
+
+    /// Divides this value by `other` the way `i32.div_s`, `i64.div_s`, `f32.div` and
+    /// `f64.div` do.
+    ///
+    /// # Errors
+    ///
+    /// Returns the trap the instruction would raise, i.e. [`DivisionByZero`] if an integer
+    /// is divided by zero and [`InvalidConversionToInt`] if the signed result overflows,
+    /// and an [`UnexpectedSignature`] trap if the values are of different types.
+    ///
+    /// [`DivisionByZero`]: enum.TrapKind.html#variant.DivisionByZero
+    /// [`InvalidConversionToInt`]: enum.TrapKind.html#variant.InvalidConversionToInt
+    /// [`UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn checked_div(self, other: RuntimeValue) -> Result<RuntimeValue, TrapKind> {
+        match (self, other) {
+            (RuntimeValue::I32(a), RuntimeValue::I32(b)) => {
+                ArithmeticOps::div(a, b).map(Into::into)
+            }
+            (RuntimeValue::I64(a), RuntimeValue::I64(b)) => {
+                ArithmeticOps::div(a, b).map(Into::into)
+            }
+            (RuntimeValue::F32(a), RuntimeValue::F32(b)) => {
+                ArithmeticOps::div(a, b).map(Into::into)
+            }
+            (RuntimeValue::F64(a), RuntimeValue::F64(b)) => {
+                ArithmeticOps::div(a, b).map(Into::into)
+            }
+            _ => Err(TrapKind::UnexpectedSignature),
+        }
+    }
+
+    /// Divides this integer by `other` the way `i32.div_u` and `i64.div_u` do.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DivisionByZero`] trap if `other` is zero and an [`UnexpectedSignature`]
+    /// trap if the values are of different types or aren't integers.
+    ///
+    /// [`DivisionByZero`]: enum.TrapKind.html#variant.DivisionByZero
+    /// [`UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn checked_div_u(self, other: RuntimeValue) -> Result<RuntimeValue, TrapKind> {
+        unsigned_integer_op!(self, other, div)
+    }
+
+    /// Computes the remainder of dividing this integer by `other` the way `i32.rem_s`
+    /// and `i64.rem_s` do.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DivisionByZero`] trap if `other` is zero and an [`UnexpectedSignature`]
+    /// trap if the values are of different types or aren't integers.
+    ///
+    /// [`DivisionByZero`]: enum.TrapKind.html#variant.DivisionByZero
+    /// [`UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn checked_rem(self, other: RuntimeValue) -> Result<RuntimeValue, TrapKind> {
+        match (self, other) {
+            (RuntimeValue::I32(a), RuntimeValue::I32(b)) => Integer::rem(a, b).map(Into::into),
+            (RuntimeValue::I64(a), RuntimeValue::I64(b)) => Integer::rem(a, b).map(Into::into),
+            _ => Err(TrapKind::UnexpectedSignature),
+        }
+    }
+
+    /// Computes the remainder of dividing this integer by `other` the way `i32.rem_u`
+    /// and `i64.rem_u` do.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DivisionByZero`] trap if `other` is zero and an [`UnexpectedSignature`]
+    /// trap if the values are of different types or aren't integers.
+    ///
+    /// [`DivisionByZero`]: enum.TrapKind.html#variant.DivisionByZero
+    /// [`UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn checked_rem_u(self, other: RuntimeValue) -> Result<RuntimeValue, TrapKind> {
+        unsigned_integer_op!(self, other, rem)
+    }
+}
+
+/// Formats the value as `<type>:<value>`, e.g. `i32:42` or `f64:-inf`.
+///
+/// Floats are written the way the wasm text format writes them. NaNs are written as `nan`
+/// if they have the canonical payload and as `nan:0x<payload>` otherwise, and keep their sign.
+impl fmt::Display for RuntimeValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RuntimeValue::I32(v) => write!(f, "i32:{}", v),
+            RuntimeValue::I64(v) => write!(f, "i64:{}", v),
+            RuntimeValue::F32(v) => {
+                let v = v.to_float();
+                let payload = u64::from(v.to_bits() & 0x7f_ffff);
+                f.write_str("f32:")?;
+                fmt_float(f, &v, v.is_nan(), v.is_sign_negative(), payload, 1 << 22)
+            }
+            RuntimeValue::F64(v) => {
+                let v = v.to_float();
+                let payload = v.to_bits() & 0xf_ffff_ffff_ffff;
+                f.write_str("f64:")?;
+                fmt_float(f, &v, v.is_nan(), v.is_sign_negative(), payload, 1 << 51)
+            }
+        }
+    }
+}
+
+fn fmt_float(
+    f: &mut fmt::Formatter,
+    value: &dyn fmt::Display,
+    is_nan: bool,
+    is_negative: bool,
+    payload: u64,
+    canonical_payload: u64,
+) -> fmt::Result {
+    if !is_nan {
+        // Infinities are written as `inf` and negative zero keeps its sign.
+        return value.fmt(f);
+    }
+    if is_negative {
+        f.write_str("-")?;
+    }
+    if payload == canonical_payload {
+        f.write_str("nan")
+    } else {
+        write!(f, "nan:{:#x}", payload)
+    }
 }
 
 impl From<i8> for RuntimeValue {
@@ -412,6 +653,42 @@ impl_try_truncate_into!(@wrapped F32, f32, u64);
 impl_try_truncate_into!(@wrapped F64, f64, u32);
 impl_try_truncate_into!(@wrapped F64, f64, u64);
 
+macro_rules! impl_truncate_saturate_into {
+    ($from:ident, $into:ident) => {
+        impl TruncateSaturateInto<$into> for $from {
+            fn truncate_saturate_into(self) -> $into {
+                // Casting from a float to an integer rounds towards zero, saturates at the
+                // bounds of the integer type and converts NaN to zero.
+                self as $into
+            }
+        }
+    };
+    (@wrapped $from:ident, $intermediate:ident, $into:ident) => {
+        impl TruncateSaturateInto<$into> for $from {
+            fn truncate_saturate_into(self) -> $into {
+                $intermediate::from(self).truncate_saturate_into()
+            }
+        }
+    };
+}
+
+impl_truncate_saturate_into!(f32, i32);
+impl_truncate_saturate_into!(f32, i64);
+impl_truncate_saturate_into!(f64, i32);
+impl_truncate_saturate_into!(f64, i64);
+impl_truncate_saturate_into!(f32, u32);
+impl_truncate_saturate_into!(f32, u64);
+impl_truncate_saturate_into!(f64, u32);
+impl_truncate_saturate_into!(f64, u64);
+impl_truncate_saturate_into!(@wrapped F32, f32, i32);
+impl_truncate_saturate_into!(@wrapped F32, f32, i64);
+impl_truncate_saturate_into!(@wrapped F64, f64, i32);
+impl_truncate_saturate_into!(@wrapped F64, f64, i64);
+impl_truncate_saturate_into!(@wrapped F32, f32, u32);
+impl_truncate_saturate_into!(@wrapped F32, f32, u64);
+impl_truncate_saturate_into!(@wrapped F64, f64, u32);
+impl_truncate_saturate_into!(@wrapped F64, f64, u64);
+
 macro_rules! impl_extend_into {
     ($from:ident, $into:ident) => {
         impl ExtendInto<$into> for $from {
@@ -738,7 +1015,7 @@ impl LittleEndianConvert for F64 {
 }
 
 macro_rules! impl_integer_arithmetic_ops {
-    ($type: ident) => {
+    ($type: ident, $signed: ident) => {
         impl ArithmeticOps<$type> for $type {
             fn add(self, other: $type) -> $type {
                 self.wrapping_add(other)
@@ -761,14 +1038,23 @@ macro_rules! impl_integer_arithmetic_ops {
                     }
                 }
             }
+            #[cfg(feature = "overflow_tracing")]
+            fn overflows_signed(self, op: OverflowingOp, other: $type) -> bool {
+                let (lhs, rhs) = (self as $signed, other as $signed);
+                match op {
+                    OverflowingOp::Add => lhs.checked_add(rhs).is_none(),
+                    OverflowingOp::Sub => lhs.checked_sub(rhs).is_none(),
+                    OverflowingOp::Mul => lhs.checked_mul(rhs).is_none(),
+                }
+            }
         }
     };
 }
 
-impl_integer_arithmetic_ops!(i32);
-impl_integer_arithmetic_ops!(u32);
-impl_integer_arithmetic_ops!(i64);
-impl_integer_arithmetic_ops!(u64);
+impl_integer_arithmetic_ops!(i32, i32);
+impl_integer_arithmetic_ops!(u32, i32);
+impl_integer_arithmetic_ops!(i64, i64);
+impl_integer_arithmetic_ops!(u64, i64);
 
 macro_rules! impl_float_arithmetic_ops {
     ($type: ident) => {
@@ -785,6 +1071,10 @@ macro_rules! impl_float_arithmetic_ops {
             fn div(self, other: $type) -> Result<$type, TrapKind> {
                 Ok(self / other)
             }
+            #[cfg(feature = "overflow_tracing")]
+            fn overflows_signed(self, _op: OverflowingOp, _other: $type) -> bool {
+                false
+            }
         }
     };
 }
@@ -848,8 +1138,11 @@ macro_rules! impl_float {
         // In this particular instance we want to directly compare floating point numbers.
         #[allow(clippy::float_cmp)]
         impl Float<$type> for $type {
+            // `abs` and `copysign` only ever touch the sign bit, which is the only bit set
+            // in `$iXX::MIN`, so that NaN payloads are preserved.
             fn abs(self) -> $type {
-                fmath::$fXX::abs($fXX::from(self)).into()
+                let int: $iXX = self.transmute_into();
+                (int & !<$iXX>::MIN).transmute_into()
             }
             fn floor(self) -> $type {
                 fmath::$fXX::floor($fXX::from(self)).into()
@@ -904,24 +1197,9 @@ macro_rules! impl_float {
                 self.max(other)
             }
             fn copysign(self, other: $type) -> $type {
-                use core::mem::size_of;
-
-                if self.is_nan() {
-                    return self;
-                }
-
-                let sign_mask: $iXX = 1 << ((size_of::<$iXX>() << 3) - 1);
                 let self_int: $iXX = self.transmute_into();
                 let other_int: $iXX = other.transmute_into();
-                let is_self_sign_set = (self_int & sign_mask) != 0;
-                let is_other_sign_set = (other_int & sign_mask) != 0;
-                if is_self_sign_set == is_other_sign_set {
-                    self
-                } else if is_other_sign_set {
-                    (self_int | sign_mask).transmute_into()
-                } else {
-                    (self_int & !sign_mask).transmute_into()
-                }
+                ((self_int & !<$iXX>::MIN) | (other_int & <$iXX>::MIN)).transmute_into()
             }
         }
     };
@@ -935,10 +1213,6 @@ impl_float!(F64, f64, i64);
 #[cfg(not(feature = "std"))]
 mod libm_adapters {
     pub mod f32 {
-        pub fn abs(v: f32) -> f32 {
-            libm::fabsf(v)
-        }
-
         pub fn floor(v: f32) -> f32 {
             libm::floorf(v)
         }
@@ -965,10 +1239,6 @@ mod libm_adapters {
     }
 
     pub mod f64 {
-        pub fn abs(v: f64) -> f64 {
-            libm::fabs(v)
-        }
-
         pub fn floor(v: f64) -> f64 {
             libm::floor(v)
         }
@@ -994,3 +1264,175 @@ mod libm_adapters {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Float, RuntimeValue, TruncateSaturateInto};
+    use crate::nan_preserving_float::{F32, F64};
+    use crate::types::ValueType;
+    use core::{f32, f64};
+
+    #[test]
+    fn display() {
+        let display = |v: RuntimeValue| alloc::format!("{}", v);
+
+        assert_eq!(display(RuntimeValue::I32(-42)), "i32:-42");
+        assert_eq!(
+            display(RuntimeValue::I64(i64::MAX)),
+            "i64:9223372036854775807"
+        );
+        assert_eq!(display(RuntimeValue::F32(1.5f32.into())), "f32:1.5");
+        assert_eq!(display(RuntimeValue::F64(0.1f64.into())), "f64:0.1");
+
+        assert_eq!(display(RuntimeValue::F32((-0.0f32).into())), "f32:-0");
+        assert_eq!(display(RuntimeValue::F64((-0.0f64).into())), "f64:-0");
+        assert_eq!(display(RuntimeValue::F32(f32::INFINITY.into())), "f32:inf");
+        assert_eq!(
+            display(RuntimeValue::F64(f64::NEG_INFINITY.into())),
+            "f64:-inf"
+        );
+
+        assert_eq!(
+            display(RuntimeValue::F32(F32::from_bits(0x7fc0_0000))),
+            "f32:nan"
+        );
+        assert_eq!(
+            display(RuntimeValue::F32(F32::from_bits(0xffc0_0000))),
+            "f32:-nan"
+        );
+        assert_eq!(
+            display(RuntimeValue::F32(F32::from_bits(0x7fa0_0001))),
+            "f32:nan:0x200001"
+        );
+        assert_eq!(
+            display(RuntimeValue::F64(F64::from_bits(0x7ff8_0000_0000_0000))),
+            "f64:nan"
+        );
+        assert_eq!(
+            display(RuntimeValue::F64(F64::from_bits(0xfff0_0000_0000_0001))),
+            "f64:-nan:0x1"
+        );
+    }
+
+    #[test]
+    fn truncate_saturate_f32() {
+        let sat_i32 = |v: f32| -> i32 { F32::from(v).truncate_saturate_into() };
+        assert_eq!(sat_i32(-0.9), 0);
+        assert_eq!(sat_i32(2147483520.0), 2147483520);
+        assert_eq!(sat_i32(2147483648.0), i32::MAX);
+        assert_eq!(sat_i32(-2147483648.0), i32::MIN);
+        assert_eq!(sat_i32(-2147483904.0), i32::MIN);
+        assert_eq!(sat_i32(f32::INFINITY), i32::MAX);
+        assert_eq!(sat_i32(f32::NEG_INFINITY), i32::MIN);
+        assert_eq!(sat_i32(f32::NAN), 0);
+
+        let sat_u32 = |v: f32| -> u32 { F32::from(v).truncate_saturate_into() };
+        assert_eq!(sat_u32(-0.9), 0);
+        assert_eq!(sat_u32(-1.0), 0);
+        assert_eq!(sat_u32(4294967040.0), 4294967040);
+        assert_eq!(sat_u32(4294967296.0), u32::MAX);
+        assert_eq!(sat_u32(f32::NAN), 0);
+
+        let sat_i64 = |v: f32| -> i64 { F32::from(v).truncate_saturate_into() };
+        assert_eq!(sat_i64(9223371487098961920.0), 9223371487098961920);
+        assert_eq!(sat_i64(9223372036854775808.0), i64::MAX);
+        assert_eq!(sat_i64(-9223372036854775808.0), i64::MIN);
+        assert_eq!(sat_i64(f32::NEG_INFINITY), i64::MIN);
+        assert_eq!(sat_i64(f32::NAN), 0);
+
+        let sat_u64 = |v: f32| -> u64 { F32::from(v).truncate_saturate_into() };
+        assert_eq!(sat_u64(-1.0), 0);
+        assert_eq!(sat_u64(18446742974197923840.0), 18446742974197923840);
+        assert_eq!(sat_u64(18446744073709551616.0), u64::MAX);
+        assert_eq!(sat_u64(f32::NAN), 0);
+    }
+
+    #[test]
+    fn truncate_saturate_f64() {
+        let sat_i32 = |v: f64| -> i32 { F64::from(v).truncate_saturate_into() };
+        assert_eq!(sat_i32(2147483647.9), i32::MAX);
+        assert_eq!(sat_i32(2147483648.0), i32::MAX);
+        assert_eq!(sat_i32(-2147483648.9), i32::MIN);
+        assert_eq!(sat_i32(-2147483649.0), i32::MIN);
+        assert_eq!(sat_i32(-0.9), 0);
+        assert_eq!(sat_i32(f64::NAN), 0);
+
+        let sat_u32 = |v: f64| -> u32 { F64::from(v).truncate_saturate_into() };
+        assert_eq!(sat_u32(4294967295.9), u32::MAX);
+        assert_eq!(sat_u32(4294967296.0), u32::MAX);
+        assert_eq!(sat_u32(-0.9), 0);
+        assert_eq!(sat_u32(-1.0), 0);
+        assert_eq!(sat_u32(f64::NEG_INFINITY), 0);
+        assert_eq!(sat_u32(f64::NAN), 0);
+
+        let sat_i64 = |v: f64| -> i64 { F64::from(v).truncate_saturate_into() };
+        assert_eq!(sat_i64(9223372036854774784.0), 9223372036854774784);
+        assert_eq!(sat_i64(9223372036854775808.0), i64::MAX);
+        assert_eq!(sat_i64(-9223372036854775808.0), i64::MIN);
+        assert_eq!(sat_i64(-9223372036854777856.0), i64::MIN);
+        assert_eq!(sat_i64(f64::NAN), 0);
+
+        let sat_u64 = |v: f64| -> u64 { F64::from(v).truncate_saturate_into() };
+        assert_eq!(sat_u64(18446744073709549568.0), 18446744073709549568);
+        assert_eq!(sat_u64(18446744073709551616.0), u64::MAX);
+        assert_eq!(sat_u64(-1.0), 0);
+        assert_eq!(sat_u64(f64::INFINITY), u64::MAX);
+        assert_eq!(sat_u64(f64::NAN), 0);
+    }
+
+    #[test]
+    fn sign_ops_preserve_nan_payload() {
+        // A negative signaling NaN and a positive quiet NaN with a payload.
+        let snan = F32::from_bits(0xff80_0001);
+        let qnan = F32::from_bits(0x7fc1_2345);
+        assert_eq!(Float::abs(snan).to_bits(), 0x7f80_0001);
+        assert_eq!(Float::abs(qnan).to_bits(), 0x7fc1_2345);
+        assert_eq!((-snan).to_bits(), 0x7f80_0001);
+        assert_eq!((-qnan).to_bits(), 0xffc1_2345);
+        assert_eq!(qnan.copysign(F32::from(-1.0)).to_bits(), 0xffc1_2345);
+        assert_eq!(snan.copysign(F32::from(1.0)).to_bits(), 0x7f80_0001);
+        assert_eq!(snan.copysign(qnan).to_bits(), 0x7f80_0001);
+        assert_eq!(F32::from(2.0).copysign(snan).to_bits(), (-2.0f32).to_bits());
+
+        let snan = F64::from_bits(0xfff0_0000_0000_0001);
+        let qnan = F64::from_bits(0x7ff8_1234_5678_9abc);
+        assert_eq!(Float::abs(snan).to_bits(), 0x7ff0_0000_0000_0001);
+        assert_eq!(Float::abs(qnan).to_bits(), 0x7ff8_1234_5678_9abc);
+        assert_eq!((-snan).to_bits(), 0x7ff0_0000_0000_0001);
+        assert_eq!((-qnan).to_bits(), 0xfff8_1234_5678_9abc);
+        assert_eq!(
+            qnan.copysign(F64::from(-1.0)).to_bits(),
+            0xfff8_1234_5678_9abc
+        );
+        assert_eq!(
+            snan.copysign(F64::from(1.0)).to_bits(),
+            0x7ff0_0000_0000_0001
+        );
+        assert_eq!(snan.copysign(qnan).to_bits(), 0x7ff0_0000_0000_0001);
+        assert_eq!(F64::from(2.0).copysign(snan).to_bits(), (-2.0f64).to_bits());
+    }
+
+    #[test]
+    fn decode_preserves_nan_payload() {
+        // Signaling NaNs are the interesting case, since a round-trip through the x87
+        // FPU or a normalizing conversion would quiet them.
+        for &bits in &[0x7f80_0001, 0xffa0_0000, 0x7fc1_2345] {
+            let value = RuntimeValue::decode_f32(bits);
+            assert_eq!(value.value_type(), ValueType::F32);
+            let float = value.try_into::<F32>().unwrap();
+            assert_eq!(float.to_bits(), bits);
+            assert_eq!(F32::from_float(float.to_float()).to_bits(), bits);
+        }
+        for &bits in &[
+            0x7ff0_0000_0000_0001,
+            0xfff4_0000_0000_0000,
+            0x7ff8_1234_5678_9abc,
+        ] {
+            let value = RuntimeValue::decode_f64(bits);
+            assert_eq!(value.value_type(), ValueType::F64);
+            let float = value.try_into::<F64>().unwrap();
+            assert_eq!(float.to_bits(), bits);
+            assert_eq!(F64::from_float(float.to_float()).to_bits(), bits);
+        }
+    }
+}
@@ -4,6 +4,10 @@ set -eux
 
 EXTRA_ARGS=""
 NO_STD_ARGS=""
+# Optional features that are tested one at a time if `FEATURE_MATRIX` is set.
+OPTIONAL_FEATURES="stack_height_checks defensive_checks execution_deadline overflow_tracing
+    compact_bytecode source_offsets stepping lazy_compilation atomics trap_state import_usage
+    preserve_nops vec_memory grow_failure_injection"
 
 if [ -n "${TARGET-}" ]; then
     # Tests build in debug mode are prohibitively
@@ -19,6 +23,12 @@ fi
 
 cd $(dirname $0)
 
-time cargo test --all ${EXTRA_ARGS} ${NO_STD_ARGS}
+if [ -n "${FEATURE_MATRIX-}" ]; then
+    for feature in ${OPTIONAL_FEATURES}; do
+        time cargo test --all ${EXTRA_ARGS} --features=${feature}
+    done
+else
+    time cargo test --all ${EXTRA_ARGS} ${NO_STD_ARGS}
+fi
 
 cd -
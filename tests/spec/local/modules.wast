@@ -0,0 +1,43 @@
+;; Invalid, malformed and unlinkable modules are rejected, and registered modules can be linked.
+
+(assert_invalid
+  (module (func (result i32) (i64.const 0)))
+  "type mismatch")
+(assert_invalid
+  (module (func (get_local 0)))
+  "unknown local")
+(assert_invalid
+  (module (func (br 1)))
+  "unknown label")
+
+(assert_malformed
+  (module binary "\00asm" "\02\00\00\00")
+  "unknown binary version")
+(assert_malformed
+  (module quote "(func (i32.const 0x1_))")
+  "unknown operator")
+
+(module $counter
+  (global $count (mut i32) (i32.const 0))
+  (func (export "next") (result i32)
+    (set_global $count (i32.add (get_global $count) (i32.const 1)))
+    (get_global $count))
+)
+(register "counter" $counter)
+
+(module
+  (import "counter" "next" (func $next (result i32)))
+  (func (export "twice") (result i32)
+    (drop (call $next))
+    (call $next))
+)
+
+(assert_return (invoke "twice") (i32.const 2))
+(assert_return (invoke $counter "next") (i32.const 3))
+
+(assert_unlinkable
+  (module (import "counter" "missing" (func)))
+  "unknown import")
+(assert_unlinkable
+  (module (import "counter" "next" (func (param i32))))
+  "incompatible import type")
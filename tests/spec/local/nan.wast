@@ -0,0 +1,37 @@
+;; Results are compared bitwise and NaNs against the canonical and arithmetic patterns.
+
+(module
+  (func (export "f32.add") (param f32 f32) (result f32)
+    (f32.add (get_local 0) (get_local 1)))
+  (func (export "f32.neg") (param f32) (result f32)
+    (f32.neg (get_local 0)))
+  (func (export "f64.mul") (param f64 f64) (result f64)
+    (f64.mul (get_local 0) (get_local 1)))
+  (func (export "f64.copysign") (param f64 f64) (result f64)
+    (f64.copysign (get_local 0) (get_local 1)))
+  (func (export "f32.sqrt") (param f32) (result f32)
+    (f32.sqrt (get_local 0)))
+  (func (export "f64.reinterpret") (param i64) (result f64)
+    (f64.reinterpret/i64 (get_local 0)))
+)
+
+(assert_return (invoke "f32.add" (f32.const 0.5) (f32.const 0.25)) (f32.const 0.75))
+(assert_return (invoke "f32.neg" (f32.const 0)) (f32.const -0))
+(assert_return (invoke "f64.mul" (f64.const -0) (f64.const 1)) (f64.const -0))
+(assert_return (invoke "f64.copysign" (f64.const 0) (f64.const -1)) (f64.const -0))
+
+;; Operations producing a new NaN produce the canonical one.
+(assert_return_canonical_nan (invoke "f32.add" (f32.const inf) (f32.const -inf)))
+(assert_return_canonical_nan (invoke "f32.sqrt" (f32.const -1)))
+(assert_return_canonical_nan (invoke "f64.mul" (f64.const 0) (f64.const inf)))
+
+;; Arithmetic on NaN operands keeps them quiet, but may change their payload.
+(assert_return_arithmetic_nan (invoke "f32.add" (f32.const nan:0x200000) (f32.const 1)))
+(assert_return_arithmetic_nan (invoke "f64.mul" (f64.const nan:0x4000000000000) (f64.const 1)))
+
+;; Sign operations and reinterpretations keep the exact bits, even of signalling NaNs.
+(assert_return (invoke "f32.neg" (f32.const nan:0x200000)) (f32.const -nan:0x200000))
+(assert_return (invoke "f64.copysign" (f64.const -nan:0x1) (f64.const 1)) (f64.const nan:0x1))
+(assert_return
+  (invoke "f64.reinterpret" (i64.const 0x7ff0000000000001))
+  (f64.const nan:0x1))
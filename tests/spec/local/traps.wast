@@ -0,0 +1,56 @@
+;; Every trap is mapped to the `TrapKind` the reference interpreter's message stands for.
+
+(module
+  (type $i32->i32 (func (param i32) (result i32)))
+  (memory 1)
+  (table 3 anyfunc)
+  (elem (i32.const 0) $id $nullary)
+
+  (func $id (type $i32->i32) (get_local 0))
+  (func $nullary (result i32) (i32.const 0))
+
+  (func (export "unreachable") (unreachable))
+  (func (export "div_s") (param i32 i32) (result i32)
+    (i32.div_s (get_local 0) (get_local 1)))
+  (func (export "rem_u") (param i64 i64) (result i64)
+    (i64.rem_u (get_local 0) (get_local 1)))
+  (func (export "trunc_f32_s") (param f32) (result i32)
+    (i32.trunc_s/f32 (get_local 0)))
+  (func (export "trunc_f64_u") (param f64) (result i64)
+    (i64.trunc_u/f64 (get_local 0)))
+  (func (export "load") (param i32) (result i32)
+    (i32.load (get_local 0)))
+  (func (export "store") (param i32)
+    (i64.store (get_local 0) (i64.const 0)))
+  (func (export "call_indirect") (param i32) (result i32)
+    (call_indirect (type $i32->i32) (i32.const 7) (get_local 0)))
+  (func $runaway (export "runaway") (call $runaway))
+)
+
+(assert_trap (invoke "unreachable") "unreachable")
+
+(assert_return (invoke "div_s" (i32.const 7) (i32.const -2)) (i32.const -3))
+(assert_trap (invoke "div_s" (i32.const 1) (i32.const 0)) "integer divide by zero")
+(assert_trap (invoke "div_s" (i32.const 0x80000000) (i32.const -1)) "integer overflow")
+(assert_trap (invoke "rem_u" (i64.const 1) (i64.const 0)) "integer divide by zero")
+
+(assert_return (invoke "trunc_f32_s" (f32.const -1.5)) (i32.const -1))
+(assert_trap (invoke "trunc_f32_s" (f32.const 2147483648)) "integer overflow")
+(assert_trap (invoke "trunc_f32_s" (f32.const nan)) "invalid conversion to integer")
+(assert_trap (invoke "trunc_f64_u" (f64.const -1)) "integer overflow")
+(assert_trap (invoke "trunc_f64_u" (f64.const -nan)) "invalid conversion to integer")
+
+(assert_return (invoke "load" (i32.const 65532)) (i32.const 0))
+(assert_trap (invoke "load" (i32.const 65533)) "out of bounds memory access")
+(assert_trap (invoke "load" (i32.const -1)) "out of bounds memory access")
+(assert_trap (invoke "store" (i32.const 65529)) "out of bounds memory access")
+
+(assert_return (invoke "call_indirect" (i32.const 0)) (i32.const 7))
+(assert_trap (invoke "call_indirect" (i32.const 1)) "indirect call type mismatch")
+(assert_trap (invoke "call_indirect" (i32.const 2)) "uninitialized element")
+(assert_trap (invoke "call_indirect" (i32.const 3)) "undefined element")
+
+(assert_exhaustion (invoke "runaway") "call stack exhausted")
+
+;; Traps in the start function fail the instantiation.
+(assert_trap (module (func $start (unreachable)) (start $start)) "unreachable")
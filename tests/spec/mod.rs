@@ -9,6 +9,21 @@ macro_rules! run_test {
     };
 }
 
+// Scripts checked in under `tests/spec/local`, runnable without the testsuite submodule.
+// To add a script, put it into that directory and list it here.
+macro_rules! run_local_test {
+    ($label: expr, $test_name: ident) => {
+        #[test]
+        fn $test_name() {
+            self::run::local_spec($label)
+        }
+    };
+}
+
+run_local_test!("modules", local_modules);
+run_local_test!("nan", local_nan);
+run_local_test!("traps", local_traps);
+
 run_test!("address", wasm_address);
 run_test!("align", wasm_align);
 run_test!("binary", wasm_binary);
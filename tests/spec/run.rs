@@ -7,9 +7,9 @@ use wabt::script::{self, Action, Command, CommandKind, ScriptParser, Value};
 use wasmi::memory_units::Pages;
 use wasmi::{
     Error as InterpreterError, Externals, FuncInstance, FuncRef, GlobalDescriptor, GlobalInstance,
-    GlobalRef, ImportResolver, ImportsBuilder, MemoryDescriptor, MemoryInstance, MemoryRef, Module,
-    ModuleImportResolver, ModuleInstance, ModuleRef, RuntimeArgs, RuntimeValue, Signature,
-    TableDescriptor, TableInstance, TableRef, Trap,
+    GlobalRef, ImportResolver, ImportsBuilder, InstantiationError, MemoryDescriptor,
+    MemoryInstance, MemoryRef, Module, ModuleImportResolver, ModuleInstance, ModuleRef,
+    RuntimeArgs, RuntimeValue, Signature, TableDescriptor, TableInstance, TableRef, Trap, TrapKind,
 };
 
 fn spec_to_runtime_value(val: Value<u32, u64>) -> RuntimeValue {
@@ -94,17 +94,16 @@ impl ModuleImportResolver for SpecModule {
             "print_f32" => PRINT_FUNC_INDEX,
             "print_f64" => PRINT_FUNC_INDEX,
             _ => {
-                return Err(InterpreterError::Instantiation(format!(
-                    "Unknown host func import {}",
-                    field_name
+                return Err(InterpreterError::Instantiation(InstantiationError::Other(
+                    format!("Unknown host func import {}", field_name),
                 )));
             }
         };
 
         if func_type.return_type().is_some() {
-            return Err(InterpreterError::Instantiation(
+            return Err(InterpreterError::Instantiation(InstantiationError::Other(
                 "Function `print_` have unit return type".into(),
-            ));
+            )));
         }
 
         let func = FuncInstance::alloc_host(func_type.clone(), index);
@@ -120,9 +119,8 @@ impl ModuleImportResolver for SpecModule {
             "global_i32" => Ok(self.global_i32.clone()),
             "global_f32" => Ok(self.global_f32.clone()),
             "global_f64" => Ok(self.global_f64.clone()),
-            _ => Err(InterpreterError::Instantiation(format!(
-                "Unknown host global import {}",
-                field_name
+            _ => Err(InterpreterError::Instantiation(InstantiationError::Other(
+                format!("Unknown host global import {}", field_name),
             ))),
         }
     }
@@ -136,9 +134,8 @@ impl ModuleImportResolver for SpecModule {
             return Ok(self.memory.clone());
         }
 
-        Err(InterpreterError::Instantiation(format!(
-            "Unknown host memory import {}",
-            field_name
+        Err(InterpreterError::Instantiation(InstantiationError::Other(
+            format!("Unknown host memory import {}", field_name),
         )))
     }
 
@@ -151,9 +148,8 @@ impl ModuleImportResolver for SpecModule {
             return Ok(self.table.clone());
         }
 
-        Err(InterpreterError::Instantiation(format!(
-            "Unknown host table import {}",
-            field_name
+        Err(InterpreterError::Instantiation(InstantiationError::Other(
+            format!("Unknown host table import {}", field_name),
         )))
     }
 }
@@ -186,17 +182,21 @@ impl SpecDriver {
 
     fn module(&self, name: &str) -> Result<ModuleRef, InterpreterError> {
         self.instances.get(name).cloned().ok_or_else(|| {
-            InterpreterError::Instantiation(format!("Module not registered {}", name))
+            InterpreterError::Instantiation(InstantiationError::Other(format!(
+                "Module not registered {}",
+                name
+            )))
         })
     }
 
     fn module_or_last(&self, name: Option<&str>) -> Result<ModuleRef, InterpreterError> {
         match name {
             Some(name) => self.module(name),
-            None => self
-                .last_module
-                .clone()
-                .ok_or_else(|| InterpreterError::Instantiation("No modules registered".into())),
+            None => self.last_module.clone().ok_or_else(|| {
+                InterpreterError::Instantiation(InstantiationError::Other(
+                    "No modules registered".into(),
+                ))
+            }),
         }
     }
 }
@@ -334,162 +334,223 @@ fn run_action(
 
 pub fn spec(name: &str) {
     println!("running test: {}", name);
-    try_spec(name).expect("Failed to run spec");
+    try_spec(name, &format!("tests/spec/testsuite/{}.wast", name)).expect("Failed to run spec");
 }
 
-fn try_spec(name: &str) -> Result<(), Error> {
+/// Runs a script of the curated subset checked in under `tests/spec/local`.
+pub fn local_spec(name: &str) {
+    println!("running local test: {}", name);
+    try_spec(name, &format!("tests/spec/local/{}.wast", name)).expect("Failed to run spec");
+}
+
+/// Returns whether `kind` is the trap the reference interpreter reports with `message`.
+///
+/// Messages that aren't known here are accepted for any trap.
+fn trap_kind_matches(message: &str, kind: &TrapKind) -> bool {
+    if message.starts_with("unreachable") {
+        matches!(kind, TrapKind::Unreachable)
+    } else if message.starts_with("integer divide by zero") {
+        matches!(kind, TrapKind::DivisionByZero)
+    } else if message.starts_with("integer overflow")
+        || message.starts_with("invalid conversion to integer")
+    {
+        matches!(kind, TrapKind::InvalidConversionToInt)
+    } else if message.starts_with("out of bounds memory access") {
+        matches!(kind, TrapKind::MemoryAccessOutOfBounds)
+    } else if message.starts_with("undefined") {
+        matches!(kind, TrapKind::TableAccessOutOfBounds)
+    } else if message.starts_with("uninitialized") {
+        matches!(kind, TrapKind::ElemUninitialized)
+    } else if message.starts_with("indirect call") {
+        matches!(kind, TrapKind::UnexpectedSignature)
+    } else if message.starts_with("call stack exhausted") {
+        matches!(kind, TrapKind::StackOverflow { .. })
+    } else {
+        true
+    }
+}
+
+/// The NaN patterns results can be compared against.
+#[derive(Debug, Clone, Copy)]
+enum NanPattern {
+    /// Only the most significant bit of the payload is set.
+    Canonical,
+    /// At least the most significant bit of the payload is set.
+    Arithmetic,
+}
+
+fn check_nan(pattern: NanPattern, actual: &RuntimeValue) -> Result<(), String> {
+    let (payload, quiet_bit) = match *actual {
+        RuntimeValue::F32(val) if val.is_nan() => {
+            (u64::from(val.to_bits() & 0x007f_ffff), 0x0040_0000)
+        }
+        RuntimeValue::F64(val) if val.is_nan() => {
+            (val.to_bits() & 0x000f_ffff_ffff_ffff, 0x0008_0000_0000_0000)
+        }
+        ref val => return Err(format!("expected {:?} nan, got {:?}", pattern, val)),
+    };
+    let matches = match pattern {
+        NanPattern::Canonical => payload == quiet_bit,
+        NanPattern::Arithmetic => payload & quiet_bit != 0,
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(format!(
+            "expected {:?} nan, got nan with payload {:#x}",
+            pattern, payload
+        ))
+    }
+}
+
+fn check_return(
+    actual: Option<RuntimeValue>,
+    expected: Vec<Value<u32, u64>>,
+) -> Result<(), String> {
+    let actual = actual.into_iter().collect::<Vec<_>>();
+    let expected = expected
+        .into_iter()
+        .map(spec_to_runtime_value)
+        .collect::<Vec<_>>();
+    if actual.len() != expected.len() {
+        return Err(format!("expected {:?}, got {:?}", expected, actual));
+    }
+    for (actual, expected) in actual.iter().zip(expected.iter()) {
+        // Floats are compared bitwise, which distinguishes the sign of zeros and NaN payloads.
+        let equal = match (*actual, *expected) {
+            (RuntimeValue::F32(actual), RuntimeValue::F32(expected)) => {
+                actual.to_bits() == expected.to_bits()
+            }
+            (RuntimeValue::F64(actual), RuntimeValue::F64(expected)) => {
+                actual.to_bits() == expected.to_bits()
+            }
+            (actual, expected) => actual == expected,
+        };
+        if !equal {
+            return Err(format!("expected {:?}, got {:?}", expected, actual));
+        }
+    }
+    Ok(())
+}
+
+/// Runs the command of a spec script and returns whether its assertion holds.
+fn run_command(
+    spec_driver: &mut SpecDriver,
+    kind: CommandKind<u32, u64>,
+    line: u64,
+) -> Result<(), String> {
+    match kind {
+        CommandKind::Module { name, module, .. } => {
+            load_module(&module.into_vec(), &name, spec_driver)
+                .map(|_| ())
+                .map_err(|e| format!("failed to load module: {:?}", e))
+        }
+        CommandKind::AssertReturn { action, expected } => match run_action(spec_driver, &action) {
+            Ok(result) => check_return(result, expected),
+            Err(e) => Err(format!(
+                "expected action to return value, got error: {:?}",
+                e
+            )),
+        },
+        CommandKind::AssertReturnCanonicalNan { action } => {
+            match run_action(spec_driver, &action) {
+                Ok(Some(result)) => check_nan(NanPattern::Canonical, &result),
+                Ok(None) => Err("expected canonical nan, got no value".to_string()),
+                Err(e) => Err(format!(
+                    "expected action to return value, got error: {:?}",
+                    e
+                )),
+            }
+        }
+        CommandKind::AssertReturnArithmeticNan { action } => {
+            match run_action(spec_driver, &action) {
+                Ok(Some(result)) => check_nan(NanPattern::Arithmetic, &result),
+                Ok(None) => Err("expected arithmetic nan, got no value".to_string()),
+                Err(e) => Err(format!(
+                    "expected action to return value, got error: {:?}",
+                    e
+                )),
+            }
+        }
+        CommandKind::AssertExhaustion { action, .. } => match run_action(spec_driver, &action) {
+            Ok(result) => Err(format!("expected exhaustion, got result: {:?}", result)),
+            Err(_e) => Ok(()),
+        },
+        CommandKind::AssertTrap { action, message } => match run_action(spec_driver, &action) {
+            Ok(result) => Err(format!(
+                "expected action to result in a trap, got result: {:?}",
+                result
+            )),
+            Err(InterpreterError::Trap(trap)) => {
+                if trap_kind_matches(&message, trap.kind()) {
+                    Ok(())
+                } else {
+                    Err(format!("expected trap \"{}\", got \"{}\"", message, trap))
+                }
+            }
+            Err(e) => Err(format!("expected trap \"{}\", got error: {:?}", message, e)),
+        },
+        CommandKind::AssertInvalid { module, .. }
+        | CommandKind::AssertMalformed { module, .. }
+        | CommandKind::AssertUnlinkable { module, .. } => {
+            match try_load(&module.into_vec(), spec_driver) {
+                Ok(_) => Err("expected invalid module definition, got some module".to_string()),
+                Err(_e) => Ok(()),
+            }
+        }
+        CommandKind::AssertUninstantiable { module, .. } => {
+            match try_load(&module.into_vec(), spec_driver) {
+                Ok(_) => Err("expected error running start function".to_string()),
+                Err(_e) => Ok(()),
+            }
+        }
+        CommandKind::Register { name, as_name, .. } => {
+            let module = spec_driver
+                .module_or_last(name.as_ref().map(|x| x.as_ref()))
+                .map_err(|e| format!("no such module: {:?}", e))?;
+            spec_driver.add_module(Some(as_name), module);
+            Ok(())
+        }
+        CommandKind::PerformAction(action) => run_action(spec_driver, &action)
+            .map(|_| ())
+            .map_err(|e| format!("failed to invoke action at line {}: {:?}", line, e)),
+    }
+}
+
+fn try_spec(name: &str, spec_script_path: &str) -> Result<(), Error> {
     let mut spec_driver = SpecDriver::new();
-    let spec_script_path = format!("tests/spec/testsuite/{}.wast", name);
 
     use std::io::Read;
     let mut spec_source = Vec::new();
-    let mut spec_file = File::open(&spec_script_path).expect("Can't open file");
+    let mut spec_file = File::open(spec_script_path).expect("Can't open file");
     spec_file
         .read_to_end(&mut spec_source)
         .expect("Can't read file");
 
     let mut parser = ScriptParser::from_source_and_name(&spec_source, &format!("{}.wast", name))
         .expect("Can't read spec script");
+    let mut passed = 0;
     let mut errors = vec![];
 
     while let Some(Command { kind, line }) = parser.next()? {
-        macro_rules! assert_eq {
-            ($a:expr, $b:expr) => {{
-                let (a, b) = ($a, $b);
-
-                if a != b {
-                    errors.push(format!(
-                        r#"ERROR (line {}):
-  expected: {:?}
-  got:      {:?}
-"#,
-                        line, b, a,
-                    ));
-                }
-            }};
-        }
-
         println!("Running spec cmd {}: {:?}", line, kind);
 
-        match kind {
-            CommandKind::Module { name, module, .. } => {
-                load_module(&module.into_vec(), &name, &mut spec_driver)
-                    .expect("Failed to load module");
-            }
-            CommandKind::AssertReturn { action, expected } => {
-                let result = run_action(&mut spec_driver, &action);
-                match result {
-                    Ok(result) => {
-                        let spec_expected = expected
-                            .iter()
-                            .cloned()
-                            .map(spec_to_runtime_value)
-                            .collect::<Vec<_>>();
-                        let actual_result = result.into_iter().collect::<Vec<RuntimeValue>>();
-                        for (actual_result, spec_expected) in
-                            actual_result.iter().zip(spec_expected.iter())
-                        {
-                            assert_eq!(actual_result.value_type(), spec_expected.value_type());
-                            // f32::NAN != f32::NAN
-                            match spec_expected {
-                                RuntimeValue::F32(val) if val.is_nan() => match actual_result {
-                                    RuntimeValue::F32(val) => assert!(val.is_nan()),
-                                    _ => unreachable!(), // checked above that types are same
-                                },
-                                RuntimeValue::F64(val) if val.is_nan() => match actual_result {
-                                    RuntimeValue::F64(val) => assert!(val.is_nan()),
-                                    _ => unreachable!(), // checked above that types are same
-                                },
-                                spec_expected => assert_eq!(actual_result, spec_expected),
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        panic!("Expected action to return value, got error: {:?}", e);
-                    }
-                }
-            }
-            CommandKind::AssertReturnCanonicalNan { action }
-            | CommandKind::AssertReturnArithmeticNan { action } => {
-                let result = run_action(&mut spec_driver, &action);
-                match result {
-                    Ok(result) => {
-                        for actual_result in result.into_iter().collect::<Vec<RuntimeValue>>() {
-                            match actual_result {
-                                RuntimeValue::F32(val) => {
-                                    if !val.is_nan() {
-                                        panic!("Expected nan value, got {:?}", val)
-                                    }
-                                }
-                                RuntimeValue::F64(val) => {
-                                    if !val.is_nan() {
-                                        panic!("Expected nan value, got {:?}", val)
-                                    }
-                                }
-                                val => {
-                                    panic!("Expected action to return float value, got {:?}", val)
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        panic!("Expected action to return value, got error: {:?}", e);
-                    }
-                }
-            }
-            CommandKind::AssertExhaustion { action, .. } => {
-                let result = run_action(&mut spec_driver, &action);
-                match result {
-                    Ok(result) => panic!("Expected exhaustion, got result: {:?}", result),
-                    Err(_e) => {}
-                }
-            }
-            CommandKind::AssertTrap { action, .. } => {
-                let result = run_action(&mut spec_driver, &action);
-                match result {
-                    Ok(result) => {
-                        panic!(
-                            "Expected action to result in a trap, got result: {:?}",
-                            result
-                        );
-                    }
-                    Err(_e) => {}
-                }
-            }
-            CommandKind::AssertInvalid { module, .. }
-            | CommandKind::AssertMalformed { module, .. }
-            | CommandKind::AssertUnlinkable { module, .. } => {
-                let module_load = try_load(&module.into_vec(), &mut spec_driver);
-                match module_load {
-                    Ok(_) => panic!("Expected invalid module definition, got some module!"),
-                    Err(_e) => {}
-                }
-            }
-            CommandKind::AssertUninstantiable { module, .. } => {
-                match try_load(&module.into_vec(), &mut spec_driver) {
-                    Ok(_) => panic!("Expected error running start function at line {}", line),
-                    Err(_e) => {}
-                }
-            }
-            CommandKind::Register { name, as_name, .. } => {
-                let module = match spec_driver.module_or_last(name.as_ref().map(|x| x.as_ref())) {
-                    Ok(module) => module,
-                    Err(e) => panic!("No such module, at line {} - ({:?})", e, line),
-                };
-                spec_driver.add_module(Some(as_name.clone()), module);
-            }
-            CommandKind::PerformAction(action) => match run_action(&mut spec_driver, &action) {
-                Ok(_) => {}
-                Err(e) => panic!("Failed to invoke action at line {}: {:?}", line, e),
-            },
+        match run_command(&mut spec_driver, kind, line) {
+            Ok(()) => passed += 1,
+            Err(err) => errors.push(format!("ERROR (line {}): {}\n", line, err)),
         }
     }
 
+    println!(
+        "{}: {} assertions passed, {} failed",
+        name,
+        passed,
+        errors.len()
+    );
     if !errors.is_empty() {
-        use std::fmt::Write;
         let mut out = "\n".to_owned();
         for err in errors {
-            write!(out, "{}", err).expect("Error formatting errors");
+            out.push_str(&err);
         }
         panic!("{}", out);
     }
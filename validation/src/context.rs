@@ -11,6 +11,7 @@ pub struct ModuleContext {
     pub globals: Vec<GlobalType>,
     pub types: Vec<FunctionType>,
     pub func_type_indexes: Vec<u32>,
+    pub elem_segments_len: u32,
 }
 
 impl ModuleContext {
@@ -34,6 +35,10 @@ impl ModuleContext {
         &self.func_type_indexes
     }
 
+    pub fn elem_segments_len(&self) -> u32 {
+        self.elem_segments_len
+    }
+
     pub fn require_memory(&self, idx: u32) -> Result<(), Error> {
         if self.memories().get(idx as usize).is_none() {
             return Err(Error(format!("Memory at index {} doesn't exists", idx)));
@@ -47,6 +52,16 @@ impl ModuleContext {
             .ok_or_else(|| Error(format!("Table at index {} doesn't exists", idx)))
     }
 
+    pub fn require_elem_segment(&self, idx: u32) -> Result<(), Error> {
+        if idx >= self.elem_segments_len() {
+            return Err(Error(format!(
+                "Element segment at index {} doesn't exists",
+                idx
+            )));
+        }
+        Ok(())
+    }
+
     pub fn require_function(&self, idx: u32) -> Result<(&[ValueType], BlockType), Error> {
         let ty_idx = self
             .func_type_indexes()
@@ -95,6 +110,7 @@ pub struct ModuleContextBuilder {
     globals: Vec<GlobalType>,
     types: Vec<FunctionType>,
     func_type_indexes: Vec<u32>,
+    elem_segments_len: u32,
 }
 
 impl ModuleContextBuilder {
@@ -122,6 +138,10 @@ impl ModuleContextBuilder {
         self.func_type_indexes.push(func_type_index);
     }
 
+    pub fn set_elem_segments_len(&mut self, elem_segments_len: u32) {
+        self.elem_segments_len = elem_segments_len;
+    }
+
     pub fn build(self) -> ModuleContext {
         let ModuleContextBuilder {
             memories,
@@ -129,6 +149,7 @@ impl ModuleContextBuilder {
             globals,
             types,
             func_type_indexes,
+            elem_segments_len,
         } = self;
 
         ModuleContext {
@@ -137,6 +158,7 @@ impl ModuleContextBuilder {
             globals,
             types,
             func_type_indexes,
+            elem_segments_len,
         }
     }
 }
@@ -4,12 +4,14 @@ use crate::{
 };
 
 use core::u32;
-use parity_wasm::elements::{BlockType, Func, FuncBody, Instruction, TableElementType, ValueType};
+#[cfg(feature = "atomics")]
+use parity_wasm::elements::AtomicsInstruction;
+use parity_wasm::elements::{
+    BlockType, BulkInstruction, Func, FuncBody, Instruction, TableElementType, ValueType,
+};
 
 /// Maximum number of entries in value stack per function.
 const DEFAULT_VALUE_STACK_LIMIT: usize = 16384;
-/// Maximum number of entries in frame stack per function.
-const DEFAULT_FRAME_STACK_LIMIT: usize = 16384;
 
 /// Control stack frame.
 #[derive(Debug, Clone)]
@@ -86,6 +88,7 @@ pub fn drive<T: FuncValidator>(
     module: &ModuleContext,
     func: &Func,
     body: &FuncBody,
+    max_nesting_depth: usize,
 ) -> Result<T::Output, Error> {
     let (params, result_ty) = module.require_function_type(func.type_ref())?;
 
@@ -99,7 +102,8 @@ pub fn drive<T: FuncValidator>(
         module,
         Locals::new(params, body.locals())?,
         DEFAULT_VALUE_STACK_LIMIT,
-        DEFAULT_FRAME_STACK_LIMIT,
+        // One extra frame for the implicit block of the function body.
+        max_nesting_depth.saturating_add(1),
         result_ty,
     )?;
 
@@ -778,6 +782,27 @@ impl<'a> FunctionValidationContext<'a> {
             F64ReinterpretI64 => {
                 self.validate_cvtop(ValueType::I64, ValueType::F64)?;
             }
+
+            Bulk(BulkInstruction::MemoryCopy) => {
+                self.validate_memory_copy()?;
+            }
+            Bulk(BulkInstruction::TableInit(segment_idx)) => {
+                self.validate_table_init(segment_idx)?;
+            }
+            Bulk(BulkInstruction::TableDrop(segment_idx)) => {
+                self.module.require_elem_segment(segment_idx)?;
+            }
+            Bulk(ref instruction) => {
+                return Err(Error(format!(
+                    "bulk memory instruction {:?} is not supported",
+                    instruction
+                )));
+            }
+
+            #[cfg(feature = "atomics")]
+            Atomics(ref instruction) => {
+                self.validate_atomic(instruction)?;
+            }
         }
 
         Ok(())
@@ -1036,6 +1061,70 @@ impl<'a> FunctionValidationContext<'a> {
         Ok(())
     }
 
+    fn validate_memory_copy(&mut self) -> Result<(), Error> {
+        self.module.require_memory(DEFAULT_MEMORY_INDEX)?;
+        for _ in 0..3 {
+            pop_value(
+                &mut self.value_stack,
+                &self.frame_stack,
+                ValueType::I32.into(),
+            )?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "atomics")]
+    fn validate_atomic(&mut self, instruction: &AtomicsInstruction) -> Result<(), Error> {
+        let (memarg, size, operands): (_, u32, &[ValueType]) = match *instruction {
+            AtomicsInstruction::AtomicWake(ref memarg) => {
+                (memarg, 4, &[ValueType::I32, ValueType::I32])
+            }
+            AtomicsInstruction::I32AtomicWait(ref memarg) => {
+                (memarg, 4, &[ValueType::I32, ValueType::I32, ValueType::I64])
+            }
+            AtomicsInstruction::I64AtomicWait(ref memarg) => {
+                (memarg, 8, &[ValueType::I32, ValueType::I64, ValueType::I64])
+            }
+            _ => {
+                return Err(Error(format!(
+                    "atomic instruction {:?} is not supported",
+                    instruction
+                )));
+            }
+        };
+        // Unlike other memory accesses, atomic ones must declare their natural alignment.
+        if 1u32.checked_shl(memarg.align.into()) != Some(size) {
+            return Err(Error(format!(
+                "Atomic memory alignment must be {}, got 2^{}",
+                size, memarg.align
+            )));
+        }
+
+        self.module.require_memory(DEFAULT_MEMORY_INDEX)?;
+        for value_type in operands.iter().rev() {
+            pop_value(
+                &mut self.value_stack,
+                &self.frame_stack,
+                (*value_type).into(),
+            )?;
+        }
+        push_value(&mut self.value_stack, ValueType::I32.into())?;
+        Ok(())
+    }
+
+    fn validate_table_init(&mut self, segment_idx: u32) -> Result<(), Error> {
+        self.module.require_table(DEFAULT_TABLE_INDEX)?;
+        self.module.require_elem_segment(segment_idx)?;
+        for _ in 0..3 {
+            pop_value(
+                &mut self.value_stack,
+                &self.frame_stack,
+                ValueType::I32.into(),
+            )?;
+        }
+        Ok(())
+    }
+
     fn validate_call_indirect(&mut self, idx: u32) -> Result<(), Error> {
         {
             let table = self.module.require_table(DEFAULT_TABLE_INDEX)?;
@@ -1153,6 +1242,12 @@ fn push_label(
     value_stack: &StackWithLimit<StackValueType>,
     frame_stack: &mut StackWithLimit<BlockFrame>,
 ) -> Result<(), Error> {
+    if frame_stack.len() >= frame_stack.limit() {
+        return Err(Error(format!(
+            "control structures nested deeper than the limit of {}",
+            frame_stack.limit() - 1
+        )));
+    }
     Ok(frame_stack.push(BlockFrame {
         started_with,
         block_type,
@@ -19,21 +19,28 @@ pub const DEFAULT_TABLE_INDEX: u32 = 0;
 /// Maximal number of pages that a wasm instance supports.
 pub const LINEAR_MEMORY_MAX_PAGES: u32 = 65536;
 
+/// Default maximal nesting depth of `block`, `loop` and `if` structures within a function.
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 16383;
+
 use alloc::{string::String, vec::Vec};
 use core::fmt;
 #[cfg(feature = "std")]
 use std::error;
 
-use self::context::ModuleContextBuilder;
+use self::context::{ModuleContext, ModuleContextBuilder};
 use parity_wasm::elements::{
-    BlockType, ExportEntry, External, FuncBody, GlobalEntry, GlobalType, InitExpr, Instruction,
-    Internal, MemoryType, Module, ResizableLimits, TableType, Type, ValueType,
+    BlockType, ExportEntry, External, FuncBody, GlobalEntry, GlobalType, ImportEntry, InitExpr,
+    Instruction, Internal, MemoryType, Module, ResizableLimits, Section, TableType, Type,
+    ValueType,
 };
 
 pub mod context;
 pub mod func;
+mod segments;
 pub mod util;
 
+pub use self::segments::{data_segments, elem_segments, DataSegment, ElemSegment, SegmentOffset};
+
 #[cfg(test)]
 mod tests;
 
@@ -65,6 +72,13 @@ pub trait Validator {
     type Output;
     type FuncValidator: FuncValidator;
     fn new(module: &Module) -> Self;
+    /// Returns the maximal nesting depth of control structures within a function body.
+    ///
+    /// Functions nesting `block`, `loop` and `if` structures any deeper are rejected, which
+    /// bounds the work needed to validate and compile adversarial modules.
+    fn max_nesting_depth(&self) -> usize {
+        DEFAULT_MAX_NESTING_DEPTH
+    }
     fn on_function_validated(
         &mut self,
         index: u32,
@@ -123,10 +137,234 @@ impl FuncValidator for PlainFuncValidator {
     fn finish(self) {}
 }
 
-pub fn validate_module<V: Validator>(module: &Module) -> Result<V::Output, Error> {
+/// The number of imports of each kind a module requires.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImportCounts {
+    /// The number of imported functions.
+    pub functions: u32,
+    /// The number of imported tables.
+    pub tables: u32,
+    /// The number of imported linear memories.
+    pub memories: u32,
+    /// The number of imported globals.
+    pub globals: u32,
+}
+
+impl ImportCounts {
+    /// Counts the imports of the given module by kind.
+    pub fn of(module: &Module) -> ImportCounts {
+        let mut counts = ImportCounts::default();
+        let entries = module
+            .import_section()
+            .map(|is| is.entries())
+            .unwrap_or_default();
+        for entry in entries {
+            match entry.external() {
+                External::Function(_) => counts.functions += 1,
+                External::Table(_) => counts.tables += 1,
+                External::Memory(_) => counts.memories += 1,
+                External::Global(_) => counts.globals += 1,
+            }
+        }
+        counts
+    }
+}
+
+/// A module that passed validation, together with the output of the [`Validator`]
+/// that checked it.
+///
+/// For a compiling validator the output is typically the code of each function
+/// body in the internal representation, indexed the same way as the code section.
+///
+/// [`Validator`]: trait.Validator.html
+#[derive(Debug, Clone)]
+pub struct ValidatedModule<T> {
+    module: Module,
+    code_map: T,
+}
+
+impl<T> ValidatedModule<T> {
+    /// Returns the validated module.
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    /// Returns the output produced by the validator for the function bodies.
+    pub fn code_map(&self) -> &T {
+        &self.code_map
+    }
+
+    /// Returns the types declared in the type section.
+    pub fn types(&self) -> &[Type] {
+        self.module
+            .type_section()
+            .map(|ts| ts.types())
+            .unwrap_or_default()
+    }
+
+    /// Returns the entries of the import section.
+    pub fn imports(&self) -> &[ImportEntry] {
+        self.module
+            .import_section()
+            .map(|is| is.entries())
+            .unwrap_or_default()
+    }
+
+    /// Returns the entries of the export section.
+    pub fn exports(&self) -> &[ExportEntry] {
+        self.module
+            .export_section()
+            .map(|es| es.entries())
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of imports of each kind.
+    pub fn import_counts(&self) -> ImportCounts {
+        ImportCounts::of(&self.module)
+    }
+
+    /// Returns `true` if the module imports a linear memory.
+    pub fn requires_memory_import(&self) -> bool {
+        self.import_counts().memories > 0
+    }
+
+    /// Returns `true` if the module imports a table.
+    pub fn requires_table_import(&self) -> bool {
+        self.import_counts().tables > 0
+    }
+
+    /// Returns the minimum number of pages of the linear memory defined by the module.
+    ///
+    /// Returns `None` if the module imports its memory or has none. This allows to
+    /// pre-allocate the memory, e.g. from a pool, before instantiating the module.
+    pub fn initial_memory_pages(&self) -> Option<u32> {
+        if self.requires_memory_import() {
+            return None;
+        }
+        self.module
+            .memory_section()
+            .and_then(|ms| ms.entries().first())
+            .map(|memory| memory.limits().initial())
+    }
+
+    /// Returns the minimum number of elements of the table defined by the module.
+    ///
+    /// Returns `None` if the module imports its table or has none.
+    pub fn initial_table_size(&self) -> Option<u32> {
+        if self.requires_table_import() {
+            return None;
+        }
+        self.module
+            .table_section()
+            .and_then(|ts| ts.entries().first())
+            .map(|table| table.limits().initial())
+    }
+
+    /// Returns the data segments of the module with their offsets evaluated where
+    /// they don't depend on imports.
+    pub fn data_segments(&self) -> impl Iterator<Item = DataSegment<'_>> + '_ {
+        data_segments(&self.module)
+    }
+
+    /// Returns the element segments of the module with their offsets evaluated where
+    /// they don't depend on imports.
+    pub fn elem_segments(&self) -> impl Iterator<Item = ElemSegment<'_>> + '_ {
+        elem_segments(&self.module)
+    }
+
+    /// Returns a hash of the semantically relevant content of the module.
+    ///
+    /// The hash covers all sections except custom sections (including the `name` and
+    /// `reloc` sections), so modules that differ only in debug info or other metadata
+    /// hash equally. The validator output isn't hashed separately since it is derived
+    /// from the function bodies, which are part of the hash.
+    ///
+    /// Sections are hashed in their canonical binary encoding, so modules that only
+    /// differ in how they were encoded, e.g. in padded LEB128 integers, hash equally too.
+    ///
+    /// The hash is the 64-bit FNV-1a hash of that encoding. It doesn't depend on the
+    /// platform or the process and only changes with a breaking release of this crate,
+    /// so it may be persisted. It is not a cryptographic hash and must not be relied
+    /// upon for integrity against deliberate collisions.
+    pub fn content_hash(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let mut hash = FNV_OFFSET_BASIS;
+        for section in self.module.sections() {
+            match *section {
+                Section::Custom(_)
+                | Section::Name(_)
+                | Section::Reloc(_)
+                | Section::Unparsed { id: 0, .. } => continue,
+                _ => {}
+            }
+            let bytes = parity_wasm::serialize(section.clone())
+                .expect("sections of a deserialized module can be serialized; qed");
+            for byte in bytes {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        }
+        hash
+    }
+
+    /// Splits this into the validated module and the validator output.
+    pub fn into_parts(self) -> (Module, T) {
+        (self.module, self.code_map)
+    }
+}
+
+/// Validates an already deserialized `module` with the given [`Validator`].
+///
+/// Unlike [`validate_module`] this takes ownership of the module, so that it can be
+/// kept together with the validator output. This allows to validate modules that
+/// were built or transformed in memory, without serializing them first.
+///
+/// # Errors
+///
+/// Returns `Err` if the module is not valid.
+///
+/// # Examples
+///
+/// ```rust
+/// use parity_wasm::{builder, elements};
+/// use wasmi_validation::{validate, PlainValidator};
+///
+/// let module = builder::module()
+///     .function()
+///         .signature().with_param(elements::ValueType::I32).build()
+///         .body().build()
+///         .build()
+///     .export()
+///         .field("f")
+///         .internal().func(0)
+///         .build()
+///     .build();
+///
+/// let validated = validate::<PlainValidator>(module).expect("module is valid");
+/// assert_eq!(validated.types().len(), 1);
+/// assert_eq!(validated.imports().len(), 0);
+/// assert_eq!(validated.exports()[0].field(), "f");
+/// ```
+///
+/// [`Validator`]: trait.Validator.html
+/// [`validate_module`]: fn.validate_module.html
+pub fn validate<V: Validator>(module: Module) -> Result<ValidatedModule<V::Output>, Error> {
+    let code_map = validate_module::<V>(&module)?;
+    Ok(ValidatedModule { module, code_map })
+}
+
+/// Builds the context function bodies of `module` are validated in, i.e. the types of its
+/// functions, tables, memories and globals.
+///
+/// Only the declarations the context is built from are checked, e.g. limits of tables and
+/// memories and initializers of globals. Use [`validate_module`] to check the whole module.
+///
+/// [`validate_module`]: fn.validate_module.html
+pub fn module_context(module: &Module) -> Result<ModuleContext, Error> {
     let mut context_builder = ModuleContextBuilder::new();
     let mut imported_globals = Vec::new();
-    let mut validation = V::new(module);
 
     // Copy types from module as is.
     context_builder.set_types(
@@ -184,7 +422,28 @@ pub fn validate_module<V: Validator>(module: &Module) -> Result<V::Output, Error
         }
     }
 
-    let context = context_builder.build();
+    if let Some(element_section) = module.elements_section() {
+        context_builder.set_elem_segments_len(element_section.entries().len() as u32);
+    }
+
+    Ok(context_builder.build())
+}
+
+/// Validates `module` with the given [`Validator`] and returns its output.
+///
+/// All function bodies are checked in order of the code section, calling
+/// [`Validator::on_function_validated`] for each of them. The module itself is
+/// checked as well (e.g. its imports, exports, segments and limits).
+///
+/// # Errors
+///
+/// Returns `Err` if the module is not valid.
+///
+/// [`Validator`]: trait.Validator.html
+/// [`Validator::on_function_validated`]: trait.Validator.html#tymethod.on_function_validated
+pub fn validate_module<V: Validator>(module: &Module) -> Result<V::Output, Error> {
+    let mut validation = V::new(module);
+    let context = module_context(module)?;
 
     let function_section_len = module
         .function_section()
@@ -214,13 +473,18 @@ pub fn validate_module<V: Validator>(module: &Module) -> Result<V::Output, Error
                 .get(index as usize)
                 .ok_or_else(|| Error(format!("Missing body for function {}", index)))?;
 
-            let output = func::drive::<V::FuncValidator>(&context, function, function_body)
-                .map_err(|Error(ref msg)| {
-                    Error(format!(
-                        "Function #{} reading/validation error: {}",
-                        index, msg
-                    ))
-                })?;
+            let output = func::drive::<V::FuncValidator>(
+                &context,
+                function,
+                function_body,
+                validation.max_nesting_depth(),
+            )
+            .map_err(|Error(ref msg)| {
+                Error(format!(
+                    "Function #{} reading/validation error: {}",
+                    index, msg
+                ))
+            })?;
             validation.on_function_validated(index as u32, output);
         }
     }
@@ -321,14 +585,13 @@ pub fn validate_module<V: Validator>(module: &Module) -> Result<V::Output, Error
     // use element section to fill tables
     if let Some(element_section) = module.elements_section() {
         for element_segment in element_section.entries() {
-            context.require_table(element_segment.index())?;
-            let offset = element_segment
-                .offset()
-                .as_ref()
-                .ok_or_else(|| Error("passive element segments are not supported".into()))?;
-            let init_ty = expr_const_type(offset, context.globals())?;
-            if init_ty != ValueType::I32 {
-                return Err(Error("segment offset should return I32".into()));
+            // passive segments are not bound to a table until they are used by `table.init`
+            if let Some(offset) = element_segment.offset() {
+                context.require_table(element_segment.index())?;
+                let init_ty = expr_const_type(offset, context.globals())?;
+                if init_ty != ValueType::I32 {
+                    return Err(Error("segment offset should return I32".into()));
+                }
             }
 
             for function_index in element_segment.members() {
@@ -354,6 +617,12 @@ fn validate_limits(limits: &ResizableLimits) -> Result<(), Error> {
 }
 
 fn validate_memory_type(memory_type: &MemoryType) -> Result<(), Error> {
+    #[cfg(feature = "atomics")]
+    {
+        if memory_type.limits().shared() && memory_type.limits().maximum().is_none() {
+            return Err(Error("shared memory must have maximum".into()));
+        }
+    }
     let initial = memory_type.limits().initial();
     let maximum: Option<u32> = memory_type.limits().maximum();
     validate_memory(initial, maximum).map_err(Error)
@@ -385,6 +654,12 @@ pub fn validate_memory(initial: u32, maximum: Option<u32>) -> Result<(), String>
 }
 
 fn validate_table_type(table_type: &TableType) -> Result<(), Error> {
+    #[cfg(feature = "atomics")]
+    {
+        if table_type.limits().shared() {
+            return Err(Error("tables can't be shared".into()));
+        }
+    }
     validate_limits(table_type.limits())
 }
 
@@ -402,36 +677,80 @@ fn validate_global_entry(global_entry: &GlobalEntry, globals: &[GlobalType]) ->
 }
 
 /// Returns type of this constant expression.
+///
+/// Besides a single `*.const` or `get_global`, the expression might combine such operands
+/// with `add`, `sub` and `mul` on `i32` and `i64` as allowed by the extended-const proposal.
 fn expr_const_type(init_expr: &InitExpr, globals: &[GlobalType]) -> Result<ValueType, Error> {
     let code = init_expr.code();
-    if code.len() != 2 {
-        return Err(Error(
-            "Init expression should always be with length 2".into(),
-        ));
+    let (last, body) = match code.split_last() {
+        Some((last, body)) if !body.is_empty() => (last, body),
+        _ => return Err(Error("Init expression should not be empty".into())),
+    };
+    if *last != Instruction::End {
+        return Err(Error("Expression doesn't ends with `end` opcode".into()));
     }
-    let expr_ty: ValueType = match code[0] {
-        Instruction::I32Const(_) => ValueType::I32,
-        Instruction::I64Const(_) => ValueType::I64,
-        Instruction::F32Const(_) => ValueType::F32,
-        Instruction::F64Const(_) => ValueType::F64,
-        Instruction::GetGlobal(idx) => match globals.get(idx as usize) {
-            Some(target_global) => {
-                if target_global.is_mutable() {
-                    return Err(Error(format!("Global {} is mutable", idx)));
-                }
-                target_global.content_type()
+    let mut stack: Vec<ValueType> = Vec::new();
+    for instruction in body {
+        let binary_op_ty = match *instruction {
+            Instruction::I32Const(_) => {
+                stack.push(ValueType::I32);
+                continue;
             }
-            None => {
-                return Err(Error(format!(
-                    "Global {} doesn't exists or not yet defined",
-                    idx
-                )));
+            Instruction::I64Const(_) => {
+                stack.push(ValueType::I64);
+                continue;
             }
-        },
-        _ => return Err(Error("Non constant opcode in init expr".into())),
-    };
-    if code[1] != Instruction::End {
-        return Err(Error("Expression doesn't ends with `end` opcode".into()));
+            Instruction::F32Const(_) => {
+                stack.push(ValueType::F32);
+                continue;
+            }
+            Instruction::F64Const(_) => {
+                stack.push(ValueType::F64);
+                continue;
+            }
+            Instruction::GetGlobal(idx) => match globals.get(idx as usize) {
+                Some(target_global) => {
+                    if target_global.is_mutable() {
+                        return Err(Error(format!("Global {} is mutable", idx)));
+                    }
+                    stack.push(target_global.content_type());
+                    continue;
+                }
+                None => {
+                    return Err(Error(format!(
+                        "Global {} doesn't exists or not yet defined",
+                        idx
+                    )));
+                }
+            },
+            Instruction::I32Add | Instruction::I32Sub | Instruction::I32Mul => ValueType::I32,
+            Instruction::I64Add | Instruction::I64Sub | Instruction::I64Mul => ValueType::I64,
+            _ => return Err(Error("Non constant opcode in init expr".into())),
+        };
+        for _ in 0..2 {
+            match stack.pop() {
+                Some(operand_ty) if operand_ty == binary_op_ty => {}
+                Some(operand_ty) => {
+                    return Err(Error(format!(
+                        "Operand of type {:?} given to {:?} in init expr",
+                        operand_ty, instruction
+                    )))
+                }
+                None => {
+                    return Err(Error(format!(
+                        "Missing operand for {:?} in init expr",
+                        instruction
+                    )))
+                }
+            }
+        }
+        stack.push(binary_op_ty);
+    }
+    match stack[..] {
+        [expr_ty] => Ok(expr_ty),
+        _ => Err(Error(format!(
+            "Init expression should leave exactly one value, but leaves {}",
+            stack.len()
+        ))),
     }
-    Ok(expr_ty)
 }
@@ -0,0 +1,102 @@
+//! Inspection of the data and element segments of a module.
+
+use alloc::vec::Vec;
+use parity_wasm::elements::{InitExpr, Instruction, Module};
+
+/// Where an active segment is placed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SegmentOffset<'a> {
+    /// The offset is a constant expression and has been evaluated.
+    Const(u32),
+    /// The offset depends on the value of a global, which is only known once the module
+    /// is instantiated with its imports.
+    Unevaluated(&'a InitExpr),
+}
+
+impl<'a> SegmentOffset<'a> {
+    fn of(init_expr: &'a InitExpr) -> SegmentOffset<'a> {
+        match eval_const_offset(init_expr) {
+            Some(offset) => SegmentOffset::Const(offset),
+            None => SegmentOffset::Unevaluated(init_expr),
+        }
+    }
+}
+
+/// A data segment of a module.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DataSegment<'a> {
+    /// The index of the linear memory the segment is copied into.
+    pub memory_index: u32,
+    /// The offset at which the segment is copied into the memory during instantiation,
+    /// `None` for passive segments.
+    pub offset: Option<SegmentOffset<'a>>,
+    /// The bytes of the segment.
+    pub data: &'a [u8],
+}
+
+/// An element segment of a module.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ElemSegment<'a> {
+    /// The index of the table the segment is copied into.
+    pub table_index: u32,
+    /// The offset at which the segment is copied into the table during instantiation,
+    /// `None` for passive segments.
+    pub offset: Option<SegmentOffset<'a>>,
+    /// The indices of the functions of the segment.
+    pub members: &'a [u32],
+}
+
+/// Returns the data segments of `module` in the order they are declared.
+pub fn data_segments(module: &Module) -> impl Iterator<Item = DataSegment<'_>> + '_ {
+    module
+        .data_section()
+        .map(|ds| ds.entries())
+        .unwrap_or_default()
+        .iter()
+        .map(|segment| DataSegment {
+            memory_index: segment.index(),
+            offset: segment.offset().as_ref().map(SegmentOffset::of),
+            data: segment.value(),
+        })
+}
+
+/// Returns the element segments of `module` in the order they are declared.
+pub fn elem_segments(module: &Module) -> impl Iterator<Item = ElemSegment<'_>> + '_ {
+    module
+        .elements_section()
+        .map(|es| es.entries())
+        .unwrap_or_default()
+        .iter()
+        .map(|segment| ElemSegment {
+            table_index: segment.index(),
+            offset: segment.offset().as_ref().map(SegmentOffset::of),
+            members: segment.members(),
+        })
+}
+
+/// Evaluates an `i32` offset expression made of constants only.
+///
+/// The expression is expected to be validated already, anything unexpected yields `None`.
+fn eval_const_offset(init_expr: &InitExpr) -> Option<u32> {
+    let mut stack = Vec::new();
+    for instruction in init_expr.code() {
+        match *instruction {
+            Instruction::I32Const(value) => stack.push(value),
+            Instruction::I32Add | Instruction::I32Sub | Instruction::I32Mul => {
+                let rhs = stack.pop()?;
+                let lhs = stack.pop()?;
+                stack.push(match *instruction {
+                    Instruction::I32Add => lhs.wrapping_add(rhs),
+                    Instruction::I32Sub => lhs.wrapping_sub(rhs),
+                    _ => lhs.wrapping_mul(rhs),
+                });
+            }
+            Instruction::End => break,
+            _ => return None,
+        }
+    }
+    match stack[..] {
+        [offset] => Some(offset as u32),
+        _ => None,
+    }
+}
@@ -51,6 +51,10 @@ where
         self.values.len()
     }
 
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
     pub fn top(&self) -> Result<&T, Error> {
         self.values
             .last()
@@ -1,9 +1,10 @@
-use crate::{Error, PlainValidator};
+use crate::{Error, ImportCounts, PlainValidator, SegmentOffset};
 use parity_wasm::{
     builder::module,
     elements::{
-        BlockType, External, GlobalEntry, GlobalType, ImportEntry, InitExpr, Instruction,
-        Instructions, MemoryType, Module, TableType, ValueType,
+        BlockType, BrTableData, BulkInstruction, CustomSection, DataSection, DataSegment,
+        ElementSection, ElementSegment, External, GlobalEntry, GlobalType, ImportEntry, InitExpr,
+        Instruction, Instructions, Local, MemoryType, Module, Section, TableType, ValueType,
     },
 };
 
@@ -64,6 +65,35 @@ fn limits() {
     }
 }
 
+#[cfg(feature = "atomics")]
+#[test]
+fn shared_memory_requires_maximum() {
+    use parity_wasm::elements::MemorySection;
+
+    for (max, is_valid) in vec![(Some(1), true), (None, false)] {
+        let mut memory_type = MemoryType::new(1, max);
+        memory_type.set_shared(true);
+
+        // defined memory
+        let m = module()
+            .with_section(Section::Memory(MemorySection::with_entries(vec![
+                memory_type.clone(),
+            ])))
+            .build();
+        assert_eq!(validate_module(&m).is_ok(), is_valid);
+
+        // imported memory
+        let m = module()
+            .with_import(ImportEntry::new(
+                "core".into(),
+                "memory".into(),
+                External::Memory(memory_type),
+            ))
+            .build();
+        assert_eq!(validate_module(&m).is_ok(), is_valid);
+    }
+}
+
 #[test]
 fn global_init_const() {
     let m = module()
@@ -166,6 +196,112 @@ fn global_init_misc() {
     assert!(validate_module(&m).is_err());
 }
 
+#[test]
+fn global_init_extended_const() {
+    // arithmetic on constants
+    let m = module()
+        .with_global(GlobalEntry::new(
+            GlobalType::new(ValueType::I32, false),
+            InitExpr::new(vec![
+                Instruction::I32Const(1),
+                Instruction::I32Const(2),
+                Instruction::I32Add,
+                Instruction::End,
+            ]),
+        ))
+        .build();
+    assert!(validate_module(&m).is_ok());
+
+    // arithmetic on imported globals
+    let m = module()
+        .with_import(ImportEntry::new(
+            "env".into(),
+            "ext_global".into(),
+            External::Global(GlobalType::new(ValueType::I64, false)),
+        ))
+        .with_global(GlobalEntry::new(
+            GlobalType::new(ValueType::I64, false),
+            InitExpr::new(vec![
+                Instruction::GetGlobal(0),
+                Instruction::I64Const(2),
+                Instruction::I64Mul,
+                Instruction::I64Const(1),
+                Instruction::I64Sub,
+                Instruction::End,
+            ]),
+        ))
+        .build();
+    assert!(validate_module(&m).is_ok());
+
+    // operand types should match the operator
+    let m = module()
+        .with_global(GlobalEntry::new(
+            GlobalType::new(ValueType::I32, false),
+            InitExpr::new(vec![
+                Instruction::I32Const(1),
+                Instruction::I64Const(2),
+                Instruction::I32Add,
+                Instruction::End,
+            ]),
+        ))
+        .build();
+    assert!(validate_module(&m).is_err());
+
+    // result type should match the global type
+    let m = module()
+        .with_global(GlobalEntry::new(
+            GlobalType::new(ValueType::I32, false),
+            InitExpr::new(vec![
+                Instruction::I64Const(1),
+                Instruction::I64Const(2),
+                Instruction::I64Add,
+                Instruction::End,
+            ]),
+        ))
+        .build();
+    assert!(validate_module(&m).is_err());
+
+    // missing operand
+    let m = module()
+        .with_global(GlobalEntry::new(
+            GlobalType::new(ValueType::I32, false),
+            InitExpr::new(vec![
+                Instruction::I32Const(1),
+                Instruction::I32Add,
+                Instruction::End,
+            ]),
+        ))
+        .build();
+    assert!(validate_module(&m).is_err());
+
+    // more than one value left
+    let m = module()
+        .with_global(GlobalEntry::new(
+            GlobalType::new(ValueType::I32, false),
+            InitExpr::new(vec![
+                Instruction::I32Const(1),
+                Instruction::I32Const(2),
+                Instruction::End,
+            ]),
+        ))
+        .build();
+    assert!(validate_module(&m).is_err());
+
+    // operators outside of the proposal are still rejected
+    let m = module()
+        .with_global(GlobalEntry::new(
+            GlobalType::new(ValueType::I32, false),
+            InitExpr::new(vec![
+                Instruction::I32Const(1),
+                Instruction::I32Const(2),
+                Instruction::I32DivS,
+                Instruction::End,
+            ]),
+        ))
+        .build();
+    assert!(validate_module(&m).is_err());
+}
+
 #[test]
 fn module_limits_validity() {
     // module cannot contain more than 1 memory atm.
@@ -227,6 +363,42 @@ fn funcs() {
     assert!(validate_module(&m).is_ok());
 }
 
+#[test]
+fn function_and_code_section_len_mismatch() {
+    let m = module()
+        .function()
+        .signature()
+        .build()
+        .body()
+        .build()
+        .build()
+        .build();
+    assert!(validate_module(&m).is_ok());
+
+    // more bodies than declared functions.
+    let mut more_bodies = m.clone();
+    let body = more_bodies.code_section().unwrap().bodies()[0].clone();
+    more_bodies
+        .code_section_mut()
+        .unwrap()
+        .bodies_mut()
+        .push(body);
+    let error = validate_module(&more_bodies).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "length of function section is 1, while len of code section is 2"
+    );
+
+    // less bodies than declared functions.
+    let mut less_bodies = m;
+    less_bodies.code_section_mut().unwrap().bodies_mut().clear();
+    let error = validate_module(&less_bodies).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "length of function section is 1, while len of code section is 0"
+    );
+}
+
 #[test]
 fn globals() {
     // import immutable global is legal.
@@ -250,6 +422,350 @@ fn globals() {
     assert!(validate_module(&m).is_ok());
 }
 
+#[test]
+fn table_init_and_elem_drop() {
+    let module_with_body = |instructions: Vec<Instruction>| {
+        let mut passive_segment = ElementSegment::new(0, None, vec![0]);
+        passive_segment.set_passive(true);
+        module()
+            .with_table(TableType::new(1, None))
+            .function()
+            .signature()
+            .build()
+            .body()
+            .with_instructions(Instructions::new(instructions))
+            .build()
+            .build()
+            .with_section(Section::Element(ElementSection::with_entries(vec![
+                passive_segment,
+            ])))
+            .build()
+    };
+
+    // passive segments can be used by `table.init` and `elem.drop`.
+    let m = module_with_body(vec![
+        Instruction::I32Const(0),
+        Instruction::I32Const(0),
+        Instruction::I32Const(1),
+        Instruction::Bulk(BulkInstruction::TableInit(0)),
+        Instruction::Bulk(BulkInstruction::TableDrop(0)),
+        Instruction::End,
+    ]);
+    assert!(validate_module(&m).is_ok());
+
+    // `table.init` expects three operands.
+    let m = module_with_body(vec![
+        Instruction::I32Const(0),
+        Instruction::I32Const(0),
+        Instruction::Bulk(BulkInstruction::TableInit(0)),
+        Instruction::End,
+    ]);
+    assert!(validate_module(&m).is_err());
+
+    // segment indices must be in range.
+    let m = module_with_body(vec![
+        Instruction::Bulk(BulkInstruction::TableDrop(1)),
+        Instruction::End,
+    ]);
+    assert!(validate_module(&m).is_err());
+}
+
+#[test]
+fn memory_copy() {
+    let module_with_body = |with_memory: bool, instructions: Vec<Instruction>| {
+        let mut builder = module();
+        if with_memory {
+            builder = builder.memory().with_min(1).build();
+        }
+        builder
+            .function()
+            .signature()
+            .build()
+            .body()
+            .with_instructions(Instructions::new(instructions))
+            .build()
+            .build()
+            .build()
+    };
+    let copy = vec![
+        Instruction::I32Const(0),
+        Instruction::I32Const(1),
+        Instruction::I32Const(2),
+        Instruction::Bulk(BulkInstruction::MemoryCopy),
+        Instruction::End,
+    ];
+
+    assert!(validate_module(&module_with_body(true, copy.clone())).is_ok());
+
+    // `memory.copy` requires a memory.
+    assert!(validate_module(&module_with_body(false, copy)).is_err());
+
+    // `memory.copy` expects three operands.
+    let m = module_with_body(
+        true,
+        vec![
+            Instruction::I32Const(0),
+            Instruction::I32Const(1),
+            Instruction::Bulk(BulkInstruction::MemoryCopy),
+            Instruction::End,
+        ],
+    );
+    assert!(validate_module(&m).is_err());
+}
+
+#[test]
+fn select_and_drop() {
+    let module_with_body = |result: Option<ValueType>, mut instructions: Vec<Instruction>| {
+        instructions.push(Instruction::End);
+        let signature = module().function().signature();
+        let signature = match result {
+            Some(result) => signature.with_result(result),
+            None => signature,
+        };
+        signature
+            .build()
+            .body()
+            .with_instructions(Instructions::new(instructions))
+            .build()
+            .build()
+            .build()
+    };
+    let select = |first: Instruction, second: Instruction, condition: Instruction| {
+        vec![first, second, condition, Instruction::Select]
+    };
+    let i32_result = Some(ValueType::I32);
+
+    let m = module_with_body(
+        i32_result,
+        select(
+            Instruction::I32Const(1),
+            Instruction::I32Const(2),
+            Instruction::I32Const(0),
+        ),
+    );
+    assert!(validate_module(&m).is_ok());
+
+    // Both operands must have the same type.
+    for (first, second) in [
+        (Instruction::I32Const(1), Instruction::F32Const(0)),
+        (Instruction::F32Const(0), Instruction::I32Const(1)),
+        (Instruction::I64Const(1), Instruction::I32Const(1)),
+    ] {
+        let m = module_with_body(None, select(first, second, Instruction::I32Const(0)));
+        assert!(validate_module(&m).is_err());
+    }
+
+    // The condition must be an i32.
+    let m = module_with_body(
+        i32_result,
+        select(
+            Instruction::I32Const(1),
+            Instruction::I32Const(2),
+            Instruction::I64Const(0),
+        ),
+    );
+    assert!(validate_module(&m).is_err());
+
+    // The result has the type of the operands.
+    let m = module_with_body(
+        i32_result,
+        select(
+            Instruction::F64Const(0),
+            Instruction::F64Const(0),
+            Instruction::I32Const(0),
+        ),
+    );
+    assert!(validate_module(&m).is_err());
+
+    // In unreachable code missing operands take the type of the present ones.
+    let body = vec![
+        Instruction::Unreachable,
+        Instruction::I32Const(2),
+        Instruction::I32Const(0),
+        Instruction::Select,
+    ];
+    assert!(validate_module(&module_with_body(i32_result, body)).is_ok());
+    let body = vec![
+        Instruction::Unreachable,
+        Instruction::F32Const(0),
+        Instruction::I32Const(0),
+        Instruction::Select,
+    ];
+    assert!(validate_module(&module_with_body(i32_result, body)).is_err());
+
+    // `drop` pops a value of any type, but there has to be one.
+    for value in [
+        Instruction::I32Const(0),
+        Instruction::I64Const(0),
+        Instruction::F32Const(0),
+        Instruction::F64Const(0),
+    ] {
+        let m = module_with_body(None, vec![value, Instruction::Drop]);
+        assert!(validate_module(&m).is_ok());
+    }
+    assert!(validate_module(&module_with_body(None, vec![Instruction::Drop])).is_err());
+    let m = module_with_body(None, vec![Instruction::Unreachable, Instruction::Drop]);
+    assert!(validate_module(&m).is_ok());
+}
+
+#[test]
+fn unreachable_code_is_stack_polymorphic() {
+    use Instruction::*;
+
+    let module_with_body = |mut instructions: Vec<Instruction>| {
+        instructions.push(End);
+        module()
+            .function()
+            .signature()
+            .with_result(ValueType::I32)
+            .build()
+            .body()
+            .with_instructions(Instructions::new(instructions))
+            .build()
+            .build()
+            .build()
+    };
+    let i32_block = || Block(BlockType::Value(ValueType::I32));
+
+    // Missing operands after an unconditional transfer of control are of any type.
+    for body in [
+        vec![Unreachable, I32Add],
+        vec![I32Const(0), Return, I32Add],
+        vec![I32Const(0), Return, Return],
+        vec![i32_block(), I32Const(0), Br(0), I32Add, End],
+        vec![
+            i32_block(),
+            I32Const(0),
+            I32Const(0),
+            BrTable(Box::new(BrTableData {
+                table: Box::new([0]),
+                default: 0,
+            })),
+            I32Add,
+            End,
+        ],
+        vec![Unreachable, I32Const(0), I32Add],
+        vec![Unreachable, Drop, Drop, I32Const(0)],
+    ] {
+        assert!(
+            validate_module(&module_with_body(body.clone())).is_ok(),
+            "{:?}",
+            body
+        );
+    }
+
+    for body in [
+        // Operands that are present must still have the right type.
+        vec![Unreachable, I64Const(0), I32Add],
+        vec![Unreachable, I64Const(0)],
+        // The stack is polymorphic up to the end of the block only.
+        vec![Block(BlockType::NoResult), Unreachable, End, I32Add],
+        // `br_if` may fall through.
+        vec![i32_block(), I32Const(0), I32Const(0), BrIf(0), I32Add, End],
+    ] {
+        assert!(
+            validate_module(&module_with_body(body.clone())).is_err(),
+            "{:?}",
+            body
+        );
+    }
+}
+
+#[test]
+fn import_counts() {
+    let m = module()
+        .function()
+        .signature()
+        .build()
+        .body()
+        .build()
+        .build()
+        .build();
+    let validated = super::validate::<PlainValidator>(m).unwrap();
+    assert_eq!(validated.import_counts(), ImportCounts::default());
+    assert!(!validated.requires_memory_import());
+    assert!(!validated.requires_table_import());
+
+    let m = module()
+        .with_import(ImportEntry::new(
+            "env".into(),
+            "memory".into(),
+            External::Memory(MemoryType::new(1, None)),
+        ))
+        .with_import(ImportEntry::new(
+            "env".into(),
+            "table".into(),
+            External::Table(TableType::new(1, None)),
+        ))
+        .with_import(ImportEntry::new(
+            "env".into(),
+            "global".into(),
+            External::Global(GlobalType::new(ValueType::I32, false)),
+        ))
+        .build();
+    let validated = super::validate::<PlainValidator>(m).unwrap();
+    assert_eq!(
+        validated.import_counts(),
+        ImportCounts {
+            functions: 0,
+            tables: 1,
+            memories: 1,
+            globals: 1,
+        }
+    );
+    assert!(validated.requires_memory_import());
+    assert!(validated.requires_table_import());
+}
+
+#[test]
+fn initial_memory_and_table_sizes() {
+    let m = module().build();
+    let validated = super::validate::<PlainValidator>(m).unwrap();
+    assert_eq!(validated.initial_memory_pages(), None);
+    assert_eq!(validated.initial_table_size(), None);
+
+    let m = module()
+        .memory()
+        .with_min(3)
+        .with_max(Some(10))
+        .build()
+        .table()
+        .with_min(7)
+        .build()
+        .build();
+    let validated = super::validate::<PlainValidator>(m).unwrap();
+    assert_eq!(validated.initial_memory_pages(), Some(3));
+    assert_eq!(validated.initial_table_size(), Some(7));
+
+    let m = module()
+        .with_import(ImportEntry::new(
+            "env".into(),
+            "memory".into(),
+            External::Memory(MemoryType::new(2, None)),
+        ))
+        .table()
+        .with_min(1)
+        .build()
+        .build();
+    let validated = super::validate::<PlainValidator>(m).unwrap();
+    assert_eq!(validated.initial_memory_pages(), None);
+    assert_eq!(validated.initial_table_size(), Some(1));
+
+    let m = module()
+        .with_import(ImportEntry::new(
+            "env".into(),
+            "table".into(),
+            External::Table(TableType::new(4, None)),
+        ))
+        .memory()
+        .with_min(0)
+        .build()
+        .build();
+    let validated = super::validate::<PlainValidator>(m).unwrap();
+    assert_eq!(validated.initial_memory_pages(), Some(0));
+    assert_eq!(validated.initial_table_size(), None);
+}
+
 #[test]
 fn if_else_with_return_type_validation() {
     let m = module()
@@ -275,3 +791,246 @@ fn if_else_with_return_type_validation() {
         .build();
     validate_module(&m).unwrap();
 }
+
+#[test]
+fn local_index_out_of_range() {
+    // The second function has one parameter and two declared locals.
+    let module_with_body = |mut instructions: Vec<Instruction>| {
+        instructions.push(Instruction::End);
+        module()
+            .function()
+            .signature()
+            .build()
+            .body()
+            .build()
+            .build()
+            .function()
+            .signature()
+            .with_param(ValueType::I32)
+            .build()
+            .body()
+            .with_locals(vec![Local::new(2, ValueType::I64)])
+            .with_instructions(Instructions::new(instructions))
+            .build()
+            .build()
+            .build()
+    };
+
+    let m = module_with_body(vec![
+        Instruction::GetLocal(0),
+        Instruction::SetLocal(0),
+        Instruction::GetLocal(2),
+        Instruction::TeeLocal(1),
+        Instruction::Drop,
+    ]);
+    assert!(validate_module(&m).is_ok());
+
+    for (instruction, index) in [
+        (Instruction::GetLocal(3), 3),
+        (Instruction::SetLocal(3), 3),
+        (Instruction::TeeLocal(3), 3),
+        (Instruction::GetLocal(u32::MAX), u32::MAX),
+    ] {
+        let m = module_with_body(vec![
+            Instruction::I32Const(0),
+            instruction,
+            Instruction::Drop,
+        ]);
+        let message = validate_module(&m)
+            .expect_err("local index is out of range")
+            .to_string();
+        assert!(message.contains("Function #1"), "{}", message);
+        assert!(
+            message.contains(&format!(
+                "local with index {} when there are only 3 locals",
+                index
+            )),
+            "{}",
+            message
+        );
+    }
+}
+
+#[test]
+fn nesting_depth_limit() {
+    use crate::{PlainFuncValidator, Validator, DEFAULT_MAX_NESTING_DEPTH};
+
+    struct ShallowValidator;
+
+    impl Validator for ShallowValidator {
+        type Output = ();
+        type FuncValidator = PlainFuncValidator;
+        fn new(_module: &Module) -> ShallowValidator {
+            ShallowValidator
+        }
+        fn max_nesting_depth(&self) -> usize {
+            4
+        }
+        fn on_function_validated(&mut self, _index: u32, _output: ()) {}
+        fn finish(self) {}
+    }
+
+    let nested_blocks = |depth: usize| {
+        let instructions = (0..depth)
+            .map(|_| Instruction::Block(BlockType::NoResult))
+            .chain((0..=depth).map(|_| Instruction::End))
+            .collect();
+        module()
+            .function()
+            .signature()
+            .build()
+            .body()
+            .with_instructions(Instructions::new(instructions))
+            .build()
+            .build()
+            .build()
+    };
+
+    assert!(super::validate_module::<ShallowValidator>(&nested_blocks(4)).is_ok());
+    let message = super::validate_module::<ShallowValidator>(&nested_blocks(5))
+        .expect_err("blocks are nested too deep")
+        .to_string();
+    assert!(
+        message.contains("control structures nested deeper than the limit of 4"),
+        "{}",
+        message
+    );
+
+    // `loop` and `if` count towards the limit as well.
+    let m = module()
+        .function()
+        .signature()
+        .build()
+        .body()
+        .with_instructions(Instructions::new(vec![
+            Instruction::Loop(BlockType::NoResult),
+            Instruction::Block(BlockType::NoResult),
+            Instruction::I32Const(1),
+            Instruction::If(BlockType::NoResult),
+            Instruction::Loop(BlockType::NoResult),
+            Instruction::I32Const(1),
+            Instruction::If(BlockType::NoResult),
+            Instruction::End,
+            Instruction::End,
+            Instruction::End,
+            Instruction::End,
+            Instruction::End,
+            Instruction::End,
+        ]))
+        .build()
+        .build()
+        .build();
+    assert!(super::validate_module::<ShallowValidator>(&m).is_err());
+    assert!(validate_module(&m).is_ok());
+
+    assert!(validate_module(&nested_blocks(DEFAULT_MAX_NESTING_DEPTH)).is_ok());
+    assert!(validate_module(&nested_blocks(DEFAULT_MAX_NESTING_DEPTH + 1)).is_err());
+    assert!(validate_module(&nested_blocks(100_000)).is_err());
+}
+
+#[test]
+fn segments() {
+    let mut passive_segment = ElementSegment::new(0, None, vec![0]);
+    passive_segment.set_passive(true);
+    let base_offset = InitExpr::new(vec![Instruction::GetGlobal(0), Instruction::End]);
+    let m = module()
+        .with_import(ImportEntry::new(
+            "env".into(),
+            "base".into(),
+            External::Global(GlobalType::new(ValueType::I32, false)),
+        ))
+        .memory()
+        .with_min(1)
+        .build()
+        .with_table(TableType::new(4, None))
+        .function()
+        .signature()
+        .build()
+        .body()
+        .build()
+        .build()
+        .with_section(Section::Data(DataSection::with_entries(vec![
+            DataSegment::new(
+                0,
+                Some(InitExpr::new(vec![
+                    Instruction::I32Const(8),
+                    Instruction::I32Const(2),
+                    Instruction::I32Mul,
+                    Instruction::End,
+                ])),
+                b"hello".to_vec(),
+            ),
+            DataSegment::new(0, Some(base_offset.clone()), b"world".to_vec()),
+        ])))
+        .with_section(Section::Element(ElementSection::with_entries(vec![
+            ElementSegment::new(
+                0,
+                Some(InitExpr::new(vec![
+                    Instruction::I32Const(-1),
+                    Instruction::End,
+                ])),
+                vec![0, 0],
+            ),
+            passive_segment,
+        ])))
+        .build();
+    let validated = crate::validate::<PlainValidator>(m).unwrap();
+
+    let data = validated.data_segments().collect::<Vec<_>>();
+    assert_eq!(data.len(), 2);
+    assert_eq!(data[0].memory_index, 0);
+    assert_eq!(data[0].offset, Some(SegmentOffset::Const(16)));
+    assert_eq!(data[0].data, b"hello");
+    assert_eq!(
+        data[1].offset,
+        Some(SegmentOffset::Unevaluated(&base_offset))
+    );
+    assert_eq!(data[1].data, b"world");
+
+    let elems = validated.elem_segments().collect::<Vec<_>>();
+    assert_eq!(elems.len(), 2);
+    assert_eq!(elems[0].table_index, 0);
+    assert_eq!(elems[0].offset, Some(SegmentOffset::Const(u32::MAX)));
+    assert_eq!(elems[0].members, [0, 0]);
+    assert_eq!(elems[1].offset, None);
+    assert_eq!(elems[1].members, [0]);
+}
+
+#[test]
+fn content_hash() {
+    fn module_returning(value: i32) -> Module {
+        module()
+            .function()
+            .signature()
+            .with_result(ValueType::I32)
+            .build()
+            .body()
+            .with_instructions(Instructions::new(vec![
+                Instruction::I32Const(value),
+                Instruction::End,
+            ]))
+            .build()
+            .build()
+            .build()
+    }
+    fn content_hash(module: Module) -> u64 {
+        super::validate::<PlainValidator>(module)
+            .unwrap()
+            .content_hash()
+    }
+
+    let hash = content_hash(module_returning(1));
+    // The hash is persistable, so it must not change between runs or platforms.
+    assert_eq!(hash, 0x0986_a596_c687_20e0);
+
+    let mut with_custom_section = module_returning(1);
+    with_custom_section
+        .sections_mut()
+        .push(Section::Custom(CustomSection::new(
+            "producers".into(),
+            vec![1, 2, 3],
+        )));
+    assert_eq!(content_hash(with_custom_section), hash);
+
+    assert_ne!(content_hash(module_returning(2)), hash);
+}